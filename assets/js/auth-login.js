@@ -1 +1 @@
-var k;function d(t){return{lang:t?.lang??k?.lang,message:t?.message,abortEarly:t?.abortEarly??k?.abortEarly,abortPipeEarly:t?.abortPipeEarly??k?.abortPipeEarly}}var j;function D(t){return j?.get(t)}var O;function $(t){return O?.get(t)}var R;function P(t,n){return R?.get(t)?.get(n)}function l(t){let n=typeof t;return n==="string"?`"${t}"`:n==="number"||n==="bigint"||n==="boolean"?`${t}`:n==="object"||n==="function"?(t&&Object.getPrototypeOf(t)?.constructor?.name)??"null":n}function e(t,n,i,s,o){let c=o&&"input"in o?o.input:i.value,p=o?.expected??t.expects??null,h=o?.received??l(c),f={kind:t.kind,type:t.type,input:c,expected:p,received:h,message:`Invalid ${n}: ${p?`Expected ${p} but r`:"R"}eceived ${h}`,requirement:t.requirement,path:o?.path,issues:o?.issues,lang:s.lang,abortEarly:s.abortEarly,abortPipeEarly:s.abortPipeEarly},v=t.kind==="schema",x=o?.message??t.message??P(t.reference,f.lang)??(v?$(f.lang):null)??s.message??D(f.lang);x!==void 0&&(f.message=typeof x=="function"?x(f):x),v&&(i.typed=!1),i.issues?i.issues.push(f):i.issues=[f]}function r(t){return{version:1,vendor:"valibot",validate(n){return t["~run"]({value:n},d())}}}var a=class extends Error{issues;constructor(n){super(n[0].message),this.name="ValiError",this.issues=n}};function m(t,n,i){return typeof t.fallback=="function"?t.fallback(n,i):t.fallback}function u(t,n,i){return typeof t.default=="function"?t.default(n,i):t.default}function I(t,n){return{kind:"schema",type:"object",reference:I,expects:"Object",async:!1,entries:t,message:n,get"~standard"(){return r(this)},"~run"(i,s){let o=i.value;if(o&&typeof o=="object"){i.typed=!0,i.value={};for(let c in this.entries){let p=this.entries[c];if(c in o||(p.type==="exact_optional"||p.type==="optional"||p.type==="nullish")&&p.default!==void 0){let h=c in o?o[c]:u(p),f=p["~run"]({value:h},s);if(f.issues){let v={type:"object",origin:"value",input:o,key:c,value:h};for(let x of f.issues)x.path?x.path.unshift(v):x.path=[v],i.issues?.push(x);if(i.issues||(i.issues=f.issues),s.abortEarly){i.typed=!1;break}}f.typed||(i.typed=!1),i.value[c]=f.value}else if(p.fallback!==void 0)i.value[c]=m(p);else if(p.type!=="exact_optional"&&p.type!=="optional"&&p.type!=="nullish"&&(e(this,"key",i,s,{input:void 0,expected:`"${c}"`,path:[{type:"object",origin:"key",input:o,key:c,value:o[c]}]}),s.abortEarly))break}}else e(this,"type",i,s);return i}}}function A(t){return{kind:"schema",type:"string",reference:A,expects:"string",async:!1,message:t,get"~standard"(){return r(this)},"~run"(n,i){return typeof n.value=="string"?n.typed=!0:e(this,"type",n,i),n}}}function w(){return{kind:"schema",type:"unknown",reference:w,expects:"unknown",async:!1,get"~standard"(){return r(this)},"~run"(t){return t.typed=!0,t}}}function S(t,n,i){let s=t["~run"]({value:n},d(i));if(s.issues)throw new a(s.issues);return s.value}var V=I({publicKey:w(),challenge_id:A()});async function L({startAuthentication:t,fetch:n=globalThis.fetch,next:i="/"}){let s=await n("/auth/login/challenge/discoverable",{method:"POST"});if(!s.ok){let f=await s.text();throw new Error(f||"challenge failed")}let{publicKey:o,challenge_id:c}=S(V,await s.json()),p=await t({optionsJSON:o}),h=await n("/auth/login/finish/discoverable",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify({challenge_id:c,credential:p})});if(!h.ok){let f=await h.text();throw new Error(f||"authentication failed")}return i}if(typeof document<"u"){let t=document.getElementById("auth-btn");if(t){let n=document.getElementById("auth-form"),i=document.getElementById("auth-error"),s=n?.dataset.next??"/";t.addEventListener("click",async()=>{i.style.display="none";try{let o=await L({startAuthentication:SimpleWebAuthnBrowser.startAuthentication,next:s});window.location.href=o}catch(o){i.textContent=o instanceof Error?o.message:String(o),i.style.display=""}})}}export{L as authenticateDiscoverable};
+var k;function d(t){return{lang:t?.lang??k?.lang,message:t?.message,abortEarly:t?.abortEarly??k?.abortEarly,abortPipeEarly:t?.abortPipeEarly??k?.abortPipeEarly}}var j;function D(t){return j?.get(t)}var O;function $(t){return O?.get(t)}var R;function P(t,n){return R?.get(t)?.get(n)}function l(t){let n=typeof t;return n==="string"?`"${t}"`:n==="number"||n==="bigint"||n==="boolean"?`${t}`:n==="object"||n==="function"?(t&&Object.getPrototypeOf(t)?.constructor?.name)??"null":n}function e(t,n,i,s,o){let c=o&&"input"in o?o.input:i.value,p=o?.expected??t.expects??null,h=o?.received??l(c),f={kind:t.kind,type:t.type,input:c,expected:p,received:h,message:`Invalid ${n}: ${p?`Expected ${p} but r`:"R"}eceived ${h}`,requirement:t.requirement,path:o?.path,issues:o?.issues,lang:s.lang,abortEarly:s.abortEarly,abortPipeEarly:s.abortPipeEarly},v=t.kind==="schema",x=o?.message??t.message??P(t.reference,f.lang)??(v?$(f.lang):null)??s.message??D(f.lang);x!==void 0&&(f.message=typeof x=="function"?x(f):x),v&&(i.typed=!1),i.issues?i.issues.push(f):i.issues=[f]}function r(t){return{version:1,vendor:"valibot",validate(n){return t["~run"]({value:n},d())}}}var a=class extends Error{issues;constructor(n){super(n[0].message),this.name="ValiError",this.issues=n}};function m(t,n,i){return typeof t.fallback=="function"?t.fallback(n,i):t.fallback}function u(t,n,i){return typeof t.default=="function"?t.default(n,i):t.default}function I(t,n){return{kind:"schema",type:"object",reference:I,expects:"Object",async:!1,entries:t,message:n,get"~standard"(){return r(this)},"~run"(i,s){let o=i.value;if(o&&typeof o=="object"){i.typed=!0,i.value={};for(let c in this.entries){let p=this.entries[c];if(c in o||(p.type==="exact_optional"||p.type==="optional"||p.type==="nullish")&&p.default!==void 0){let h=c in o?o[c]:u(p),f=p["~run"]({value:h},s);if(f.issues){let v={type:"object",origin:"value",input:o,key:c,value:h};for(let x of f.issues)x.path?x.path.unshift(v):x.path=[v],i.issues?.push(x);if(i.issues||(i.issues=f.issues),s.abortEarly){i.typed=!1;break}}f.typed||(i.typed=!1),i.value[c]=f.value}else if(p.fallback!==void 0)i.value[c]=m(p);else if(p.type!=="exact_optional"&&p.type!=="optional"&&p.type!=="nullish"&&(e(this,"key",i,s,{input:void 0,expected:`"${c}"`,path:[{type:"object",origin:"key",input:o,key:c,value:o[c]}]}),s.abortEarly))break}}else e(this,"type",i,s);return i}}}function A(t){return{kind:"schema",type:"string",reference:A,expects:"string",async:!1,message:t,get"~standard"(){return r(this)},"~run"(n,i){return typeof n.value=="string"?n.typed=!0:e(this,"type",n,i),n}}}function w(){return{kind:"schema",type:"unknown",reference:w,expects:"unknown",async:!1,get"~standard"(){return r(this)},"~run"(t){return t.typed=!0,t}}}function S(t,n,i){let s=t["~run"]({value:n},d(i));if(s.issues)throw new a(s.issues);return s.value}var V=I({publicKey:w(),challenge_id:A()});async function L({startAuthentication:t,fetch:n=globalThis.fetch,next:i="/",kiosk:K=!1}){let s=await n("/auth/login/challenge/discoverable",{method:"POST"});if(!s.ok){let f=await s.text();throw new Error(f||"challenge failed")}let{publicKey:o,challenge_id:c}=S(V,await s.json()),p=await t({optionsJSON:o}),h=await n("/auth/login/finish/discoverable",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify({challenge_id:c,credential:p,kiosk:K})});if(!h.ok){let f=await h.text();throw new Error(f||"authentication failed")}return i}if(typeof document<"u"){let t=document.getElementById("auth-btn");if(t){let n=document.getElementById("auth-form"),i=document.getElementById("auth-error"),K=document.getElementById("auth-kiosk"),s=n?.dataset.next??"/";t.addEventListener("click",async()=>{i.style.display="none";try{let o=await L({startAuthentication:SimpleWebAuthnBrowser.startAuthentication,next:s,kiosk:K?.checked??!1});window.location.href=o}catch(o){i.textContent=o instanceof Error?o.message:String(o),i.style.display=""}})}}export{L as authenticateDiscoverable};
@@ -1 +1 @@
-var k;function d(t){return{lang:t?.lang??k?.lang,message:t?.message,abortEarly:t?.abortEarly??k?.abortEarly,abortPipeEarly:t?.abortPipeEarly??k?.abortPipeEarly}}var P;function S(t){return P?.get(t)}var j;function $(t){return j?.get(t)}var O;function D(t,i){return O?.get(t)?.get(i)}function f(t){let i=typeof t;return i==="string"?`"${t}"`:i==="number"||i==="bigint"||i==="boolean"?`${t}`:i==="object"||i==="function"?(t&&Object.getPrototypeOf(t)?.constructor?.name)??"null":i}function e(t,i,n,s,o){let l=o&&"input"in o?o.input:n.value,p=o?.expected??t.expects??null,x=o?.received??f(l),c={kind:t.kind,type:t.type,input:l,expected:p,received:x,message:`Invalid ${i}: ${p?`Expected ${p} but r`:"R"}eceived ${x}`,requirement:t.requirement,path:o?.path,issues:o?.issues,lang:s.lang,abortEarly:s.abortEarly,abortPipeEarly:s.abortPipeEarly},v=t.kind==="schema",h=o?.message??t.message??D(t.reference,c.lang)??(v?$(c.lang):null)??s.message??S(c.lang);h!==void 0&&(c.message=typeof h=="function"?h(c):h),v&&(n.typed=!1),n.issues?n.issues.push(c):n.issues=[c]}function r(t){return{version:1,vendor:"valibot",validate(i){return t["~run"]({value:i},d())}}}var a=class extends Error{issues;constructor(i){super(i[0].message),this.name="ValiError",this.issues=i}};function m(t,i,n){return typeof t.fallback=="function"?t.fallback(i,n):t.fallback}function u(t,i,n){return typeof t.default=="function"?t.default(i,n):t.default}function I(t,i){return{kind:"schema",type:"object",reference:I,expects:"Object",async:!1,entries:t,message:i,get"~standard"(){return r(this)},"~run"(n,s){let o=n.value;if(o&&typeof o=="object"){n.typed=!0,n.value={};for(let l in this.entries){let p=this.entries[l];if(l in o||(p.type==="exact_optional"||p.type==="optional"||p.type==="nullish")&&p.default!==void 0){let x=l in o?o[l]:u(p),c=p["~run"]({value:x},s);if(c.issues){let v={type:"object",origin:"value",input:o,key:l,value:x};for(let h of c.issues)h.path?h.path.unshift(v):h.path=[v],n.issues?.push(h);if(n.issues||(n.issues=c.issues),s.abortEarly){n.typed=!1;break}}c.typed||(n.typed=!1),n.value[l]=c.value}else if(p.fallback!==void 0)n.value[l]=m(p);else if(p.type!=="exact_optional"&&p.type!=="optional"&&p.type!=="nullish"&&(e(this,"key",n,s,{input:void 0,expected:`"${l}"`,path:[{type:"object",origin:"key",input:o,key:l,value:o[l]}]}),s.abortEarly))break}}else e(this,"type",n,s);return n}}}function A(){return{kind:"schema",type:"unknown",reference:A,expects:"unknown",async:!1,get"~standard"(){return r(this)},"~run"(t){return t.typed=!0,t}}}function w(t,i,n){let s=t["~run"]({value:i},d(n));if(s.issues)throw new a(s.issues);return s.value}var T=I({publicKey:A()});async function V(t,{startRegistration:i,fetch:n=globalThis.fetch}){if(t=t.trim(),!t)throw new Error("enter your username");let s=await n("/auth/register/challenge",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify({username:t})});if(!s.ok){let x=await s.text();throw new Error(x||"challenge failed")}let{publicKey:o}=w(T,await s.json()),l=await i({optionsJSON:o}),p=await n("/auth/register/finish",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify({username:t,credential:l})});if(!p.ok){let x=await p.text();throw new Error(x||"registration failed")}return"/"}if(typeof document<"u"){let t=document.getElementById("reg-btn");if(t){let i=document.getElementById("username"),n=document.getElementById("reg-error");t.addEventListener("click",async()=>{let s=i?.value.trim()??"";n.style.display="none";try{let o=await V(s,{startRegistration:SimpleWebAuthnBrowser.startRegistration});window.location.href=o}catch(o){n.textContent=o instanceof Error?o.message:String(o),n.style.display=""}})}}export{V as register};
+var k;function d(t){return{lang:t?.lang??k?.lang,message:t?.message,abortEarly:t?.abortEarly??k?.abortEarly,abortPipeEarly:t?.abortPipeEarly??k?.abortPipeEarly}}var P;function S(t){return P?.get(t)}var j;function $(t){return j?.get(t)}var O;function D(t,i){return O?.get(t)?.get(i)}function f(t){let i=typeof t;return i==="string"?`"${t}"`:i==="number"||i==="bigint"||i==="boolean"?`${t}`:i==="object"||i==="function"?(t&&Object.getPrototypeOf(t)?.constructor?.name)??"null":i}function e(t,i,n,s,o){let l=o&&"input"in o?o.input:n.value,p=o?.expected??t.expects??null,x=o?.received??f(l),c={kind:t.kind,type:t.type,input:l,expected:p,received:x,message:`Invalid ${i}: ${p?`Expected ${p} but r`:"R"}eceived ${x}`,requirement:t.requirement,path:o?.path,issues:o?.issues,lang:s.lang,abortEarly:s.abortEarly,abortPipeEarly:s.abortPipeEarly},v=t.kind==="schema",h=o?.message??t.message??D(t.reference,c.lang)??(v?$(c.lang):null)??s.message??S(c.lang);h!==void 0&&(c.message=typeof h=="function"?h(c):h),v&&(n.typed=!1),n.issues?n.issues.push(c):n.issues=[c]}function r(t){return{version:1,vendor:"valibot",validate(i){return t["~run"]({value:i},d())}}}var a=class extends Error{issues;constructor(i){super(i[0].message),this.name="ValiError",this.issues=i}};function m(t,i,n){return typeof t.fallback=="function"?t.fallback(i,n):t.fallback}function u(t,i,n){return typeof t.default=="function"?t.default(i,n):t.default}function I(t,i){return{kind:"schema",type:"object",reference:I,expects:"Object",async:!1,entries:t,message:i,get"~standard"(){return r(this)},"~run"(n,s){let o=n.value;if(o&&typeof o=="object"){n.typed=!0,n.value={};for(let l in this.entries){let p=this.entries[l];if(l in o||(p.type==="exact_optional"||p.type==="optional"||p.type==="nullish")&&p.default!==void 0){let x=l in o?o[l]:u(p),c=p["~run"]({value:x},s);if(c.issues){let v={type:"object",origin:"value",input:o,key:l,value:x};for(let h of c.issues)h.path?h.path.unshift(v):h.path=[v],n.issues?.push(h);if(n.issues||(n.issues=c.issues),s.abortEarly){n.typed=!1;break}}c.typed||(n.typed=!1),n.value[l]=c.value}else if(p.fallback!==void 0)n.value[l]=m(p);else if(p.type!=="exact_optional"&&p.type!=="optional"&&p.type!=="nullish"&&(e(this,"key",n,s,{input:void 0,expected:`"${l}"`,path:[{type:"object",origin:"key",input:o,key:l,value:o[l]}]}),s.abortEarly))break}}else e(this,"type",n,s);return n}}}function A(){return{kind:"schema",type:"unknown",reference:A,expects:"unknown",async:!1,get"~standard"(){return r(this)},"~run"(t){return t.typed=!0,t}}}function w(t,i,n){let s=t["~run"]({value:i},d(n));if(s.issues)throw new a(s.issues);return s.value}var T=I({publicKey:A()});async function V(t,{startRegistration:i,fetch:n=globalThis.fetch,invite:K}){if(t=t.trim(),!t)throw new Error("enter your username");let s=await n("/auth/register/challenge",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify({username:t,invite:K})});if(!s.ok){let x=await s.text();throw new Error(x||"challenge failed")}let{publicKey:o}=w(T,await s.json()),l=await i({optionsJSON:o}),p=await n("/auth/register/finish",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify({username:t,credential:l})});if(!p.ok){let x=await p.text();throw new Error(x||"registration failed")}return"/"}async function W({startRegistration:t,fetch:i=globalThis.fetch}){let n=await i("/auth/account/passkeys/challenge",{method:"POST"});if(!n.ok){let p=await n.text();throw new Error(p||"challenge failed")}let{publicKey:s}=w(T,await n.json()),o=await t({optionsJSON:s}),l=await i("/auth/account/passkeys/finish",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify({credential:o})});if(!l.ok){let p=await l.text();throw new Error(p||"adding passkey failed")}return"/auth/account"}if(typeof document<"u"){let t=document.getElementById("reg-btn");if(t){let i=document.getElementById("username"),n=document.getElementById("reg-error"),g=document.getElementById("reg-form")?.dataset.invite;t.addEventListener("click",async()=>{let s=i?.value.trim()??"";n.style.display="none";try{let o=await V(s,{startRegistration:SimpleWebAuthnBrowser.startRegistration,invite:g});window.location.href=o}catch(o){n.textContent=o instanceof Error?o.message:String(o),n.style.display=""}})}let y=document.getElementById("add-passkey-btn");if(y){let i=document.getElementById("add-passkey-error");y.addEventListener("click",async()=>{i.style.display="none";try{let n=await W({startRegistration:SimpleWebAuthnBrowser.startRegistration});window.location.href=n}catch(n){i.textContent=n instanceof Error?n.message:String(n),i.style.display=""}})}}export{W as addPasskey,V as register};
@@ -0,0 +1 @@
+var c="X-CSRF-Token",f="csrf_token";function h(e){return e.querySelector('meta[name="csrf-token"]')?.getAttribute("content")||null}function o(e){return!["GET","HEAD","OPTIONS","TRACE"].includes((e??"GET").toUpperCase())}function r(e,t){try{return new URL(e,t).origin===t}catch{return!1}}function m(e,t,n){return(s,i)=>{let a=s instanceof Request?s:null,u=a?a.url:String(s),l=i?.method??a?.method;if(!o(l)||!r(u,n))return e(s,i);let d=new Headers(i?.headers??a?.headers);return d.has(c)||d.set(c,t),e(s,{...i,headers:d})}}function p(e,t,n){return!o(e.method)||!r(e.action,n)||e.querySelector(`input[name="${f}"]`)?!1:(e.appendHidden(f,t),!0)}if(typeof document<"u"){let e=h(document),t=globalThis.location.origin;e&&(globalThis.fetch=m(globalThis.fetch.bind(globalThis),e,t),document.addEventListener("submit",n=>{let s=n.target;p({method:s.method,action:s.action,querySelector:i=>s.querySelector(i),appendHidden(i,a){let u=document.createElement("input");u.type="hidden",u.name=i,u.value=a,s.appendChild(u)}},e,t)},!0),document.addEventListener("htmx:configRequest",n=>{let s=n.detail;o(s.verb)&&r(s.path,t)&&(s.headers[c]=e)}))}export{c as CSRF_HEADER,f as CSRF_FIELD,p as addFormToken,r as isSameOrigin,o as needsToken,h as readToken,m as withCsrf};
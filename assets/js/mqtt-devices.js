@@ -1 +1 @@
-async function d(s,t,{fetch:e=globalThis.fetch}={}){let n=`/api/mqtt/device-messages?integration=${encodeURIComponent(s)}&device=${encodeURIComponent(t)}`;return(await e(n)).text()}async function u(s,t,{fetch:e=globalThis.fetch}={}){let n=await e("/api/mqtt/publish",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify({topic:s,payload:t})});return n.ok?{ok:!0}:{ok:!1,status:n.status}}async function p(s,t,e,{fetch:n=globalThis.fetch}={}){let o=e.wasOpen;if(e.closeAll(),o)return;e.markOpen();let a;try{a=await d(s,t,{fetch:n})}catch(r){a=`<p class="leet-muted">failed to load messages: ${r instanceof Error?r.message:String(r)}</p>`}e.isStillOpen()&&e.insertPanel(a)}if(typeof document<"u"){let s=function(t){return{get wasOpen(){return t.hasAttribute("data-panel-open")},closeAll(){document.querySelectorAll(".device-panel-row").forEach(e=>e.remove()),document.querySelectorAll("[data-panel-open]").forEach(e=>e.removeAttribute("data-panel-open"))},markOpen(){t.setAttribute("data-panel-open","")},isStillOpen(){return t.hasAttribute("data-panel-open")},insertPanel(e){let n=document.createElement("tr");n.className="device-panel-row",n.innerHTML=`<td colspan="5"><div class="device-panel">${e}</div></td>`,t.after(n)}}};document.querySelectorAll("tr.device-row").forEach(t=>{let e=t;e.addEventListener("click",()=>{let n=e.dataset.integration??"",o=e.dataset.device??"";p(n,o,s(e))})}),document.addEventListener("submit",async t=>{let e=t.target.closest(".device-cmd-form");if(!e)return;t.preventDefault();let n=e.querySelector(".device-cmd-topic"),o=e.querySelector(".device-cmd-payload"),a=e.querySelector(".device-cmd-status"),r=e.querySelector('button[type="submit"]'),c=n?.value.trim()??"",l=o?.value.trim()??"";if(!c){a&&(a.textContent="topic required");return}r&&(r.disabled=!0),a&&(a.textContent="sending\u2026");try{let i=await u(c,l);i.ok?(a&&(a.textContent="sent"),o&&(o.value="")):a&&(a.textContent=`error ${i.status}`)}catch(i){a&&(a.textContent=`failed: ${i instanceof Error?i.message:String(i)}`)}finally{r&&(r.disabled=!1)}})}export{d as fetchDeviceMessages,p as handleDeviceRowClick,u as sendCommand};
+async function d(s,t,{fetch:e=globalThis.fetch}={}){let n=`/api/mqtt/device-messages?integration=${encodeURIComponent(s)}&device=${encodeURIComponent(t)}`;return(await e(n)).text()}async function u(s,t,{fetch:e=globalThis.fetch,broker:o}={}){let n=await e("/api/mqtt/publish",{method:"POST",headers:{"Content-Type":"application/json"},body:JSON.stringify(o?{broker:o,topic:s,payload:t}:{topic:s,payload:t})});return n.ok?{ok:!0}:{ok:!1,status:n.status}}async function p(s,t,e,{fetch:n=globalThis.fetch}={}){let o=e.wasOpen;if(e.closeAll(),o)return;e.markOpen();let a;try{a=await d(s,t,{fetch:n})}catch(r){a=`<p class="leet-muted">failed to load messages: ${r instanceof Error?r.message:String(r)}</p>`}e.isStillOpen()&&e.insertPanel(a)}if(typeof document<"u"){let s=function(t){return{get wasOpen(){return t.hasAttribute("data-panel-open")},closeAll(){document.querySelectorAll(".device-panel-row").forEach(e=>e.remove()),document.querySelectorAll("[data-panel-open]").forEach(e=>e.removeAttribute("data-panel-open"))},markOpen(){t.setAttribute("data-panel-open","")},isStillOpen(){return t.hasAttribute("data-panel-open")},insertPanel(e){let n=document.createElement("tr");n.className="device-panel-row",n.innerHTML=`<td colspan="5"><div class="device-panel">${e}</div></td>`,t.after(n)}}};document.querySelectorAll("tr.device-row").forEach(t=>{let e=t;e.addEventListener("click",()=>{let n=e.dataset.integration??"",o=e.dataset.device??"";p(n,o,s(e))})}),document.addEventListener("submit",async t=>{let e=t.target.closest(".device-cmd-form");if(!e)return;t.preventDefault();let n=e.querySelector(".device-cmd-topic"),o=e.querySelector(".device-cmd-payload"),a=e.querySelector(".device-cmd-status"),r=e.querySelector('button[type="submit"]'),br=e.querySelector(".device-cmd-broker"),c=n?.value.trim()??"",l=o?.value.trim()??"";if(!c){a&&(a.textContent="topic required");return}r&&(r.disabled=!0),a&&(a.textContent="sending\u2026");try{let i=await u(c,l,{broker:br?.value||void 0});i.ok?(a&&(a.textContent="sent"),o&&(o.value="")):a&&(a.textContent=`error ${i.status}`)}catch(i){a&&(a.textContent=`failed: ${i instanceof Error?i.message:String(i)}`)}finally{r&&(r.disabled=!1)}})}export{d as fetchDeviceMessages,p as handleDeviceRowClick,u as sendCommand};
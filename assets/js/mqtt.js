@@ -1 +1 @@
-var R=Symbol.for("preact-signals");function B(){if(h>1)h--;else{var i,t=!1;for(function(){var o=q;for(q=void 0;o!==void 0;)o.S.v===o.v&&(o.S.i=o.i),o=o.o}();x!==void 0;){var e=x;for(x=void 0,L++;e!==void 0;){var n=e.u;if(e.u=void 0,e.f&=-3,!(8&e.f)&&D(e))try{e.c()}catch(o){t||(i=o,t=!0)}e=n}}if(L=0,h--,t)throw i}}function b(i){if(h>0)return i();N=++X,h++;try{return i()}finally{B()}}var s=void 0;function W(i){var t=s;s=void 0;try{return i()}finally{s=t}}var H,x=void 0,h=0,L=0,X=0,N=0,q=void 0,M=0;function j(i){if(s!==void 0){var t=i.n;if(t===void 0||t.t!==s)return t={i:0,S:i,p:s.s,n:void 0,t:s,e:void 0,x:void 0,r:t},s.s!==void 0&&(s.s.n=t),s.s=t,i.n=t,32&s.f&&i.S(t),t;if(t.i===-1)return t.i=0,t.n!==void 0&&(t.n.p=t.p,t.p!==void 0&&(t.p.n=t.n),t.p=s.s,t.n=void 0,s.s.n=t,s.s=t),t}}function u(i,t){this.v=i,this.i=0,this.n=void 0,this.t=void 0,this.l=0,this.W=t?.watched,this.Z=t?.unwatched,this.name=t?.name}u.prototype.brand=R;u.prototype.h=function(){return!0};u.prototype.S=function(i){var t=this,e=this.t;e!==i&&i.e===void 0&&(i.x=e,this.t=i,e!==void 0?e.e=i:W(function(){var n;(n=t.W)==null||n.call(t)}))};u.prototype.U=function(i){var t=this;if(this.t!==void 0){var e=i.e,n=i.x;e!==void 0&&(e.x=n,i.e=void 0),n!==void 0&&(n.e=e,i.x=void 0),i===this.t&&(this.t=n,n===void 0&&W(function(){var o;(o=t.Z)==null||o.call(t)}))}};u.prototype.subscribe=function(i){var t=this;return S(function(){var e=t.value,n=s;s=void 0;try{i(e)}finally{s=n}},{name:"sub"})};u.prototype.valueOf=function(){return this.value};u.prototype.toString=function(){return this.value+""};u.prototype.toJSON=function(){return this.value};u.prototype.peek=function(){var i=s;s=void 0;try{return this.value}finally{s=i}};Object.defineProperty(u.prototype,"value",{get:function(){var i=j(this);return i!==void 0&&(i.i=this.i),this.v},set:function(i){if(i!==this.v){if(L>100)throw new Error("Cycle detected");(function(e){h!==0&&L===0&&e.l!==N&&(e.l=N,q={S:e,v:e.v,i:e.i,o:q})})(this),this.v=i,this.i++,M++,h++;try{for(var t=this.t;t!==void 0;t=t.x)t.t.N()}finally{B()}}}});function m(i,t){return new u(i,t)}function D(i){for(var t=i.s;t!==void 0;t=t.n)if(t.S.i!==t.i||!t.S.h()||t.S.i!==t.i)return!0;return!1}function Z(i){for(var t=i.s;t!==void 0;t=t.n){var e=t.S.n;if(e!==void 0&&(t.r=e),t.S.n=t,t.i=-1,t.n===void 0){i.s=t;break}}}function _(i){for(var t=i.s,e=void 0;t!==void 0;){var n=t.p;t.i===-1?(t.S.U(t),n!==void 0&&(n.n=t.n),t.n!==void 0&&(t.n.p=n)):e=t,t.S.n=t.r,t.r!==void 0&&(t.r=void 0),t=n}i.s=e}function y(i,t){u.call(this,void 0),this.x=i,this.s=void 0,this.g=M-1,this.f=4,this.W=t?.watched,this.Z=t?.unwatched,this.name=t?.name}y.prototype=new u;y.prototype.h=function(){if(this.f&=-3,1&this.f)return!1;if((36&this.f)==32||(this.f&=-5,this.g===M))return!0;if(this.g=M,this.f|=1,this.i>0&&!D(this))return this.f&=-2,!0;var i=s;try{Z(this),s=this;var t=this.x();(16&this.f||this.v!==t||this.i===0)&&(this.v=t,this.f&=-17,this.i++)}catch(e){this.v=e,this.f|=16,this.i++}return s=i,_(this),this.f&=-2,!0};y.prototype.S=function(i){if(this.t===void 0){this.f|=36;for(var t=this.s;t!==void 0;t=t.n)t.S.S(t)}u.prototype.S.call(this,i)};y.prototype.U=function(i){if(this.t!==void 0&&(u.prototype.U.call(this,i),this.t===void 0)){this.f&=-33;for(var t=this.s;t!==void 0;t=t.n)t.S.U(t)}};y.prototype.N=function(){if(!(2&this.f)){this.f|=6;for(var i=this.t;i!==void 0;i=i.x)i.t.N()}};Object.defineProperty(y.prototype,"value",{get:function(){if(1&this.f)throw new Error("Cycle detected");var i=j(this);if(this.h(),i!==void 0&&(i.i=this.i),16&this.f)throw this.v;return this.v}});function $(i,t){return new y(i,t)}function P(i){var t=i.m;if(i.m=void 0,typeof t=="function"){h++;var e=s;s=void 0;try{t()}catch(n){throw i.f&=-2,i.f|=8,T(i),n}finally{s=e,B()}}}function T(i){for(var t=i.s;t!==void 0;t=t.n)t.S.U(t);i.x=void 0,i.s=void 0,P(i)}function K(i){if(s!==this)throw new Error("Out-of-order effect");_(this),s=i,this.f&=-2,8&this.f&&T(this),B()}function w(i,t){this.x=i,this.m=void 0,this.s=void 0,this.u=void 0,this.f=32,this.name=t?.name,H&&H.push(this)}w.prototype.c=function(){var i=this.S();try{if(8&this.f||this.x===void 0)return;var t=this.x();typeof t=="function"&&(this.m=t)}finally{i()}};w.prototype.S=function(){if(1&this.f)throw new Error("Cycle detected");this.f|=1,this.f&=-9,P(this),Z(this),h++;var i=s;return s=this,K.bind(this,i)};w.prototype.N=function(){2&this.f||(this.f|=2,this.u=x,x=this)};w.prototype.d=function(){this.f|=8,1&this.f||T(this)};w.prototype.dispose=function(){this.d()};function S(i,t){var e=new w(i,t);try{e.c()}catch(o){throw e.d(),o}var n=e.d.bind(e);return n[Symbol.dispose]=n,n}var F=20,G=500;function Q(i,t){if(!t)return i;let e=t.toLowerCase();return i.filter(n=>(n.dataset?.topic??"").toLowerCase().includes(e))}function V(i,t){if(!t)return i;let e=t.toLowerCase();return i.filter(n=>{let o=(n.dataset?.topic??"").toLowerCase();return o===e||o.startsWith(e+"/")})}function Y(i,t,e){let n=t*e;return i.slice(n,n+e)}function z(i,t){return Math.max(1,Math.ceil(i.length/t))}function tt(i,t){if(t<=7)return Array.from({length:t},(c,l)=>l);let e=Math.max(1,i-2),n=Math.min(t-2,i+2);n-e<4&&(e===1?n=Math.min(t-2,e+4):e=Math.max(1,n-4));let o=[0];e>1&&o.push(null);for(let c=e;c<=n;c++)o.push(c);return n<t-2&&o.push(null),o.push(t-1),o}function it(i,t,e){let n=[],o=i===0?" disabled":"";n.push(`<button class="leet-btn mqtt-page-btn" data-page="${i-1}"${o}>[ \u2190 ]</button>`);for(let l of tt(i,t))if(l===null)n.push('<span class="mqtt-page-ellipsis">\u2026</span>');else{let v=l===i?" leet-btn-active":"",p=l===0&&e>0&&i>0?`<span class="mqtt-page-new">(+${e})</span>`:"";n.push(`<button class="leet-btn mqtt-page-btn${v}" data-page="${l}">[ ${l+1}${p} ]</button>`)}let c=i===t-1?" disabled":"";return n.push(`<button class="leet-btn mqtt-page-btn" data-page="${i+1}"${c}>[ \u2192 ]</button>`),n.join("")}if(typeof document<"u"){let C=function(){let r=I.value;v.value>=r&&(v.value=Math.max(0,r-1)),l.value=Y(O.value,v.value,F)},i=document.getElementById("mqtt-feed"),t=document.getElementById("mqtt-status-bar"),e=document.getElementById("mqtt-filter"),n=document.getElementById("mqtt-controls"),o=document.getElementById("mqtt-topics"),c=m([]),l=m([]),v=m(0),p=m(0),k=m(""),E=m(""),J=$(()=>{let r=new Set;for(let a of c.value){let f=(a.dataset?.topic??"").split("/")[0];f&&r.add(f)}return[...r].sort()}),O=$(()=>{let r=c.value;return E.value&&(r=V(r,E.value)),k.value&&(r=Q(r,k.value)),r}),I=$(()=>z(O.value,F));S(()=>{if(i){i.innerHTML="";for(let r of l.value)i.appendChild(r.cloneNode(!0))}}),S(()=>{n&&(n.innerHTML=it(v.value,I.value,p.value))}),S(()=>{if(!o)return;let r=J.value,a=E.value;o.innerHTML="";let f=document.createElement("button");f.className="leet-btn"+(a===""?" leet-btn-active":""),f.dataset.prefix="",f.textContent="all",o.appendChild(f);for(let g of r){let d=document.createElement("button");d.className="leet-btn"+(g===a?" leet-btn-active":""),d.dataset.prefix=g,d.textContent=g,o.appendChild(d)}}),o?.addEventListener("click",r=>{let a=r.target.closest("[data-prefix]");a&&b(()=>{E.value=a.dataset.prefix??"",v.value=0,p.value=0,C()})}),i?.addEventListener("click",r=>{let a=r.target.closest(".mqtt-msg-header");a&&a.closest(".mqtt-msg")?.classList.toggle("mqtt-msg-expanded")}),n?.addEventListener("click",r=>{let a=r.target.closest("[data-page]");if(!a||a.disabled)return;let f=parseInt(a.dataset.page,10);f<0||f>=I.value||b(()=>{v.value=f,p.value=0,C()})}),e?.addEventListener("input",()=>{b(()=>{k.value=e.value.toLowerCase(),v.value=0,p.value=0,C()})});let U=new EventSource("/api/mqtt/stream");U.addEventListener("broker",r=>{t&&(t.innerHTML=r.data)}),U.addEventListener("message",r=>{let a=document.createElement("div");a.innerHTML=r.data;let f=a.firstElementChild;if(!f)return;let g=f.dataset.receivedAt;if(g){let A=f.querySelector(".mqtt-msg-time");A&&(A.textContent=new Date(g).toLocaleTimeString())}let d=c.value.slice();d.unshift(f),d.length>G&&(d.length=G),v.value===0?b(()=>{c.value=d,C()}):b(()=>{c.value=d,p.value++})})}export{Q as applyFilter,V as filterByPrefix,Y as getPage,it as renderControls,z as totalPages};
+var R=Symbol.for("preact-signals");function B(){if(h>1)h--;else{var i,t=!1;for(function(){var o=q;for(q=void 0;o!==void 0;)o.S.v===o.v&&(o.S.i=o.i),o=o.o}();x!==void 0;){var e=x;for(x=void 0,L++;e!==void 0;){var n=e.u;if(e.u=void 0,e.f&=-3,!(8&e.f)&&D(e))try{e.c()}catch(o){t||(i=o,t=!0)}e=n}}if(L=0,h--,t)throw i}}function b(i){if(h>0)return i();N=++X,h++;try{return i()}finally{B()}}var s=void 0;function W(i){var t=s;s=void 0;try{return i()}finally{s=t}}var H,x=void 0,h=0,L=0,X=0,N=0,q=void 0,M=0;function j(i){if(s!==void 0){var t=i.n;if(t===void 0||t.t!==s)return t={i:0,S:i,p:s.s,n:void 0,t:s,e:void 0,x:void 0,r:t},s.s!==void 0&&(s.s.n=t),s.s=t,i.n=t,32&s.f&&i.S(t),t;if(t.i===-1)return t.i=0,t.n!==void 0&&(t.n.p=t.p,t.p!==void 0&&(t.p.n=t.n),t.p=s.s,t.n=void 0,s.s.n=t,s.s=t),t}}function u(i,t){this.v=i,this.i=0,this.n=void 0,this.t=void 0,this.l=0,this.W=t?.watched,this.Z=t?.unwatched,this.name=t?.name}u.prototype.brand=R;u.prototype.h=function(){return!0};u.prototype.S=function(i){var t=this,e=this.t;e!==i&&i.e===void 0&&(i.x=e,this.t=i,e!==void 0?e.e=i:W(function(){var n;(n=t.W)==null||n.call(t)}))};u.prototype.U=function(i){var t=this;if(this.t!==void 0){var e=i.e,n=i.x;e!==void 0&&(e.x=n,i.e=void 0),n!==void 0&&(n.e=e,i.x=void 0),i===this.t&&(this.t=n,n===void 0&&W(function(){var o;(o=t.Z)==null||o.call(t)}))}};u.prototype.subscribe=function(i){var t=this;return S(function(){var e=t.value,n=s;s=void 0;try{i(e)}finally{s=n}},{name:"sub"})};u.prototype.valueOf=function(){return this.value};u.prototype.toString=function(){return this.value+""};u.prototype.toJSON=function(){return this.value};u.prototype.peek=function(){var i=s;s=void 0;try{return this.value}finally{s=i}};Object.defineProperty(u.prototype,"value",{get:function(){var i=j(this);return i!==void 0&&(i.i=this.i),this.v},set:function(i){if(i!==this.v){if(L>100)throw new Error("Cycle detected");(function(e){h!==0&&L===0&&e.l!==N&&(e.l=N,q={S:e,v:e.v,i:e.i,o:q})})(this),this.v=i,this.i++,M++,h++;try{for(var t=this.t;t!==void 0;t=t.x)t.t.N()}finally{B()}}}});function m(i,t){return new u(i,t)}function D(i){for(var t=i.s;t!==void 0;t=t.n)if(t.S.i!==t.i||!t.S.h()||t.S.i!==t.i)return!0;return!1}function Z(i){for(var t=i.s;t!==void 0;t=t.n){var e=t.S.n;if(e!==void 0&&(t.r=e),t.S.n=t,t.i=-1,t.n===void 0){i.s=t;break}}}function _(i){for(var t=i.s,e=void 0;t!==void 0;){var n=t.p;t.i===-1?(t.S.U(t),n!==void 0&&(n.n=t.n),t.n!==void 0&&(t.n.p=n)):e=t,t.S.n=t.r,t.r!==void 0&&(t.r=void 0),t=n}i.s=e}function y(i,t){u.call(this,void 0),this.x=i,this.s=void 0,this.g=M-1,this.f=4,this.W=t?.watched,this.Z=t?.unwatched,this.name=t?.name}y.prototype=new u;y.prototype.h=function(){if(this.f&=-3,1&this.f)return!1;if((36&this.f)==32||(this.f&=-5,this.g===M))return!0;if(this.g=M,this.f|=1,this.i>0&&!D(this))return this.f&=-2,!0;var i=s;try{Z(this),s=this;var t=this.x();(16&this.f||this.v!==t||this.i===0)&&(this.v=t,this.f&=-17,this.i++)}catch(e){this.v=e,this.f|=16,this.i++}return s=i,_(this),this.f&=-2,!0};y.prototype.S=function(i){if(this.t===void 0){this.f|=36;for(var t=this.s;t!==void 0;t=t.n)t.S.S(t)}u.prototype.S.call(this,i)};y.prototype.U=function(i){if(this.t!==void 0&&(u.prototype.U.call(this,i),this.t===void 0)){this.f&=-33;for(var t=this.s;t!==void 0;t=t.n)t.S.U(t)}};y.prototype.N=function(){if(!(2&this.f)){this.f|=6;for(var i=this.t;i!==void 0;i=i.x)i.t.N()}};Object.defineProperty(y.prototype,"value",{get:function(){if(1&this.f)throw new Error("Cycle detected");var i=j(this);if(this.h(),i!==void 0&&(i.i=this.i),16&this.f)throw this.v;return this.v}});function $(i,t){return new y(i,t)}function P(i){var t=i.m;if(i.m=void 0,typeof t=="function"){h++;var e=s;s=void 0;try{t()}catch(n){throw i.f&=-2,i.f|=8,T(i),n}finally{s=e,B()}}}function T(i){for(var t=i.s;t!==void 0;t=t.n)t.S.U(t);i.x=void 0,i.s=void 0,P(i)}function K(i){if(s!==this)throw new Error("Out-of-order effect");_(this),s=i,this.f&=-2,8&this.f&&T(this),B()}function w(i,t){this.x=i,this.m=void 0,this.s=void 0,this.u=void 0,this.f=32,this.name=t?.name,H&&H.push(this)}w.prototype.c=function(){var i=this.S();try{if(8&this.f||this.x===void 0)return;var t=this.x();typeof t=="function"&&(this.m=t)}finally{i()}};w.prototype.S=function(){if(1&this.f)throw new Error("Cycle detected");this.f|=1,this.f&=-9,P(this),Z(this),h++;var i=s;return s=this,K.bind(this,i)};w.prototype.N=function(){2&this.f||(this.f|=2,this.u=x,x=this)};w.prototype.d=function(){this.f|=8,1&this.f||T(this)};w.prototype.dispose=function(){this.d()};function S(i,t){var e=new w(i,t);try{e.c()}catch(o){throw e.d(),o}var n=e.d.bind(e);return n[Symbol.dispose]=n,n}var F=20,G=500,Sp=["topic","integration","device","max_payload"];function Su(i){let t=new URLSearchParams(i),e=new URLSearchParams;for(let o of Sp){let c=t.get(o);c&&e.set(o,c)}let n=e.toString();return n?`/api/mqtt/stream?${n}`:"/api/mqtt/stream"}function Q(i,t){if(!t)return i;let e=t.toLowerCase();return i.filter(n=>(n.dataset?.topic??"").toLowerCase().includes(e))}function V(i,t){if(!t)return i;let e=t.toLowerCase();return i.filter(n=>{let o=(n.dataset?.topic??"").toLowerCase();return o===e||o.startsWith(e+"/")})}function Bt(i,t){return t?i.filter(e=>e.dataset?.broker===t):i}function Y(i,t,e){let n=t*e;return i.slice(n,n+e)}function z(i,t){return Math.max(1,Math.ceil(i.length/t))}function tt(i,t){if(t<=7)return Array.from({length:t},(c,l)=>l);let e=Math.max(1,i-2),n=Math.min(t-2,i+2);n-e<4&&(e===1?n=Math.min(t-2,e+4):e=Math.max(1,n-4));let o=[0];e>1&&o.push(null);for(let c=e;c<=n;c++)o.push(c);return n<t-2&&o.push(null),o.push(t-1),o}function it(i,t,e){let n=[],o=i===0?" disabled":"";n.push(`<button class="leet-btn mqtt-page-btn" data-page="${i-1}"${o}>[ \u2190 ]</button>`);for(let l of tt(i,t))if(l===null)n.push('<span class="mqtt-page-ellipsis">\u2026</span>');else{let v=l===i?" leet-btn-active":"",p=l===0&&e>0&&i>0?`<span class="mqtt-page-new">(+${e})</span>`:"";n.push(`<button class="leet-btn mqtt-page-btn${v}" data-page="${l}">[ ${l+1}${p} ]</button>`)}let c=i===t-1?" disabled":"";return n.push(`<button class="leet-btn mqtt-page-btn" data-page="${i+1}"${c}>[ \u2192 ]</button>`),n.join("")}if(typeof document<"u"){let C=function(){let r=I.value;v.value>=r&&(v.value=Math.max(0,r-1)),l.value=Y(O.value,v.value,F)},i=document.getElementById("mqtt-feed"),t=document.getElementById("mqtt-status-bar"),e=document.getElementById("mqtt-filter"),n=document.getElementById("mqtt-controls"),o=document.getElementById("mqtt-topics"),Bn=document.getElementById("mqtt-brokers"),c=m([]),l=m([]),v=m(0),p=m(0),k=m(""),E=m(""),Bs=m(""),J=$(()=>{let r=new Set;for(let a of c.value){let f=(a.dataset?.topic??"").split("/")[0];f&&r.add(f)}return[...r].sort()}),O=$(()=>{let r=c.value;return Bs.value&&(r=Bt(r,Bs.value)),E.value&&(r=V(r,E.value)),k.value&&(r=Q(r,k.value)),r}),I=$(()=>z(O.value,F));S(()=>{if(i){i.innerHTML="";for(let r of l.value)i.appendChild(r.cloneNode(!0))}}),S(()=>{n&&(n.innerHTML=it(v.value,I.value,p.value))}),S(()=>{if(!o)return;let r=J.value,a=E.value;o.innerHTML="";let f=document.createElement("button");f.className="leet-btn"+(a===""?" leet-btn-active":""),f.dataset.prefix="",f.textContent="all",o.appendChild(f);for(let g of r){let d=document.createElement("button");d.className="leet-btn"+(g===a?" leet-btn-active":""),d.dataset.prefix=g,d.textContent=g,o.appendChild(d)}}),o?.addEventListener("click",r=>{let a=r.target.closest("[data-prefix]");a&&b(()=>{E.value=a.dataset.prefix??"",v.value=0,p.value=0,C()})}),Bn?.addEventListener("click",r=>{let a=r.target.closest("[data-broker]");a&&(Bn.querySelectorAll("[data-broker]").forEach(f=>f.classList.toggle("leet-btn-active",f===a)),b(()=>{Bs.value=a.dataset.broker??"",v.value=0,p.value=0,C()}))}),i?.addEventListener("click",r=>{let a=r.target.closest(".mqtt-msg-header");a&&a.closest(".mqtt-msg")?.classList.toggle("mqtt-msg-expanded")}),n?.addEventListener("click",r=>{let a=r.target.closest("[data-page]");if(!a||a.disabled)return;let f=parseInt(a.dataset.page,10);f<0||f>=I.value||b(()=>{v.value=f,p.value=0,C()})}),e?.addEventListener("input",()=>{b(()=>{k.value=e.value.toLowerCase(),v.value=0,p.value=0,C()})});let U=new EventSource(Su(globalThis.location.search));U.addEventListener("broker",r=>{t&&(t.innerHTML=r.data)}),U.addEventListener("message",r=>{let a=document.createElement("div");a.innerHTML=r.data;let f=a.firstElementChild;if(!f)return;let g=f.dataset.receivedAt;if(g){let A=f.querySelector(".mqtt-msg-time");A&&(A.textContent=new Date(g).toLocaleTimeString())}let d=c.value.slice();d.unshift(f),d.length>G&&(d.length=G),v.value===0?b(()=>{c.value=d,C()}):b(()=>{c.value=d,p.value++})})}export{Q as applyFilter,Bt as filterByBroker,V as filterByPrefix,Y as getPage,it as renderControls,Su as streamUrl,z as totalPages};
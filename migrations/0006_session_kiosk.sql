@@ -0,0 +1,3 @@
+ALTER TABLE sessions ADD COLUMN kiosk BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX sessions_last_seen_idx ON sessions (last_seen);
@@ -0,0 +1,10 @@
+-- Per-credential metadata for the passkeys stored in `passkeys.credentials`.
+-- `created_at` is NULL for passkeys registered before this table existed.
+CREATE TABLE passkey_meta (
+    user_id       UUID        NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    credential_id TEXT        NOT NULL,
+    nickname      TEXT,
+    created_at    TIMESTAMPTZ,
+    last_used_at  TIMESTAMPTZ,
+    PRIMARY KEY (user_id, credential_id)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE invites (
+    token_hash  TEXT        PRIMARY KEY,
+    username    TEXT,
+    role        TEXT        NOT NULL,
+    created_by  TEXT        NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at  TIMESTAMPTZ NOT NULL,
+    used_at     TIMESTAMPTZ
+);
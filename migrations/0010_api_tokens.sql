@@ -0,0 +1,10 @@
+CREATE TABLE api_tokens (
+    token_hash    TEXT        PRIMARY KEY,
+    name          TEXT        NOT NULL,
+    user_id       UUID        NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    scopes        TEXT[]      NOT NULL DEFAULT '{}',
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at  TIMESTAMPTZ
+);
+
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,13 @@
+CREATE TABLE audit_events (
+    id          BIGSERIAL   PRIMARY KEY,
+    at          TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    actor       TEXT,
+    action      TEXT        NOT NULL,
+    target      TEXT,
+    ip          TEXT,
+    user_agent  TEXT,
+    outcome     TEXT        NOT NULL
+);
+
+CREATE INDEX audit_events_at_idx ON audit_events (at);
+CREATE INDEX audit_events_actor_idx ON audit_events (actor, at);
@@ -0,0 +1,8 @@
+ALTER TABLE users ADD COLUMN email TEXT;
+
+CREATE TABLE backup_codes (
+    user_id     UUID        NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash   TEXT        NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE challenges (
+    kind        TEXT        NOT NULL,
+    key         TEXT        NOT NULL,
+    state       JSONB       NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at  TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (kind, key)
+);
+
+CREATE INDEX challenges_expires_at_idx ON challenges (expires_at);
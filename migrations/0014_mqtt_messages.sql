@@ -0,0 +1,12 @@
+CREATE TABLE mqtt_messages (
+    id           BIGSERIAL   PRIMARY KEY,
+    broker       TEXT        NOT NULL,
+    topic        TEXT        NOT NULL,
+    payload      TEXT        NOT NULL,
+    received_at  TIMESTAMPTZ NOT NULL,
+    expires_at   TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX mqtt_messages_received_at_idx ON mqtt_messages (received_at);
+CREATE INDEX mqtt_messages_topic_idx ON mqtt_messages (topic, received_at);
+CREATE INDEX mqtt_messages_expires_at_idx ON mqtt_messages (expires_at);
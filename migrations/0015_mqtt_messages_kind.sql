@@ -0,0 +1 @@
+ALTER TABLE mqtt_messages ADD COLUMN kind TEXT NOT NULL DEFAULT 'text';
@@ -0,0 +1,11 @@
+CREATE TABLE device_aliases (
+    integration   TEXT        NOT NULL,
+    device_id     TEXT        NOT NULL,
+    name          TEXT        NOT NULL,
+    room          TEXT        NOT NULL DEFAULT '',
+    notes         TEXT        NOT NULL DEFAULT '',
+    tags          TEXT[]      NOT NULL DEFAULT '{}',
+    breaker_slot  TEXT,
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (integration, device_id)
+);
@@ -0,0 +1,436 @@
+//! Self-service account page: the signed-in user's sessions, passkeys and
+//! recovery options.
+//!
+//! Everything here acts on the caller's own account only — session hashes and
+//! credential keys submitted by the forms are checked against the user's own
+//! lists before anything is changed.
+
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    Form,
+    extract::{Query, State},
+    response::{Html, Redirect},
+};
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::{
+    ServerState,
+    audit::{AuditAction, AuditOutcome, RequestMeta},
+    auth::{AuthUserInfo, SessionUser, credential_key, session_token},
+    error::Error,
+    index::NavLink,
+    recovery::{generate_backup_codes, hash_backup_code},
+    session::hash_token,
+};
+
+const ACCOUNT_PATH: &str = "/auth/account";
+
+/// One logged-in device, as shown on the account page.
+#[derive(Debug)]
+pub struct SessionRow {
+    /// Token hash identifying the session in revoke forms.
+    pub id: String,
+    pub created_at: String,
+    pub last_seen: String,
+    pub user_agent: String,
+    pub kiosk: bool,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+/// One registered passkey, as shown on the account page.
+#[derive(Debug)]
+pub struct PasskeyRow {
+    /// [`credential_key`] identifying the passkey in rename/delete forms.
+    pub id: String,
+    pub nickname: String,
+    pub created_at: String,
+    pub last_used_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "auth_account.html")]
+pub struct AccountPage {
+    pub version: &'static str,
+    pub auth_user: Option<AuthUserInfo>,
+    pub nav_links: Arc<[NavLink]>,
+    pub sessions: Vec<SessionRow>,
+    pub passkeys: Vec<PasskeyRow>,
+    /// Address recovery codes are emailed to; empty if unset.
+    pub email: String,
+    /// Whether `[auth.smtp]` is configured, so the email field is useful.
+    pub email_enabled: bool,
+    pub backup_codes_left: i64,
+    pub error: Option<String>,
+}
+
+/// Shown once, right after a new set of backup codes is generated.
+#[derive(Template)]
+#[template(path = "auth_backup_codes.html")]
+pub struct BackupCodesPage {
+    pub version: &'static str,
+    pub auth_user: Option<AuthUserInfo>,
+    pub nav_links: Arc<[NavLink]>,
+    pub codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct AccountQuery {
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdForm {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailForm {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenamePasskeyForm {
+    pub id: String,
+    pub nickname: String,
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC, matching the device inventory page.
+pub(crate) fn format_timestamp(t: OffsetDateTime) -> String {
+    let t = t.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        t.year(),
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+fn format_optional(t: Option<OffsetDateTime>) -> String {
+    t.map(format_timestamp).unwrap_or_else(|| "—".to_owned())
+}
+
+fn error_redirect(e: &Error) -> Redirect {
+    let message: String = url::form_urlencoded::byte_serialize(e.to_string().as_bytes()).collect();
+    Redirect::to(&format!("{ACCOUNT_PATH}?error={message}"))
+}
+
+/// GET `/auth/account` — list the caller's sessions and passkeys.
+pub async fn account_page(
+    SessionUser(user): SessionUser,
+    State(s): State<ServerState>,
+    jar: CookieJar,
+    Query(q): Query<AccountQuery>,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    let current = session_token(&jar).map(|t| hash_token(&t));
+
+    let sessions = auth
+        .sessions
+        .list_for_user(user.user_id)
+        .await?
+        .into_iter()
+        .map(|(id, session)| SessionRow {
+            current: current.as_deref() == Some(id.as_str()),
+            id,
+            created_at: format_timestamp(session.created_at),
+            last_seen: format_timestamp(session.last_seen),
+            user_agent: session.user_agent.unwrap_or_else(|| "unknown".to_owned()),
+            kiosk: session.kiosk,
+        })
+        .collect();
+
+    let (_, credentials) = auth
+        .load_passkeys_by_id(user.user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let mut meta = auth.passkey_meta(user.user_id).await?;
+    let passkeys = credentials
+        .iter()
+        .map(|pk| {
+            let id = credential_key(pk.cred_id());
+            let meta = meta.remove(&id).unwrap_or_default();
+            PasskeyRow {
+                id,
+                nickname: meta.nickname.unwrap_or_default(),
+                created_at: format_optional(meta.created_at),
+                last_used_at: format_optional(meta.last_used_at),
+            }
+        })
+        .collect();
+
+    let email = auth
+        .load_user(user.user_id)
+        .await?
+        .and_then(|record| record.email)
+        .unwrap_or_default();
+
+    let page = AccountPage {
+        version: crate::VERSION,
+        auth_user: Some(user.info()),
+        nav_links: s.nav_links.clone(),
+        sessions,
+        passkeys,
+        email,
+        email_enabled: auth.config.smtp.is_some(),
+        backup_codes_left: auth.backup_codes.remaining(user.user_id).await?,
+        error: q.error,
+    };
+    Ok(Html(page.render()?))
+}
+
+/// POST `/auth/account/sessions/revoke` — sign out one of the caller's devices.
+pub async fn revoke_session(
+    SessionUser(user): SessionUser,
+    State(s): State<ServerState>,
+    Form(form): Form<IdForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+
+    let owned = auth
+        .sessions
+        .list_for_user(user.user_id)
+        .await?
+        .iter()
+        .any(|(id, _)| *id == form.id);
+    if !owned {
+        return Err(Error::NotFound);
+    }
+    auth.sessions.remove(&form.id).await?;
+
+    tracing::info!(username = %user.username, "user revoked a session");
+    Ok(Redirect::to(ACCOUNT_PATH))
+}
+
+/// POST `/auth/account/passkeys/rename` — set a passkey's nickname. An empty
+/// nickname clears it.
+pub async fn rename_passkey(
+    SessionUser(user): SessionUser,
+    State(s): State<ServerState>,
+    Form(form): Form<RenamePasskeyForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+
+    let (_, credentials) = auth
+        .load_passkeys_by_id(user.user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+    if !credentials
+        .iter()
+        .any(|pk| credential_key(pk.cred_id()) == form.id)
+    {
+        return Err(Error::NotFound);
+    }
+
+    let nickname = form.nickname.trim();
+    let nickname = (!nickname.is_empty()).then_some(nickname);
+    auth.rename_passkey(user.user_id, &form.id, nickname).await?;
+    Ok(Redirect::to(ACCOUNT_PATH))
+}
+
+/// POST `/auth/account/passkeys/delete` — remove one of the caller's passkeys.
+/// The last passkey can't be deleted; the page shows an error instead.
+pub async fn delete_passkey(
+    SessionUser(user): SessionUser,
+    State(s): State<ServerState>,
+    Form(form): Form<IdForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+
+    match auth.delete_passkey(user.user_id, &form.id).await {
+        Ok(()) => {
+            tracing::info!(username = %user.username, "user deleted a passkey");
+            Ok(Redirect::to(ACCOUNT_PATH))
+        }
+        Err(e @ Error::LastPasskey) => Ok(error_redirect(&e)),
+        Err(e) => Err(e),
+    }
+}
+
+/// POST `/auth/account/email` — set the address recovery codes are emailed
+/// to. An empty address clears it.
+pub async fn set_email(
+    SessionUser(user): SessionUser,
+    State(s): State<ServerState>,
+    Form(form): Form<EmailForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+
+    let email = form.email.trim();
+    let email = if email.is_empty() {
+        None
+    } else {
+        match email.parse::<lettre::Address>() {
+            Ok(address) => Some(address.to_string()),
+            Err(e) => return Ok(error_redirect(&Error::InvalidEmail(e.to_string()))),
+        }
+    };
+    auth.set_email(user.user_id, email).await?;
+    Ok(Redirect::to(ACCOUNT_PATH))
+}
+
+/// POST `/auth/account/backup-codes` — replace the caller's backup codes with
+/// a new set and show them once.
+pub async fn generate_codes(
+    SessionUser(user): SessionUser,
+    State(s): State<ServerState>,
+    meta: RequestMeta,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+
+    let codes = generate_backup_codes();
+    let hashes: Vec<String> = codes
+        .iter()
+        .map(|code| hash_backup_code(user.user_id, code))
+        .collect();
+    let result = auth.backup_codes.replace(user.user_id, &hashes).await;
+    auth.audit(
+        meta.event(AuditAction::BackupCodesGenerated, AuditOutcome::from(&result))
+            .actor(&user.username),
+    )
+    .await;
+    result?;
+
+    tracing::info!(username = %user.username, "user generated new backup codes");
+    let page = BackupCodesPage {
+        version: crate::VERSION,
+        auth_user: Some(user.info()),
+        nav_links: s.nav_links.clone(),
+        codes,
+    };
+    Ok(Html(page.render()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::{get, post},
+    };
+    use tower::ServiceExt as _;
+    use uuid::Uuid;
+
+    use crate::{auth::Role, recovery::BACKUP_CODE_COUNT, test_support};
+
+    async fn test_state() -> ServerState {
+        test_support::server_state(test_support::auth_config()).await
+    }
+
+    fn router(state: ServerState) -> axum::Router {
+        axum::Router::new()
+            .route(ACCOUNT_PATH, get(account_page))
+            .route("/auth/account/sessions/revoke", post(revoke_session))
+            .route("/auth/account/backup-codes", post(generate_codes))
+            .with_state(state)
+    }
+
+    fn revoke_request(token: &str, id: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/auth/account/sessions/revoke")
+            .header("cookie", format!("green_session={token}"))
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from(format!("id={id}")))
+            .unwrap()
+    }
+
+    #[test]
+    fn format_timestamp_is_utc_seconds() {
+        let t = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        assert_eq!(format_timestamp(t), "2023-11-14 22:13:20");
+    }
+
+    #[tokio::test]
+    async fn account_page_requires_login() {
+        let res = router(test_state().await)
+            .oneshot(Request::builder().uri(ACCOUNT_PATH).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn revoke_removes_own_other_session() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let alice = Uuid::new_v4();
+        let current = auth.create_session(alice, "alice", Role::Player, None, false).await.unwrap();
+        let other = auth.create_session(alice, "alice", Role::Player, None, false).await.unwrap();
+
+        let res = router(state)
+            .oneshot(revoke_request(&current, &hash_token(&other)))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert!(auth.get_session(&other).await.is_none());
+        assert!(auth.get_session(&current).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn generating_backup_codes_replaces_the_old_set() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let alice = Uuid::new_v4();
+        let token = auth.create_session(alice, "alice", Role::Player, None, false).await.unwrap();
+        let old = hash_backup_code(alice, "OLDOL-DOLDO");
+        auth.backup_codes.replace(alice, std::slice::from_ref(&old)).await.unwrap();
+
+        let res = router(state)
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/auth/account/backup-codes")
+                    .header("cookie", format!("green_session={token}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        let shown: Vec<&str> = body
+            .split("<li class=\"account-backup-code\">")
+            .skip(1)
+            .map(|s| &s[..11])
+            .collect();
+        assert_eq!(shown.len(), BACKUP_CODE_COUNT);
+        assert!(!auth.backup_codes.consume(alice, &old).await.unwrap());
+        assert!(
+            auth.backup_codes
+                .consume(alice, &hash_backup_code(alice, shown[0]))
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            auth.backup_codes.remaining(alice).await.unwrap(),
+            BACKUP_CODE_COUNT as i64 - 1
+        );
+    }
+
+    #[tokio::test]
+    async fn revoke_refuses_another_users_session() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let alice = auth.create_session(Uuid::new_v4(), "alice", Role::Player, None, false).await.unwrap();
+        let bob = auth.create_session(Uuid::new_v4(), "bob", Role::Player, None, false).await.unwrap();
+
+        let res = router(state)
+            .oneshot(revoke_request(&alice, &hash_token(&bob)))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(auth.get_session(&bob).await.is_some());
+    }
+}
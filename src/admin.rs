@@ -0,0 +1,840 @@
+//! GM-only administration pages.
+//!
+//! `/admin/users` lists every account and lets a GM change roles, disable
+//! accounts and delete users. GMs can't act on their own account here, which
+//! also guarantees at least one GM always remains.
+//!
+//! `/admin/invites` creates and revokes single-use registration invites.
+//!
+//! `/admin/tokens` issues and revokes scoped API tokens.
+//!
+//! `/admin/audit` shows the audit log, filtered by user, action and date.
+
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    Form,
+    extract::{Query, State},
+    response::{Html, Redirect},
+};
+use serde::Deserialize;
+use strum::IntoEnumIterator as _;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    ServerState,
+    account::format_timestamp,
+    audit::{AuditAction, AuditFilter},
+    auth::{AuthState, AuthUserInfo, GmUser, Role, UserSummary},
+    error::Error,
+    index::NavLink,
+    permission::{Permission, Permissions},
+    qr::qr_svg,
+};
+
+const USERS_PATH: &str = "/admin/users";
+const INVITES_PATH: &str = "/admin/invites";
+const TOKENS_PATH: &str = "/admin/tokens";
+
+/// Most audit events shown at once.
+const AUDIT_PAGE_LIMIT: i64 = 500;
+
+/// Longest API token name accepted by the create form.
+const MAX_TOKEN_NAME_LEN: usize = 64;
+
+/// Invite lifetime offered by the create form.
+const DEFAULT_INVITE_HOURS: u32 = 72;
+/// Longest invite lifetime a GM can choose (30 days).
+const MAX_INVITE_HOURS: u32 = 30 * 24;
+
+#[derive(Template)]
+#[template(path = "admin_users.html")]
+pub struct AdminUsersPage {
+    pub version: &'static str,
+    pub auth_user: Option<AuthUserInfo>,
+    pub nav_links: Arc<[NavLink]>,
+    pub users: Vec<UserSummary>,
+    /// The viewing GM, whose row has no action buttons.
+    pub self_id: Uuid,
+    /// Roles offered in each row's role picker.
+    pub roles: Vec<Role>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct AdminQuery {
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserIdForm {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRoleForm {
+    pub id: Uuid,
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetDisabledForm {
+    pub id: Uuid,
+    pub disabled: bool,
+}
+
+/// One invite, as shown on the invites page.
+#[derive(Debug)]
+pub struct InviteRow {
+    /// Token hash identifying the invite in revoke forms.
+    pub id: String,
+    pub username: String,
+    pub role: Role,
+    pub created_by: String,
+    pub created_at: String,
+    pub expires_at: String,
+    /// `pending`, `used` or `expired`.
+    pub status: &'static str,
+}
+
+/// An invite that was just created, shown once with its link.
+#[derive(Debug)]
+pub struct CreatedInvite {
+    pub link: String,
+    /// QR code of `link` as an inline SVG document.
+    pub qr_svg: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_invites.html")]
+pub struct AdminInvitesPage {
+    pub version: &'static str,
+    pub auth_user: Option<AuthUserInfo>,
+    pub nav_links: Arc<[NavLink]>,
+    pub invites: Vec<InviteRow>,
+    pub created: Option<CreatedInvite>,
+    /// Roles offered in the create form.
+    pub roles: Vec<Role>,
+    pub default_hours: u32,
+    pub max_hours: u32,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteForm {
+    /// Restrict the invite to this username; empty means any.
+    #[serde(default)]
+    pub username: String,
+    pub role: Role,
+    pub expires_hours: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteIdForm {
+    pub id: String,
+}
+
+/// One API token, as shown on the tokens page.
+#[derive(Debug)]
+pub struct TokenRow {
+    /// Token hash identifying the token in revoke forms.
+    pub id: String,
+    pub name: String,
+    /// Account the token acts as.
+    pub username: String,
+    pub scopes: Vec<Permission>,
+    pub created_at: String,
+    /// `never` if the token hasn't been used yet.
+    pub last_used_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_tokens.html")]
+pub struct AdminTokensPage {
+    pub version: &'static str,
+    pub auth_user: Option<AuthUserInfo>,
+    pub nav_links: Arc<[NavLink]>,
+    pub tokens: Vec<TokenRow>,
+    /// A token that was just created, shown once.
+    pub created: Option<String>,
+    /// Scopes offered in the create form.
+    pub permissions: Vec<Permission>,
+    pub error: Option<String>,
+}
+
+/// Fields of the create-token form. Scopes arrive as repeated `scope`
+/// checkboxes, which the plain `Form` extractor can't collect into a list, so
+/// the form is read as key/value pairs.
+#[derive(Debug, Default)]
+pub struct CreateTokenForm {
+    pub name: String,
+    pub scopes: Permissions,
+}
+
+impl CreateTokenForm {
+    fn from_fields(fields: Vec<(String, String)>) -> Result<Self, Error> {
+        let mut form = CreateTokenForm::default();
+        for (key, value) in fields {
+            match key.as_str() {
+                "name" => form.name = value.trim().to_owned(),
+                "scope" => {
+                    let permission = value
+                        .parse()
+                        .map_err(|_| Error::InvalidTokenRequest(format!("unknown scope `{value}`")))?;
+                    let _ = form.scopes.insert(permission);
+                }
+                _ => {}
+            }
+        }
+        if form.name.is_empty() || form.name.len() > MAX_TOKEN_NAME_LEN {
+            return Err(Error::InvalidTokenRequest(format!(
+                "name must be 1 to {MAX_TOKEN_NAME_LEN} characters"
+            )));
+        }
+        if form.scopes.is_empty() {
+            return Err(Error::InvalidTokenRequest("pick at least one scope".into()));
+        }
+        Ok(form)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenIdForm {
+    pub id: String,
+}
+
+/// Filters from the audit page's form. Empty fields match everything.
+#[derive(Debug, Deserialize, Default)]
+pub struct AuditQuery {
+    #[serde(default)]
+    pub user: String,
+    #[serde(default)]
+    pub action: String,
+    /// First day to include, `YYYY-MM-DD` (UTC).
+    #[serde(default)]
+    pub from: String,
+    /// Last day to include, `YYYY-MM-DD` (UTC).
+    #[serde(default)]
+    pub to: String,
+}
+
+impl AuditQuery {
+    fn filter(&self) -> Result<AuditFilter, Error> {
+        fn non_empty(s: &str) -> Option<&str> {
+            Some(s.trim()).filter(|s| !s.is_empty())
+        }
+        fn day(s: &str) -> Result<OffsetDateTime, Error> {
+            let date = time::Date::parse(s, time::macros::format_description!("[year]-[month]-[day]"))
+                .map_err(|_| Error::InvalidAuditFilter(format!("`{s}` is not a YYYY-MM-DD date")))?;
+            Ok(date.midnight().assume_utc())
+        }
+
+        Ok(AuditFilter {
+            actor: non_empty(&self.user).map(str::to_owned),
+            action: non_empty(&self.action)
+                .map(|a| {
+                    a.parse()
+                        .map_err(|_| Error::InvalidAuditFilter(format!("unknown action `{a}`")))
+                })
+                .transpose()?,
+            since: non_empty(&self.from).map(day).transpose()?,
+            until: non_empty(&self.to)
+                .map(|to| day(to).map(|t| t + time::Duration::days(1)))
+                .transpose()?,
+            limit: AUDIT_PAGE_LIMIT,
+        })
+    }
+}
+
+/// One audit event, as shown on the audit page.
+#[derive(Debug)]
+pub struct AuditRow {
+    pub at: String,
+    pub actor: String,
+    pub action: AuditAction,
+    pub target: String,
+    pub ip: String,
+    pub user_agent: String,
+    pub outcome: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_audit.html")]
+pub struct AdminAuditPage {
+    pub version: &'static str,
+    pub auth_user: Option<AuthUserInfo>,
+    pub nav_links: Arc<[NavLink]>,
+    pub events: Vec<AuditRow>,
+    /// The filters as submitted, to refill the form.
+    pub query: AuditQuery,
+    /// Actions offered in the filter form.
+    pub actions: Vec<AuditAction>,
+    pub limit: i64,
+    pub error: Option<String>,
+}
+
+/// A redirect back to the users page with an error if `id` is the acting GM's
+/// own account.
+fn refuse_self(user: &GmUser, id: Uuid) -> Option<Redirect> {
+    (user.0.user_id == id).then(|| {
+        Redirect::to(&format!(
+            "{USERS_PATH}?error=you+can%27t+change+your+own+account+here"
+        ))
+    })
+}
+
+/// GET `/admin/users` — list every account (GM only).
+pub async fn users_page(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Query(q): Query<AdminQuery>,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    let users = auth.list_users().await?;
+    let page = AdminUsersPage {
+        version: crate::VERSION,
+        self_id: user.0.user_id,
+        auth_user: Some(user.0.info()),
+        nav_links: s.nav_links.clone(),
+        users,
+        roles: auth.config.assignable_roles(),
+        error: q.error,
+    };
+    Ok(Html(page.render()?))
+}
+
+async fn render_invites_page(
+    user: GmUser,
+    s: &ServerState,
+    auth: &AuthState,
+    created: Option<CreatedInvite>,
+    error: Option<String>,
+) -> Result<Html<String>, Error> {
+    let now = OffsetDateTime::now_utc();
+    let invites = auth
+        .invites
+        .list()
+        .await?
+        .into_iter()
+        .map(|(id, invite)| InviteRow {
+            status: if invite.used_at.is_some() {
+                "used"
+            } else if invite.is_usable(now) {
+                "pending"
+            } else {
+                "expired"
+            },
+            id,
+            username: invite.username.unwrap_or_else(|| "any".to_owned()),
+            role: invite.role,
+            created_by: invite.created_by,
+            created_at: format_timestamp(invite.created_at),
+            expires_at: format_timestamp(invite.expires_at),
+        })
+        .collect();
+
+    let page = AdminInvitesPage {
+        version: crate::VERSION,
+        auth_user: Some(user.0.info()),
+        nav_links: s.nav_links.clone(),
+        invites,
+        created,
+        roles: auth.config.assignable_roles(),
+        default_hours: DEFAULT_INVITE_HOURS,
+        max_hours: MAX_INVITE_HOURS,
+        error,
+    };
+    Ok(Html(page.render()?))
+}
+
+/// GET `/admin/invites` — list invites and show the create form (GM only).
+pub async fn invites_page(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Query(q): Query<AdminQuery>,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    render_invites_page(user, &s, auth, None, q.error).await
+}
+
+/// POST `/admin/invites` — create an invite and show its link once (GM only).
+///
+/// The page is rendered directly instead of redirecting so the token never
+/// appears in a URL the server logs.
+pub async fn create_invite(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Form(form): Form<CreateInviteForm>,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    let username = form.username.trim();
+    let username = (!username.is_empty()).then(|| username.to_owned());
+    let hours = form.expires_hours.clamp(1, MAX_INVITE_HOURS);
+    let role = auth.config.check_role(form.role)?;
+
+    let token = auth
+        .create_invite(
+            username.clone(),
+            role.clone(),
+            &user.0.username,
+            std::time::Duration::from_secs(u64::from(hours) * 60 * 60),
+        )
+        .await?;
+    tracing::info!(gm = %user.0.username, username = ?username, %role, hours, "created invite");
+
+    let link = format!(
+        "{}/auth/register?invite={token}",
+        auth.config.rp_origin.trim_end_matches('/')
+    );
+    let created = CreatedInvite {
+        qr_svg: qr_svg(&link)?,
+        link,
+    };
+    render_invites_page(user, &s, auth, Some(created), None).await
+}
+
+/// POST `/admin/invites/revoke` — delete an invite so it can't be used (GM only).
+pub async fn revoke_invite(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Form(form): Form<InviteIdForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    auth.invites.remove(&form.id).await?;
+    tracing::info!(gm = %user.0.username, "revoked invite");
+    Ok(Redirect::to(INVITES_PATH))
+}
+
+async fn render_tokens_page(
+    user: GmUser,
+    s: &ServerState,
+    auth: &AuthState,
+    created: Option<String>,
+    error: Option<String>,
+) -> Result<Html<String>, Error> {
+    let tokens = auth
+        .api_tokens
+        .list()
+        .await?
+        .into_iter()
+        .map(|(id, token)| TokenRow {
+            id,
+            name: token.name,
+            username: token.username,
+            scopes: token.scopes.into_iter().collect(),
+            created_at: format_timestamp(token.created_at),
+            last_used_at: token
+                .last_used_at
+                .map_or_else(|| "never".to_owned(), format_timestamp),
+        })
+        .collect();
+
+    let page = AdminTokensPage {
+        version: crate::VERSION,
+        auth_user: Some(user.0.info()),
+        nav_links: s.nav_links.clone(),
+        tokens,
+        created,
+        permissions: Permission::iter().collect(),
+        error,
+    };
+    Ok(Html(page.render()?))
+}
+
+/// GET `/admin/tokens` — list API tokens and show the create form (GM only).
+pub async fn tokens_page(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Query(q): Query<AdminQuery>,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    render_tokens_page(user, &s, auth, None, q.error).await
+}
+
+/// POST `/admin/tokens` — issue an API token acting as the GM and show it once
+/// (GM only). Rendered directly for the same reason as [`create_invite`].
+pub async fn create_token(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    let form = match CreateTokenForm::from_fields(fields) {
+        Ok(form) => form,
+        Err(e) => return render_tokens_page(user, &s, auth, None, Some(e.to_string())).await,
+    };
+
+    let token = auth.create_api_token(&user.0, &form.name, form.scopes).await?;
+    tracing::info!(gm = %user.0.username, name = %form.name, "created API token");
+    render_tokens_page(user, &s, auth, Some(token), None).await
+}
+
+/// POST `/admin/tokens/revoke` — delete an API token so it stops working (GM only).
+pub async fn revoke_token(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Form(form): Form<TokenIdForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    auth.api_tokens.remove(&form.id).await?;
+    tracing::info!(gm = %user.0.username, "revoked API token");
+    Ok(Redirect::to(TOKENS_PATH))
+}
+
+/// GET `/admin/audit` — list audit events, newest first (GM only).
+pub async fn audit_page(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    let (events, error) = match query.filter() {
+        Ok(filter) => (auth.audit_log.list(&filter).await?, None),
+        Err(e) => (Vec::new(), Some(e.to_string())),
+    };
+    let events = events
+        .into_iter()
+        .map(|e| AuditRow {
+            at: format_timestamp(e.at),
+            actor: e.actor.unwrap_or_default(),
+            action: e.action,
+            target: e.target.unwrap_or_default(),
+            ip: e.ip.unwrap_or_default(),
+            user_agent: e.user_agent.unwrap_or_default(),
+            outcome: e.outcome.to_string(),
+        })
+        .collect();
+
+    let page = AdminAuditPage {
+        version: crate::VERSION,
+        auth_user: Some(user.0.info()),
+        nav_links: s.nav_links.clone(),
+        events,
+        query,
+        actions: AuditAction::iter().collect(),
+        limit: AUDIT_PAGE_LIMIT,
+        error,
+    };
+    Ok(Html(page.render()?))
+}
+
+/// POST `/admin/users/role` — change a user's role (GM only).
+pub async fn set_role(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Form(form): Form<SetRoleForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    if let Some(redirect) = refuse_self(&user, form.id) {
+        return Ok(redirect);
+    }
+    let role = auth.config.check_role(form.role)?;
+    auth.set_role(form.id, role.clone()).await?;
+    tracing::info!(gm = %user.0.username, user_id = %form.id, %role, "changed user role");
+    Ok(Redirect::to(USERS_PATH))
+}
+
+/// POST `/admin/users/disable` — disable or re-enable an account (GM only).
+pub async fn set_disabled(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Form(form): Form<SetDisabledForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    if let Some(redirect) = refuse_self(&user, form.id) {
+        return Ok(redirect);
+    }
+    auth.set_disabled(form.id, form.disabled).await?;
+    tracing::info!(gm = %user.0.username, user_id = %form.id, disabled = form.disabled, "changed user status");
+    Ok(Redirect::to(USERS_PATH))
+}
+
+/// POST `/admin/users/delete` — delete an account with its passkeys and sessions (GM only).
+pub async fn delete_user(
+    user: GmUser,
+    State(s): State<ServerState>,
+    Form(form): Form<UserIdForm>,
+) -> Result<Redirect, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    if let Some(redirect) = refuse_self(&user, form.id) {
+        return Ok(redirect);
+    }
+    auth.delete_user(form.id).await?;
+    tracing::info!(gm = %user.0.username, user_id = %form.id, "deleted user");
+    Ok(Redirect::to(USERS_PATH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode, header},
+        routing::{get, post},
+    };
+    use tower::ServiceExt as _;
+
+    use crate::{
+        test_support,
+        user::{MemoryUserStore, UserStore as _},
+    };
+
+    async fn test_state() -> ServerState {
+        test_support::server_state(test_support::auth_config()).await
+    }
+
+    fn router(state: ServerState) -> axum::Router {
+        axum::Router::new()
+            .route(USERS_PATH, get(users_page))
+            .route("/admin/users/role", post(set_role))
+            .route("/admin/users/disable", post(set_disabled))
+            .route("/admin/users/delete", post(delete_user))
+            .route(TOKENS_PATH, get(tokens_page).post(create_token))
+            .route("/admin/audit", get(audit_page))
+            .with_state(state)
+    }
+
+    fn form_request(uri: &str, token: &str, body: String) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("cookie", format!("green_session={token}"))
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn users_page_forbidden_for_player() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let token = auth
+            .create_session(Uuid::new_v4(), "alice", Role::Player, None, false)
+            .await
+            .unwrap();
+        let res = router(state)
+            .oneshot(
+                Request::builder()
+                    .uri(USERS_PATH)
+                    .header("cookie", format!("green_session={token}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn set_role_forbidden_for_player() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let player = Uuid::new_v4();
+        let token = auth
+            .create_session(player, "alice", Role::Player, None, false)
+            .await
+            .unwrap();
+        let res = router(state)
+            .oneshot(form_request("/admin/users/role", &token, format!("id={player}&role=Gm")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn gm_cannot_delete_own_account() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let gm = Uuid::new_v4();
+        let token = auth
+            .create_session(gm, "gm", Role::Gm, None, false)
+            .await
+            .unwrap();
+        let res = router(state)
+            .oneshot(form_request("/admin/users/delete", &token, format!("id={gm}")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let location = res.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("/admin/users?error="), "got {location}");
+        assert!(auth.get_session(&token).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn gm_changes_disables_and_deletes_an_account() {
+        let mut state = test_state().await;
+        let store = Arc::new(MemoryUserStore::default());
+        let bob = store.insert("bob", Role::Player).await;
+        Arc::get_mut(state.auth_state.as_mut().unwrap()).unwrap().users = store.clone();
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let gm = auth.create_session(Uuid::new_v4(), "gm", Role::Gm, None, false).await.unwrap();
+        let bob_session = auth.create_session(bob, "bob", Role::Player, None, false).await.unwrap();
+
+        let (status, body) = get_page(state.clone(), USERS_PATH, &gm).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("bob"));
+
+        let res = router(state.clone())
+            .oneshot(form_request("/admin/users/role", &gm, format!("id={bob}&role=Gm")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(store.load(bob).await.unwrap().unwrap().role, Role::Gm);
+
+        let res = router(state.clone())
+            .oneshot(form_request("/admin/users/disable", &gm, format!("id={bob}&disabled=true")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert!(store.load(bob).await.unwrap().unwrap().disabled);
+        assert!(auth.get_session(&bob_session).await.is_none(), "disabling signs the user out");
+
+        let res = router(state.clone())
+            .oneshot(form_request("/admin/users/delete", &gm, format!("id={bob}")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert!(store.load(bob).await.unwrap().is_none());
+        let res = router(state)
+            .oneshot(form_request("/admin/users/delete", &gm, format!("id={bob}")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn create_token_forbidden_for_player() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let token = auth
+            .create_session(Uuid::new_v4(), "alice", Role::Player, None, false)
+            .await
+            .unwrap();
+        let res = router(state)
+            .oneshot(form_request(TOKENS_PATH, &token, "name=ha&scope=mqtt.publish".into()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert!(auth.api_tokens.list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn gm_creates_scoped_token() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let token = auth
+            .create_session(Uuid::new_v4(), "gm", Role::Gm, None, false)
+            .await
+            .unwrap();
+        let res = router(state)
+            .oneshot(form_request(
+                TOKENS_PATH,
+                &token,
+                "name=ha&scope=mqtt.publish&scope=services.read".into(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(crate::api_token::TOKEN_PREFIX));
+
+        let tokens = auth.api_tokens.list().await.unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].1.name, "ha");
+        assert_eq!(
+            tokens[0].1.scopes,
+            Permissions::from([Permission::MqttPublish, Permission::ServicesRead])
+        );
+    }
+
+    async fn get_page(state: ServerState, uri: &str, token: &str) -> (StatusCode, String) {
+        let res = router(state)
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header("cookie", format!("green_session={token}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn audit_page_forbidden_for_player() {
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let token = auth
+            .create_session(Uuid::new_v4(), "alice", Role::Player, None, false)
+            .await
+            .unwrap();
+        let (status, _) = get_page(state, "/admin/audit", &token).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn audit_page_filters_by_user() {
+        use crate::audit::{AuditOutcome, RequestMeta};
+
+        let state = test_state().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let token = auth
+            .create_session(Uuid::new_v4(), "gm", Role::Gm, None, false)
+            .await
+            .unwrap();
+        let meta = RequestMeta::default();
+        auth.audit(meta.event(AuditAction::Login, AuditOutcome::Success).actor("alice"))
+            .await;
+        auth.audit(meta.event(AuditAction::Login, AuditOutcome::Failure).actor("mallory"))
+            .await;
+
+        let (status, body) = get_page(state.clone(), "/admin/audit?user=alice&action=login", &token).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("alice"));
+        assert!(!body.contains("mallory"));
+
+        let (status, body) = get_page(state, "/admin/audit?from=yesterday", &token).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("not a YYYY-MM-DD date"));
+    }
+
+    #[test]
+    fn audit_query_to_date_includes_whole_day() {
+        let filter = AuditQuery {
+            from: "2026-03-01".into(),
+            to: "2026-03-01".into(),
+            ..AuditQuery::default()
+        }
+        .filter()
+        .unwrap();
+        let since = filter.since.unwrap();
+        assert_eq!(filter.until.unwrap() - since, time::Duration::days(1));
+        assert!(filter.actor.is_none() && filter.action.is_none());
+    }
+
+    #[test]
+    fn token_form_needs_name_and_known_scopes() {
+        let fields = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+                .collect::<Vec<_>>()
+        };
+        assert!(CreateTokenForm::from_fields(fields(&[("name", "ha")])).is_err());
+        assert!(CreateTokenForm::from_fields(fields(&[("scope", "logs.read")])).is_err());
+        assert!(
+            CreateTokenForm::from_fields(fields(&[("name", "ha"), ("scope", "root")])).is_err()
+        );
+        let form =
+            CreateTokenForm::from_fields(fields(&[("name", " ha "), ("scope", "logs.read")])).unwrap();
+        assert_eq!(form.name, "ha");
+        assert_eq!(form.scopes, Permissions::from([Permission::LogsRead]));
+    }
+}
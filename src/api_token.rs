@@ -0,0 +1,279 @@
+//! Bearer tokens for scripts and Home Assistant.
+//!
+//! A GM issues a named token from `/admin/tokens` with a set of scopes. The
+//! token acts as the issuing account, but only with the scopes it was given
+//! (and never more than that account's role currently allows). Requests send
+//! it as `Authorization: Bearer <token>`. As with sessions, only the SHA-256
+//! hash of the token is stored.
+
+use futures::future::BoxFuture;
+use sqlx::{PgPool, Row, postgres::PgRow};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    auth::Role,
+    error::Error,
+    permission::{Permission, Permissions},
+};
+
+/// Prefix of every issued token, so leaked tokens are easy to recognise.
+pub const TOKEN_PREFIX: &str = "green_";
+
+/// A new random token. Only its hash is ever stored.
+pub fn generate_token() -> String {
+    format!("{TOKEN_PREFIX}{}", Uuid::new_v4().simple())
+}
+
+/// One issued API token.
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    /// Label chosen by the GM, e.g. `home-assistant`.
+    pub name: String,
+    /// Account the token acts as.
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: Role,
+    /// Permissions the token may use.
+    pub scopes: Permissions,
+    pub created_at: OffsetDateTime,
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+/// Storage backend for API tokens, keyed by [`crate::session::hash_token`].
+pub trait ApiTokenStore: Send + Sync + std::fmt::Debug {
+    fn insert<'a>(&'a self, token_hash: &'a str, token: &'a ApiToken)
+    -> BoxFuture<'a, Result<(), Error>>;
+
+    /// The token, or `None` if it doesn't exist or its account is disabled.
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<ApiToken>, Error>>;
+
+    /// Every token with its hash, newest first.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(String, ApiToken)>, Error>>;
+
+    /// Record use of a token by moving its `last_used_at` to `now`.
+    fn touch<'a>(&'a self, token_hash: &'a str, now: OffsetDateTime)
+    -> BoxFuture<'a, Result<(), Error>>;
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+// ─── Postgres ─────────────────────────────────────────────────────────────────
+
+/// [`ApiTokenStore`] backed by the `api_tokens` table.
+///
+/// Like sessions, lookups read the role from the `users` row and skip disabled
+/// accounts.
+#[derive(Debug, Clone)]
+pub struct PgApiTokenStore {
+    db: PgPool,
+}
+
+impl PgApiTokenStore {
+    pub fn new(db: PgPool) -> Self {
+        PgApiTokenStore { db }
+    }
+}
+
+fn api_token_from_row(row: &PgRow) -> Result<ApiToken, Error> {
+    // Scopes naming a permission that no longer exists are dropped.
+    let scopes = row
+        .get::<Vec<String>, _>("scopes")
+        .iter()
+        .filter_map(|s| s.parse::<Permission>().ok())
+        .collect();
+    Ok(ApiToken {
+        name: row.get("name"),
+        user_id: row.get("user_id"),
+        username: row.get("username"),
+        role: Role::from(row.get::<String, _>("role")),
+        scopes,
+        created_at: row.get("created_at"),
+        last_used_at: row.get("last_used_at"),
+    })
+}
+
+impl ApiTokenStore for PgApiTokenStore {
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        token: &'a ApiToken,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let scopes: Vec<String> = token.scopes.iter().map(ToString::to_string).collect();
+            let _ = sqlx::query(
+                "INSERT INTO api_tokens (token_hash, name, user_id, scopes, created_at, last_used_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind(token_hash)
+            .bind(&token.name)
+            .bind(token.user_id)
+            .bind(scopes)
+            .bind(token.created_at)
+            .bind(token.last_used_at)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<ApiToken>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "SELECT t.name, t.user_id, u.username, u.role, t.scopes, t.created_at, t.last_used_at \
+                 FROM api_tokens t \
+                 JOIN users u ON u.id = t.user_id \
+                 WHERE t.token_hash = $1 AND NOT u.disabled",
+            )
+            .bind(token_hash)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            row.as_ref().map(api_token_from_row).transpose()
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(String, ApiToken)>, Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT t.token_hash, t.name, t.user_id, u.username, u.role, t.scopes, \
+                        t.created_at, t.last_used_at \
+                 FROM api_tokens t \
+                 JOIN users u ON u.id = t.user_id \
+                 ORDER BY t.created_at DESC",
+            )
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            rows.iter()
+                .map(|row| Ok((row.get("token_hash"), api_token_from_row(row)?)))
+                .collect()
+        })
+    }
+
+    fn touch<'a>(
+        &'a self,
+        token_hash: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query("UPDATE api_tokens SET last_used_at = $2 WHERE token_hash = $1")
+                .bind(token_hash)
+                .bind(now)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query("DELETE FROM api_tokens WHERE token_hash = $1")
+                .bind(token_hash)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+}
+
+// ─── In-memory (tests) ────────────────────────────────────────────────────────
+
+/// [`ApiTokenStore`] kept in process memory, so handler tests don't need Postgres.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryApiTokenStore {
+    tokens: tokio::sync::RwLock<std::collections::HashMap<String, ApiToken>>,
+}
+
+#[cfg(test)]
+impl ApiTokenStore for MemoryApiTokenStore {
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        token: &'a ApiToken,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = self
+                .tokens
+                .write()
+                .await
+                .insert(token_hash.to_owned(), token.clone());
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<ApiToken>, Error>> {
+        Box::pin(async move { Ok(self.tokens.read().await.get(token_hash).cloned()) })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(String, ApiToken)>, Error>> {
+        Box::pin(async move {
+            let mut tokens: Vec<_> = self
+                .tokens
+                .read()
+                .await
+                .iter()
+                .map(|(hash, token)| (hash.clone(), token.clone()))
+                .collect();
+            tokens.sort_by_key(|(_, t)| std::cmp::Reverse(t.created_at));
+            Ok(tokens)
+        })
+    }
+
+    fn touch<'a>(
+        &'a self,
+        token_hash: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            if let Some(token) = self.tokens.write().await.get_mut(token_hash) {
+                token.last_used_at = Some(now);
+            }
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = self.tokens.write().await.remove(token_hash);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_tokens_are_prefixed_and_unique() {
+        let a = generate_token();
+        let b = generate_token();
+        assert!(a.starts_with(TOKEN_PREFIX));
+        assert_ne!(a, b);
+    }
+
+    #[tokio::test]
+    async fn memory_store_touch_and_remove() {
+        let store = MemoryApiTokenStore::default();
+        let token = ApiToken {
+            name: "ha".into(),
+            user_id: Uuid::new_v4(),
+            username: "gm".into(),
+            role: Role::Gm,
+            scopes: Permissions::from([Permission::MqttPublish]),
+            created_at: OffsetDateTime::now_utc(),
+            last_used_at: None,
+        };
+        store.insert("h", &token).await.unwrap();
+        let now = OffsetDateTime::now_utc();
+        store.touch("h", now).await.unwrap();
+        assert_eq!(store.get("h").await.unwrap().unwrap().last_used_at, Some(now));
+        store.remove("h").await.unwrap();
+        assert!(store.list().await.unwrap().is_empty());
+    }
+}
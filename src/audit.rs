@@ -0,0 +1,307 @@
+//! Audit log of security-relevant events.
+//!
+//! Logins, logouts, registrations, recoveries and MQTT publishes are written
+//! to the `audit_events` table so they outlive log rotation and can be
+//! filtered per user at `/admin/audit`. Recording is best-effort: a failed
+//! write is logged and never fails the request being audited.
+
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row, postgres::PgRow};
+use time::OffsetDateTime;
+
+use crate::error::Error;
+
+/// What happened.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditAction {
+    /// A new account was created with its first passkey.
+    Register,
+    /// A passkey login.
+    Login,
+    Logout,
+    /// Someone asked for a recovery code to be sent.
+    RecoveryRequested,
+    /// A recovery code was entered.
+    Recovery,
+    /// A user generated a new set of backup codes.
+    BackupCodesGenerated,
+    /// A message was published to the MQTT broker.
+    MqttPublish,
+}
+
+/// Whether the audited action succeeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl<T, E> From<&Result<T, E>> for AuditOutcome {
+    fn from(result: &Result<T, E>) -> Self {
+        if result.is_ok() {
+            AuditOutcome::Success
+        } else {
+            AuditOutcome::Failure
+        }
+    }
+}
+
+/// One audit log entry.
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub at: OffsetDateTime,
+    /// Username that performed (or claimed to perform) the action.
+    pub actor: Option<String>,
+    pub action: AuditAction,
+    /// What the action applied to, e.g. an MQTT topic.
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: AuditOutcome,
+}
+
+impl AuditEvent {
+    pub fn actor(mut self, actor: &str) -> Self {
+        self.actor = Some(actor.to_owned());
+        self
+    }
+
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_owned());
+        self
+    }
+}
+
+/// Client address and user agent of the current request, for audit events.
+/// Always succeeds; fields are `None` when unknown.
+#[derive(Debug, Clone, Default)]
+pub struct RequestMeta {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl RequestMeta {
+    /// Start an event for this request at the current time.
+    pub fn event(&self, action: AuditAction, outcome: AuditOutcome) -> AuditEvent {
+        AuditEvent {
+            at: OffsetDateTime::now_utc(),
+            actor: None,
+            action,
+            target: None,
+            ip: self.ip.clone(),
+            user_agent: self.user_agent.clone(),
+            outcome,
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestMeta {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let peer = ConnectInfo::<SocketAddr>::from_request_parts(parts, state).await.ok();
+        Ok(RequestMeta {
+            ip: peer.map(|ConnectInfo(addr)| addr.ip().to_canonical().to_string()),
+            user_agent: parts
+                .headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned),
+        })
+    }
+}
+
+/// Which events to list. Unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    /// Inclusive lower bound on `at`.
+    pub since: Option<OffsetDateTime>,
+    /// Exclusive upper bound on `at`.
+    pub until: Option<OffsetDateTime>,
+    pub limit: i64,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.actor
+            .as_deref()
+            .is_none_or(|a| event.actor.as_deref() == Some(a))
+            && self.action.is_none_or(|a| event.action == a)
+            && self.since.is_none_or(|t| event.at >= t)
+            && self.until.is_none_or(|t| event.at < t)
+    }
+}
+
+/// Storage backend for audit events.
+pub trait AuditStore: Send + Sync + std::fmt::Debug {
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Events matching `filter`, newest first, at most `filter.limit`.
+    fn list<'a>(&'a self, filter: &'a AuditFilter) -> BoxFuture<'a, Result<Vec<AuditEvent>, Error>>;
+}
+
+// ─── Postgres ─────────────────────────────────────────────────────────────────
+
+/// [`AuditStore`] backed by the `audit_events` table.
+#[derive(Debug, Clone)]
+pub struct PgAuditStore {
+    db: PgPool,
+}
+
+impl PgAuditStore {
+    pub fn new(db: PgPool) -> Self {
+        PgAuditStore { db }
+    }
+}
+
+fn audit_event_from_row(row: &PgRow) -> Result<AuditEvent, Error> {
+    let action: String = row.get("action");
+    let outcome: String = row.get("outcome");
+    Ok(AuditEvent {
+        at: row.get("at"),
+        actor: row.get("actor"),
+        action: action
+            .parse()
+            .map_err(|_| Error::Database(format!("unknown audit action `{action}`")))?,
+        target: row.get("target"),
+        ip: row.get("ip"),
+        user_agent: row.get("user_agent"),
+        outcome: outcome
+            .parse()
+            .map_err(|_| Error::Database(format!("unknown audit outcome `{outcome}`")))?,
+    })
+}
+
+impl AuditStore for PgAuditStore {
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query(
+                "INSERT INTO audit_events (at, actor, action, target, ip, user_agent, outcome) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(event.at)
+            .bind(event.actor.as_deref())
+            .bind(event.action.to_string())
+            .bind(event.target.as_deref())
+            .bind(event.ip.as_deref())
+            .bind(event.user_agent.as_deref())
+            .bind(event.outcome.to_string())
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn list<'a>(&'a self, filter: &'a AuditFilter) -> BoxFuture<'a, Result<Vec<AuditEvent>, Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT at, actor, action, target, ip, user_agent, outcome \
+                 FROM audit_events \
+                 WHERE ($1::text IS NULL OR actor = $1) \
+                   AND ($2::text IS NULL OR action = $2) \
+                   AND ($3::timestamptz IS NULL OR at >= $3) \
+                   AND ($4::timestamptz IS NULL OR at < $4) \
+                 ORDER BY at DESC, id DESC \
+                 LIMIT $5",
+            )
+            .bind(filter.actor.as_deref())
+            .bind(filter.action.map(|a| a.to_string()))
+            .bind(filter.since)
+            .bind(filter.until)
+            .bind(filter.limit)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            rows.iter().map(audit_event_from_row).collect()
+        })
+    }
+}
+
+// ─── In-memory (tests) ────────────────────────────────────────────────────────
+
+/// [`AuditStore`] kept in process memory, so handler tests don't need Postgres.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryAuditStore {
+    events: tokio::sync::RwLock<Vec<AuditEvent>>,
+}
+
+#[cfg(test)]
+impl AuditStore for MemoryAuditStore {
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.events.write().await.push(event.clone());
+            Ok(())
+        })
+    }
+
+    fn list<'a>(&'a self, filter: &'a AuditFilter) -> BoxFuture<'a, Result<Vec<AuditEvent>, Error>> {
+        Box::pin(async move {
+            Ok(self
+                .events
+                .read()
+                .await
+                .iter()
+                .rev()
+                .filter(|e| filter.matches(e))
+                .take(usize::try_from(filter.limit).unwrap_or(0))
+                .cloned()
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_use_snake_case_names() {
+        assert_eq!(AuditAction::RecoveryRequested.to_string(), "recovery_requested");
+        assert_eq!("mqtt_publish".parse::<AuditAction>().unwrap(), AuditAction::MqttPublish);
+        assert_eq!(AuditOutcome::Failure.to_string(), "failure");
+    }
+
+    #[test]
+    fn filter_matches_actor_action_and_time_range() {
+        let now = OffsetDateTime::now_utc();
+        let event = RequestMeta::default()
+            .event(AuditAction::Login, AuditOutcome::Success)
+            .actor("alice");
+        let filter = AuditFilter {
+            actor: Some("alice".into()),
+            action: Some(AuditAction::Login),
+            since: Some(now - time::Duration::minutes(1)),
+            until: Some(now + time::Duration::minutes(1)),
+            limit: 10,
+        };
+        assert!(filter.matches(&event));
+        assert!(!AuditFilter { actor: Some("bob".into()), ..filter.clone() }.matches(&event));
+        assert!(!AuditFilter { action: Some(AuditAction::Logout), ..filter.clone() }.matches(&event));
+        assert!(!AuditFilter { until: Some(now - time::Duration::minutes(1)), ..filter }.matches(&event));
+    }
+}
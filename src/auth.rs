@@ -1,19 +1,20 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     convert::Infallible,
     sync::Arc,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use axum::{
     extract::FromRequestParts,
-    http::request::Parts,
+    http::{HeaderMap, StatusCode, header, request::Parts},
     response::{IntoResponse, Redirect, Response},
 };
 use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, Row};
-use tokio::sync::{Mutex, RwLock};
+use strum::IntoEnumIterator as _;
+use time::OffsetDateTime;
 use url::Url;
 use uuid::Uuid;
 use webauthn_rs::prelude::{
@@ -21,25 +22,83 @@ use webauthn_rs::prelude::{
     WebauthnBuilder,
 };
 
-use crate::{ServerState, error::Error, index::NavLink};
+use crate::{
+    ServerState,
+    api_token::{ApiToken, ApiTokenStore, PgApiTokenStore, generate_token},
+    audit::{AuditAction, AuditEvent, AuditOutcome, AuditStore, PgAuditStore, RequestMeta},
+    challenge::{
+        ChallengeBackend, ChallengeKind, ChallengeStore, MemoryChallengeStore, PgChallengeStore,
+    },
+    error::Error,
+    index::NavLink,
+    invite::{Invite, InviteStore, PgInviteStore, RegistrationMode},
+    permission::{Permission, Permissions},
+    rate_limit::{RateLimitConfig, RateLimits},
+    recovery::{
+        BackupCodeStore, PgBackupCodeStore, RecoveryDelivery, SmtpConfig, generate_otc,
+        hash_backup_code,
+    },
+    session::{PgSessionStore, SessionData, SessionStore, SessionTtl, hash_token},
+    user::{PgUserStore, UserStore},
+};
 
 // ─── TTL constants ─────────────────────────────────────────────────────────
 
-/// How long a session token remains valid after creation.
-const SESSION_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
-
-/// How long a WebAuthn registration or authentication challenge remains valid.
-const CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+/// How often expired sessions and challenges are swept from their stores.
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
-/// How long a one-time recovery code remains valid.
-const OTC_TTL: Duration = Duration::from_secs(10 * 60);
+/// Minimum gap between `last_seen` writes for the same session (and
+/// `last_used_at` writes for the same API token), so busy pages don't turn
+/// every request into a database write.
+const LAST_SEEN_RESOLUTION: Duration = Duration::from_secs(60);
 
 // ─── Public config types ───────────────────────────────────────────────────
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// An account's role, stored by name in `users.role`. `Gm` and `Player` are
+/// built in; any other name refers to a role defined under `[auth.roles]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum Role {
+    /// Holds every permission and manages accounts.
     Gm,
     Player,
+    /// A role defined in `[auth.roles]`.
+    Custom(String),
+}
+
+impl Role {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::Gm => "Gm",
+            Role::Player => "Player",
+            Role::Custom(name) => name,
+        }
+    }
+}
+
+impl From<String> for Role {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "Gm" => Role::Gm,
+            "Player" => Role::Player,
+            _ => Role::Custom(name),
+        }
+    }
+}
+
+impl From<Role> for String {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Custom(name) => name,
+            role => role.as_str().to_owned(),
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,20 +106,91 @@ pub struct AuthConfig {
     pub rp_id: String,
     pub rp_origin: String,
     pub db_url: String,
+    /// Usernames that start out as GMs when their account is created. After
+    /// that, `users.role` is authoritative and is managed at `/admin/users`.
     #[serde(default)]
     pub gm_users: Vec<String>,
+    /// ntfy topic URL that recovery codes are posted to.
     #[serde(default)]
     pub ntfy_url: Option<String>,
+    /// Email recovery codes to the address on the account.
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+    /// Seconds of inactivity after which a session expires. Defaults to 7 days.
+    #[serde(default = "default_idle_ttl")]
+    pub idle_ttl: u64,
+    /// Seconds after login at which a session expires regardless of activity.
+    /// Defaults to 30 days.
+    #[serde(default = "default_absolute_ttl")]
+    pub absolute_ttl: u64,
+    /// Seconds after login at which a "remember this device" (kiosk) session
+    /// expires. Kiosk sessions have no idle timeout. Defaults to 365 days.
+    #[serde(default = "default_kiosk_ttl")]
+    pub kiosk_ttl: u64,
+    /// Who may create new accounts: `open`, `invite` or `closed`. Defaults to `open`.
+    #[serde(default)]
+    pub registration: RegistrationMode,
+    /// Permissions of each non-GM role, e.g. `housemate = ["breaker.read"]`.
+    /// GMs always have every permission; `Player` has none unless listed.
+    #[serde(default)]
+    pub roles: BTreeMap<String, Permissions>,
+    /// Limits on `/auth/*` requests and pending challenges.
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Where pending WebAuthn challenges and recovery codes are kept:
+    /// `memory` (default) or `postgres`, which survives restarts and works
+    /// across instances.
+    #[serde(default)]
+    pub challenge_store: ChallengeBackend,
 }
 
-// ─── Session storage ───────────────────────────────────────────────────────
+fn default_idle_ttl() -> u64 {
+    7 * 24 * 60 * 60
+}
 
-#[derive(Debug)]
-pub struct SessionData {
-    pub user_id: Uuid,
-    pub username: String,
-    pub role: Role,
-    pub created_at: Instant,
+fn default_absolute_ttl() -> u64 {
+    30 * 24 * 60 * 60
+}
+
+fn default_kiosk_ttl() -> u64 {
+    365 * 24 * 60 * 60
+}
+
+impl AuthConfig {
+    /// Session lifetime policy derived from the TTL settings.
+    pub fn session_ttl(&self) -> SessionTtl {
+        SessionTtl {
+            idle: Duration::from_secs(self.idle_ttl),
+            absolute: Duration::from_secs(self.absolute_ttl),
+            kiosk: Duration::from_secs(self.kiosk_ttl),
+        }
+    }
+
+    /// Everything `role` is allowed to do.
+    pub fn permissions(&self, role: &Role) -> Permissions {
+        match role {
+            Role::Gm => Permission::iter().collect(),
+            role => self.roles.get(role.as_str()).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// `role` if it's built in or defined in `[auth.roles]`.
+    pub fn check_role(&self, role: Role) -> Result<Role, Error> {
+        match role {
+            Role::Custom(name) if !self.roles.contains_key(&name) => Err(Error::UnknownRole(name)),
+            role => Ok(role),
+        }
+    }
+
+    /// Roles a GM can assign: the built-in ones, then those from `[auth.roles]`.
+    pub fn assignable_roles(&self) -> Vec<Role> {
+        let custom = self
+            .roles
+            .keys()
+            .map(|name| Role::from(name.clone()))
+            .filter(|role| matches!(role, Role::Custom(_)));
+        [Role::Gm, Role::Player].into_iter().chain(custom).collect()
+    }
 }
 
 // ─── AuthUserInfo — for templates ─────────────────────────────────────────
@@ -70,12 +200,118 @@ pub struct SessionData {
 pub struct AuthUserInfo {
     pub username: String,
     pub role: Role,
+    pub permissions: Permissions,
+    /// Token for the page's `<meta name="csrf-token">`; see [`crate::csrf`].
+    pub csrf_token: String,
 }
 
 impl AuthUserInfo {
     pub fn is_gm(&self) -> bool {
         self.role == Role::Gm
     }
+
+    pub fn can(&self, permission: &Permission) -> bool {
+        self.permissions.contains(permission)
+    }
+}
+
+// ─── Registration ─────────────────────────────────────────────────────────
+
+/// What a registration ceremony will do with the new passkey.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegistrationKind {
+    /// Create a new account. Carries the hash of the invite to redeem once the
+    /// passkey is verified.
+    NewAccount { invite: Option<String> },
+    /// Add the passkey to the signed-in user's existing account.
+    AddPasskey,
+}
+
+/// A registration ceremony waiting for the browser's response.
+///
+/// New-account ceremonies are stored as [`ChallengeKind::Registration`] keyed
+/// by username, add-passkey ceremonies as [`ChallengeKind::AddPasskey`] keyed
+/// by the user's id; `kind` and `user_id` are checked again when finishing.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingRegistration {
+    pub state: PasskeyRegistration,
+    /// Id the passkey was bound to; also the new account's id.
+    pub user_id: Uuid,
+    pub kind: RegistrationKind,
+}
+
+// ─── Accounts ─────────────────────────────────────────────────────────────
+
+/// A row of the `users` table.
+#[derive(Debug, Clone)]
+pub struct UserRecord {
+    pub id: Uuid,
+    pub username: String,
+    pub role: Role,
+    /// Disabled accounts can't sign in and have no live sessions.
+    pub disabled: bool,
+    /// Where email recovery codes are sent.
+    pub email: Option<String>,
+}
+
+impl UserRecord {
+    pub(crate) fn from_row(row: &sqlx::postgres::PgRow) -> Result<Self, Error> {
+        Ok(UserRecord {
+            id: row.get("id"),
+            username: row.get("username"),
+            role: Role::from(row.get::<String, _>("role")),
+            disabled: row.get("disabled"),
+            email: row.get("email"),
+        })
+    }
+}
+
+/// A [`UserRecord`] with counts shown on the admin page.
+#[derive(Debug, Clone)]
+pub struct UserSummary {
+    pub user: UserRecord,
+    pub passkey_count: i64,
+    pub session_count: i64,
+}
+
+// ─── Passkey metadata ─────────────────────────────────────────────────────
+
+/// User-facing details about one passkey, kept in `passkey_meta` alongside the
+/// opaque `passkeys.credentials` list.
+#[derive(Debug, Clone, Default)]
+pub struct PasskeyMeta {
+    pub nickname: Option<String>,
+    /// `None` for passkeys registered before metadata was tracked.
+    pub created_at: Option<OffsetDateTime>,
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+/// Hex-encoded credential ID, used as the `passkey_meta` key and as the
+/// identifier in account-page forms.
+pub fn credential_key(cred_id: &[u8]) -> String {
+    cred_id.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// `passkeys` minus the one identified by `key`. Fails with
+/// [`Error::NotFound`] if no passkey matches and [`Error::LastPasskey`] if it
+/// is the only one left.
+fn without_passkey<P>(
+    passkeys: Vec<P>,
+    key: &str,
+    cred_id: impl Fn(&P) -> &[u8],
+) -> Result<Vec<P>, Error> {
+    let before = passkeys.len();
+    let remaining: Vec<P> = passkeys
+        .into_iter()
+        .filter(|pk| credential_key(cred_id(pk)) != key)
+        .collect();
+    if remaining.len() == before {
+        return Err(Error::NotFound);
+    }
+    if remaining.is_empty() {
+        return Err(Error::LastPasskey);
+    }
+    Ok(remaining)
 }
 
 // ─── AuthState ────────────────────────────────────────────────────────────
@@ -85,11 +321,17 @@ pub struct AuthState {
     pub webauthn: Arc<Webauthn>,
     pub config: AuthConfig,
     pub db: PgPool,
-    pub session_store: Arc<RwLock<HashMap<String, SessionData>>>,
-    pub reg_states: Arc<Mutex<HashMap<String, (PasskeyRegistration, Instant)>>>,
-    pub discoverable_states: Arc<Mutex<HashMap<String, (DiscoverableAuthentication, Instant)>>>,
-    pub otc_store: Arc<RwLock<HashMap<String, (String, Instant)>>>,
-    pub http_client: reqwest::Client,
+    pub sessions: Arc<dyn SessionStore>,
+    pub invites: Arc<dyn InviteStore>,
+    pub api_tokens: Arc<dyn ApiTokenStore>,
+    pub audit_log: Arc<dyn AuditStore>,
+    /// Pending registrations, logins and recovery codes.
+    pub challenges: Arc<dyn ChallengeStore>,
+    pub rate_limits: Arc<RateLimits>,
+    /// Where one-time recovery codes are sent; may be empty.
+    pub recovery_channels: Vec<Arc<dyn RecoveryDelivery>>,
+    pub backup_codes: Arc<dyn BackupCodeStore>,
+    pub users: Arc<dyn UserStore>,
 }
 
 impl std::fmt::Debug for AuthState {
@@ -119,19 +361,31 @@ impl AuthState {
             .await
             .map_err(|e| Error::AuthSetup(format!("db migrate: {e}")))?;
 
+        let rate_limits = Arc::new(RateLimits::new(&config.rate_limit));
+        let recovery_channels = crate::recovery::channels(&config)?;
+        let challenges: Arc<dyn ChallengeStore> = match config.challenge_store {
+            ChallengeBackend::Memory => Arc::new(MemoryChallengeStore::default()),
+            ChallengeBackend::Postgres => Arc::new(PgChallengeStore::new(db.clone())),
+        };
         Ok(AuthState {
             webauthn: Arc::new(webauthn),
             config,
+            sessions: Arc::new(PgSessionStore::new(db.clone())),
+            invites: Arc::new(PgInviteStore::new(db.clone())),
+            api_tokens: Arc::new(PgApiTokenStore::new(db.clone())),
+            audit_log: Arc::new(PgAuditStore::new(db.clone())),
+            backup_codes: Arc::new(PgBackupCodeStore::new(db.clone())),
+            users: Arc::new(PgUserStore::new(db.clone())),
             db,
-            session_store: Arc::new(RwLock::new(HashMap::new())),
-            reg_states: Arc::new(Mutex::new(HashMap::new())),
-            discoverable_states: Arc::new(Mutex::new(HashMap::new())),
-            otc_store: Arc::new(RwLock::new(HashMap::new())),
-            http_client: reqwest::Client::new(),
+            challenges,
+            rate_limits,
+            recovery_channels,
         })
     }
 
-    fn role_for(&self, username: &str) -> Role {
+    /// Role given to a newly created account without an invite. `gm_users`
+    /// only seeds the role; afterwards `users.role` is authoritative.
+    fn seed_role(&self, username: &str) -> Role {
         if self.config.gm_users.iter().any(|u| u == username) {
             Role::Gm
         } else {
@@ -140,168 +394,433 @@ impl AuthState {
     }
 
     /// Returns `None` if no user with this username exists.
-    async fn load_passkeys(&self, username: &str) -> Result<Option<(Uuid, Vec<Passkey>)>, Error> {
-        let row = sqlx::query(
-            "SELECT u.id, COALESCE(p.credentials, '[]'::jsonb) AS credentials \
-             FROM users u \
-             LEFT JOIN passkeys p ON p.user_id = u.id \
-             WHERE u.username = $1",
-        )
-        .bind(username)
-        .fetch_optional(&self.db)
-        .await
-        .map_err(|e| Error::Database(e.to_string()))?;
-
-        match row {
-            None => Ok(None),
-            Some(row) => {
-                let id: Uuid = row.get("id");
-                let credentials: Value = row.get("credentials");
-                let passkeys: Vec<Passkey> = serde_json::from_value(credentials)
-                    .map_err(|e| Error::Database(format!("failed to deserialize passkeys: {e}")))?;
-                Ok(Some((id, passkeys)))
-            }
-        }
+    pub(crate) async fn load_passkeys(&self, username: &str) -> Result<Option<(Uuid, Vec<Passkey>)>, Error> {
+        self.users.load_passkeys(username).await
     }
 
-    async fn save_passkeys(
+    /// Create a new account with `role` and its first passkey. Fails with
+    /// [`Error::UsernameTaken`] if the username already exists — never touches
+    /// an existing account.
+    pub(crate) async fn create_user(
         &self,
         user_id: Uuid,
         username: &str,
-        display_name: &str,
-        role: &Role,
+        role: Role,
+        passkey: &Passkey,
+    ) -> Result<UserRecord, Error> {
+        self.users.create(user_id, username, role, passkey).await
+    }
+
+    /// Write `passkeys` as an existing user's full credential list. Returns the
+    /// account, whose role and disabled flag callers must honour.
+    pub(crate) async fn save_passkeys(
+        &self,
+        user_id: Uuid,
         passkeys: &[Passkey],
-    ) -> Result<(), Error> {
-        let role_str = match role {
-            Role::Gm => "Gm",
-            Role::Player => "Player",
-        };
-        let credentials = serde_json::to_value(passkeys)
-            .map_err(|e| Error::Database(format!("failed to serialize passkeys: {e}")))?;
+    ) -> Result<UserRecord, Error> {
+        self.users.save_passkeys(user_id, passkeys).await
+    }
 
-        let mut tx = self
-            .db
-            .begin()
-            .await
-            .map_err(|e| Error::Database(e.to_string()))?;
+    /// Returns `None` if no user with this UUID exists.
+    pub(crate) async fn load_user(&self, user_id: Uuid) -> Result<Option<UserRecord>, Error> {
+        self.users.load(user_id).await
+    }
 
-        let _ = sqlx::query(
-            "INSERT INTO users (id, username, display_name, role) \
-             VALUES ($1, $2, $3, $4) \
-             ON CONFLICT (username) DO UPDATE SET display_name = EXCLUDED.display_name, role = EXCLUDED.role",
-        )
-        .bind(user_id)
-        .bind(username)
-        .bind(display_name)
-        .bind(role_str)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| Error::Database(e.to_string()))?;
+    /// Every account with its passkey and live-session counts, for the admin page.
+    pub(crate) async fn list_users(&self) -> Result<Vec<UserSummary>, Error> {
+        let mut summaries = Vec::new();
+        for (user, passkey_count) in self.users.list().await? {
+            let session_count = self.sessions.list_for_user(user.id).await?.len() as i64;
+            summaries.push(UserSummary { user, passkey_count, session_count });
+        }
+        Ok(summaries)
+    }
 
-        let _ = sqlx::query(
-            "INSERT INTO passkeys (user_id, credentials, updated_at) \
-             VALUES ($1, $2, NOW()) \
-             ON CONFLICT (user_id) DO UPDATE SET credentials = EXCLUDED.credentials, updated_at = NOW()",
-        )
-        .bind(user_id)
-        .bind(credentials)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| Error::Database(e.to_string()))?;
+    /// Change a user's role. Live sessions pick up the new role on their next request.
+    pub(crate) async fn set_role(&self, user_id: Uuid, role: Role) -> Result<(), Error> {
+        self.users.set_role(user_id, role).await
+    }
 
-        tx.commit()
-            .await
-            .map_err(|e| Error::Database(e.to_string()))?;
+    /// Disable or re-enable an account. Disabling also signs the user out everywhere.
+    pub(crate) async fn set_disabled(&self, user_id: Uuid, disabled: bool) -> Result<(), Error> {
+        self.users.set_disabled(user_id, disabled).await?;
+        if disabled {
+            let _ = self.sessions.remove_for_user(user_id).await?;
+        }
+        Ok(())
+    }
 
+    /// Set or clear the address email recovery codes are sent to.
+    pub(crate) async fn set_email(&self, user_id: Uuid, email: Option<String>) -> Result<(), Error> {
+        self.users.set_email(user_id, email).await
+    }
+
+    /// Delete an account with its passkeys and sessions.
+    pub(crate) async fn delete_user(&self, user_id: Uuid) -> Result<(), Error> {
+        self.users.delete(user_id).await?;
+        // The Postgres store cascades; this covers any other session backend.
+        let _ = self.sessions.remove_for_user(user_id).await?;
         Ok(())
     }
 
-    /// Retrieve session data for a cookie value. Returns `None` if missing or expired.
-    pub async fn get_session(&self, token: &str) -> Option<AuthUserInfo> {
-        let store = self.session_store.read().await;
-        store
-            .get(token)
-            .filter(|s| session_is_valid(s))
-            .map(|s| AuthUserInfo {
-                username: s.username.clone(),
-                role: s.role.clone(),
-            })
+    /// Nickname and timestamps for each of `user_id`'s passkeys, keyed by
+    /// [`credential_key`].
+    pub(crate) async fn passkey_meta(
+        &self,
+        user_id: Uuid,
+    ) -> Result<HashMap<String, PasskeyMeta>, Error> {
+        self.users.passkey_meta(user_id).await
     }
 
-    /// Purge registration challenge states older than [`CHALLENGE_TTL`].
-    pub async fn cleanup_reg_states(&self) {
-        let mut map = self.reg_states.lock().await;
-        map.retain(|_, (_, ts)| ts.elapsed() <= CHALLENGE_TTL);
+    /// Stamp `created_at` on a freshly registered passkey.
+    async fn record_passkey_created(&self, user_id: Uuid, cred_id: &[u8]) -> Result<(), Error> {
+        self.users
+            .record_passkey_created(user_id, &credential_key(cred_id))
+            .await
     }
 
-    /// Returns `None` if no user with this UUID exists.
-    async fn load_passkeys_by_id(
+    /// Stamp `last_used_at` on the passkey that just signed in.
+    async fn record_passkey_used(&self, user_id: Uuid, cred_id: &[u8]) -> Result<(), Error> {
+        self.users
+            .record_passkey_used(user_id, &credential_key(cred_id))
+            .await
+    }
+
+    /// Set or clear the user-assigned nickname of one passkey.
+    pub(crate) async fn rename_passkey(
         &self,
         user_id: Uuid,
-    ) -> Result<Option<(String, Vec<Passkey>)>, Error> {
-        let row = sqlx::query(
-            "SELECT u.username, COALESCE(p.credentials, '[]'::jsonb) AS credentials \
-             FROM users u \
-             LEFT JOIN passkeys p ON p.user_id = u.id \
-             WHERE u.id = $1",
-        )
-        .bind(user_id)
-        .fetch_optional(&self.db)
-        .await
-        .map_err(|e| Error::Database(e.to_string()))?;
-
-        match row {
-            None => Ok(None),
-            Some(row) => {
-                let username: String = row.get("username");
-                let credentials: Value = row.get("credentials");
-                let passkeys: Vec<Passkey> = serde_json::from_value(credentials)
-                    .map_err(|e| Error::Database(format!("failed to deserialize passkeys: {e}")))?;
-                Ok(Some((username, passkeys)))
+        key: &str,
+        nickname: Option<&str>,
+    ) -> Result<(), Error> {
+        self.users.rename_passkey(user_id, key, nickname).await
+    }
+
+    /// Remove one passkey from `user_id`'s credential list along with its
+    /// metadata. Refuses to remove the last remaining passkey.
+    pub(crate) async fn delete_passkey(&self, user_id: Uuid, key: &str) -> Result<(), Error> {
+        let (_, passkeys) = self
+            .load_passkeys_by_id(user_id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        let remaining = without_passkey(passkeys, key, |pk| pk.cred_id().as_ref())?;
+
+        let _ = self.save_passkeys(user_id, &remaining).await?;
+        self.users.delete_passkey_meta(user_id, key).await
+    }
+
+    /// Create an invite and return the raw token for the invite link.
+    pub(crate) async fn create_invite(
+        &self,
+        username: Option<String>,
+        role: Role,
+        created_by: &str,
+        ttl: Duration,
+    ) -> Result<String, Error> {
+        let token = Uuid::new_v4().to_string();
+        let now = OffsetDateTime::now_utc();
+        let invite = Invite {
+            username,
+            role,
+            created_by: created_by.to_owned(),
+            created_at: now,
+            expires_at: now + ttl,
+            used_at: None,
+        };
+        self.invites.insert(&hash_token(&token), &invite).await?;
+        Ok(token)
+    }
+
+    /// Look up an invite token that is still usable. Returns `None` if it's
+    /// unknown, used or expired.
+    pub(crate) async fn usable_invite(&self, token: &str) -> Result<Option<Invite>, Error> {
+        let invite = self.invites.get(&hash_token(token)).await?;
+        Ok(invite.filter(|i| i.is_usable(OffsetDateTime::now_utc())))
+    }
+
+    /// Apply the registration mode to a new registration for `username`.
+    /// Returns the hash of the invite to redeem when registration finishes.
+    ///
+    /// In `invite` mode, usernames listed in `gm_users` may register without an
+    /// invite while no GM account exists yet, so a fresh install can bootstrap
+    /// its first GM. Once there is a GM, further accounts need an invite.
+    async fn check_registration(
+        &self,
+        username: &str,
+        invite: Option<&str>,
+    ) -> Result<Option<String>, Error> {
+        match (self.config.registration, invite) {
+            (RegistrationMode::Closed, _) => Err(Error::RegistrationClosed),
+            (RegistrationMode::Open, _) => Ok(None),
+            (RegistrationMode::Invite, Some(token)) => {
+                let invite = self.usable_invite(token).await?.ok_or(Error::InvalidInvite)?;
+                if !invite.allows(username) {
+                    return Err(Error::InvalidInvite);
+                }
+                Ok(Some(hash_token(token)))
+            }
+            (RegistrationMode::Invite, None) => {
+                if self.seed_role(username) == Role::Gm && !self.users.gm_exists().await? {
+                    Ok(None)
+                } else {
+                    Err(Error::InvalidInvite)
+                }
             }
         }
     }
 
-    /// Purge discoverable challenge states older than [`CHALLENGE_TTL`].
-    pub async fn cleanup_discoverable_states(&self) {
-        let mut map = self.discoverable_states.lock().await;
-        map.retain(|_, (_, ts)| ts.elapsed() <= CHALLENGE_TTL);
+    /// Persist a new session for `user_id` and return the cookie token.
+    pub async fn create_session(
+        &self,
+        user_id: Uuid,
+        username: &str,
+        role: Role,
+        user_agent: Option<String>,
+        kiosk: bool,
+    ) -> Result<String, Error> {
+        let token = Uuid::new_v4().to_string();
+        let now = OffsetDateTime::now_utc();
+        let session = SessionData {
+            user_id,
+            username: username.to_owned(),
+            role,
+            created_at: now,
+            last_seen: now,
+            user_agent,
+            kiosk,
+        };
+        self.sessions.insert(&hash_token(&token), &session).await?;
+        Ok(token)
     }
 
-    /// Purge one-time recovery codes older than [`OTC_TTL`].
-    pub async fn cleanup_otc_store(&self) {
-        let mut map = self.otc_store.write().await;
-        map.retain(|_, (_, ts)| ts.elapsed() <= OTC_TTL);
+    /// Look up the session for a cookie value and record the activity.
+    /// Returns `None` if missing or expired.
+    pub async fn session(&self, token: &str) -> Result<Option<SessionData>, Error> {
+        let token_hash = hash_token(token);
+        let Some(mut session) = self.sessions.get(&token_hash).await? else {
+            return Ok(None);
+        };
+        let now = OffsetDateTime::now_utc();
+        if !self.config.session_ttl().is_valid(&session, now) {
+            return Ok(None);
+        }
+        if now - session.last_seen >= LAST_SEEN_RESOLUTION {
+            self.sessions.touch(&token_hash, now).await?;
+            session.last_seen = now;
+        }
+        Ok(Some(session))
     }
 
-    /// Purge sessions older than [`SESSION_TTL`].
-    pub async fn cleanup_sessions(&self) {
-        let mut map = self.session_store.write().await;
-        map.retain(|_, session| session_is_valid(session));
+    /// Issue an API token acting as `owner` with `scopes` and return the raw
+    /// bearer token.
+    pub(crate) async fn create_api_token(
+        &self,
+        owner: &AuthUser,
+        name: &str,
+        scopes: Permissions,
+    ) -> Result<String, Error> {
+        let token = generate_token();
+        let api_token = ApiToken {
+            name: name.to_owned(),
+            user_id: owner.user_id,
+            username: owner.username.clone(),
+            role: owner.role.clone(),
+            scopes,
+            created_at: OffsetDateTime::now_utc(),
+            last_used_at: None,
+        };
+        self.api_tokens.insert(&hash_token(&token), &api_token).await?;
+        Ok(token)
+    }
+
+    /// Resolve a bearer token to the user it acts as and record the use.
+    /// The user's permissions are the token's scopes, limited to what their
+    /// role allows now. Returns `None` for unknown or revoked tokens and for
+    /// tokens of disabled accounts.
+    pub async fn api_token_user(&self, token: &str) -> Result<Option<AuthUser>, Error> {
+        let token_hash = hash_token(token);
+        let Some(api_token) = self.api_tokens.get(&token_hash).await? else {
+            return Ok(None);
+        };
+        let now = OffsetDateTime::now_utc();
+        if api_token
+            .last_used_at
+            .is_none_or(|t| now - t >= LAST_SEEN_RESOLUTION)
+        {
+            self.api_tokens.touch(&token_hash, now).await?;
+        }
+        let permissions = self
+            .config
+            .permissions(&api_token.role)
+            .intersection(&api_token.scopes)
+            .copied()
+            .collect();
+        Ok(Some(AuthUser {
+            user_id: api_token.user_id,
+            username: api_token.username,
+            role: api_token.role,
+            permissions,
+            api_token: Some(api_token.name),
+            csrf_token: String::new(),
+        }))
+    }
+
+    /// Retrieve session data for a cookie value. Returns `None` if missing, expired,
+    /// or the store is unreachable.
+    pub async fn get_session(&self, token: &str) -> Option<AuthUserInfo> {
+        match self.session(token).await {
+            Ok(session) => session.map(|s| AuthUserInfo {
+                permissions: self.config.permissions(&s.role),
+                username: s.username,
+                role: s.role,
+                csrf_token: crate::csrf::token_for(token),
+            }),
+            Err(e) => {
+                tracing::warn!(error = %e, "session lookup failed");
+                None
+            }
+        }
+    }
+
+    /// Write an audit event. Failures are logged, never returned, so auditing
+    /// can't break the action being audited.
+    pub async fn audit(&self, event: AuditEvent) {
+        if let Err(e) = self.audit_log.record(&event).await {
+            tracing::warn!(error = %e, action = %event.action, "failed to record audit event");
+        }
+    }
+
+    /// Store the state of a ceremony until its "finish" request. Fails with
+    /// [`Error::RateLimited`] if too many of `kind` are already pending.
+    async fn stash_challenge<T: Serialize>(
+        &self,
+        kind: ChallengeKind,
+        key: &str,
+        state: &T,
+    ) -> Result<(), Error> {
+        let now = OffsetDateTime::now_utc();
+        let pending = self.challenges.pending(kind, now).await?;
+        self.rate_limits
+            .ensure_capacity(pending.count, pending.oldest_age, kind.ttl())?;
+        let state = serde_json::to_value(state)
+            .map_err(|e| Error::WebAuthn(format!("failed to serialize challenge: {e}")))?;
+        self.challenges.insert(kind, key, &state, now).await
+    }
+
+    /// Remove and return a ceremony's state, or `None` if there is none or
+    /// it has expired.
+    async fn take_challenge<T: serde::de::DeserializeOwned>(
+        &self,
+        kind: ChallengeKind,
+        key: &str,
+    ) -> Result<Option<T>, Error> {
+        let Some(state) = self
+            .challenges
+            .take(kind, key, OffsetDateTime::now_utc())
+            .await?
+        else {
+            return Ok(None);
+        };
+        serde_json::from_value(state)
+            .map(Some)
+            .map_err(|e| Error::Database(format!("failed to deserialize challenge: {e}")))
+    }
+
+    /// Returns `None` if no user with this UUID exists.
+    pub(crate) async fn load_passkeys_by_id(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Option<(String, Vec<Passkey>)>, Error> {
+        self.users.load_passkeys_by_id(user_id).await
     }
-}
 
-// ─── Session validity ──────────────────────────────────────────────────────
+    /// Delete sessions past their idle or absolute lifetime from the store.
+    pub async fn cleanup_sessions(&self) -> Result<u64, Error> {
+        self.sessions
+            .purge_expired(self.config.session_ttl(), OffsetDateTime::now_utc())
+            .await
+    }
+}
 
-fn session_is_valid(session: &SessionData) -> bool {
-    session.created_at.elapsed() <= SESSION_TTL
+/// Background task: periodically delete expired sessions and challenges
+/// from their stores.
+pub async fn run_session_sweep_task(auth: Arc<AuthState>) {
+    let mut interval = tokio::time::interval(SESSION_SWEEP_INTERVAL);
+    loop {
+        let _ = interval.tick().await;
+        match auth.cleanup_sessions().await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!(n, "purged expired sessions"),
+            Err(e) => tracing::warn!(error = %e, "session sweep failed"),
+        }
+        match auth.challenges.purge_expired(OffsetDateTime::now_utc()).await {
+            Ok(0) => {}
+            Ok(n) => tracing::debug!(n, "purged expired challenges"),
+            Err(e) => tracing::warn!(error = %e, "challenge sweep failed"),
+        }
+    }
 }
 
 // ─── Extractors ────────────────────────────────────────────────────────────
 
 const SESSION_COOKIE: &str = "green_session";
 
-fn session_token_from_parts(parts: &Parts) -> Option<String> {
-    let jar = CookieJar::from_headers(&parts.headers);
+/// The raw session token from the request's cookies, if any.
+pub fn session_token(jar: &CookieJar) -> Option<String> {
     jar.get(SESSION_COOKIE).map(|c| c.value().to_owned())
 }
 
+fn session_token_from_parts(parts: &Parts) -> Option<String> {
+    session_token(&CookieJar::from_headers(&parts.headers))
+}
+
+/// The token from an `Authorization: Bearer` header, if any.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// The request's `User-Agent` header, recorded on new sessions.
+fn user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned)
+}
+
 /// Resolves to an authenticated user, or redirects to `/auth/login`.
+///
+/// Requests with an `Authorization: Bearer` header are authenticated by API
+/// token instead of the session cookie; an invalid token gets a 401.
+#[derive(Debug)]
 pub struct AuthUser {
-    #[allow(dead_code)]
     pub user_id: Uuid,
     pub username: String,
     pub role: Role,
+    /// What `role` is allowed to do under the current config, narrowed to the
+    /// token's scopes for API token requests.
+    pub permissions: Permissions,
+    /// Name of the API token that authenticated the request, or `None` for a
+    /// browser session.
+    pub api_token: Option<String>,
+    /// CSRF token bound to the session cookie. Empty for API token requests,
+    /// which are exempt from CSRF checks.
+    pub csrf_token: String,
+}
+
+impl AuthUser {
+    /// The user as shown in page templates.
+    pub fn info(&self) -> AuthUserInfo {
+        AuthUserInfo {
+            username: self.username.clone(),
+            role: self.role.clone(),
+            permissions: self.permissions.clone(),
+            csrf_token: self.csrf_token.clone(),
+        }
+    }
 }
 
 impl FromRequestParts<ServerState> for AuthUser {
@@ -316,6 +835,14 @@ impl FromRequestParts<ServerState> for AuthUser {
             .as_ref()
             .ok_or_else(|| Redirect::to("/").into_response())?;
 
+        if let Some(token) = bearer_token(&parts.headers) {
+            return auth
+                .api_token_user(token)
+                .await
+                .map_err(IntoResponse::into_response)?
+                .ok_or_else(|| Error::Unauthorized.into_response());
+        }
+
         let next = parts
             .uri
             .path_and_query()
@@ -327,23 +854,48 @@ impl FromRequestParts<ServerState> for AuthUser {
         let token = session_token_from_parts(parts)
             .ok_or_else(|| Redirect::to(&login_url).into_response())?;
 
-        let store = auth.session_store.read().await;
-        let session = store
-            .get(&token)
-            .filter(|s| session_is_valid(s))
+        let session = auth
+            .session(&token)
+            .await
+            .map_err(IntoResponse::into_response)?
             .ok_or_else(|| Redirect::to(&login_url).into_response())?;
 
         Ok(AuthUser {
+            permissions: auth.config.permissions(&session.role),
             user_id: session.user_id,
-            username: session.username.clone(),
-            role: session.role.clone(),
+            username: session.username,
+            role: session.role,
+            api_token: None,
+            csrf_token: crate::csrf::token_for(&token),
         })
     }
 }
 
-/// Resolves only if the authenticated user has the GM role.
+/// Resolves only for a browser session, never an API token. Used for account
+/// and admin actions, so a leaked token can't add passkeys or issue more tokens.
+/// Unauthenticated requests are redirected to `/auth/login` (same as `AuthUser`).
+/// API token requests get a 403.
+pub struct SessionUser(pub AuthUser);
+
+impl FromRequestParts<ServerState> for SessionUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        if user.api_token.is_some() {
+            return Err(Error::Forbidden.into_response());
+        }
+        Ok(SessionUser(user))
+    }
+}
+
+/// Resolves only if the authenticated user has the GM role and is using a
+/// browser session.
 /// Unauthenticated requests are redirected to `/auth/login` (same as `AuthUser`).
-/// Authenticated non-GM requests get a 403.
+/// Authenticated non-GM requests and API token requests get a 403.
 pub struct GmUser(pub AuthUser);
 
 impl FromRequestParts<ServerState> for GmUser {
@@ -353,7 +905,7 @@ impl FromRequestParts<ServerState> for GmUser {
         parts: &mut Parts,
         state: &ServerState,
     ) -> Result<Self, Self::Rejection> {
-        let user = AuthUser::from_request_parts(parts, state).await?; // propagates the /auth/login redirect if unauthenticated
+        let SessionUser(user) = SessionUser::from_request_parts(parts, state).await?; // propagates the /auth/login redirect if unauthenticated
         if user.role != Role::Gm {
             return Err(Error::Forbidden.into_response());
         }
@@ -384,13 +936,18 @@ impl FromRequestParts<ServerState> for MaybeAuthUser {
 
 // ─── Cookie helpers ────────────────────────────────────────────────────────
 
-pub fn make_session_cookie(token: String) -> Cookie<'static> {
-    Cookie::build((SESSION_COOKIE, token))
+/// Build the session cookie. Regular sessions use a browser-session cookie;
+/// kiosk sessions pass `max_age` so the cookie survives browser restarts.
+pub fn make_session_cookie(token: String, max_age: Option<Duration>) -> Cookie<'static> {
+    let mut cookie = Cookie::build((SESSION_COOKIE, token))
         .http_only(true)
         .secure(true)
         .same_site(SameSite::Strict)
-        .path("/")
-        .build()
+        .path("/");
+    if let Some(max_age) = max_age.and_then(|d| time::Duration::try_from(d).ok()) {
+        cookie = cookie.max_age(max_age);
+    }
+    cookie.build()
 }
 
 pub fn clear_session_cookie() -> Cookie<'static> {
@@ -408,12 +965,16 @@ pub fn clear_session_cookie() -> Cookie<'static> {
 #[derive(Debug, Deserialize)]
 pub struct StartRegRequest {
     pub username: String,
+    /// Invite token from the registration link, required in `invite` mode.
+    #[serde(default)]
+    pub invite: Option<String>,
 }
 
 // ─── Handlers ─────────────────────────────────────────────────────────────
 
 use askama::Template;
 use axum::{Form, Json, extract::{Query, State}, response::Html};
+use crate::csrf::CsrfToken;
 use serde_json::Value;
 
 #[derive(Debug, Deserialize, Default)]
@@ -426,6 +987,7 @@ pub struct LoginQuery {
 pub struct LoginPage {
     pub version: &'static str,
     pub auth_user: Option<AuthUserInfo>,
+    pub csrf_token: String,
     pub next_url: String,
     pub nav_links: Arc<[NavLink]>,
 }
@@ -435,17 +997,32 @@ pub struct LoginPage {
 pub struct RegisterPage {
     pub version: &'static str,
     pub auth_user: Option<AuthUserInfo>,
+    pub csrf_token: String,
     pub nav_links: Arc<[NavLink]>,
+    pub mode: RegistrationMode,
+    /// Token of a usable invite from the link, passed back on registration.
+    pub invite: Option<String>,
+    /// Username the invite is restricted to, prefilled and locked in the form.
+    pub invite_username: Option<String>,
+    /// The link carried an invite that is unknown, used or expired.
+    pub invite_invalid: bool,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct RegisterQuery {
+    pub invite: Option<String>,
 }
 
 pub async fn login_page(
     State(s): State<ServerState>,
+    CsrfToken(csrf_token): CsrfToken,
     Query(q): Query<LoginQuery>,
 ) -> Result<Html<String>, Error> {
     Ok(Html(
         LoginPage {
             version: crate::VERSION,
             auth_user: None,
+            csrf_token,
             next_url: q
                 .next
                 .filter(|n| n.starts_with('/') && !n.starts_with("//"))
@@ -456,12 +1033,35 @@ pub async fn login_page(
     ))
 }
 
-pub async fn register_page(State(s): State<ServerState>) -> Result<Html<String>, Error> {
+pub async fn register_page(
+    State(s): State<ServerState>,
+    CsrfToken(csrf_token): CsrfToken,
+    Query(q): Query<RegisterQuery>,
+) -> Result<Html<String>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+
+    let mut invite_invalid = false;
+    let (invite, invite_username) = match q.invite {
+        Some(token) => match auth.usable_invite(&token).await? {
+            Some(invite) => (Some(token), invite.username),
+            None => {
+                invite_invalid = true;
+                (None, None)
+            }
+        },
+        None => (None, None),
+    };
+
     Ok(Html(
         RegisterPage {
             version: crate::VERSION,
             auth_user: None,
+            csrf_token,
             nav_links: s.nav_links.clone(),
+            mode: auth.config.registration,
+            invite,
+            invite_username,
+            invite_invalid,
         }
         .render()?,
     ))
@@ -472,24 +1072,31 @@ pub async fn start_registration(
     Json(req): Json<StartRegRequest>,
 ) -> Result<Json<Value>, Error> {
     let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+    auth.rate_limits.username.check(&req.username)?;
 
-    let user_id = auth
-        .load_passkeys(&req.username)
-        .await?
-        .map(|(id, _)| id)
-        .unwrap_or_else(Uuid::new_v4);
+    let invite = auth
+        .check_registration(&req.username, req.invite.as_deref())
+        .await?;
 
-    auth.cleanup_reg_states().await;
+    // Registration only creates accounts; passkeys are added to an existing
+    // account from its own session via `start_add_passkey`.
+    if auth.load_passkeys(&req.username).await?.is_some() {
+        return Err(Error::UsernameTaken);
+    }
+    let user_id = Uuid::new_v4();
 
     let (ccr, reg_state) = auth
         .webauthn
         .start_passkey_registration(user_id, &req.username, &req.username, None)
         .map_err(|e| Error::WebAuthn(format!("{e:?}")))?;
 
-    {
-        let mut states = auth.reg_states.lock().await;
-        let _ = states.insert(req.username.clone(), (reg_state, Instant::now()));
-    }
+    let pending = PendingRegistration {
+        state: reg_state,
+        user_id,
+        kind: RegistrationKind::NewAccount { invite },
+    };
+    auth.stash_challenge(ChallengeKind::Registration, &req.username, &pending)
+        .await?;
 
     Ok(Json(
         serde_json::to_value(ccr).map_err(|e| Error::WebAuthn(e.to_string()))?,
@@ -498,6 +1105,8 @@ pub async fn start_registration(
 
 pub async fn finish_registration(
     State(s): State<ServerState>,
+    headers: HeaderMap,
+    meta: RequestMeta,
     Json(body): Json<Value>,
 ) -> Result<(CookieJar, Redirect), Error> {
     let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
@@ -508,12 +1117,29 @@ pub async fn finish_registration(
         .ok_or_else(|| Error::WebAuthn("missing username in finish_registration body".into()))?
         .to_owned();
 
-    let reg_state = {
-        let mut states = auth.reg_states.lock().await;
-        states
-            .remove(&username)
-            .ok_or_else(|| Error::WebAuthn("no pending registration for that username".into()))?
-            .0
+    let result = complete_registration(auth, username.clone(), body, &headers).await;
+    auth.audit(
+        meta.event(AuditAction::Register, AuditOutcome::from(&result))
+            .actor(&username),
+    )
+    .await;
+    result
+}
+
+/// The part of [`finish_registration`] after the username is known: verify
+/// the credential, create the account and log it in.
+async fn complete_registration(
+    auth: &AuthState,
+    username: String,
+    body: Value,
+    headers: &HeaderMap,
+) -> Result<(CookieJar, Redirect), Error> {
+    let pending: PendingRegistration = auth
+        .take_challenge(ChallengeKind::Registration, &username)
+        .await?
+        .ok_or_else(|| Error::WebAuthn("no pending registration for that username".into()))?;
+    let RegistrationKind::NewAccount { invite } = pending.kind else {
+        return Err(Error::WebAuthn("no pending registration for that username".into()));
     };
 
     let credential_json: Value = body
@@ -526,58 +1152,130 @@ pub async fn finish_registration(
 
     let passkey = auth
         .webauthn
-        .finish_passkey_registration(&reg_public_key, &reg_state)
+        .finish_passkey_registration(&reg_public_key, &pending.state)
         .map_err(|e| Error::WebAuthn(format!("{e:?}")))?;
 
-    let (user_id, mut passkeys) = auth
-        .load_passkeys(&username)
-        .await?
-        .unwrap_or_else(|| (Uuid::new_v4(), vec![]));
-
-    passkeys.push(passkey);
-    let role = auth.role_for(&username);
-    auth.save_passkeys(user_id, &username, &username, &role, &passkeys)
-        .await?;
+    // Checked before redeeming so a taken name doesn't burn the invite;
+    // `create_user` refuses existing names again atomically.
+    if auth.load_passkeys(&username).await?.is_some() {
+        return Err(Error::UsernameTaken);
+    }
+    let role = match &invite {
+        Some(invite_hash) => {
+            let invite = auth
+                .invites
+                .redeem(invite_hash, OffsetDateTime::now_utc())
+                .await?
+                .filter(|invite| invite.allows(&username))
+                .ok_or(Error::InvalidInvite)?;
+            tracing::info!(username, created_by = %invite.created_by, "invite redeemed");
+            invite.role
+        }
+        None => auth.seed_role(&username),
+    };
+    let user_id = pending.user_id;
+    let user = auth.create_user(user_id, &username, role, &passkey).await?;
+    auth.record_passkey_created(user_id, passkey.cred_id()).await?;
+    let role = user.role;
 
-    tracing::info!(username, "user registered passkey");
+    tracing::info!(username, "user created account");
 
     // Log the user in immediately after registration.
-    auth.cleanup_sessions().await;
-    let token = Uuid::new_v4().to_string();
-    {
-        let mut sessions = auth.session_store.write().await;
-        let _ = sessions.insert(
-            token.clone(),
-            SessionData {
-                user_id,
-                username: username.clone(),
-                role,
-                created_at: Instant::now(),
-            },
-        );
-    }
+    let token = auth
+        .create_session(user_id, &username, role, user_agent(headers), false)
+        .await?;
 
-    let jar = CookieJar::new().add(make_session_cookie(token));
+    let jar = CookieJar::new().add(make_session_cookie(token, None));
     Ok((jar, Redirect::to("/")))
 }
 
-// ─── Discoverable / conditional-UI authentication ─────────────────────────────
-
-#[derive(Debug, Serialize)]
-pub struct DiscoverableChallengeResponse {
-    #[serde(rename = "publicKey")]
-    pub public_key: Value,
-    pub challenge_id: String,
+#[derive(Debug, Deserialize)]
+pub struct FinishAddPasskeyRequest {
+    pub credential: Value,
 }
 
-/// Start a discoverable (conditional-UI) authentication.
-/// No username is required; the browser presents passkeys via its autofill UI.
-pub async fn start_discoverable_auth(
+/// Start adding a passkey to the signed-in user's own account, e.g. from a
+/// second device. The registration mode doesn't apply here.
+pub async fn start_add_passkey(
+    SessionUser(user): SessionUser,
     State(s): State<ServerState>,
-) -> Result<Json<DiscoverableChallengeResponse>, Error> {
+) -> Result<Json<Value>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
+
+    let (username, passkeys) = auth
+        .load_passkeys_by_id(user.user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let exclude = passkeys.iter().map(|pk| pk.cred_id().clone()).collect();
+
+    let (ccr, reg_state) = auth
+        .webauthn
+        .start_passkey_registration(user.user_id, &username, &username, Some(exclude))
+        .map_err(|e| Error::WebAuthn(format!("{e:?}")))?;
+
+    let pending = PendingRegistration {
+        state: reg_state,
+        user_id: user.user_id,
+        kind: RegistrationKind::AddPasskey,
+    };
+    auth.stash_challenge(ChallengeKind::AddPasskey, &user.user_id.to_string(), &pending)
+        .await?;
+
+    Ok(Json(
+        serde_json::to_value(ccr).map_err(|e| Error::WebAuthn(e.to_string()))?,
+    ))
+}
+
+/// Finish adding a passkey started by [`start_add_passkey`] in the same account.
+pub async fn finish_add_passkey(
+    SessionUser(user): SessionUser,
+    State(s): State<ServerState>,
+    Json(req): Json<FinishAddPasskeyRequest>,
+) -> Result<StatusCode, Error> {
     let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
 
-    auth.cleanup_discoverable_states().await;
+    let pending = auth
+        .take_challenge::<PendingRegistration>(ChallengeKind::AddPasskey, &user.user_id.to_string())
+        .await?
+        .filter(|p| p.kind == RegistrationKind::AddPasskey && p.user_id == user.user_id)
+        .ok_or_else(|| Error::WebAuthn("no pending passkey registration for this account".into()))?;
+
+    let reg_public_key = serde_json::from_value(req.credential)
+        .map_err(|e| Error::WebAuthn(format!("invalid credential: {e}")))?;
+
+    let passkey = auth
+        .webauthn
+        .finish_passkey_registration(&reg_public_key, &pending.state)
+        .map_err(|e| Error::WebAuthn(format!("{e:?}")))?;
+
+    let (_, mut passkeys) = auth
+        .load_passkeys_by_id(user.user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let cred_id = passkey.cred_id().to_vec();
+    passkeys.push(passkey);
+    let _ = auth.save_passkeys(user.user_id, &passkeys).await?;
+    auth.record_passkey_created(user.user_id, &cred_id).await?;
+
+    tracing::info!(username = %user.username, "user added passkey");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ─── Discoverable / conditional-UI authentication ─────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct DiscoverableChallengeResponse {
+    #[serde(rename = "publicKey")]
+    pub public_key: Value,
+    pub challenge_id: String,
+}
+
+/// Start a discoverable (conditional-UI) authentication.
+/// No username is required; the browser presents passkeys via its autofill UI.
+pub async fn start_discoverable_auth(
+    State(s): State<ServerState>,
+) -> Result<Json<DiscoverableChallengeResponse>, Error> {
+    let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
 
     let (rcr, state) = auth
         .webauthn
@@ -585,10 +1283,8 @@ pub async fn start_discoverable_auth(
         .map_err(|e| Error::WebAuthn(format!("{e:?}")))?;
 
     let challenge_id = Uuid::new_v4().to_string();
-    {
-        let mut states = auth.discoverable_states.lock().await;
-        let _ = states.insert(challenge_id.clone(), (state, Instant::now()));
-    }
+    auth.stash_challenge(ChallengeKind::Authentication, &challenge_id, &state)
+        .await?;
 
     let public_key = serde_json::to_value(&rcr)
         .map_err(|e| Error::WebAuthn(e.to_string()))?
@@ -606,36 +1302,42 @@ pub async fn start_discoverable_auth(
 pub struct FinishDiscoverableRequest {
     pub challenge_id: String,
     pub credential: Value,
+    /// Request a long-lived "remember this device" session.
+    #[serde(default)]
+    pub kiosk: bool,
 }
 
 /// Finish a discoverable (conditional-UI) authentication.
 /// Looks up the user by the `userHandle` embedded in the credential.
 pub async fn finish_discoverable_auth(
     State(s): State<ServerState>,
+    headers: HeaderMap,
+    meta: RequestMeta,
     jar: CookieJar,
     Json(req): Json<FinishDiscoverableRequest>,
 ) -> Result<(CookieJar, Redirect), Error> {
     let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
 
-    let disc_state = {
-        let mut states = auth.discoverable_states.lock().await;
-        states
-            .remove(&req.challenge_id)
-            .ok_or_else(|| Error::WebAuthn("no pending discoverable challenge".into()))?
-            .0
-    };
+    let disc_state: DiscoverableAuthentication = auth
+        .take_challenge(ChallengeKind::Authentication, &req.challenge_id)
+        .await?
+        .ok_or_else(|| Error::WebAuthn("no pending discoverable challenge".into()))?;
 
     let auth_result_raw: webauthn_rs::prelude::PublicKeyCredential =
         serde_json::from_value(req.credential)
             .map_err(|e| Error::WebAuthn(format!("invalid credential: {e}")))?;
 
-    let (user_id, _cred_id) = auth
+    let (user_id, _cred_id) = match auth
         .webauthn
         .identify_discoverable_authentication(&auth_result_raw)
-        .map_err(|e| {
+    {
+        Ok(identified) => identified,
+        Err(e) => {
             tracing::warn!("discoverable auth identify failed");
-            Error::WebAuthn(format!("{e:?}"))
-        })?;
+            auth.audit(meta.event(AuditAction::Login, AuditOutcome::Failure)).await;
+            return Err(Error::WebAuthn(format!("{e:?}")));
+        }
+    };
 
     let (username, mut passkeys) = auth
         .load_passkeys_by_id(user_id)
@@ -645,57 +1347,67 @@ pub async fn finish_discoverable_auth(
     let discoverable_creds: Vec<DiscoverableKey> =
         passkeys.iter().map(DiscoverableKey::from).collect();
 
-    let auth_result = auth
-        .webauthn
-        .finish_discoverable_authentication(&auth_result_raw, disc_state, &discoverable_creds)
-        .map_err(|e| {
+    let auth_result = match auth.webauthn.finish_discoverable_authentication(
+        &auth_result_raw,
+        disc_state,
+        &discoverable_creds,
+    ) {
+        Ok(auth_result) => auth_result,
+        Err(e) => {
             tracing::warn!(username, "failed discoverable auth attempt");
-            Error::WebAuthn(format!("{e:?}"))
-        })?;
+            auth.audit(meta.event(AuditAction::Login, AuditOutcome::Failure).actor(&username))
+                .await;
+            return Err(Error::WebAuthn(format!("{e:?}")));
+        }
+    };
 
     for pk in &mut passkeys {
         let _ = pk.update_credential(&auth_result);
     }
 
-    let role = auth.role_for(&username);
-    auth.save_passkeys(user_id, &username, &username, &role, &passkeys)
-        .await?;
+    let user = auth.save_passkeys(user_id, &passkeys).await?;
+    if user.disabled {
+        tracing::warn!(username, "disabled user attempted to log in");
+        auth.audit(meta.event(AuditAction::Login, AuditOutcome::Failure).actor(&username))
+            .await;
+        return Err(Error::AccountDisabled);
+    }
+    auth.record_passkey_used(user_id, auth_result.cred_id()).await?;
+    let role = user.role;
 
-    tracing::info!(username, ?role, "user logged in via discoverable auth");
+    tracing::info!(username, ?role, kiosk = req.kiosk, "user logged in via discoverable auth");
+    auth.audit(meta.event(AuditAction::Login, AuditOutcome::Success).actor(&username))
+        .await;
 
-    auth.cleanup_sessions().await;
-    let token = Uuid::new_v4().to_string();
-    {
-        let mut sessions = auth.session_store.write().await;
-        let _ = sessions.insert(
-            token.clone(),
-            SessionData {
-                user_id,
-                username: username.clone(),
-                role,
-                created_at: Instant::now(),
-            },
-        );
-    }
+    let token = auth
+        .create_session(user_id, &username, role, user_agent(&headers), req.kiosk)
+        .await?;
 
-    let jar = jar.add(make_session_cookie(token));
+    let max_age = req.kiosk.then(|| auth.config.session_ttl().kiosk);
+    let jar = jar.add(make_session_cookie(token, max_age));
     Ok((jar, Redirect::to("/")))
 }
 
-pub async fn logout(State(s): State<ServerState>, jar: CookieJar) -> (CookieJar, Redirect) {
+pub async fn logout(
+    State(s): State<ServerState>,
+    meta: RequestMeta,
+    jar: CookieJar,
+) -> (CookieJar, Redirect) {
     if let Some(auth) = s.auth_state.as_ref()
         && let Some(token) = jar.get(SESSION_COOKIE).map(|c| c.value().to_owned())
     {
-        let username = {
-            let store = auth.session_store.read().await;
-            store.get(&token).map(|s| s.username.clone())
-        };
-        {
-            let mut store = auth.session_store.write().await;
-            let _ = store.remove(&token);
+        let username = auth.get_session(&token).await.map(|s| s.username);
+        let removed = auth.sessions.remove(&hash_token(&token)).await;
+        if let Err(e) = &removed {
+            tracing::warn!(error = %e, "failed to delete session on logout");
         }
         if let Some(username) = username {
             tracing::info!(username, "user logged out");
+            auth.audit(
+                meta.event(AuditAction::Logout, AuditOutcome::from(&removed))
+                    .actor(&username),
+            )
+            .await;
         }
     }
     let jar = jar.add(clear_session_cookie());
@@ -727,23 +1439,37 @@ pub struct RecoveryQuery {
 pub struct RecoveryPage {
     pub version: &'static str,
     pub auth_user: Option<AuthUserInfo>,
+    pub csrf_token: String,
     pub sent: bool,
     pub username: String,
     pub error: Option<String>,
+    /// Where to look for a one-time code, e.g. `your ntfy app or your email`.
+    /// Empty when only backup codes can be used.
+    pub channels: String,
     pub nav_links: Arc<[NavLink]>,
 }
 
 pub async fn recover_page(
     State(s): State<ServerState>,
+    CsrfToken(csrf_token): CsrfToken,
     Query(q): Query<RecoveryQuery>,
 ) -> Result<Html<String>, Error> {
     Ok(Html(
         RecoveryPage {
             version: crate::VERSION,
             auth_user: None,
+            csrf_token,
             sent: q.sent.unwrap_or(false),
             username: q.username.unwrap_or_default(),
             error: q.error,
+            channels: s
+                .auth_state
+                .as_ref()
+                .map(|auth| {
+                    let names: Vec<_> = auth.recovery_channels.iter().map(|c| c.name()).collect();
+                    names.join(" or ")
+                })
+                .unwrap_or_default(),
             nav_links: s.nav_links.clone(),
         }
         .render()?,
@@ -767,54 +1493,40 @@ fn percent_encode(s: &str) -> String {
     out
 }
 
-fn generate_otc() -> String {
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
-    // 252 = 7 × 36 — reject bytes ≥ 252 to avoid modulo bias
-    let mut code = String::with_capacity(6);
-    while code.len() < 6 {
-        for &b in Uuid::new_v4().into_bytes().iter() {
-            if code.len() == 6 {
-                break;
-            }
-            if b < 252 {
-                code.push(CHARSET[(b as usize) % 36] as char);
-            }
-        }
-    }
-    code
-}
-
 pub async fn start_recovery(
     State(s): State<ServerState>,
+    meta: RequestMeta,
     Form(req): Form<StartRecoveryRequest>,
 ) -> Result<Redirect, Error> {
     let auth = s.auth_state.as_ref().ok_or(Error::NotFound)?;
-
-    // Check user exists but don't reveal the result (anti-enumeration)
-    let user_exists = auth
-        .load_passkeys(&req.username)
-        .await
-        .is_ok_and(|r| r.is_some());
-
-    if user_exists {
+    // Applies whether or not the user exists, so it doesn't reveal either.
+    let limited = auth.rate_limits.username.check(&req.username);
+    auth.audit(
+        meta.event(AuditAction::RecoveryRequested, AuditOutcome::from(&limited))
+            .actor(&req.username),
+    )
+    .await;
+    limited?;
+
+    if auth.recovery_channels.is_empty() {
+        // Nothing to send; the verify form still accepts backup codes.
+        tracing::debug!(username = %req.username, "no recovery channels configured");
+    } else if let Some(user) = recovery_user(auth, &req.username).await {
+        // Whether or not the user exists, the redirect below is the same
+        // (anti-enumeration).
         let code = generate_otc();
-        auth.cleanup_otc_store().await;
-        let _ = auth.otc_store
-            .write()
-            .await
-            .insert(req.username.clone(), (code.clone(), Instant::now()));
-
-        if let Some(ref ntfy_url) = auth.config.ntfy_url
-            && let Err(e) = auth
-                .http_client
-                .post(ntfy_url)
-                .header("Title", "green recovery")
-                .header("Priority", "high")
-                .body(code)
-                .send()
-                .await
-        {
-            tracing::error!(error = %e, username = %req.username, "failed to send recovery notification");
+        auth.stash_challenge(ChallengeKind::RecoveryCode, &req.username, &hash_otc(&code))
+            .await?;
+
+        for channel in &auth.recovery_channels {
+            if let Err(e) = channel.deliver(&user, &code).await {
+                tracing::error!(
+                    error = %e,
+                    username = %req.username,
+                    channel = channel.name(),
+                    "failed to send recovery code"
+                );
+            }
         }
     }
 
@@ -824,6 +1536,8 @@ pub async fn start_recovery(
 
 pub async fn verify_recovery(
     State(s): State<ServerState>,
+    headers: HeaderMap,
+    meta: RequestMeta,
     jar: CookieJar,
     Form(req): Form<VerifyRecoveryRequest>,
 ) -> Response {
@@ -836,43 +1550,79 @@ pub async fn verify_recovery(
         return Redirect::to("/").into_response();
     };
 
+    let result = recovery_session(auth, &req, &headers).await;
+    auth.audit(
+        meta.event(AuditAction::Recovery, AuditOutcome::from(&result))
+            .actor(&req.username),
+    )
+    .await;
+
+    match result {
+        Ok(token) => {
+            tracing::info!(username = %req.username, "user recovered account via OTC");
+            let jar = jar.add(make_session_cookie(token, None));
+            (jar, Redirect::to("/")).into_response()
+        }
+        Err(None) => Redirect::to(&error_url).into_response(),
+        Err(Some(e)) => e.into_response(),
+    }
+}
+
+/// Hash of a one-time recovery code as kept in the challenge store. Case and
+/// surrounding whitespace are ignored.
+fn hash_otc(code: &str) -> String {
+    hash_token(&code.trim().to_ascii_uppercase())
+}
+
+/// The enabled account named `username`, or `None` if there is none (or it
+/// couldn't be loaded).
+async fn recovery_user(auth: &AuthState, username: &str) -> Option<UserRecord> {
+    let (user_id, _) = auth.load_passkeys(username).await.ok()??;
+    auth.load_user(user_id)
+        .await
+        .ok()?
+        .filter(|user| !user.disabled)
+}
+
+/// Check a recovery code — the pending one-time code or one of the user's
+/// backup codes — and, if it's valid, replace all of the user's sessions
+/// with a new one, returning its token. `Err(None)` means the code was
+/// wrong, expired or for an unknown user.
+async fn recovery_session(
+    auth: &AuthState,
+    req: &VerifyRecoveryRequest,
+    headers: &HeaderMap,
+) -> Result<String, Option<Error>> {
+    auth.rate_limits.username.check(&req.username)?;
+
     // Atomically remove the OTC — prevents any race between check and delete.
     // The OTC is consumed whether the code matches or not (no brute-force retries).
-    let removed = auth.otc_store.write().await.remove(&req.username);
-    let Some((stored_code, created_at)) = removed else {
-        return Redirect::to(&error_url).into_response();
-    };
-
-    if created_at.elapsed() > OTC_TTL || req.code != stored_code {
-        return Redirect::to(&error_url).into_response();
-    }
+    let stored: Option<String> = auth
+        .take_challenge(ChallengeKind::RecoveryCode, &req.username)
+        .await?;
+    let otc_matches = stored.is_some_and(|hash| hash == hash_otc(&req.code));
 
     let Ok(Some((user_id, _))) = auth.load_passkeys(&req.username).await else {
-        return Redirect::to(&error_url).into_response();
+        return Err(None);
     };
-
-    let role = auth.role_for(&req.username);
-    auth.cleanup_sessions().await;
-    let token = Uuid::new_v4().to_string();
+    if !otc_matches
+        && !auth
+            .backup_codes
+            .consume(user_id, &hash_backup_code(user_id, &req.code))
+            .await?
     {
-        let mut sessions = auth.session_store.write().await;
-        // Invalidate all existing sessions for this user before creating the recovery session.
-        sessions.retain(|_, data| data.username != req.username);
-        let _ = sessions.insert(
-            token.clone(),
-            SessionData {
-                user_id,
-                username: req.username.clone(),
-                role,
-                created_at: Instant::now(),
-            },
-        );
+        return Err(None);
     }
-
-    tracing::info!(username = %req.username, "user recovered account via OTC");
-
-    let jar = jar.add(make_session_cookie(token));
-    (jar, Redirect::to("/")).into_response()
+    let user = auth.load_user(user_id).await?.ok_or(None)?;
+    if user.disabled {
+        return Err(Some(Error::AccountDisabled));
+    }
+    let role = user.role;
+    // Invalidate all existing sessions for this user before creating the recovery session.
+    let _ = auth.sessions.remove_for_user(user_id).await?;
+    Ok(auth
+        .create_session(user_id, &req.username, role, user_agent(headers), false)
+        .await?)
 }
 
 // ─── Tests ────────────────────────────────────────────────────────────────────
@@ -892,15 +1642,21 @@ impl AuthState {
         let db = PgPool::connect_lazy("postgres://localhost/nonexistent")
             .map_err(|e| Error::AuthSetup(format!("connect_lazy: {e}")))?;
 
+        let rate_limits = Arc::new(RateLimits::new(&config.rate_limit));
+        let recovery_channels = crate::recovery::channels(&config)?;
         Ok(AuthState {
             webauthn: Arc::new(webauthn),
             config,
             db,
-            session_store: Arc::new(RwLock::new(HashMap::new())),
-            reg_states: Arc::new(Mutex::new(HashMap::new())),
-            discoverable_states: Arc::new(Mutex::new(HashMap::new())),
-            otc_store: Arc::new(RwLock::new(HashMap::new())),
-            http_client: reqwest::Client::new(),
+            sessions: Arc::new(crate::session::MemorySessionStore::default()),
+            invites: Arc::new(crate::invite::MemoryInviteStore::default()),
+            api_tokens: Arc::new(crate::api_token::MemoryApiTokenStore::default()),
+            audit_log: Arc::new(crate::audit::MemoryAuditStore::default()),
+            backup_codes: Arc::new(crate::recovery::MemoryBackupCodeStore::default()),
+            users: Arc::new(crate::user::MemoryUserStore::default()),
+            challenges: Arc::new(MemoryChallengeStore::default()),
+            rate_limits,
+            recovery_channels,
         })
     }
 }
@@ -914,65 +1670,53 @@ mod tests {
         response::Html,
         routing::get,
     };
-    use crate::ServerState;
-    use std::path::Path;
+    use crate::{ServerState, user::MemoryUserStore};
     use tower::ServiceExt;
 
     async fn state_with_auth() -> ServerState {
-        use crate::{
-            breaker::BreakerContent,
-            breaker_detail::{BreakerData, BreakerDetailStore, BreakerStore},
-            index::Index,
-            route::Routes,
-        };
+        state_with_registration(RegistrationMode::Open).await
+    }
 
-        let auth_config = AuthConfig {
-            rp_id: "localhost".to_string(),
-            rp_origin: "http://localhost".to_string(),
-            db_url: "postgres://localhost/nonexistent".to_string(),
-            gm_users: vec!["gm".to_string()],
-            ntfy_url: None,
-        };
-        let auth_state = AuthState::new_for_testing(auth_config).unwrap();
+    async fn state_with_registration(registration: RegistrationMode) -> ServerState {
+        state_with_config(AuthConfig {
+            registration,
+            ..test_config()
+        })
+        .await
+    }
 
-        let data = BreakerData {
-            todos: vec![],
-            slots: HashMap::new(),
-            couples: vec![],
-        };
-        let store = Arc::new(BreakerStore::from_data(data).unwrap());
-        let breaker_content = Arc::new(BreakerContent::new(store.as_ref()));
-        let breaker_detail_store: Arc<dyn BreakerDetailStore> = store;
-        let index = Index::new(Routes::default(), false, false, false, false, &Default::default(), None, Arc::new([])).await.unwrap();
-
-        ServerState {
-            certificate: Arc::from("fake-cert"),
-            breaker_content,
-            breaker_detail_store,
-            index,
-            tailscale_socket: Arc::from(Path::new("/tmp/fake.sock")),
-            notes_store: None,
-            auth_state: Some(Arc::new(auth_state)),
-            mqtt_state: None,
-            log_config: None,
-            systemd_config: None,
-            nav_links: Arc::new([]),
+    /// Replace the user store of a freshly built `state` with an in-memory one
+    /// holding `users`, and return it for inspection.
+    async fn seed_users(state: &mut ServerState, users: &[(&str, Role)]) -> Arc<MemoryUserStore> {
+        let store = Arc::new(MemoryUserStore::default());
+        for (username, role) in users {
+            let _ = store.insert(username, role.clone()).await;
+        }
+        let auth = Arc::get_mut(state.auth_state.as_mut().unwrap()).unwrap();
+        auth.users = store.clone();
+        store
+    }
+
+    fn test_config() -> AuthConfig {
+        AuthConfig {
+            gm_users: vec!["gm".to_string()],
+            roles: BTreeMap::from([(
+                "housemate".to_string(),
+                Permissions::from([Permission::BreakerRead, Permission::MqttRead]),
+            )]),
+            ..crate::test_support::auth_config()
         }
     }
 
+    async fn state_with_config(auth_config: AuthConfig) -> ServerState {
+        crate::test_support::server_state(auth_config).await
+    }
+
     async fn insert_session(state: &ServerState, username: &str, role: Role) -> String {
         let auth = state.auth_state.as_ref().unwrap();
-        let token = Uuid::new_v4().to_string();
-        let _ = auth.session_store.write().await.insert(
-            token.clone(),
-            SessionData {
-                user_id: Uuid::new_v4(),
-                username: username.to_string(),
-                role,
-                created_at: Instant::now(),
-            },
-        );
-        token
+        auth.create_session(Uuid::new_v4(), username, role, None, false)
+            .await
+            .unwrap()
     }
 
     async fn gm_only(_user: GmUser) -> Html<&'static str> {
@@ -1071,103 +1815,977 @@ mod tests {
         assert_eq!(res.status(), StatusCode::OK);
     }
 
-    // ── Recovery tests ────────────────────────────────────────────────────────
+    // ── permissions ─────────────────────────────────────────────────────────
 
-    fn recovery_router(state: ServerState) -> axum::Router {
+    async fn breaker_only(
+        _user: crate::permission::RequirePermission<crate::permission::BreakerRead>,
+    ) -> Html<&'static str> {
+        Html("ok")
+    }
+
+    async fn breaker_only_status(state: ServerState, token: Option<&str>) -> StatusCode {
+        let mut req = Request::builder().uri("/breaker-only");
+        if let Some(token) = token {
+            req = req.header("cookie", format!("green_session={token}"));
+        }
         axum::Router::new()
-            .route("/auth/recover/verify", axum::routing::post(verify_recovery))
+            .route("/breaker-only", get(breaker_only))
             .with_state(state)
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
     }
 
-    fn verify_request(username: &str, code: &str) -> Request<Body> {
-        Request::builder()
-            .method("POST")
-            .uri("/auth/recover/verify")
-            .header("content-type", "application/x-www-form-urlencoded")
-            .body(Body::from(format!("username={username}&code={code}")))
-            .unwrap()
+    #[tokio::test]
+    async fn require_permission_without_session_redirects_to_login() {
+        let state = state_with_auth().await;
+        assert_eq!(breaker_only_status(state, None).await, StatusCode::SEE_OTHER);
     }
 
-    async fn insert_otc(state: &ServerState, username: &str, code: &str) {
-        let _ = state
+    #[tokio::test]
+    async fn require_permission_forbids_role_without_it() {
+        let state = state_with_auth().await;
+        let token = insert_session(&state, "alice", Role::Player).await;
+        assert_eq!(breaker_only_status(state, Some(&token)).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn require_permission_allows_configured_role() {
+        let state = state_with_auth().await;
+        let token = insert_session(&state, "hal", Role::Custom("housemate".into())).await;
+        assert_eq!(breaker_only_status(state, Some(&token)).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn require_permission_allows_gm() {
+        let state = state_with_auth().await;
+        let token = insert_session(&state, "gm", Role::Gm).await;
+        assert_eq!(breaker_only_status(state, Some(&token)).await, StatusCode::OK);
+    }
+
+    #[test]
+    fn gm_has_every_permission() {
+        let config = test_config();
+        assert_eq!(
+            config.permissions(&Role::Gm),
+            Permission::iter().collect::<Permissions>()
+        );
+        assert!(config.permissions(&Role::Player).is_empty());
+        assert!(config.permissions(&Role::Custom("removed".into())).is_empty());
+        assert!(config
+            .permissions(&Role::Custom("housemate".into()))
+            .contains(&Permission::MqttRead));
+    }
+
+    #[test]
+    fn roles_round_trip_by_name() {
+        for name in ["Gm", "Player", "housemate"] {
+            assert_eq!(Role::from(name.to_string()).to_string(), name);
+        }
+        assert_eq!(Role::from("Gm".to_string()), Role::Gm);
+        assert_eq!(
+            serde_json::from_str::<Role>("\"housemate\"").unwrap(),
+            Role::Custom("housemate".into())
+        );
+    }
+
+    #[test]
+    fn only_configured_roles_can_be_assigned() {
+        let config = test_config();
+        assert_eq!(
+            config.assignable_roles(),
+            vec![Role::Gm, Role::Player, Role::Custom("housemate".into())]
+        );
+        assert!(config.check_role(Role::Player).is_ok());
+        assert!(matches!(
+            config.check_role(Role::Custom("wizard".into())),
+            Err(Error::UnknownRole(_))
+        ));
+    }
+
+    // ── API tokens ──────────────────────────────────────────────────────────
+
+    async fn insert_api_token(state: &ServerState, role: Role, scopes: &[Permission]) -> String {
+        let owner = AuthUser {
+            user_id: Uuid::new_v4(),
+            username: "owner".into(),
+            role,
+            permissions: Permissions::new(),
+            api_token: None,
+            csrf_token: String::new(),
+        };
+        state
             .auth_state
             .as_ref()
             .unwrap()
-            .otc_store
-            .write()
+            .create_api_token(&owner, "script", scopes.iter().copied().collect())
             .await
-            .insert(username.to_string(), (code.to_string(), Instant::now()));
+            .unwrap()
     }
 
-    #[test]
-    fn generate_otc_has_valid_format() {
-        const CHARSET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
-        for _ in 0..200 {
-            let code = generate_otc();
-            assert_eq!(code.len(), 6, "code must be 6 chars");
-            for ch in code.chars() {
-                assert!(CHARSET.contains(ch), "'{ch}' not in CHARSET");
-            }
-        }
+    fn bearer_request(uri: &str, token: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .header("authorization", format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn breaker_only_bearer_status(state: ServerState, token: &str) -> StatusCode {
+        axum::Router::new()
+            .route("/breaker-only", get(breaker_only))
+            .with_state(state)
+            .oneshot(bearer_request("/breaker-only", token))
+            .await
+            .unwrap()
+            .status()
     }
 
     #[tokio::test]
-    async fn verify_recovery_no_otc_redirects_with_error() {
+    async fn api_token_with_scope_is_accepted_and_records_use() {
         let state = state_with_auth().await;
-        let res = recovery_router(state)
-            .oneshot(verify_request("alice", "ABCDEF"))
-            .await
-            .unwrap();
-        assert_eq!(res.status(), StatusCode::SEE_OTHER);
-        let loc = res.headers().get("location").unwrap().to_str().unwrap();
-        assert!(loc.contains("error="), "expected error param in redirect: {loc}");
+        let token = insert_api_token(&state, Role::Gm, &[Permission::BreakerRead]).await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        assert_eq!(breaker_only_bearer_status(state, &token).await, StatusCode::OK);
+        let (_, stored) = auth.api_tokens.list().await.unwrap().remove(0);
+        assert!(stored.last_used_at.is_some());
     }
 
     #[tokio::test]
-    async fn verify_recovery_wrong_code_redirects_with_error() {
+    async fn api_token_without_scope_is_forbidden() {
         let state = state_with_auth().await;
-        insert_otc(&state, "alice", "ABCDEF").await;
-        let res = recovery_router(state)
-            .oneshot(verify_request("alice", "XXXXXX"))
-            .await
-            .unwrap();
-        assert_eq!(res.status(), StatusCode::SEE_OTHER);
-        let loc = res.headers().get("location").unwrap().to_str().unwrap();
-        assert!(loc.contains("error="), "expected error param in redirect: {loc}");
+        let token = insert_api_token(&state, Role::Gm, &[Permission::MqttPublish]).await;
+        assert_eq!(breaker_only_bearer_status(state, &token).await, StatusCode::FORBIDDEN);
     }
 
     #[tokio::test]
-    async fn verify_recovery_expired_otc_redirects_with_error() {
+    async fn api_token_scopes_are_capped_by_role() {
         let state = state_with_auth().await;
-        {
-            let auth = state.auth_state.as_ref().unwrap();
-            let old = Instant::now() - Duration::from_secs(601);
-            let _ = auth.otc_store
-                .write()
-                .await
-                .insert("alice".to_string(), ("ABCDEF".to_string(), old));
-        }
-        let res = recovery_router(state)
-            .oneshot(verify_request("alice", "ABCDEF"))
-            .await
-            .unwrap();
-        assert_eq!(res.status(), StatusCode::SEE_OTHER);
-        let loc = res.headers().get("location").unwrap().to_str().unwrap();
-        assert!(loc.contains("error="), "expected error param in redirect: {loc}");
+        let token = insert_api_token(&state, Role::Player, &[Permission::BreakerRead]).await;
+        assert_eq!(breaker_only_bearer_status(state, &token).await, StatusCode::FORBIDDEN);
     }
 
     #[tokio::test]
-    async fn verify_recovery_consumes_otc_even_on_wrong_code() {
+    async fn unknown_or_revoked_api_token_is_unauthorized() {
         let state = state_with_auth().await;
+        let token = insert_api_token(&state, Role::Gm, &[Permission::BreakerRead]).await;
         let auth = Arc::clone(state.auth_state.as_ref().unwrap());
-        insert_otc(&state, "alice", "ABCDEF").await;
-        let _ = recovery_router(state)
-            .oneshot(verify_request("alice", "XXXXXX"))
+        assert_eq!(
+            breaker_only_bearer_status(state.clone(), "green_bogus").await,
+            StatusCode::UNAUTHORIZED
+        );
+        auth.api_tokens.remove(&hash_token(&token)).await.unwrap();
+        assert_eq!(breaker_only_bearer_status(state, &token).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn api_token_cannot_reach_gm_routes() {
+        let state = state_with_auth().await;
+        let token = insert_api_token(&state, Role::Gm, &[Permission::BreakerRead]).await;
+        let res = gm_router(state)
+            .oneshot(bearer_request("/gm-only", &token))
             .await
             .unwrap();
-        assert!(
-            !auth.otc_store.read().await.contains_key("alice"),
-            "OTC must be consumed even on a wrong-code attempt"
-        );
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    /// Insert a GM session with explicit timestamps and return its cookie token.
+    async fn insert_aged_session(
+        state: &ServerState,
+        created_at: OffsetDateTime,
+        last_seen: OffsetDateTime,
+        kiosk: bool,
+    ) -> String {
+        let token = Uuid::new_v4().to_string();
+        state
+            .auth_state
+            .as_ref()
+            .unwrap()
+            .sessions
+            .insert(
+                &hash_token(&token),
+                &SessionData {
+                    user_id: Uuid::new_v4(),
+                    username: "gm".to_string(),
+                    role: Role::Gm,
+                    created_at,
+                    last_seen,
+                    user_agent: None,
+                    kiosk,
+                },
+            )
+            .await
+            .unwrap();
+        token
+    }
+
+    async fn gm_only_status(state: ServerState, token: &str) -> StatusCode {
+        gm_router(state)
+            .oneshot(
+                Request::builder()
+                    .uri("/gm-only")
+                    .header("cookie", format!("green_session={token}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn expired_session_redirects_to_login() {
+        let state = state_with_auth().await;
+        let now = OffsetDateTime::now_utc();
+        let created_at = now - time::Duration::days(1) - Duration::from_secs(1);
+        let token = insert_aged_session(&state, created_at, now, false).await;
+        assert_eq!(gm_only_status(state, &token).await, StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn idle_session_redirects_to_login() {
+        let state = state_with_auth().await;
+        let now = OffsetDateTime::now_utc();
+        let last_seen = now - time::Duration::hours(1) - Duration::from_secs(1);
+        let token = insert_aged_session(&state, last_seen, last_seen, false).await;
+        assert_eq!(gm_only_status(state, &token).await, StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn kiosk_session_survives_idle_timeout() {
+        let state = state_with_auth().await;
+        let week_ago = OffsetDateTime::now_utc() - time::Duration::days(7);
+        let token = insert_aged_session(&state, week_ago, week_ago, true).await;
+        assert_eq!(gm_only_status(state, &token).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn authenticated_request_refreshes_last_seen() {
+        let state = state_with_auth().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let then = OffsetDateTime::now_utc() - time::Duration::minutes(30);
+        let token = insert_aged_session(&state, then, then, false).await;
+        assert_eq!(gm_only_status(state, &token).await, StatusCode::OK);
+
+        let session = auth.sessions.get(&hash_token(&token)).await.unwrap().unwrap();
+        assert!(session.last_seen > then + time::Duration::minutes(29));
+    }
+
+    #[test]
+    fn kiosk_cookie_has_max_age() {
+        let cookie = make_session_cookie("t".into(), Some(Duration::from_secs(60)));
+        assert_eq!(cookie.max_age(), Some(time::Duration::seconds(60)));
+        assert!(make_session_cookie("t".into(), None).max_age().is_none());
+    }
+
+    #[tokio::test]
+    async fn sessions_are_stored_by_token_hash() {
+        let state = state_with_auth().await;
+        let token = insert_session(&state, "alice", Role::Player).await;
+        let sessions = &state.auth_state.as_ref().unwrap().sessions;
+        assert!(sessions.get(&token).await.unwrap().is_none(), "raw token must not be a key");
+        assert!(sessions.get(&hash_token(&token)).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn logout_deletes_session() {
+        let state = state_with_auth().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let token = insert_session(&state, "alice", Role::Player).await;
+        let res = axum::Router::new()
+            .route("/auth/logout", axum::routing::post(logout))
+            .with_state(state)
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/auth/logout")
+                    .header("cookie", format!("green_session={token}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert!(auth.get_session(&token).await.is_none(), "session must be gone after logout");
+    }
+
+    // ── Passkey management ───────────────────────────────────────────────────
+
+    fn ids(ids: &[&[u8]]) -> Vec<Vec<u8>> {
+        ids.iter().map(|id| id.to_vec()).collect()
+    }
+
+    #[test]
+    fn credential_key_is_lowercase_hex() {
+        assert_eq!(credential_key(&[0x00, 0xab, 0x10]), "00ab10");
+    }
+
+    #[test]
+    fn without_passkey_removes_matching_credential() {
+        let remaining = without_passkey(ids(&[b"\x01", b"\x02"]), "01", |id| id).unwrap();
+        assert_eq!(remaining, ids(&[b"\x02"]));
+    }
+
+    #[test]
+    fn without_passkey_refuses_last_credential() {
+        let err = without_passkey(ids(&[b"\x01"]), "01", |id| id).unwrap_err();
+        assert!(matches!(err, Error::LastPasskey));
+    }
+
+    #[test]
+    fn without_passkey_rejects_unknown_credential() {
+        let err = without_passkey(ids(&[b"\x01", b"\x02"]), "ff", |id| id).unwrap_err();
+        assert!(matches!(err, Error::NotFound));
+    }
+
+    #[tokio::test]
+    async fn cleanup_sessions_purges_only_expired() {
+        let state = state_with_auth().await;
+        let auth = state.auth_state.as_ref().unwrap();
+        let live = insert_session(&state, "alice", Role::Player).await;
+        let old = OffsetDateTime::now_utc() - time::Duration::days(2);
+        auth.sessions
+            .insert(
+                "stale",
+                &SessionData {
+                    user_id: Uuid::new_v4(),
+                    username: "bob".to_string(),
+                    role: Role::Player,
+                    created_at: old,
+                    last_seen: old,
+                    user_agent: None,
+                    kiosk: false,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(auth.cleanup_sessions().await.unwrap(), 1);
+        assert!(auth.get_session(&live).await.is_some());
+    }
+
+    // ── Recovery tests ────────────────────────────────────────────────────────
+
+    fn recovery_router(state: ServerState) -> axum::Router {
+        axum::Router::new()
+            .route("/auth/recover/verify", axum::routing::post(verify_recovery))
+            .with_state(state)
+    }
+
+    fn verify_request(username: &str, code: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/auth/recover/verify")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from(format!("username={username}&code={code}")))
+            .unwrap()
+    }
+
+    async fn insert_otc_at(state: &ServerState, username: &str, code: &str, at: OffsetDateTime) {
+        let hash = Value::String(hash_otc(code));
+        state
+            .auth_state
+            .as_ref()
+            .unwrap()
+            .challenges
+            .insert(ChallengeKind::RecoveryCode, username, &hash, at)
+            .await
+            .unwrap();
+    }
+
+    async fn insert_otc(state: &ServerState, username: &str, code: &str) {
+        insert_otc_at(state, username, code, OffsetDateTime::now_utc()).await;
+    }
+
+    async fn pending_challenges(auth: &AuthState, kind: ChallengeKind) -> usize {
+        auth.challenges
+            .pending(kind, OffsetDateTime::now_utc())
+            .await
+            .unwrap()
+            .count
+    }
+
+    #[test]
+    fn generate_otc_has_valid_format() {
+        const CHARSET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        for _ in 0..200 {
+            let code = generate_otc();
+            assert_eq!(code.len(), 6, "code must be 6 chars");
+            for ch in code.chars() {
+                assert!(CHARSET.contains(ch), "'{ch}' not in CHARSET");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_recovery_no_otc_redirects_with_error() {
+        let state = state_with_auth().await;
+        let res = recovery_router(state)
+            .oneshot(verify_request("alice", "ABCDEF"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let loc = res.headers().get("location").unwrap().to_str().unwrap();
+        assert!(loc.contains("error="), "expected error param in redirect: {loc}");
+    }
+
+    #[tokio::test]
+    async fn verify_recovery_wrong_code_redirects_with_error() {
+        let state = state_with_auth().await;
+        insert_otc(&state, "alice", "ABCDEF").await;
+        let res = recovery_router(state)
+            .oneshot(verify_request("alice", "XXXXXX"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let loc = res.headers().get("location").unwrap().to_str().unwrap();
+        assert!(loc.contains("error="), "expected error param in redirect: {loc}");
+    }
+
+    #[tokio::test]
+    async fn verify_recovery_expired_otc_redirects_with_error() {
+        let state = state_with_auth().await;
+        let old = OffsetDateTime::now_utc() - Duration::from_secs(601);
+        insert_otc_at(&state, "alice", "ABCDEF", old).await;
+        let res = recovery_router(state)
+            .oneshot(verify_request("alice", "ABCDEF"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let loc = res.headers().get("location").unwrap().to_str().unwrap();
+        assert!(loc.contains("error="), "expected error param in redirect: {loc}");
+    }
+
+    #[tokio::test]
+    async fn verify_recovery_consumes_otc_even_on_wrong_code() {
+        let state = state_with_auth().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        insert_otc(&state, "alice", "ABCDEF").await;
+        let _ = recovery_router(state)
+            .oneshot(verify_request("alice", "XXXXXX"))
+            .await
+            .unwrap();
+        assert_eq!(
+            pending_challenges(&auth, ChallengeKind::RecoveryCode).await,
+            0,
+            "OTC must be consumed even on a wrong-code attempt"
+        );
+    }
+
+    async fn audit_events(state: &ServerState) -> Vec<AuditEvent> {
+        state
+            .auth_state
+            .as_ref()
+            .unwrap()
+            .audit_log
+            .list(&crate::audit::AuditFilter { limit: 100, ..Default::default() })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn failed_recovery_is_audited() {
+        let state = state_with_auth().await;
+        insert_otc(&state, "alice", "ABCDEF").await;
+        let _ = recovery_router(state.clone())
+            .oneshot(verify_request("alice", "XXXXXX"))
+            .await
+            .unwrap();
+        let events = audit_events(&state).await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, AuditAction::Recovery);
+        assert_eq!(events[0].outcome, AuditOutcome::Failure);
+        assert_eq!(events[0].actor.as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn logout_is_audited_with_request_meta() {
+        let state = state_with_auth().await;
+        let token = insert_session(&state, "alice", Role::Player).await;
+        let peer: std::net::SocketAddr = "10.0.0.7:40000".parse().unwrap();
+        let res = axum::Router::new()
+            .route("/auth/logout", axum::routing::post(logout))
+            .with_state(state.clone())
+            .layer(axum::extract::connect_info::MockConnectInfo(peer))
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/auth/logout")
+                    .header("cookie", format!("green_session={token}"))
+                    .header("user-agent", "curl/8")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let events = audit_events(&state).await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, AuditAction::Logout);
+        assert_eq!(events[0].outcome, AuditOutcome::Success);
+        assert_eq!(events[0].ip.as_deref(), Some("10.0.0.7"));
+        assert_eq!(events[0].user_agent.as_deref(), Some("curl/8"));
+    }
+
+    // ── rate limits ─────────────────────────────────────────────────────────
+
+    async fn state_with_rate_limit(rate_limit: RateLimitConfig) -> ServerState {
+        state_with_config(AuthConfig {
+            rate_limit,
+            ..test_config()
+        })
+        .await
+    }
+
+    fn start_recovery_request(username: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/auth/recover")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from(format!("username={username}")))
+            .unwrap()
+    }
+
+    fn discoverable_request() -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/auth/login/challenge/discoverable")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn start_recovery_is_rate_limited_per_username() {
+        let state = state_with_rate_limit(RateLimitConfig {
+            username_burst: 1,
+            ..RateLimitConfig::default()
+        })
+        .await;
+        let app = axum::Router::new()
+            .route("/auth/recover", axum::routing::post(start_recovery))
+            .with_state(state);
+
+        let res = app.clone().oneshot(start_recovery_request("alice")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let res = app.clone().oneshot(start_recovery_request("alice")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(header::RETRY_AFTER));
+        let res = app.oneshot(start_recovery_request("bob")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    }
+
+    async fn recover_page_body(state: ServerState) -> String {
+        let res = axum::Router::new()
+            .route("/auth/recover", get(recover_page))
+            .with_state(state)
+            .oneshot(
+                Request::builder()
+                    .uri("/auth/recover?sent=true&username=alice")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn recover_page_names_configured_channels() {
+        let body = recover_page_body(state_with_auth().await).await;
+        assert!(body.contains("enter one of your backup codes"));
+        assert!(!body.contains("6-character code"));
+
+        let state = state_with_config(AuthConfig {
+            ntfy_url: Some("http://ntfy.invalid/topic".into()),
+            ..test_config()
+        })
+        .await;
+        let body = recover_page_body(state).await;
+        assert!(body.contains("check your ntfy app for a 6-character code"));
+    }
+
+    #[tokio::test]
+    async fn login_page_embeds_csrf_token() {
+        let res = axum::Router::new()
+            .route("/auth/login", get(login_page))
+            .with_state(state_with_auth().await)
+            .oneshot(
+                Request::builder()
+                    .uri("/auth/login")
+                    .header("cookie", "green_session=stale")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let meta = format!(r#"<meta name="csrf-token" content="{}">"#, crate::csrf::token_for("stale"));
+        assert!(body.contains(&meta));
+    }
+
+    #[tokio::test]
+    async fn pending_discoverable_challenges_are_capped() {
+        let state = state_with_rate_limit(RateLimitConfig {
+            max_pending: 1,
+            ..RateLimitConfig::default()
+        })
+        .await;
+        let app = axum::Router::new()
+            .route(
+                "/auth/login/challenge/discoverable",
+                axum::routing::post(start_discoverable_auth),
+            )
+            .with_state(state);
+
+        let res = app.clone().oneshot(discoverable_request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app.oneshot(discoverable_request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn auth_routes_are_rate_limited_per_ip() {
+        let state = state_with_rate_limit(RateLimitConfig {
+            ip_burst: 1,
+            ..RateLimitConfig::default()
+        })
+        .await;
+        let app = |ip: &str| {
+            let peer: std::net::SocketAddr = format!("{ip}:40000").parse().unwrap();
+            axum::Router::new()
+                .route(
+                    "/auth/login/challenge/discoverable",
+                    axum::routing::post(start_discoverable_auth),
+                )
+                .route_layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    crate::rate_limit::limit_by_ip,
+                ))
+                .with_state(state.clone())
+                .layer(axum::extract::connect_info::MockConnectInfo(peer))
+        };
+
+        let res = app("10.0.0.1").oneshot(discoverable_request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app("10.0.0.1").oneshot(discoverable_request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let res = app("10.0.0.2").oneshot(discoverable_request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn clients_behind_a_trusted_proxy_are_limited_separately() {
+        let state = state_with_rate_limit(RateLimitConfig {
+            ip_burst: 1,
+            trusted_proxies: vec!["127.0.0.1/32".parse().unwrap()],
+            ..RateLimitConfig::default()
+        })
+        .await;
+        let peer: std::net::SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let app = axum::Router::new()
+            .route(
+                "/auth/login/challenge/discoverable",
+                axum::routing::post(start_discoverable_auth),
+            )
+            .route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                crate::rate_limit::limit_by_ip,
+            ))
+            .with_state(state)
+            .layer(axum::extract::connect_info::MockConnectInfo(peer));
+        let forwarded = |client: &str| {
+            let mut req = discoverable_request();
+            let _ = req
+                .headers_mut()
+                .insert("x-forwarded-for", client.parse().unwrap());
+            req
+        };
+
+        let res = app.clone().oneshot(forwarded("100.64.0.1")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app.clone().oneshot(forwarded("100.64.0.1")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let res = app.oneshot(forwarded("100.64.0.2")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    // ── registration modes ──────────────────────────────────────────────────
+
+    fn register_router(state: ServerState) -> axum::Router {
+        axum::Router::new()
+            .route("/auth/register/challenge", axum::routing::post(start_registration))
+            .route("/auth/register/finish", axum::routing::post(finish_registration))
+            .route("/auth/account/passkeys/challenge", axum::routing::post(start_add_passkey))
+            .route("/auth/account/passkeys/finish", axum::routing::post(finish_add_passkey))
+            .with_state(state)
+    }
+
+    fn json_request(uri: &str, token: Option<&str>, body: Value) -> Request<Body> {
+        let mut req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(token) = token {
+            req = req.header("cookie", format!("green_session={token}"));
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn challenge_status(state: ServerState, body: Value) -> StatusCode {
+        register_router(state)
+            .oneshot(json_request("/auth/register/challenge", None, body))
+            .await
+            .unwrap()
+            .status()
+    }
+
+    /// Put an add-passkey ceremony for `user_id` in flight, as
+    /// `start_add_passkey` would.
+    async fn pending_add_passkey(auth: &AuthState, user_id: Uuid, username: &str) {
+        let (_, state) = auth
+            .webauthn
+            .start_passkey_registration(user_id, username, username, None)
+            .unwrap();
+        let pending = PendingRegistration {
+            state,
+            user_id,
+            kind: RegistrationKind::AddPasskey,
+        };
+        auth.stash_challenge(ChallengeKind::AddPasskey, &user_id.to_string(), &pending)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn pending_registration_round_trips_through_the_challenge_store() {
+        let state = state_with_auth().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let gm = Uuid::new_v4();
+        pending_add_passkey(&auth, gm, "gm").await;
+
+        let pending: PendingRegistration = auth
+            .take_challenge(ChallengeKind::AddPasskey, &gm.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.kind, RegistrationKind::AddPasskey);
+        assert_eq!(pending.user_id, gm);
+    }
+
+    #[tokio::test]
+    async fn add_passkey_requires_login() {
+        let state = state_with_auth().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let router = register_router(state);
+
+        let res = router
+            .clone()
+            .oneshot(json_request("/auth/account/passkeys/challenge", None, serde_json::json!({})))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+
+        let res = router
+            .oneshot(json_request(
+                "/auth/account/passkeys/finish",
+                None,
+                serde_json::json!({ "credential": {} }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(pending_challenges(&auth, ChallengeKind::AddPasskey).await, 0);
+    }
+
+    #[tokio::test]
+    async fn add_passkey_cannot_finish_another_users_ceremony() {
+        let state = state_with_auth().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let gm = Uuid::new_v4();
+        pending_add_passkey(&auth, gm, "gm").await;
+        let mallory = insert_session(&state, "mallory", Role::Player).await;
+
+        let res = register_router(state)
+            .oneshot(json_request(
+                "/auth/account/passkeys/finish",
+                Some(&mallory),
+                serde_json::json!({ "credential": {} }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(pending_challenges(&auth, ChallengeKind::AddPasskey).await, 1);
+    }
+
+    #[tokio::test]
+    async fn register_finish_cannot_complete_add_passkey_ceremony() {
+        let state = state_with_auth().await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let gm = Uuid::new_v4();
+        pending_add_passkey(&auth, gm, "gm").await;
+
+        // Unauthenticated, naming the ceremony's key as the "username".
+        let router = register_router(state);
+        let res = router
+            .clone()
+            .oneshot(json_request(
+                "/auth/register/challenge",
+                None,
+                serde_json::json!({ "username": gm.to_string() }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = router
+            .oneshot(json_request(
+                "/auth/register/finish",
+                None,
+                serde_json::json!({ "username": gm.to_string(), "credential": {} }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // The victim's ceremony was neither overwritten nor consumed.
+        let pending: PendingRegistration = auth
+            .take_challenge(ChallengeKind::AddPasskey, &gm.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.kind, RegistrationKind::AddPasskey);
+        assert_eq!(pending.user_id, gm);
+    }
+
+    #[tokio::test]
+    async fn registering_an_existing_username_adds_no_credential() {
+        let mut state = state_with_auth().await;
+        let users = seed_users(&mut state, &[("alice", Role::Player)]).await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let router = register_router(state);
+
+        let res = router
+            .clone()
+            .oneshot(json_request(
+                "/auth/register/challenge",
+                None,
+                serde_json::json!({ "username": "alice" }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        assert_eq!(pending_challenges(&auth, ChallengeKind::Registration).await, 0);
+
+        let res = router
+            .oneshot(json_request(
+                "/auth/register/finish",
+                None,
+                serde_json::json!({ "username": "alice", "credential": {} }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let (_, passkeys) = users.load_passkeys("alice").await.unwrap().unwrap();
+        assert!(passkeys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn closed_registration_is_forbidden() {
+        let state = state_with_registration(RegistrationMode::Closed).await;
+        let status = challenge_status(state, serde_json::json!({ "username": "alice" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn invite_mode_requires_invite() {
+        let state = state_with_registration(RegistrationMode::Invite).await;
+        let status = challenge_status(state, serde_json::json!({ "username": "alice" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn invite_mode_lets_first_gm_register_without_invite() {
+        let state = state_with_registration(RegistrationMode::Invite).await;
+        let status = challenge_status(state, serde_json::json!({ "username": "gm" })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn invite_mode_requires_invite_for_gm_users_once_a_gm_exists() {
+        let mut state = state_with_config(AuthConfig {
+            registration: RegistrationMode::Invite,
+            gm_users: vec!["gm".into(), "second-gm".into()],
+            ..test_config()
+        })
+        .await;
+        let _ = seed_users(&mut state, &[("gm", Role::Gm)]).await;
+        let status =
+            challenge_status(state, serde_json::json!({ "username": "second-gm" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn invite_mode_rejects_unknown_invite() {
+        let state = state_with_registration(RegistrationMode::Invite).await;
+        let status = challenge_status(
+            state,
+            serde_json::json!({ "username": "alice", "invite": "not-a-token" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn invite_mode_rejects_expired_invite() {
+        let state = state_with_registration(RegistrationMode::Invite).await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let token = auth
+            .create_invite(None, Role::Player, "gm", Duration::ZERO)
+            .await
+            .unwrap();
+        let status = challenge_status(
+            state,
+            serde_json::json!({ "username": "alice", "invite": token }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn invite_for_other_username_is_rejected() {
+        let state = state_with_registration(RegistrationMode::Invite).await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let token = auth
+            .create_invite(Some("bob".into()), Role::Player, "gm", Duration::from_secs(3600))
+            .await
+            .unwrap();
+        let status = challenge_status(
+            state,
+            serde_json::json!({ "username": "alice", "invite": token }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn usable_invite_ignores_redeemed_invites() {
+        let state = state_with_registration(RegistrationMode::Invite).await;
+        let auth = state.auth_state.as_ref().unwrap();
+        let token = auth
+            .create_invite(Some("alice".into()), Role::Gm, "gm", Duration::from_secs(3600))
+            .await
+            .unwrap();
+        let invite = auth.usable_invite(&token).await.unwrap().unwrap();
+        assert_eq!(invite.role, Role::Gm);
+        assert!(auth
+            .invites
+            .redeem(&hash_token(&token), OffsetDateTime::now_utc())
+            .await
+            .unwrap()
+            .is_some());
+        assert!(auth.usable_invite(&token).await.unwrap().is_none());
     }
 }
@@ -6,7 +6,7 @@ use axum::{
 
 use std::sync::Arc;
 
-use crate::{auth::{AuthUserInfo, GmUser}, breaker_detail::{BreakerDetailStore, BreakerSlot}, error::Error, index::NavLink, ServerState};
+use crate::{auth::AuthUserInfo, breaker_detail::{BreakerDetailStore, BreakerSlot}, error::Error, index::NavLink, permission::{BreakerRead, RequirePermission}, ServerState};
 
 /// Pre-computed breaker panel HTML content (the circuit layout).
 /// Stored in ServerState and used to construct `BreakerPage` per request.
@@ -124,13 +124,10 @@ fn render_from_store(store: &dyn BreakerDetailStore) -> String {
 }
 
 pub async fn breaker_route(
-    user: GmUser,
+    user: RequirePermission<BreakerRead>,
     State(state): State<ServerState>,
 ) -> Result<Html<String>, Error> {
-    let auth_user = Some(AuthUserInfo {
-        username: user.0.username.clone(),
-        role: user.0.role.clone(),
-    });
+    let auth_user = Some(user.0.info());
     let page = BreakerPage {
         content: state.breaker_content.0.clone(),
         version: crate::VERSION,
@@ -147,7 +144,7 @@ pub struct BreakerDetailTemplate<'a> {
 }
 
 pub async fn breaker_detail_route(
-    _user: GmUser,
+    _user: RequirePermission<BreakerRead>,
     Path(key): Path<String>,
     State(state): State<ServerState>,
 ) -> Result<Html<String>, Error> {
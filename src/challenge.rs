@@ -0,0 +1,327 @@
+//! Short-lived state for ceremonies that span two requests.
+//!
+//! Starting a passkey registration or login, or sending a recovery code,
+//! leaves state behind that the matching "finish" request consumes. By
+//! default it is kept in process memory; with `challenge_store = "postgres"`
+//! it goes in the `challenges` table instead, so it survives restarts and is
+//! shared between instances. Recovery codes are only ever stored hashed.
+//!
+//! WebAuthn ceremony state is serialized with webauthn-rs's
+//! `danger-allow-state-serialisation` feature. It never leaves the server.
+
+use std::{collections::HashMap, time::Duration};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+
+use crate::error::Error;
+
+/// How long a WebAuthn registration or authentication challenge remains valid.
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a one-time recovery code remains valid.
+pub const OTC_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// What a pending challenge is for. Keys are only unique within a kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum ChallengeKind {
+    /// A new-account passkey registration, keyed by username.
+    Registration,
+    /// A passkey being added to a signed-in account, keyed by user id. Kept
+    /// apart from [`ChallengeKind::Registration`] so the unauthenticated
+    /// registration endpoints can't reach it by passing an id as username.
+    AddPasskey,
+    /// A discoverable login, keyed by the challenge id sent to the browser.
+    Authentication,
+    /// The hash of a one-time recovery code, keyed by username.
+    RecoveryCode,
+}
+
+impl ChallengeKind {
+    pub fn ttl(self) -> Duration {
+        match self {
+            ChallengeKind::Registration
+            | ChallengeKind::AddPasskey
+            | ChallengeKind::Authentication => CHALLENGE_TTL,
+            ChallengeKind::RecoveryCode => OTC_TTL,
+        }
+    }
+}
+
+/// Where pending challenges are kept: `memory` (the default) or `postgres`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengeBackend {
+    #[default]
+    Memory,
+    Postgres,
+}
+
+/// Unexpired challenges of one kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pending {
+    pub count: usize,
+    /// How long ago the oldest one was created.
+    pub oldest_age: Option<Duration>,
+}
+
+/// Storage backend for pending challenges. Expired entries are never
+/// returned, whether or not they have been purged yet.
+pub trait ChallengeStore: Send + Sync + std::fmt::Debug {
+    /// Store `state` under `(kind, key)` until `now + kind.ttl()`, replacing
+    /// any earlier challenge with the same key.
+    fn insert<'a>(
+        &'a self,
+        kind: ChallengeKind,
+        key: &'a str,
+        state: &'a Value,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Remove the challenge and return its state if it hasn't expired.
+    fn take<'a>(
+        &'a self,
+        kind: ChallengeKind,
+        key: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<Option<Value>, Error>>;
+
+    fn pending(&self, kind: ChallengeKind, now: OffsetDateTime) -> BoxFuture<'_, Result<Pending, Error>>;
+
+    /// Delete every expired challenge, returning how many were removed.
+    fn purge_expired(&self, now: OffsetDateTime) -> BoxFuture<'_, Result<u64, Error>>;
+}
+
+fn expiry(kind: ChallengeKind, now: OffsetDateTime) -> OffsetDateTime {
+    now + kind.ttl()
+}
+
+fn age(since: OffsetDateTime, now: OffsetDateTime) -> Duration {
+    Duration::try_from(now - since).unwrap_or_default()
+}
+
+// ─── In-memory ────────────────────────────────────────────────────────────────
+
+#[derive(Debug)]
+struct Entry {
+    state: Value,
+    created_at: OffsetDateTime,
+    expires_at: OffsetDateTime,
+}
+
+/// [`ChallengeStore`] kept in process memory. Pending ceremonies are lost on
+/// restart and aren't visible to other instances.
+#[derive(Debug, Default)]
+pub struct MemoryChallengeStore {
+    entries: Mutex<HashMap<(ChallengeKind, String), Entry>>,
+}
+
+impl ChallengeStore for MemoryChallengeStore {
+    fn insert<'a>(
+        &'a self,
+        kind: ChallengeKind,
+        key: &'a str,
+        state: &'a Value,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut entries = self.entries.lock().await;
+            entries.retain(|_, e| e.expires_at > now);
+            let entry = Entry {
+                state: state.clone(),
+                created_at: now,
+                expires_at: expiry(kind, now),
+            };
+            let _ = entries.insert((kind, key.to_owned()), entry);
+            Ok(())
+        })
+    }
+
+    fn take<'a>(
+        &'a self,
+        kind: ChallengeKind,
+        key: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<Option<Value>, Error>> {
+        Box::pin(async move {
+            let entry = self.entries.lock().await.remove(&(kind, key.to_owned()));
+            Ok(entry.filter(|e| e.expires_at > now).map(|e| e.state))
+        })
+    }
+
+    fn pending(&self, kind: ChallengeKind, now: OffsetDateTime) -> BoxFuture<'_, Result<Pending, Error>> {
+        Box::pin(async move {
+            let entries = self.entries.lock().await;
+            let live: Vec<&Entry> = entries
+                .iter()
+                .filter(|((k, _), e)| *k == kind && e.expires_at > now)
+                .map(|(_, e)| e)
+                .collect();
+            Ok(Pending {
+                count: live.len(),
+                oldest_age: live.iter().map(|e| e.created_at).min().map(|t| age(t, now)),
+            })
+        })
+    }
+
+    fn purge_expired(&self, now: OffsetDateTime) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            let mut entries = self.entries.lock().await;
+            let before = entries.len();
+            entries.retain(|_, e| e.expires_at > now);
+            Ok((before - entries.len()) as u64)
+        })
+    }
+}
+
+// ─── Postgres ─────────────────────────────────────────────────────────────────
+
+/// [`ChallengeStore`] backed by the `challenges` table.
+#[derive(Debug, Clone)]
+pub struct PgChallengeStore {
+    db: PgPool,
+}
+
+impl PgChallengeStore {
+    pub fn new(db: PgPool) -> Self {
+        PgChallengeStore { db }
+    }
+}
+
+impl ChallengeStore for PgChallengeStore {
+    fn insert<'a>(
+        &'a self,
+        kind: ChallengeKind,
+        key: &'a str,
+        state: &'a Value,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query(
+                "INSERT INTO challenges (kind, key, state, created_at, expires_at) \
+                 VALUES ($1, $2, $3, $4, $5) \
+                 ON CONFLICT (kind, key) DO UPDATE SET state = EXCLUDED.state, \
+                     created_at = EXCLUDED.created_at, expires_at = EXCLUDED.expires_at",
+            )
+            .bind(kind.to_string())
+            .bind(key)
+            .bind(state)
+            .bind(now)
+            .bind(expiry(kind, now))
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn take<'a>(
+        &'a self,
+        kind: ChallengeKind,
+        key: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<Option<Value>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "DELETE FROM challenges WHERE kind = $1 AND key = $2 RETURNING state, expires_at",
+            )
+            .bind(kind.to_string())
+            .bind(key)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(row
+                .filter(|row| row.get::<OffsetDateTime, _>("expires_at") > now)
+                .map(|row| row.get("state")))
+        })
+    }
+
+    fn pending(&self, kind: ChallengeKind, now: OffsetDateTime) -> BoxFuture<'_, Result<Pending, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "SELECT COUNT(*) AS count, MIN(created_at) AS oldest \
+                 FROM challenges WHERE kind = $1 AND expires_at > $2",
+            )
+            .bind(kind.to_string())
+            .bind(now)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(Pending {
+                count: usize::try_from(row.get::<i64, _>("count")).unwrap_or(0),
+                oldest_age: row
+                    .get::<Option<OffsetDateTime>, _>("oldest")
+                    .map(|t| age(t, now)),
+            })
+        })
+    }
+
+    fn purge_expired(&self, now: OffsetDateTime) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            let result = sqlx::query("DELETE FROM challenges WHERE expires_at <= $1")
+                .bind(now)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(result.rows_affected())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_store_take_is_single_use_and_honours_expiry() {
+        let store = MemoryChallengeStore::default();
+        let now = OffsetDateTime::now_utc();
+        let state = serde_json::json!({ "challenge": "abc" });
+
+        store.insert(ChallengeKind::Authentication, "c1", &state, now).await.unwrap();
+        store.insert(ChallengeKind::RecoveryCode, "c1", &state, now).await.unwrap();
+        assert_eq!(
+            store.take(ChallengeKind::Authentication, "c1", now).await.unwrap(),
+            Some(state.clone())
+        );
+        assert_eq!(store.take(ChallengeKind::Authentication, "c1", now).await.unwrap(), None);
+
+        // Keys are per kind, and a recovery code outlives a login challenge.
+        let later = now + CHALLENGE_TTL + Duration::from_secs(1);
+        assert_eq!(
+            store.take(ChallengeKind::RecoveryCode, "c1", later).await.unwrap(),
+            Some(state.clone())
+        );
+
+        store.insert(ChallengeKind::Registration, "alice", &state, now).await.unwrap();
+        assert_eq!(store.take(ChallengeKind::Registration, "alice", later).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn memory_store_counts_pending_and_purges_expired() {
+        let store = MemoryChallengeStore::default();
+        let now = OffsetDateTime::now_utc();
+        let earlier = now - Duration::from_secs(60);
+        let state = Value::Null;
+
+        store.insert(ChallengeKind::Authentication, "a", &state, earlier).await.unwrap();
+        store.insert(ChallengeKind::Authentication, "b", &state, now).await.unwrap();
+        store.insert(ChallengeKind::RecoveryCode, "alice", &state, now).await.unwrap();
+        assert_eq!(
+            store.pending(ChallengeKind::Authentication, now).await.unwrap(),
+            Pending {
+                count: 2,
+                oldest_age: Some(Duration::from_secs(60)),
+            }
+        );
+
+        let later = now + CHALLENGE_TTL;
+        assert_eq!(store.purge_expired(later).await.unwrap(), 2);
+        assert_eq!(store.pending(ChallengeKind::RecoveryCode, later).await.unwrap().count, 1);
+    }
+}
@@ -0,0 +1,314 @@
+//! CSRF protection for cookie-authenticated requests.
+//!
+//! Every page carries a token in a `<meta name="csrf-token">` tag (see
+//! `base.html`), and `assets/js/csrf.js` sends it back with each form post,
+//! `fetch` and htmx request. The [`protect`] middleware rejects any
+//! `POST`/`PUT`/`PATCH`/`DELETE` whose token doesn't match with
+//! [`Error::InvalidCsrfToken`].
+//!
+//! The token is a hash of the session cookie, so it changes on every login.
+//! Visitors without a session get a random `green_csrf` cookie to bind the
+//! token to instead, set only on HTML responses since only pages hand the
+//! token out. Requests with an `Authorization: Bearer` header carry no
+//! ambient credentials and are exempt.
+
+use std::convert::Infallible;
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{FromRequestParts, Request},
+    http::{HeaderMap, HeaderValue, Method, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use uuid::Uuid;
+
+use crate::{
+    auth::{bearer_token, session_token},
+    error::Error,
+    session::hash_token,
+};
+
+/// Cookie holding the random secret for visitors without a session.
+const CSRF_COOKIE: &str = "green_csrf";
+
+/// Header checked on `fetch` and htmx requests.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Form field checked on `application/x-www-form-urlencoded` posts.
+pub const CSRF_FIELD: &str = "csrf_token";
+
+/// Largest form body buffered while looking for [`CSRF_FIELD`].
+const MAX_FORM_BYTES: usize = 64 * 1024;
+
+/// The token pages must send back for the client holding `secret` (a session
+/// token or the `green_csrf` cookie).
+pub fn token_for(secret: &str) -> String {
+    hash_token(&format!("csrf:{secret}"))
+}
+
+/// The secret the request's token is bound to, if it has one yet.
+fn binding(jar: &CookieJar) -> Option<String> {
+    session_token(jar).or_else(|| jar.get(CSRF_COOKIE).map(|c| c.value().to_owned()))
+}
+
+/// The CSRF token for the current request, for pages rendered to visitors who
+/// may not be signed in. Signed-in pages get it from
+/// [`AuthUserInfo`](crate::auth::AuthUserInfo) instead.
+#[derive(Debug, Clone, Default)]
+pub struct CsrfToken(pub String);
+
+impl<S: Send + Sync> FromRequestParts<S> for CsrfToken {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(token) = parts.extensions.get::<CsrfToken>() {
+            return Ok(token.clone());
+        }
+        let jar = CookieJar::from_headers(&parts.headers);
+        Ok(CsrfToken(binding(&jar).map(|s| token_for(&s)).unwrap_or_default()))
+    }
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE)
+}
+
+fn has_content_type(headers: &HeaderMap, mime: &str) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(mime))
+}
+
+fn is_form(headers: &HeaderMap) -> bool {
+    has_content_type(headers, "application/x-www-form-urlencoded")
+}
+
+/// Check the token on a state-changing request, handing back the request
+/// with its body intact.
+async fn verify(request: Request, expected: &str) -> Result<Request, Error> {
+    let header_token = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+
+    let (request, presented) = match header_token {
+        Some(token) => (request, Some(token)),
+        None if is_form(request.headers()) => {
+            let (parts, body) = request.into_parts();
+            let bytes = to_bytes(body, MAX_FORM_BYTES)
+                .await
+                .map_err(|_| Error::InvalidCsrfToken)?;
+            let token = url::form_urlencoded::parse(&bytes)
+                .find(|(k, _)| k == CSRF_FIELD)
+                .map(|(_, v)| v.into_owned());
+            (Request::from_parts(parts, Body::from(bytes)), token)
+        }
+        None => (request, None),
+    };
+
+    // Compare digests so the comparison time doesn't depend on how much of
+    // the token matched.
+    match presented {
+        Some(token) if hash_token(&token) == hash_token(expected) => Ok(request),
+        _ => Err(Error::InvalidCsrfToken),
+    }
+}
+
+/// Middleware: require a valid CSRF token on state-changing requests, and
+/// make the current token available to handlers as [`CsrfToken`].
+pub async fn protect(jar: CookieJar, mut request: Request, next: Next) -> Response {
+    let (secret, new_cookie) = match binding(&jar) {
+        Some(secret) => (secret, None),
+        None => {
+            let secret = Uuid::new_v4().simple().to_string();
+            (secret.clone(), Some(secret))
+        }
+    };
+    let token = token_for(&secret);
+
+    if !is_safe(request.method()) && bearer_token(request.headers()).is_none() {
+        let uri = request.uri().clone();
+        request = match verify(request, &token).await {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::warn!(%uri, "CSRF check failed");
+                return e.into_response();
+            }
+        };
+    }
+
+    let _ = request.extensions_mut().insert(CsrfToken(token));
+    let mut response = next.run(request).await;
+
+    // Assets, metrics and JSON APIs never carry the token, so there's no
+    // reason to hand their clients a cookie.
+    if let Some(secret) = new_cookie
+        && has_content_type(response.headers(), "text/html")
+    {
+        let cookie = Cookie::build((CSRF_COOKIE, secret))
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Strict)
+            .path("/")
+            .build();
+        if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+            let _ = response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Form, Router,
+        body::Body,
+        http::{Request, StatusCode},
+        response::Html,
+        routing::post,
+    };
+    use serde::Deserialize;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct NameForm {
+        name: String,
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/echo",
+                post(|Form(form): Form<NameForm>| async move { form.name }),
+            )
+            .route(
+                "/token",
+                axum::routing::get(|CsrfToken(token): CsrfToken| async move { Html(token) }),
+            )
+            .route("/healthcheck", axum::routing::get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(protect))
+    }
+
+    fn form_post(cookie: &str) -> axum::http::request::Builder {
+        Request::post("/echo")
+            .header(header::COOKIE, cookie)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+    }
+
+    async fn body_text(res: Response) -> String {
+        let bytes = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn post_without_token_is_rejected() {
+        let res = app()
+            .oneshot(form_post("green_session=abc").body(Body::from("name=x")).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn post_with_header_token_is_accepted() {
+        let res = app()
+            .oneshot(
+                form_post("green_session=abc")
+                    .header(CSRF_HEADER, token_for("abc"))
+                    .body(Body::from("name=x"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn form_field_is_accepted_and_body_still_reaches_handler() {
+        let body = format!("name=hal&{CSRF_FIELD}={}", token_for("abc"));
+        let res = app()
+            .oneshot(form_post("green_session=abc").body(Body::from(body)).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_text(res).await, "hal");
+    }
+
+    #[tokio::test]
+    async fn token_for_another_session_is_rejected() {
+        let res = app()
+            .oneshot(
+                form_post("green_session=abc")
+                    .header(CSRF_HEADER, token_for("other"))
+                    .body(Body::from("name=x"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn bearer_requests_are_exempt() {
+        let res = app()
+            .oneshot(
+                Request::post("/echo")
+                    .header(header::AUTHORIZATION, "Bearer api-token")
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from("name=x"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn visitor_without_session_gets_a_cookie_bound_token() {
+        let res = app()
+            .oneshot(Request::get("/token").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = res
+            .headers()
+            .get(header::SET_COOKIE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned)
+            .unwrap();
+        let secret = cookie
+            .strip_prefix("green_csrf=")
+            .and_then(|rest| rest.split(';').next())
+            .unwrap()
+            .to_owned();
+        assert!(cookie.contains("HttpOnly"));
+        assert_eq!(body_text(res).await, token_for(&secret));
+
+        // The next post from the same visitor passes with that token.
+        let res = app()
+            .oneshot(
+                form_post(&format!("{CSRF_COOKIE}={secret}"))
+                    .header(CSRF_HEADER, token_for(&secret))
+                    .body(Body::from("name=x"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn non_html_responses_set_no_cookie() {
+        let res = app()
+            .oneshot(Request::get("/healthcheck").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+    }
+}
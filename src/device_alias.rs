@@ -0,0 +1,520 @@
+//! Friendly names and metadata for devices in the MQTT inventory.
+//!
+//! The device tracker only knows what it can read from topics, so a Zigbee
+//! sensor shows up as `0x00158d0001234567`. An alias records that it is the
+//! garage door sensor, which room it's in and which breaker feeds it. Aliases
+//! are keyed by `(integration, device_id)`, the pair the tracker extracts, and
+//! are edited from the device panel on `/mqtt/devices`.
+//!
+//! [`DeviceAliases`] keeps every alias in memory so message cards and metric
+//! labels resolve without a query; the [`AliasStore`] is read once at startup
+//! and written on each edit.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use axum::{Form, extract::State, response::Redirect};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+
+use crate::{
+    ServerState,
+    breaker_detail::BreakerDetailStore,
+    error::Error,
+    mqtt::{Integration, device_for_topic, html_escape},
+    permission::{MqttAdmin, RequirePermission},
+};
+
+/// Longest name or room, in characters.
+const MAX_NAME_CHARS: usize = 64;
+/// Longest notes, in characters.
+const MAX_NOTES_CHARS: usize = 1000;
+/// Most tags per device.
+const MAX_TAGS: usize = 16;
+/// Longest tag, in characters.
+const MAX_TAG_CHARS: usize = 32;
+
+/// Where the edit form redirects back to.
+const DEVICES_PATH: &str = "/mqtt/devices";
+
+/// A friendly name and notes for one tracked device.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceAlias {
+    /// Integration display name, as in `mqtt_devices`.
+    pub integration: String,
+    /// Device identifier as seen in the topic.
+    pub device_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub room: String,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Breaker panel slot feeding the device, e.g. `3-left`.
+    #[serde(default)]
+    pub breaker_slot: Option<String>,
+}
+
+/// Storage backend for device aliases.
+pub trait AliasStore: Send + Sync + std::fmt::Debug {
+    fn list(&self) -> BoxFuture<'_, Result<Vec<DeviceAlias>, Error>>;
+
+    /// Create the alias or replace the one for the same device.
+    fn upsert<'a>(&'a self, alias: &'a DeviceAlias) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Remove a device's alias, returning whether it had one.
+    fn delete<'a>(&'a self, integration: &'a str, device_id: &'a str) -> BoxFuture<'a, Result<bool, Error>>;
+}
+
+/// [`AliasStore`] backed by the `device_aliases` table.
+#[derive(Debug, Clone)]
+pub struct PgAliasStore {
+    db: PgPool,
+}
+
+impl PgAliasStore {
+    pub fn new(db: PgPool) -> Self {
+        PgAliasStore { db }
+    }
+}
+
+impl AliasStore for PgAliasStore {
+    fn list(&self) -> BoxFuture<'_, Result<Vec<DeviceAlias>, Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT integration, device_id, name, room, notes, tags, breaker_slot \
+                 FROM device_aliases ORDER BY integration, device_id",
+            )
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(rows
+                .iter()
+                .map(|row| DeviceAlias {
+                    integration: row.get("integration"),
+                    device_id: row.get("device_id"),
+                    name: row.get("name"),
+                    room: row.get("room"),
+                    notes: row.get("notes"),
+                    tags: row.get("tags"),
+                    breaker_slot: row.get("breaker_slot"),
+                })
+                .collect())
+        })
+    }
+
+    fn upsert<'a>(&'a self, alias: &'a DeviceAlias) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query(
+                "INSERT INTO device_aliases (integration, device_id, name, room, notes, tags, breaker_slot) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7) \
+                 ON CONFLICT (integration, device_id) DO UPDATE SET name = EXCLUDED.name, \
+                     room = EXCLUDED.room, notes = EXCLUDED.notes, tags = EXCLUDED.tags, \
+                     breaker_slot = EXCLUDED.breaker_slot, updated_at = NOW()",
+            )
+            .bind(&alias.integration)
+            .bind(&alias.device_id)
+            .bind(&alias.name)
+            .bind(&alias.room)
+            .bind(&alias.notes)
+            .bind(&alias.tags)
+            .bind(&alias.breaker_slot)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, integration: &'a str, device_id: &'a str) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let result = sqlx::query("DELETE FROM device_aliases WHERE integration = $1 AND device_id = $2")
+                .bind(integration)
+                .bind(device_id)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(result.rows_affected() > 0)
+        })
+    }
+}
+
+/// [`AliasStore`] kept in process memory, for tests.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryAliasStore {
+    aliases: tokio::sync::Mutex<Vec<DeviceAlias>>,
+}
+
+#[cfg(test)]
+impl AliasStore for MemoryAliasStore {
+    fn list(&self) -> BoxFuture<'_, Result<Vec<DeviceAlias>, Error>> {
+        Box::pin(async move { Ok(self.aliases.lock().await.clone()) })
+    }
+
+    fn upsert<'a>(&'a self, alias: &'a DeviceAlias) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut aliases = self.aliases.lock().await;
+            aliases.retain(|a| (&a.integration, &a.device_id) != (&alias.integration, &alias.device_id));
+            aliases.push(alias.clone());
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, integration: &'a str, device_id: &'a str) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let mut aliases = self.aliases.lock().await;
+            let before = aliases.len();
+            aliases.retain(|a| (a.integration.as_str(), a.device_id.as_str()) != (integration, device_id));
+            Ok(aliases.len() < before)
+        })
+    }
+}
+
+// ─── In-memory lookup ─────────────────────────────────────────────────────────
+
+/// Every alias, by `(integration, device_id)`, with the integrations needed to
+/// find the device a topic belongs to.
+#[derive(Debug, Default)]
+pub struct DeviceAliases {
+    integrations: Arc<Vec<Integration>>,
+    by_device: RwLock<HashMap<(String, String), DeviceAlias>>,
+}
+
+impl DeviceAliases {
+    pub(crate) fn new(integrations: Arc<Vec<Integration>>, aliases: Vec<DeviceAlias>) -> Self {
+        let by_device = aliases
+            .into_iter()
+            .map(|a| ((a.integration.clone(), a.device_id.clone()), a))
+            .collect();
+        DeviceAliases {
+            integrations,
+            by_device: RwLock::new(by_device),
+        }
+    }
+
+    pub fn get(&self, integration: &str, device_id: &str) -> Option<DeviceAlias> {
+        self.by_device
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&(integration.to_owned(), device_id.to_owned()))
+            .cloned()
+    }
+
+    /// The friendly name of a device, if it has one.
+    pub fn name(&self, integration: &str, device_id: &str) -> Option<String> {
+        self.get(integration, device_id).map(|a| a.name)
+    }
+
+    /// The friendly name of the device `topic` belongs to, if any.
+    pub fn name_for_topic(&self, topic: &str) -> Option<String> {
+        let (integration, device_id) = device_for_topic(&self.integrations, topic)?;
+        self.name(integration, device_id)
+    }
+
+    /// The device's name for a Prometheus `device` label: `None` if it has no
+    /// alias or another device has the same one, since their series would
+    /// otherwise merge into one.
+    pub fn metric_label(&self, integration: &str, device_id: &str) -> Option<String> {
+        let by_device = self.by_device.read().unwrap_or_else(PoisonError::into_inner);
+        let name = &by_device.get(&(integration.to_owned(), device_id.to_owned()))?.name;
+        let shared = by_device.values().filter(|a| a.name == *name).nth(1).is_some();
+        (!shared).then(|| name.clone())
+    }
+
+    /// [`Self::metric_label`] for the device `topic` belongs to.
+    pub fn metric_label_for_topic(&self, topic: &str) -> Option<String> {
+        let (integration, device_id) = device_for_topic(&self.integrations, topic)?;
+        self.metric_label(integration, device_id)
+    }
+
+    pub fn set(&self, alias: DeviceAlias) {
+        let key = (alias.integration.clone(), alias.device_id.clone());
+        let _ = self.by_device.write().unwrap_or_else(PoisonError::into_inner).insert(key, alias);
+    }
+
+    pub fn remove(&self, integration: &str, device_id: &str) {
+        let _ = self
+            .by_device
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&(integration.to_owned(), device_id.to_owned()));
+    }
+}
+
+// ─── Editing ──────────────────────────────────────────────────────────────────
+
+/// Form body for `POST /mqtt/devices/alias`.
+#[derive(Debug, Default, Deserialize)]
+pub struct AliasForm {
+    pub integration: String,
+    pub device_id: String,
+    /// Blank removes the alias.
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub room: String,
+    #[serde(default)]
+    pub notes: String,
+    /// Comma-separated.
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub breaker_slot: String,
+}
+
+fn check_len(field: &str, value: &str, max: usize) -> Result<(), Error> {
+    if value.chars().count() > max {
+        return Err(Error::InvalidDeviceAlias(format!("{field} is longer than {max} characters")));
+    }
+    Ok(())
+}
+
+/// Is `key` a slot on the breaker panel, i.e. `{row}-left` or `{row}-right`?
+fn is_breaker_slot(breakers: &dyn BreakerDetailStore, key: &str) -> bool {
+    key.split_once('-').is_some_and(|(row, side)| {
+        matches!(side, "left" | "right")
+            && row.parse::<u32>().is_ok_and(|row| (1..=breakers.row_count()).contains(&row))
+    })
+}
+
+impl AliasForm {
+    /// The alias to save, or `None` when the name is blank and the alias
+    /// should be removed.
+    pub fn alias(self, breakers: &dyn BreakerDetailStore) -> Result<Option<DeviceAlias>, Error> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Ok(None);
+        }
+        let room = self.room.trim();
+        let notes = self.notes.trim();
+        check_len("name", name, MAX_NAME_CHARS)?;
+        check_len("room", room, MAX_NAME_CHARS)?;
+        check_len("notes", notes, MAX_NOTES_CHARS)?;
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            check_len("a tag", tag, MAX_TAG_CHARS)?;
+            if !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_owned());
+            }
+        }
+        if tags.len() > MAX_TAGS {
+            return Err(Error::InvalidDeviceAlias(format!("at most {MAX_TAGS} tags")));
+        }
+
+        let breaker_slot = match self.breaker_slot.trim() {
+            "" => None,
+            slot if is_breaker_slot(breakers, slot) => Some(slot.to_owned()),
+            slot => return Err(Error::InvalidDeviceAlias(format!("no breaker slot `{slot}`"))),
+        };
+
+        Ok(Some(DeviceAlias {
+            integration: self.integration,
+            device_id: self.device_id,
+            name: name.to_owned(),
+            room: room.to_owned(),
+            notes: notes.to_owned(),
+            tags,
+            breaker_slot,
+        }))
+    }
+}
+
+/// The edit form shown in a device's panel, filled in with its current alias.
+pub(crate) fn render_alias_form(integration: &str, device_id: &str, alias: Option<&DeviceAlias>) -> String {
+    let alias = alias.cloned().unwrap_or_default();
+    format!(
+        r#"<form method="POST" action="/mqtt/devices/alias" class="device-alias-form">
+<input type="hidden" name="integration" value="{integration}">
+<input type="hidden" name="device_id" value="{device_id}">
+<div class="device-alias-fields">
+<input class="leet-input" name="name" type="text" placeholder="name  e.g. garage door sensor" value="{name}" maxlength="{MAX_NAME_CHARS}">
+<input class="leet-input" name="room" type="text" placeholder="room" value="{room}" maxlength="{MAX_NAME_CHARS}">
+<input class="leet-input" name="tags" type="text" placeholder="tags, comma separated" value="{tags}">
+<input class="leet-input" name="breaker_slot" type="text" placeholder="breaker  e.g. 3-left" value="{breaker_slot}">
+<textarea class="leet-input" name="notes" rows="2" placeholder="notes" maxlength="{MAX_NOTES_CHARS}">{notes}</textarea>
+</div>
+<button class="leet-btn" type="submit">save name</button>
+<span class="leet-muted">a blank name removes the alias</span>
+</form>"#,
+        integration = html_escape(integration),
+        device_id = html_escape(device_id),
+        name = html_escape(&alias.name),
+        room = html_escape(&alias.room),
+        tags = html_escape(&alias.tags.join(", ")),
+        breaker_slot = html_escape(alias.breaker_slot.as_deref().unwrap_or_default()),
+        notes = html_escape(&alias.notes),
+    )
+}
+
+/// POST `/mqtt/devices/alias` — save or remove a device's alias.
+pub async fn save_alias_route(
+    _user: RequirePermission<MqttAdmin>,
+    State(state): State<ServerState>,
+    Form(form): Form<AliasForm>,
+) -> Result<Redirect, Error> {
+    let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    let store = mqtt.alias_store.as_ref().ok_or(Error::NotFound)?;
+    if !mqtt.integrations.iter().any(|i| i.name() == form.integration) {
+        return Err(Error::InvalidDeviceAlias(format!("unknown integration `{}`", form.integration)));
+    }
+
+    let (integration, device_id) = (form.integration.clone(), form.device_id.clone());
+    match form.alias(state.breaker_detail_store.as_ref())? {
+        Some(alias) => {
+            store.upsert(&alias).await?;
+            tracing::info!(integration, device_id, name = %alias.name, "saved device alias");
+            mqtt.aliases.set(alias);
+        }
+        None => {
+            let _ = store.delete(&integration, &device_id).await?;
+            mqtt.aliases.remove(&integration, &device_id);
+            tracing::info!(integration, device_id, "removed device alias");
+        }
+    }
+    Ok(Redirect::to(DEVICES_PATH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        breaker_detail::{BreakerData, BreakerSlot, BreakerStore},
+        mqtt::{IntegrationConfig, parse_integrations},
+    };
+
+    fn breakers() -> BreakerStore {
+        let slot = BreakerSlot { label: None, amperage: None, devices: None, notes: None };
+        BreakerStore::from_data(BreakerData {
+            todos: vec![],
+            slots: HashMap::from([("4-left".to_owned(), slot)]),
+            couples: vec![],
+        })
+        .unwrap()
+    }
+
+    fn form(name: &str) -> AliasForm {
+        AliasForm {
+            integration: "zigbee2mqtt".into(),
+            device_id: "0x00158d0001234567".into(),
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn form_trims_fields_and_dedups_tags() {
+        let alias = AliasForm {
+            room: " garage ".into(),
+            tags: "door, sensor,, door ".into(),
+            breaker_slot: "2-right".into(),
+            ..form("  garage door sensor ")
+        }
+        .alias(&breakers())
+        .unwrap()
+        .unwrap();
+        assert_eq!(alias.name, "garage door sensor");
+        assert_eq!(alias.room, "garage");
+        assert_eq!(alias.tags, ["door", "sensor"]);
+        assert_eq!(alias.breaker_slot.as_deref(), Some("2-right"));
+    }
+
+    #[test]
+    fn blank_name_means_remove() {
+        assert_eq!(form("   ").alias(&breakers()).unwrap(), None);
+    }
+
+    #[test]
+    fn form_rejects_unknown_breaker_slots_and_long_fields() {
+        for slot in ["5-left", "0-left", "2-middle", "garage"] {
+            let form = AliasForm { breaker_slot: slot.into(), ..form("door") };
+            assert!(matches!(form.alias(&breakers()), Err(Error::InvalidDeviceAlias(_))), "{slot}");
+        }
+        let long = form(&"x".repeat(MAX_NAME_CHARS + 1));
+        assert!(matches!(long.alias(&breakers()), Err(Error::InvalidDeviceAlias(_))));
+        let tags = (0..=MAX_TAGS).map(|i| i.to_string()).collect::<Vec<_>>().join(",");
+        let many = AliasForm { tags, ..form("door") };
+        assert!(matches!(many.alias(&breakers()), Err(Error::InvalidDeviceAlias(_))));
+    }
+
+    #[test]
+    fn aliases_resolve_by_device_and_topic() {
+        let integrations = Arc::new(parse_integrations(&[IntegrationConfig {
+            pattern: "zigbee2mqtt/{device}/**".into(),
+            name: None,
+            ..Default::default()
+        }]));
+        let door = DeviceAlias {
+            integration: "zigbee2mqtt".into(),
+            device_id: "0xABCD".into(),
+            name: "garage door".into(),
+            ..Default::default()
+        };
+        let aliases = DeviceAliases::new(integrations, vec![door.clone()]);
+        assert_eq!(aliases.name_for_topic("zigbee2mqtt/0xABCD/availability").as_deref(), Some("garage door"));
+        assert_eq!(aliases.name_for_topic("zigbee2mqtt/0xEEEE"), None);
+        assert_eq!(aliases.name_for_topic("frigate/events"), None);
+
+        aliases.set(DeviceAlias { name: "side door".into(), ..door });
+        assert_eq!(aliases.name("zigbee2mqtt", "0xABCD").as_deref(), Some("side door"));
+        aliases.remove("zigbee2mqtt", "0xABCD");
+        assert_eq!(aliases.get("zigbee2mqtt", "0xABCD"), None);
+    }
+
+    #[test]
+    fn shared_aliases_are_not_metric_labels() {
+        let integrations = Arc::new(parse_integrations(&[IntegrationConfig {
+            pattern: "zigbee2mqtt/{device}/**".into(),
+            name: None,
+            ..Default::default()
+        }]));
+        let lamp = |device_id: &str, name: &str| DeviceAlias {
+            integration: "zigbee2mqtt".into(),
+            device_id: device_id.into(),
+            name: name.into(),
+            ..Default::default()
+        };
+        let aliases = DeviceAliases::new(
+            integrations,
+            vec![lamp("0x01", "lamp"), lamp("0x02", "lamp"), lamp("0x03", "desk lamp")],
+        );
+        assert_eq!(aliases.metric_label("zigbee2mqtt", "0x01"), None);
+        assert_eq!(aliases.metric_label_for_topic("zigbee2mqtt/0x02"), None);
+        assert_eq!(aliases.metric_label("zigbee2mqtt", "0x03").as_deref(), Some("desk lamp"));
+        assert_eq!(aliases.metric_label("zigbee2mqtt", "0x04"), None);
+
+        aliases.set(lamp("0x02", "floor lamp"));
+        assert_eq!(aliases.metric_label("zigbee2mqtt", "0x01").as_deref(), Some("lamp"));
+    }
+
+    #[tokio::test]
+    async fn memory_store_upserts_and_deletes() {
+        let store = MemoryAliasStore::default();
+        let alias = form("door").alias(&breakers()).unwrap().unwrap();
+        store.upsert(&alias).await.unwrap();
+        store.upsert(&DeviceAlias { name: "gate".into(), ..alias.clone() }).await.unwrap();
+        let listed = store.list().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "gate");
+        assert!(store.delete(&alias.integration, &alias.device_id).await.unwrap());
+        assert!(!store.delete(&alias.integration, &alias.device_id).await.unwrap());
+    }
+
+    #[test]
+    fn alias_form_is_prefilled_and_escaped() {
+        let alias = DeviceAlias {
+            name: r#"the "big" door"#.into(),
+            tags: vec!["a".into(), "b".into()],
+            ..Default::default()
+        };
+        let html = render_alias_form("zigbee2mqtt", "0x<1>", Some(&alias));
+        assert!(html.contains(r#"value="the &quot;big&quot; door""#));
+        assert!(html.contains(r#"value="a, b""#));
+        assert!(html.contains(r#"name="device_id" value="0x&lt;1&gt;""#));
+    }
+}
@@ -0,0 +1,281 @@
+//! Noticing when MQTT devices stop reporting.
+//!
+//! Devices of an integration with `expected_interval_mins` set are watched: one
+//! that has been silent that long is [`DeviceHealth::Stale`], and after twice
+//! as long it is [`DeviceHealth::Offline`]. The device tracker keeps an
+//! [`OfflineWatch`] of when each device was last heard from and checks it every
+//! [`CHECK_INTERVAL`]. A device going offline, or coming back, is posted to
+//! ntfy as a [`DeviceAlert`].
+
+use std::{collections::HashMap, time::Duration};
+
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::recovery::NtfyDelivery;
+
+/// How often the tracker looks for devices that have gone offline.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether a watched device is reporting as often as expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum DeviceHealth {
+    /// Heard from within its expected interval.
+    Online,
+    /// Silent for longer than its expected interval.
+    Stale,
+    /// Silent for more than twice its expected interval.
+    Offline,
+}
+
+impl DeviceHealth {
+    /// Health of a device that has been silent for `silent_for` and is
+    /// expected to report at least every `expected_interval`.
+    pub fn of(silent_for: Duration, expected_interval: Duration) -> Self {
+        if silent_for >= expected_interval * 2 {
+            DeviceHealth::Offline
+        } else if silent_for >= expected_interval {
+            DeviceHealth::Stale
+        } else {
+            DeviceHealth::Online
+        }
+    }
+
+    /// Health of a device last heard from at `last_seen`.
+    pub fn at(last_seen: OffsetDateTime, now: OffsetDateTime, expected_interval: Duration) -> Self {
+        DeviceHealth::of(silence(last_seen, now), expected_interval)
+    }
+}
+
+/// Time between `last_seen` and `now`; zero if the clock went backwards.
+fn silence(last_seen: OffsetDateTime, now: OffsetDateTime) -> Duration {
+    (now - last_seen).try_into().unwrap_or_default()
+}
+
+/// `2h 5m`-style rendering of a silence, to the minute.
+pub fn format_silence(d: Duration) -> String {
+    let minutes = d.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => "under a minute".to_owned(),
+        (0, 0, m) => format!("{m}m"),
+        (0, h, 0) => format!("{h}h"),
+        (0, h, m) => format!("{h}h {m}m"),
+        (d, 0, _) => format!("{d}d"),
+        (d, h, _) => format!("{d}d {h}h"),
+    }
+}
+
+// ─── Alerts ───────────────────────────────────────────────────────────────────
+
+/// A watched device changing between offline and reporting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceAlert {
+    /// The device has been silent for more than twice its interval.
+    Offline {
+        integration: String,
+        device_id: String,
+        silent_for: Duration,
+    },
+    /// An offline device sent a message again.
+    BackOnline {
+        integration: String,
+        device_id: String,
+        silent_for: Duration,
+    },
+}
+
+impl DeviceAlert {
+    /// `(integration, device_id)` of the device the alert is about.
+    pub fn device(&self) -> (&str, &str) {
+        match self {
+            DeviceAlert::Offline { integration, device_id, .. }
+            | DeviceAlert::BackOnline { integration, device_id, .. } => (integration, device_id),
+        }
+    }
+
+    /// ntfy title, naming the device by `name` (its alias) when it has one.
+    pub fn title(&self, name: Option<&str>) -> String {
+        let name = name.unwrap_or(self.device().1);
+        match self {
+            DeviceAlert::Offline { .. } => format!("{name} is offline"),
+            DeviceAlert::BackOnline { .. } => format!("{name} is back online"),
+        }
+    }
+
+    /// ntfy message body.
+    pub fn body(&self) -> String {
+        let (integration, device_id) = self.device();
+        match self {
+            DeviceAlert::Offline { silent_for, .. } => {
+                format!("{integration}/{device_id}: no messages for {}", format_silence(*silent_for))
+            }
+            DeviceAlert::BackOnline { silent_for, .. } => {
+                format!("{integration}/{device_id}: reporting again after {}", format_silence(*silent_for))
+            }
+        }
+    }
+
+    /// Post the alert to `ntfy`. Failures are logged, not retried.
+    pub async fn send(self, ntfy: &NtfyDelivery, name: Option<String>) {
+        let priority = match self {
+            DeviceAlert::Offline { .. } => "high",
+            DeviceAlert::BackOnline { .. } => "default",
+        };
+        let (integration, device_id) = self.device();
+        if let Err(err) = ntfy.notify(&self.title(name.as_deref()), priority, self.body()).await {
+            tracing::warn!(%err, integration, device_id, "failed to send device alert");
+        }
+    }
+}
+
+// ─── Watch ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug)]
+struct Watched {
+    last_seen: OffsetDateTime,
+    expected_interval: Duration,
+    offline: bool,
+}
+
+/// When each watched device was last heard from, and which are offline.
+#[derive(Debug, Default)]
+pub struct OfflineWatch {
+    devices: HashMap<(String, String), Watched>,
+}
+
+impl OfflineWatch {
+    /// Start watching a device last heard from at `last_seen`, as read from
+    /// the database at startup. A device that is already offline is marked so
+    /// without an alert, so a restart doesn't repeat old alerts.
+    pub fn seed(
+        &mut self,
+        integration: &str,
+        device_id: &str,
+        last_seen: OffsetDateTime,
+        expected_interval: Duration,
+        now: OffsetDateTime,
+    ) {
+        let offline = DeviceHealth::at(last_seen, now, expected_interval) == DeviceHealth::Offline;
+        let _ = self.devices.insert(
+            (integration.to_owned(), device_id.to_owned()),
+            Watched { last_seen, expected_interval, offline },
+        );
+    }
+
+    /// Record a message from a device, returning [`DeviceAlert::BackOnline`]
+    /// if it had been offline.
+    pub fn seen(
+        &mut self,
+        integration: &str,
+        device_id: &str,
+        expected_interval: Duration,
+        now: OffsetDateTime,
+    ) -> Option<DeviceAlert> {
+        let key = (integration.to_owned(), device_id.to_owned());
+        let Some(watched) = self.devices.get_mut(&key) else {
+            let _ = self.devices.insert(key, Watched { last_seen: now, expected_interval, offline: false });
+            return None;
+        };
+        let silent_for = silence(watched.last_seen, now);
+        let was_offline = watched.offline;
+        *watched = Watched { last_seen: now, expected_interval, offline: false };
+        was_offline.then(|| DeviceAlert::BackOnline {
+            integration: integration.to_owned(),
+            device_id: device_id.to_owned(),
+            silent_for,
+        })
+    }
+
+    /// Devices that have gone offline since the last check.
+    pub fn check(&mut self, now: OffsetDateTime) -> Vec<DeviceAlert> {
+        let mut alerts: Vec<DeviceAlert> = self
+            .devices
+            .iter_mut()
+            .filter(|(_, w)| !w.offline && DeviceHealth::at(w.last_seen, now, w.expected_interval) == DeviceHealth::Offline)
+            .map(|((integration, device_id), w)| {
+                w.offline = true;
+                DeviceAlert::Offline {
+                    integration: integration.clone(),
+                    device_id: device_id.clone(),
+                    silent_for: silence(w.last_seen, now),
+                }
+            })
+            .collect();
+        alerts.sort_by(|a, b| a.device().cmp(&b.device()));
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn t0() -> OffsetDateTime {
+        time::macros::datetime!(2026-03-15 12:00 UTC)
+    }
+
+    #[test]
+    fn health_is_stale_after_the_interval_and_offline_after_twice_it() {
+        assert_eq!(DeviceHealth::of(HOUR / 2, HOUR), DeviceHealth::Online);
+        assert_eq!(DeviceHealth::of(HOUR, HOUR), DeviceHealth::Stale);
+        assert_eq!(DeviceHealth::of(HOUR * 2, HOUR), DeviceHealth::Offline);
+        // A last_seen in the future (clock skew) is just online.
+        assert_eq!(DeviceHealth::at(t0() + HOUR, t0(), HOUR), DeviceHealth::Online);
+    }
+
+    #[test]
+    fn format_silence_rounds_to_the_minute() {
+        assert_eq!(format_silence(Duration::from_secs(59)), "under a minute");
+        assert_eq!(format_silence(Duration::from_secs(45 * 60)), "45m");
+        assert_eq!(format_silence(HOUR * 2), "2h");
+        assert_eq!(format_silence(HOUR * 2 + Duration::from_secs(5 * 60)), "2h 5m");
+        assert_eq!(format_silence(HOUR * 24), "1d");
+        assert_eq!(format_silence(HOUR * 75), "3d 3h");
+    }
+
+    #[test]
+    fn device_alerts_once_when_offline_and_once_when_back() {
+        let mut watch = OfflineWatch::default();
+        assert_eq!(watch.seen("zigbee2mqtt", "door", HOUR, t0()), None);
+
+        assert!(watch.check(t0() + HOUR).is_empty(), "stale isn't alerted");
+        let alerts = watch.check(t0() + HOUR * 2);
+        assert_eq!(
+            alerts,
+            vec![DeviceAlert::Offline {
+                integration: "zigbee2mqtt".into(),
+                device_id: "door".into(),
+                silent_for: HOUR * 2,
+            }]
+        );
+        assert!(watch.check(t0() + HOUR * 3).is_empty(), "already alerted");
+
+        let back = watch.seen("zigbee2mqtt", "door", HOUR, t0() + HOUR * 5).unwrap();
+        assert_eq!(back.title(Some("front door")), "front door is back online");
+        assert_eq!(back.body(), "zigbee2mqtt/door: reporting again after 5h");
+        assert_eq!(watch.seen("zigbee2mqtt", "door", HOUR, t0() + HOUR * 6), None);
+    }
+
+    #[test]
+    fn seeding_an_offline_device_does_not_alert_until_it_returns() {
+        let mut watch = OfflineWatch::default();
+        watch.seed("zigbee2mqtt", "old", t0(), HOUR, t0() + HOUR * 10);
+        watch.seed("zigbee2mqtt", "recent", t0() + HOUR * 9, HOUR, t0() + HOUR * 10);
+        assert!(watch.check(t0() + HOUR * 10).is_empty());
+
+        let alerts = watch.check(t0() + HOUR * 11);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].title(None), "recent is offline");
+        assert_eq!(alerts[0].body(), "zigbee2mqtt/recent: no messages for 2h");
+
+        assert!(matches!(
+            watch.seen("zigbee2mqtt", "old", HOUR, t0() + HOUR * 12),
+            Some(DeviceAlert::BackOnline { .. })
+        ));
+    }
+}
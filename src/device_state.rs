@@ -0,0 +1,347 @@
+//! The latest state of each MQTT device, merged from its messages.
+//!
+//! zigbee2mqtt publishes a device's whole state as one JSON object on the
+//! device topic, while other integrations spread it over sub-topics. The
+//! device tracker folds every message into a [`DeviceState`]: the keys of a
+//! JSON object on the device's own topic are merged in, anything else there is
+//! kept as `value`, and a payload on a sub-topic such as `availability` is kept
+//! under the sub-topic's name. Command topics (`…/set`, `…/get`) are ignored.
+//!
+//! Numeric fields also keep their last [`HISTORY_POINTS`] values, drawn as
+//! sparklines on `/mqtt/devices/{integration}/{device}`. State lives in memory
+//! only, so it starts empty after a restart.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use askama::Template;
+use axum::{
+    Json,
+    extract::{Path as UrlPath, State},
+    response::Html,
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+
+use crate::{
+    ServerState,
+    account::format_timestamp,
+    auth::AuthUserInfo,
+    device_alias::DeviceAlias,
+    device_health::DeviceHealth,
+    error::Error,
+    index::NavLink,
+    mqtt::{Integration, MqttMessage, MqttState, html_escape},
+    permission::{MqttRead, RequirePermission},
+};
+
+/// Values of each numeric field kept for its sparkline.
+pub const HISTORY_POINTS: usize = 60;
+
+/// Most fields kept per device; later new keys are dropped.
+pub const MAX_FIELDS: usize = 100;
+
+/// Sparkline size, in SVG user units.
+const SPARK_WIDTH: f64 = 120.0;
+const SPARK_HEIGHT: f64 = 24.0;
+
+/// What a device last reported.
+#[derive(Debug, Clone)]
+pub struct DeviceState {
+    /// Top-level fields, merged across messages.
+    pub fields: Map<String, Value>,
+    /// `received_at` of the latest message folded in.
+    pub updated_at: String,
+    /// When the latest message was folded in.
+    pub updated: OffsetDateTime,
+    /// Recent values of numeric fields, oldest first.
+    history: BTreeMap<String, Vec<f64>>,
+}
+
+impl DeviceState {
+    fn new(now: OffsetDateTime) -> Self {
+        DeviceState {
+            fields: Map::new(),
+            updated_at: String::new(),
+            updated: now,
+            history: BTreeMap::new(),
+        }
+    }
+
+    /// Fold a message on `subtopic` (the part of its topic after the device,
+    /// empty for the device topic itself) into the state. Returns whether
+    /// anything changed.
+    pub fn apply(&mut self, subtopic: &str, msg: &MqttMessage, now: OffsetDateTime) -> bool {
+        if !msg.kind.is_text() || matches!(subtopic.rsplit('/').next(), Some("set" | "get")) {
+            return false;
+        }
+        // Plain text such as `online` isn't JSON; keep it as a string.
+        let value = serde_json::from_str(&msg.payload).unwrap_or_else(|_| Value::String(msg.payload.clone()));
+        let fields = match (subtopic, value) {
+            ("", Value::Object(object)) => object.into_iter().collect(),
+            ("", value) => vec![("value".to_owned(), value)],
+            (subtopic, value) => vec![(subtopic.to_owned(), value)],
+        };
+        for (key, value) in fields {
+            if !self.fields.contains_key(&key) && self.fields.len() >= MAX_FIELDS {
+                continue;
+            }
+            if let Some(n) = value.as_f64() {
+                let points = self.history.entry(key.clone()).or_default();
+                if points.len() == HISTORY_POINTS {
+                    let _ = points.remove(0);
+                }
+                points.push(n);
+            }
+            let _ = self.fields.insert(key, value);
+        }
+        self.updated_at.clone_from(&msg.received_at);
+        self.updated = now;
+        true
+    }
+
+    /// Recent values of the numeric field `key`, oldest first.
+    pub fn history(&self, key: &str) -> &[f64] {
+        self.history.get(key).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// Every device's [`DeviceState`], keyed by `(integration, device_id)`.
+#[derive(Debug, Default)]
+pub struct DeviceStates {
+    by_device: RwLock<HashMap<(String, String), DeviceState>>,
+}
+
+impl DeviceStates {
+    /// Fold `msg` into the state of `device_id` under `integration`.
+    pub fn record(&self, integration: &str, device_id: &str, subtopic: &str, msg: &MqttMessage, now: OffsetDateTime) {
+        let mut by_device = self.by_device.write().unwrap_or_else(PoisonError::into_inner);
+        let key = (integration.to_owned(), device_id.to_owned());
+        let mut state = by_device.remove(&key).unwrap_or_else(|| DeviceState::new(now));
+        if state.apply(subtopic, msg, now) {
+            let _ = by_device.insert(key, state);
+        }
+    }
+
+    pub fn get(&self, integration: &str, device_id: &str) -> Option<DeviceState> {
+        self.by_device
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&(integration.to_owned(), device_id.to_owned()))
+            .cloned()
+    }
+}
+
+/// Path of a device's state page, with both parts percent-encoded.
+pub fn device_page_path(integration: &str, device_id: &str) -> String {
+    let encode = |s: &str| url::form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>().replace('+', "%20");
+    format!("/mqtt/devices/{}/{}", encode(integration), encode(device_id))
+}
+
+/// An inline SVG line through `points`, scaled to their range. A flat series
+/// is drawn across the middle.
+pub fn sparkline(points: &[f64]) -> String {
+    let (min, max) = points.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &p| (lo.min(p), hi.max(p)));
+    let step = SPARK_WIDTH / (points.len().max(2) - 1) as f64;
+    let coords: Vec<String> = points
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| {
+            let y = if max > min {
+                SPARK_HEIGHT - 1.0 - (p - min) / (max - min) * (SPARK_HEIGHT - 2.0)
+            } else {
+                SPARK_HEIGHT / 2.0
+            };
+            format!("{:.1},{y:.1}", i as f64 * step)
+        })
+        .collect();
+    format!(
+        r#"<svg class="device-state-spark" viewBox="0 0 {SPARK_WIDTH} {SPARK_HEIGHT}" width="{SPARK_WIDTH}" height="{SPARK_HEIGHT}" preserveAspectRatio="none" aria-hidden="true"><polyline points="{}"/></svg>"#,
+        coords.join(" ")
+    )
+}
+
+// ─── Page and API ─────────────────────────────────────────────────────────────
+
+/// The integration called `name`, 404 if there isn't one.
+fn find_integration<'m>(mqtt: &'m MqttState, name: &str) -> Result<&'m Integration, Error> {
+    mqtt.integrations.iter().find(|i| i.name() == name).ok_or(Error::NotFound)
+}
+
+/// One field on the device page.
+#[derive(Debug)]
+struct StateRow {
+    key: String,
+    value: String,
+    /// Pre-rendered SVG, for numeric fields with more than one value.
+    sparkline: Option<String>,
+    /// `min – max` of the sparkline's values.
+    range: Option<String>,
+}
+
+impl StateRow {
+    fn new(key: &str, value: &Value, history: &[f64]) -> Self {
+        let numeric = value.is_number() && history.len() > 1;
+        let range = numeric.then(|| {
+            let min = history.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = history.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            format!("{min} – {max}")
+        });
+        StateRow {
+            key: key.to_owned(),
+            value: match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            },
+            sparkline: numeric.then(|| sparkline(history)),
+            range,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "mqtt_device.html")]
+struct DevicePage {
+    version: &'static str,
+    auth_user: Option<AuthUserInfo>,
+    nav_links: Arc<[NavLink]>,
+    integration: String,
+    device_id: String,
+    alias: Option<DeviceAlias>,
+    health: Option<DeviceHealth>,
+    updated: Option<String>,
+    rows: Vec<StateRow>,
+    api_path: String,
+}
+
+/// GET `/mqtt/devices/{integration}/{device}` — a device's latest state, with
+/// sparklines of its numeric fields (requires `mqtt.read`).
+pub async fn device_page(
+    user: RequirePermission<MqttRead>,
+    UrlPath((integration, device_id)): UrlPath<(String, String)>,
+    State(state): State<ServerState>,
+) -> Result<Html<String>, Error> {
+    let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    let integration = find_integration(mqtt, &integration)?;
+    let name = integration.name();
+    let device_state = mqtt.device_states.get(name, &device_id);
+    let health = device_state
+        .as_ref()
+        .zip(integration.expected_interval(&device_id))
+        .map(|(s, after)| DeviceHealth::at(s.updated, OffsetDateTime::now_utc(), after));
+    let rows = device_state
+        .as_ref()
+        .map(|s| s.fields.iter().map(|(key, value)| StateRow::new(key, value, s.history(key))).collect())
+        .unwrap_or_default();
+
+    let page = DevicePage {
+        version: crate::VERSION,
+        auth_user: Some(user.0.info()),
+        nav_links: state.nav_links.clone(),
+        alias: mqtt.aliases.get(name, &device_id),
+        health,
+        updated: device_state.as_ref().map(|s| format_timestamp(s.updated)),
+        rows,
+        api_path: format!("/api{}/state", device_page_path(name, &device_id)),
+        integration: name.to_owned(),
+        device_id,
+    };
+    Ok(Html(page.render()?))
+}
+
+/// Body of `/api/mqtt/devices/{integration}/{device}/state`.
+#[derive(Debug, Serialize)]
+pub struct DeviceStateResponse {
+    pub integration: String,
+    pub device_id: String,
+    /// The device's alias, if it has one.
+    pub name: Option<String>,
+    /// `received_at` of the latest message folded into `state`.
+    pub updated_at: String,
+    pub state: Map<String, Value>,
+}
+
+/// GET `/api/mqtt/devices/{integration}/{device}/state` — a device's latest
+/// merged state as JSON; 404 until it has sent something (requires
+/// `mqtt.read`).
+pub async fn device_state_api(
+    _user: RequirePermission<MqttRead>,
+    UrlPath((integration, device_id)): UrlPath<(String, String)>,
+    State(state): State<ServerState>,
+) -> Result<Json<DeviceStateResponse>, Error> {
+    let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    let name = find_integration(mqtt, &integration)?.name();
+    let device_state = mqtt.device_states.get(name, &device_id).ok_or(Error::NotFound)?;
+    Ok(Json(DeviceStateResponse {
+        name: mqtt.aliases.name(name, &device_id),
+        integration,
+        device_id,
+        updated_at: device_state.updated_at,
+        state: device_state.fields,
+    }))
+}
+
+/// Link from the devices panel to a device's state page.
+pub(crate) fn render_state_link(integration: &str, device_id: &str) -> String {
+    format!(
+        r#"<p class="device-state-link"><a href="{}" class="leet-link">state &rarr;</a></p>"#,
+        html_escape(&device_page_path(integration, device_id))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::message;
+
+    fn t0() -> OffsetDateTime {
+        time::macros::datetime!(2026-03-15 12:00 UTC)
+    }
+
+    #[test]
+    fn merges_objects_and_keeps_subtopics_and_plain_values() {
+        let mut state = DeviceState::new(t0());
+        assert!(state.apply("", &message("z/lamp", r#"{"state":"ON","brightness":200}"#), t0()));
+        assert!(state.apply("", &message("z/lamp", r#"{"brightness":120,"linkquality":90}"#), t0()));
+        assert!(state.apply("availability", &message("z/lamp/availability", "online"), t0()));
+        assert!(!state.apply("set", &message("z/lamp/set", r#"{"state":"OFF"}"#), t0()));
+
+        assert_eq!(
+            Value::Object(state.fields.clone()),
+            serde_json::json!({"state": "ON", "brightness": 120, "linkquality": 90, "availability": "online"})
+        );
+        assert_eq!(state.history("brightness"), [200.0, 120.0]);
+        assert!(state.history("state").is_empty());
+
+        let mut sensor = DeviceState::new(t0());
+        assert!(sensor.apply("", &message("sensors/attic", "21.5"), t0()));
+        assert_eq!(sensor.fields.get("value"), Some(&serde_json::json!(21.5)));
+    }
+
+    #[test]
+    fn history_keeps_the_latest_points() {
+        let mut state = DeviceState::new(t0());
+        for i in 0..HISTORY_POINTS + 5 {
+            let _ = state.apply("", &message("z/t", &format!(r#"{{"temperature":{i}}}"#)), t0());
+        }
+        let history = state.history("temperature");
+        assert_eq!(history.len(), HISTORY_POINTS);
+        assert_eq!(history.first(), Some(&5.0));
+    }
+
+    #[test]
+    fn sparkline_scales_to_the_range() {
+        let svg = sparkline(&[0.0, 5.0, 10.0]);
+        assert!(svg.contains(r#"points="0.0,23.0 60.0,12.0 120.0,1.0""#), "{svg}");
+        let flat = sparkline(&[3.0, 3.0]);
+        assert!(flat.contains(r#"points="0.0,12.0 120.0,12.0""#), "{flat}");
+    }
+
+    #[test]
+    fn page_path_encodes_both_parts() {
+        assert_eq!(device_page_path("Home Assistant", "sensor/1"), "/mqtt/devices/Home%20Assistant/sensor%2F1");
+    }
+}
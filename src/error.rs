@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
 use tracing_subscriber::filter::ParseError;
 
 use crate::{breaker_detail, notes};
@@ -63,12 +66,16 @@ pub enum Error {
     #[error("failed to deserialize Tailscale response: {source}")]
     TailscaleDeserialize { source: serde_json::Error },
 
+    /// A missing or wrong bearer token. Answered with `WWW-Authenticate: Bearer`.
     #[error("authentication required")]
     Unauthorized,
 
     #[error("insufficient permissions")]
     Forbidden,
 
+    #[error("missing or invalid CSRF token")]
+    InvalidCsrfToken,
+
     #[error("WebAuthn error: {0}")]
     WebAuthn(String),
 
@@ -81,9 +88,57 @@ pub enum Error {
     #[error("invalid or expired recovery code")]
     InvalidRecoveryCode,
 
+    #[error("failed to send recovery code: {0}")]
+    RecoveryDelivery(String),
+
+    #[error("invalid email address: {0}")]
+    InvalidEmail(String),
+
+    #[error("cannot delete your only passkey")]
+    LastPasskey,
+
+    #[error("this account has been disabled")]
+    AccountDisabled,
+
+    #[error("registration is closed")]
+    RegistrationClosed,
+
+    #[error("a valid invite is required to register")]
+    InvalidInvite,
+
+    #[error("username is already taken")]
+    UsernameTaken,
+
+    #[error("unknown role `{0}`")]
+    UnknownRole(String),
+
+    #[error("invalid API token: {0}")]
+    InvalidTokenRequest(String),
+
+    #[error("invalid audit filter: {0}")]
+    InvalidAuditFilter(String),
+
+    #[error("too many requests; try again in {}s", retry_after_secs(*retry_after))]
+    RateLimited { retry_after: std::time::Duration },
+
     #[error("mqtt not configured")]
     MqttNotConfigured,
 
+    #[error("invalid mqtt configuration: {0}")]
+    MqttSetup(String),
+
+    #[error("invalid publish request: {0}")]
+    InvalidPublishRequest(String),
+
+    #[error("invalid stream filter: {0}")]
+    InvalidStreamFilter(String),
+
+    #[error("invalid history search: {0}")]
+    InvalidHistoryQuery(String),
+
+    #[error("invalid device alias: {0}")]
+    InvalidDeviceAlias(String),
+
     #[error("prometheus encode error: {0}")]
     PrometheusEncode(String),
 
@@ -96,13 +151,28 @@ impl IntoResponse for Error {
         let status = match &self {
             Error::NotFound => StatusCode::NOT_FOUND,
             Error::Unauthorized => StatusCode::UNAUTHORIZED,
-            Error::Forbidden => StatusCode::FORBIDDEN,
-            Error::WebAuthn(_) | Error::InvalidRecoveryCode | Error::QrEncode { .. } => {
-                StatusCode::BAD_REQUEST
-            }
+            Error::Forbidden
+            | Error::AccountDisabled
+            | Error::RegistrationClosed
+            | Error::InvalidInvite
+            | Error::InvalidCsrfToken => StatusCode::FORBIDDEN,
+            Error::LastPasskey | Error::UsernameTaken => StatusCode::CONFLICT,
+            Error::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Error::WebAuthn(_)
+            | Error::InvalidRecoveryCode
+            | Error::QrEncode { .. }
+            | Error::UnknownRole(_)
+            | Error::InvalidTokenRequest(_)
+            | Error::InvalidAuditFilter(_)
+            | Error::InvalidPublishRequest(_)
+            | Error::InvalidHistoryQuery(_)
+            | Error::InvalidStreamFilter(_)
+            | Error::InvalidDeviceAlias(_)
+            | Error::InvalidEmail(_) => StatusCode::BAD_REQUEST,
             Error::TailscaleConnect { .. }
             | Error::TailscaleParse(_)
-            | Error::TailscaleDeserialize { .. } => StatusCode::BAD_GATEWAY,
+            | Error::TailscaleDeserialize { .. }
+            | Error::RecoveryDelivery(_) => StatusCode::BAD_GATEWAY,
             Error::MqttNotConfigured | Error::LogsNotConfigured => StatusCode::NOT_FOUND,
             Error::EnvLevel { .. }
             | Error::DeserializeTomlFile { .. }
@@ -111,6 +181,7 @@ impl IntoResponse for Error {
             | Error::ServerStart { .. }
             | Error::SetGlobalSubscriber { .. }
             | Error::AuthSetup(_)
+            | Error::MqttSetup(_)
             | Error::Database(_)
             | Error::PrometheusEncode(_)
             | Error::BreakerStore { .. }
@@ -125,16 +196,33 @@ impl IntoResponse for Error {
             tracing::warn!(error = %self, "request error");
         }
 
+        let extra_header = match &self {
+            Error::RateLimited { retry_after } => {
+                Some((header::RETRY_AFTER, retry_after_secs(*retry_after).to_string()))
+            }
+            Error::Unauthorized => Some((header::WWW_AUTHENTICATE, "Bearer".to_owned())),
+            _ => None,
+        };
+
         // Sanitize WebAuthn internals — don't expose implementation details to clients.
         let body = match self {
             Error::WebAuthn(_) => "authentication error".to_owned(),
             other => other.to_string(),
         };
 
-        (status, body).into_response()
+        match extra_header {
+            Some(header) => (status, [header], body).into_response(),
+            None => (status, body).into_response(),
+        }
     }
 }
 
+/// Whole seconds for a `Retry-After` header, rounded up so clients never
+/// retry early.
+fn retry_after_secs(retry_after: std::time::Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,6 +263,87 @@ mod tests {
         assert_eq!(status(Error::InvalidRecoveryCode), StatusCode::BAD_REQUEST);
     }
 
+    #[test]
+    fn account_disabled_is_403() {
+        assert_eq!(status(Error::AccountDisabled), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn registration_closed_is_403() {
+        assert_eq!(status(Error::RegistrationClosed), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn invalid_invite_is_403() {
+        assert_eq!(status(Error::InvalidInvite), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn invalid_csrf_token_is_403() {
+        assert_eq!(status(Error::InvalidCsrfToken), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn username_taken_is_409() {
+        assert_eq!(status(Error::UsernameTaken), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn unknown_role_is_400() {
+        assert_eq!(status(Error::UnknownRole("wizard".into())), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn invalid_token_request_is_400() {
+        assert_eq!(
+            status(Error::InvalidTokenRequest("pick at least one scope".into())),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn invalid_audit_filter_is_400() {
+        assert_eq!(
+            status(Error::InvalidAuditFilter("bad date".into())),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn invalid_email_is_400() {
+        assert_eq!(status(Error::InvalidEmail("nope".into())), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn recovery_delivery_is_502() {
+        assert_eq!(
+            status(Error::RecoveryDelivery("connection refused".into())),
+            StatusCode::BAD_GATEWAY
+        );
+    }
+
+    #[test]
+    fn rate_limited_is_429_with_retry_after() {
+        let res = Error::RateLimited {
+            retry_after: std::time::Duration::from_millis(1500),
+        }
+        .into_response();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "2");
+    }
+
+    #[test]
+    fn unauthorized_is_401_with_bearer_challenge() {
+        let res = Error::Unauthorized.into_response();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[test]
+    fn last_passkey_is_409() {
+        assert_eq!(status(Error::LastPasskey), StatusCode::CONFLICT);
+    }
+
     #[test]
     fn tailscale_parse_is_502() {
         assert_eq!(
@@ -217,6 +386,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn invalid_publish_request_is_400() {
+        assert_eq!(
+            status(Error::InvalidPublishRequest("unknown broker".into())),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn invalid_history_query_is_400() {
+        assert_eq!(
+            status(Error::InvalidHistoryQuery("bad topic filter".into())),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn invalid_stream_filter_is_400() {
+        assert_eq!(
+            status(Error::InvalidStreamFilter("unknown integration".into())),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn invalid_device_alias_is_400() {
+        assert_eq!(
+            status(Error::InvalidDeviceAlias("unknown integration".into())),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn mqtt_setup_is_500() {
+        assert_eq!(
+            status(Error::MqttSetup("bad config".into())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
     #[test]
     fn server_start_is_500() {
         let source = std::io::Error::new(std::io::ErrorKind::AddrInUse, "busy");
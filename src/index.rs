@@ -3,13 +3,26 @@ use std::{collections::HashSet, sync::Arc};
 use askama::Template;
 use axum::{extract::State, response::Html};
 
-use crate::{Routes, ServerState, auth::{AuthUserInfo, MaybeAuthUser}, error::Error, services::ServiceStatus};
+use crate::{Routes, ServerState, auth::{AuthUserInfo, MaybeAuthUser}, error::Error, permission::Permission, services::ServiceStatus};
+
+/// Whether a link guarded by `permission` should be shown to `viewer`.
+fn visible_to(permission: Option<Permission>, viewer: Option<&AuthUserInfo>) -> bool {
+    permission.is_none_or(|p| viewer.is_some_and(|u| u.can(&p)))
+}
 
 /// A navigation link shown in the site-wide nav bar.
 #[derive(Debug, Clone)]
 pub struct NavLink {
     pub name: String,
     pub href: String,
+    /// Hidden from viewers without this permission.
+    pub permission: Option<Permission>,
+}
+
+impl NavLink {
+    pub fn visible_to(&self, viewer: Option<&AuthUserInfo>) -> bool {
+        visible_to(self.permission, viewer)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -18,6 +31,14 @@ pub struct IndexEntry {
     pub href: String,
     pub description: String,
     pub icon_url: Option<String>,
+    /// Hidden from viewers without this permission.
+    pub permission: Option<Permission>,
+}
+
+impl IndexEntry {
+    pub fn visible_to(&self, viewer: Option<&AuthUserInfo>) -> bool {
+        visible_to(self.permission, viewer)
+    }
 }
 
 #[derive(Debug, Clone, Template)]
@@ -32,17 +53,18 @@ pub struct Index {
 }
 
 impl Index {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(routes: Routes, has_notes: bool, has_mqtt: bool, has_mqtt_devices: bool, has_logs: bool, service_urls: &HashSet<String>, logo_url: Option<String>, nav_links: Arc<[NavLink]>) -> Result<Self, Error> {
         let static_entries = [
-            IndexEntry { name: "breaker box".into(), href: "/breaker".into(), description: "Electrical circuit layout".into(), icon_url: None },
-            IndexEntry { name: "qr code".into(), href: "/qr".into(), description: "Generate a QR code".into(), icon_url: None },
-            IndexEntry { name: "tailscale".into(), href: "/tailscale".into(), description: "Tailscale peer list".into(), icon_url: None },
+            IndexEntry { name: "breaker box".into(), href: "/breaker".into(), description: "Electrical circuit layout".into(), icon_url: None, permission: Some(Permission::BreakerRead) },
+            IndexEntry { name: "qr code".into(), href: "/qr".into(), description: "Generate a QR code".into(), icon_url: None, permission: None },
+            IndexEntry { name: "tailscale".into(), href: "/tailscale".into(), description: "Tailscale peer list".into(), icon_url: None, permission: Some(Permission::TailscaleRead) },
         ];
 
-        let notes_entry = has_notes.then_some(IndexEntry { name: "notes".into(), href: "/notes".into(), description: "D&D campaign notes".into(), icon_url: None });
-        let mqtt_entry = has_mqtt.then_some(IndexEntry { name: "mqtt".into(), href: "/mqtt".into(), description: "Live MQTT message feed".into(), icon_url: None });
-        let mqtt_devices_entry = has_mqtt_devices.then_some(IndexEntry { name: "mqtt devices".into(), href: "/mqtt/devices".into(), description: "MQTT device inventory".into(), icon_url: None });
-        let logs_entry = has_logs.then_some(IndexEntry { name: "logs".into(), href: "/logs/app".into(), description: "Dev server log viewer".into(), icon_url: None });
+        let notes_entry = has_notes.then_some(IndexEntry { name: "notes".into(), href: "/notes".into(), description: "D&D campaign notes".into(), icon_url: None, permission: None });
+        let mqtt_entry = has_mqtt.then_some(IndexEntry { name: "mqtt".into(), href: "/mqtt".into(), description: "Live MQTT message feed".into(), icon_url: None, permission: Some(Permission::MqttRead) });
+        let mqtt_devices_entry = has_mqtt_devices.then_some(IndexEntry { name: "mqtt devices".into(), href: "/mqtt/devices".into(), description: "MQTT device inventory".into(), icon_url: None, permission: Some(Permission::MqttRead) });
+        let logs_entry = has_logs.then_some(IndexEntry { name: "logs".into(), href: "/logs/app".into(), description: "Dev server log viewer".into(), icon_url: None, permission: Some(Permission::LogsRead) });
 
         let mut routes: Vec<IndexEntry> = routes
             .into_iter()
@@ -52,6 +74,7 @@ impl Index {
                 href: format!("https://{}", info.url),
                 description: info.description,
                 icon_url: info.icon_url,
+                permission: None,
             })
             .chain(static_entries)
             .chain(notes_entry)
@@ -164,6 +187,24 @@ mod tests {
         assert_eq!(devices_pos, mqtt_pos + 1, "mqtt devices should immediately follow mqtt");
     }
 
+    #[tokio::test]
+    async fn index_entries_hidden_without_permission() {
+        let index = Index::new(Routes::default(), false, true, false, false, &HashSet::new(), None, Arc::new([])).await.unwrap();
+        let mqtt = index.routes.iter().find(|r| r.href == "/mqtt").unwrap();
+        let qr = index.routes.iter().find(|r| r.href == "/qr").unwrap();
+        let viewer = |permissions: &[Permission]| AuthUserInfo {
+            username: "hal".into(),
+            role: crate::auth::Role::Custom("housemate".into()),
+            permissions: permissions.iter().copied().collect(),
+            csrf_token: String::new(),
+        };
+
+        assert!(!mqtt.visible_to(None));
+        assert!(!mqtt.visible_to(Some(&viewer(&[Permission::BreakerRead]))));
+        assert!(mqtt.visible_to(Some(&viewer(&[Permission::MqttRead]))));
+        assert!(qr.visible_to(None));
+    }
+
     #[tokio::test]
     async fn index_deduplicates_routes_matching_service_urls() {
         // Build a Routes with one entry via TOML deserialization.
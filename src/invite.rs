@@ -0,0 +1,312 @@
+//! Single-use registration invites.
+//!
+//! A GM creates an invite from `/admin/invites`; the resulting link carries a
+//! random token that lets one person create an account while registration is
+//! in `invite` mode. As with sessions, only the SHA-256 hash of the token is
+//! stored.
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row, postgres::PgRow};
+use time::OffsetDateTime;
+
+use crate::{auth::Role, error::Error};
+
+/// Who may create a new account via `/auth/register`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum RegistrationMode {
+    /// Anyone who can reach the server may register.
+    #[default]
+    Open,
+    /// New accounts need a valid invite link.
+    Invite,
+    /// No new accounts.
+    Closed,
+}
+
+/// A pending or used invite.
+#[derive(Debug, Clone)]
+pub struct Invite {
+    /// If set, the invite can only be used to register this username.
+    pub username: Option<String>,
+    /// Role given to the account created with this invite.
+    pub role: Role,
+    /// Username of the GM who created the invite.
+    pub created_by: String,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+    pub used_at: Option<OffsetDateTime>,
+}
+
+impl Invite {
+    /// Whether the invite can still be redeemed at `now`.
+    pub fn is_usable(&self, now: OffsetDateTime) -> bool {
+        self.used_at.is_none() && now < self.expires_at
+    }
+
+    /// Whether the invite allows registering `username`.
+    pub fn allows(&self, username: &str) -> bool {
+        self.username.as_deref().is_none_or(|u| u == username)
+    }
+}
+
+/// Storage backend for invites, keyed by [`crate::session::hash_token`].
+pub trait InviteStore: Send + Sync + std::fmt::Debug {
+    fn insert<'a>(&'a self, token_hash: &'a str, invite: &'a Invite)
+    -> BoxFuture<'a, Result<(), Error>>;
+
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<Invite>, Error>>;
+
+    /// Every invite with its token hash, newest first.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(String, Invite)>, Error>>;
+
+    /// Atomically mark a usable invite as used at `now`. Returns the invite, or
+    /// `None` if it doesn't exist, has expired or was already used.
+    fn redeem<'a>(
+        &'a self,
+        token_hash: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<Option<Invite>, Error>>;
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+// ─── Postgres ─────────────────────────────────────────────────────────────────
+
+/// [`InviteStore`] backed by the `invites` table.
+#[derive(Debug, Clone)]
+pub struct PgInviteStore {
+    db: PgPool,
+}
+
+impl PgInviteStore {
+    pub fn new(db: PgPool) -> Self {
+        PgInviteStore { db }
+    }
+}
+
+fn invite_from_row(row: &PgRow) -> Result<Invite, Error> {
+    Ok(Invite {
+        username: row.get("username"),
+        role: Role::from(row.get::<String, _>("role")),
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+        expires_at: row.get("expires_at"),
+        used_at: row.get("used_at"),
+    })
+}
+
+impl InviteStore for PgInviteStore {
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        invite: &'a Invite,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let role = invite.role.as_str();
+            let _ = sqlx::query(
+                "INSERT INTO invites (token_hash, username, role, created_by, created_at, expires_at, used_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(token_hash)
+            .bind(invite.username.as_deref())
+            .bind(role)
+            .bind(&invite.created_by)
+            .bind(invite.created_at)
+            .bind(invite.expires_at)
+            .bind(invite.used_at)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<Invite>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "SELECT username, role, created_by, created_at, expires_at, used_at \
+                 FROM invites WHERE token_hash = $1",
+            )
+            .bind(token_hash)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            row.as_ref().map(invite_from_row).transpose()
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(String, Invite)>, Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT token_hash, username, role, created_by, created_at, expires_at, used_at \
+                 FROM invites ORDER BY created_at DESC",
+            )
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            rows.iter()
+                .map(|row| Ok((row.get("token_hash"), invite_from_row(row)?)))
+                .collect()
+        })
+    }
+
+    fn redeem<'a>(
+        &'a self,
+        token_hash: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<Option<Invite>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "UPDATE invites SET used_at = $2 \
+                 WHERE token_hash = $1 AND used_at IS NULL AND expires_at > $2 \
+                 RETURNING username, role, created_by, created_at, expires_at, used_at",
+            )
+            .bind(token_hash)
+            .bind(now)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            row.as_ref().map(invite_from_row).transpose()
+        })
+    }
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query("DELETE FROM invites WHERE token_hash = $1")
+                .bind(token_hash)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+}
+
+// ─── In-memory (tests) ────────────────────────────────────────────────────────
+
+/// [`InviteStore`] kept in process memory, so handler tests don't need Postgres.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryInviteStore {
+    invites: tokio::sync::RwLock<std::collections::HashMap<String, Invite>>,
+}
+
+#[cfg(test)]
+impl InviteStore for MemoryInviteStore {
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        invite: &'a Invite,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = self
+                .invites
+                .write()
+                .await
+                .insert(token_hash.to_owned(), invite.clone());
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<Invite>, Error>> {
+        Box::pin(async move { Ok(self.invites.read().await.get(token_hash).cloned()) })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(String, Invite)>, Error>> {
+        Box::pin(async move {
+            let mut invites: Vec<_> = self
+                .invites
+                .read()
+                .await
+                .iter()
+                .map(|(hash, invite)| (hash.clone(), invite.clone()))
+                .collect();
+            invites.sort_by_key(|(_, i)| std::cmp::Reverse(i.created_at));
+            Ok(invites)
+        })
+    }
+
+    fn redeem<'a>(
+        &'a self,
+        token_hash: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<Option<Invite>, Error>> {
+        Box::pin(async move {
+            let mut invites = self.invites.write().await;
+            Ok(invites
+                .get_mut(token_hash)
+                .filter(|invite| invite.is_usable(now))
+                .map(|invite| {
+                    invite.used_at = Some(now);
+                    invite.clone()
+                }))
+        })
+    }
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = self.invites.write().await.remove(token_hash);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite(username: Option<&str>, expires_in: time::Duration) -> Invite {
+        let now = OffsetDateTime::now_utc();
+        Invite {
+            username: username.map(str::to_owned),
+            role: Role::Player,
+            created_by: "gm".into(),
+            created_at: now,
+            expires_at: now + expires_in,
+            used_at: None,
+        }
+    }
+
+    #[test]
+    fn registration_mode_parses_lowercase() {
+        #[derive(Deserialize)]
+        struct Cfg {
+            registration: RegistrationMode,
+        }
+        let cfg: Cfg = toml::from_str(r#"registration = "invite""#).unwrap();
+        assert_eq!(cfg.registration, RegistrationMode::Invite);
+        assert_eq!(RegistrationMode::default(), RegistrationMode::Open);
+    }
+
+    #[test]
+    fn invite_allows_only_its_username() {
+        let open = invite(None, time::Duration::hours(1));
+        assert!(open.allows("anyone"));
+        let named = invite(Some("alice"), time::Duration::hours(1));
+        assert!(named.allows("alice"));
+        assert!(!named.allows("mallory"));
+    }
+
+    #[test]
+    fn expired_invite_is_not_usable() {
+        let now = OffsetDateTime::now_utc();
+        assert!(invite(None, time::Duration::hours(1)).is_usable(now));
+        assert!(!invite(None, -time::Duration::hours(1)).is_usable(now));
+    }
+
+    #[tokio::test]
+    async fn memory_store_redeem_is_single_use() {
+        let store = MemoryInviteStore::default();
+        store
+            .insert("h", &invite(None, time::Duration::hours(1)))
+            .await
+            .unwrap();
+        let now = OffsetDateTime::now_utc();
+        assert!(store.redeem("h", now).await.unwrap().is_some());
+        assert!(store.redeem("h", now).await.unwrap().is_none());
+        assert!(store.get("h").await.unwrap().unwrap().used_at.is_some());
+    }
+}
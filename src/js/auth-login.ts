@@ -30,10 +30,13 @@ export async function authenticateDiscoverable({
     startAuthentication,
     fetch: fetchFn = globalThis.fetch,
     next = '/',
+    kiosk = false,
 }: {
     startAuthentication: StartAuthentication;
     fetch?: typeof globalThis.fetch;
     next?: string;
+    /** Request a long-lived "remember this device" session. */
+    kiosk?: boolean;
 }): Promise<string> {
     const challengeRes = await fetchFn('/auth/login/challenge/discoverable', { method: 'POST' });
     if (!challengeRes.ok) {
@@ -47,7 +50,7 @@ export async function authenticateDiscoverable({
     const finishRes = await fetchFn('/auth/login/finish/discoverable', {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
-        body: JSON.stringify({ challenge_id: challengeId, credential }),
+        body: JSON.stringify({ challenge_id: challengeId, credential, kiosk }),
     });
     if (!finishRes.ok) {
         const msg = await finishRes.text();
@@ -63,6 +66,7 @@ if (typeof document !== 'undefined') {
     if (btn) {
         const form = document.getElementById('auth-form');
         const errEl = document.getElementById('auth-error')!;
+        const kioskEl = document.getElementById('auth-kiosk') as HTMLInputElement | null;
         const next = form?.dataset.next ?? '/';
 
         btn.addEventListener('click', async () => {
@@ -71,6 +75,7 @@ if (typeof document !== 'undefined') {
                 const redirect = await authenticateDiscoverable({
                     startAuthentication: SimpleWebAuthnBrowser.startAuthentication,
                     next,
+                    kiosk: kioskEl?.checked ?? false,
                 });
                 window.location.href = redirect;
             } catch (err) {
@@ -1,7 +1,8 @@
 /**
- * WebAuthn registration flow for the register page.
+ * WebAuthn registration flows: creating an account on the register page and
+ * adding a passkey to the signed-in account on the account page.
  *
- * `register` is a pure logic function — all side-effectful dependencies
+ * `register` and `addPasskey` are pure logic functions — all side-effectful dependencies
  * (fetch, startRegistration) are injected so it can be unit-tested without
  * a browser or network.
  *
@@ -26,9 +27,12 @@ export async function register(
     {
         startRegistration,
         fetch: fetchFn = globalThis.fetch,
+        invite,
     }: {
         startRegistration: StartRegistration;
         fetch?: typeof globalThis.fetch;
+        /** Invite token from the register link, required in invite-only mode. */
+        invite?: string;
     },
 ): Promise<string> {
     username = username.trim();
@@ -39,7 +43,7 @@ export async function register(
     const challengeRes = await fetchFn('/auth/register/challenge', {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
-        body: JSON.stringify({ username }),
+        body: JSON.stringify({ username, invite }),
     });
     if (!challengeRes.ok) {
         const msg = await challengeRes.text();
@@ -63,12 +67,43 @@ export async function register(
     return '/';
 }
 
+/** Add a passkey to the signed-in user's own account. */
+export async function addPasskey({
+    startRegistration,
+    fetch: fetchFn = globalThis.fetch,
+}: {
+    startRegistration: StartRegistration;
+    fetch?: typeof globalThis.fetch;
+}): Promise<string> {
+    const challengeRes = await fetchFn('/auth/account/passkeys/challenge', { method: 'POST' });
+    if (!challengeRes.ok) {
+        const msg = await challengeRes.text();
+        throw new Error(msg || 'challenge failed');
+    }
+    const { publicKey } = v.parse(ChallengeSchema, await challengeRes.json());
+
+    const credential = await startRegistration({ optionsJSON: publicKey });
+
+    const finishRes = await fetchFn('/auth/account/passkeys/finish', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ credential }),
+    });
+    if (!finishRes.ok) {
+        const msg = await finishRes.text();
+        throw new Error(msg || 'adding passkey failed');
+    }
+
+    return '/auth/account';
+}
+
 // DOM binding — only runs in the browser
 if (typeof document !== 'undefined') {
     const btn = document.getElementById('reg-btn');
     if (btn) {
         const usernameInput = document.getElementById('username') as HTMLInputElement | null;
         const errEl = document.getElementById('reg-error')!;
+        const invite = document.getElementById('reg-form')?.dataset.invite;
 
         btn.addEventListener('click', async () => {
             const username = usernameInput?.value.trim() ?? '';
@@ -77,6 +112,26 @@ if (typeof document !== 'undefined') {
             try {
                 const redirect = await register(username, {
                     startRegistration: SimpleWebAuthnBrowser.startRegistration,
+                    invite,
+                });
+                window.location.href = redirect;
+            } catch (err) {
+                errEl.textContent = err instanceof Error ? err.message : String(err);
+                errEl.style.display = '';
+            }
+        });
+    }
+
+    const addBtn = document.getElementById('add-passkey-btn');
+    if (addBtn) {
+        const errEl = document.getElementById('add-passkey-error')!;
+
+        addBtn.addEventListener('click', async () => {
+            errEl.style.display = 'none';
+
+            try {
+                const redirect = await addPasskey({
+                    startRegistration: SimpleWebAuthnBrowser.startRegistration,
                 });
                 window.location.href = redirect;
             } catch (err) {
@@ -0,0 +1,104 @@
+/**
+ * CSRF token plumbing, loaded on every page from `base.html`.
+ *
+ * The server renders the token into `<meta name="csrf-token">` and rejects
+ * state-changing requests that don't send it back. This adds it to:
+ *  - `fetch` calls, as an `X-CSRF-Token` header;
+ *  - htmx requests, via `htmx:configRequest`;
+ *  - plain `<form method="post">` submissions, as a hidden `csrf_token` field.
+ *
+ * The helpers are pure exported functions so they can be unit-tested without
+ * a browser. DOM binding at the bottom only runs in the browser.
+ */
+
+export const CSRF_HEADER = 'X-CSRF-Token';
+export const CSRF_FIELD = 'csrf_token';
+
+/** Read the page's token, or `null` if the page has none. */
+export function readToken(doc: Pick<Document, 'querySelector'>): string | null {
+    const meta = doc.querySelector('meta[name="csrf-token"]');
+    return meta?.getAttribute('content') || null;
+}
+
+/** Does a request with this method change state (and so need a token)? */
+export function needsToken(method: string | undefined): boolean {
+    return !['GET', 'HEAD', 'OPTIONS', 'TRACE'].includes((method ?? 'GET').toUpperCase());
+}
+
+/** Is `url` on the page's own origin? Never send the token anywhere else. */
+export function isSameOrigin(url: string, origin: string): boolean {
+    try {
+        return new URL(url, origin).origin === origin;
+    } catch {
+        return false;
+    }
+}
+
+/** Wrap `fetchFn` so same-origin state-changing requests carry the token. */
+export function withCsrf(
+    fetchFn: typeof globalThis.fetch,
+    token: string,
+    origin: string,
+): typeof globalThis.fetch {
+    return (input, init) => {
+        const request = input instanceof Request ? input : null;
+        const url = request ? request.url : String(input);
+        const method = init?.method ?? request?.method;
+        if (!needsToken(method) || !isSameOrigin(url, origin)) return fetchFn(input, init);
+
+        const headers = new Headers(init?.headers ?? request?.headers);
+        if (!headers.has(CSRF_HEADER)) headers.set(CSRF_HEADER, token);
+        return fetchFn(input, { ...init, headers });
+    };
+}
+
+/** Minimal view of a form for `addFormToken`. */
+export interface TokenForm {
+    readonly method: string;
+    readonly action: string;
+    querySelector(selectors: string): unknown;
+    appendHidden(name: string, value: string): void;
+}
+
+/** Add the token as a hidden field to a same-origin POST form that lacks one. */
+export function addFormToken(form: TokenForm, token: string, origin: string): boolean {
+    if (!needsToken(form.method) || !isSameOrigin(form.action, origin)) return false;
+    if (form.querySelector(`input[name="${CSRF_FIELD}"]`)) return false;
+    form.appendHidden(CSRF_FIELD, token);
+    return true;
+}
+
+// --- DOM binding (browser only, not tested) ---
+
+if (typeof document !== 'undefined') {
+    const token = readToken(document);
+    const origin = globalThis.location.origin;
+
+    if (token) {
+        globalThis.fetch = withCsrf(globalThis.fetch.bind(globalThis), token, origin);
+
+        // Capture phase so the field is in place before any other submit handler runs.
+        document.addEventListener('submit', (e) => {
+            const form = e.target as HTMLFormElement;
+            addFormToken({
+                method: form.method,
+                action: form.action,
+                querySelector: (s) => form.querySelector(s),
+                appendHidden(name, value) {
+                    const input = document.createElement('input');
+                    input.type = 'hidden';
+                    input.name = name;
+                    input.value = value;
+                    form.appendChild(input);
+                },
+            }, token, origin);
+        }, true);
+
+        document.addEventListener('htmx:configRequest', (e) => {
+            const detail = (e as CustomEvent<{ verb: string; path: string; headers: Record<string, string> }>).detail;
+            if (needsToken(detail.verb) && isSameOrigin(detail.path, origin)) {
+                detail.headers[CSRF_HEADER] = token;
+            }
+        });
+    }
+}
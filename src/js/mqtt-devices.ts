@@ -38,16 +38,19 @@ export async function fetchDeviceMessages(
     return resp.text();
 }
 
-/** POST a message to `/api/mqtt/publish`. Returns a typed result. */
+/**
+ * POST a message to `/api/mqtt/publish`, on `broker` if given (required when
+ * several brokers are configured). Returns a typed result.
+ */
 export async function sendCommand(
     topic: string,
     payload: string,
-    { fetch: fetchFn = globalThis.fetch }: { fetch?: typeof globalThis.fetch } = {},
+    { fetch: fetchFn = globalThis.fetch, broker }: { fetch?: typeof globalThis.fetch; broker?: string } = {},
 ): Promise<SendResult> {
     const resp = await fetchFn('/api/mqtt/publish', {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
-        body: JSON.stringify({ topic, payload }),
+        body: JSON.stringify(broker ? { broker, topic, payload } : { topic, payload }),
     });
     if (resp.ok) return { ok: true };
     return { ok: false, status: resp.status };
@@ -124,6 +127,7 @@ if (typeof document !== 'undefined') {
         const topicEl = form.querySelector('.device-cmd-topic') as HTMLInputElement | null;
         const payloadEl = form.querySelector('.device-cmd-payload') as HTMLTextAreaElement | null;
         const statusEl = form.querySelector('.device-cmd-status') as HTMLElement | null;
+        const brokerEl = form.querySelector('.device-cmd-broker') as HTMLSelectElement | null;
         const btn = form.querySelector('button[type="submit"]') as HTMLButtonElement | null;
 
         const topic = topicEl?.value.trim() ?? '';
@@ -138,7 +142,7 @@ if (typeof document !== 'undefined') {
         if (statusEl) statusEl.textContent = 'sending\u2026';
 
         try {
-            const result = await sendCommand(topic, payload);
+            const result = await sendCommand(topic, payload, { broker: brokerEl?.value || undefined });
             if (result.ok) {
                 if (statusEl) statusEl.textContent = 'sent';
                 if (payloadEl) payloadEl.value = '';
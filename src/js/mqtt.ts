@@ -1,7 +1,7 @@
 /**
  * MQTT live-feed page — EventSource + pagination.
  *
- * Pure exported functions (applyFilter, getPage, totalPages, renderControls)
+ * Pure exported functions (streamUrl, applyFilter, filterByBroker, getPage, totalPages, renderControls)
  * are tested in test/js/mqtt.test.ts.
  *
  * DOM binding at the bottom only runs in the browser.
@@ -11,14 +11,31 @@ import { batch, computed, effect, signal } from '@preact/signals-core';
 
 const PAGE_SIZE = 20;
 const MAX_CARDS = 500;
+/** Page query parameters forwarded to the stream for server-side filtering. */
+const STREAM_PARAMS = ['topic', 'integration', 'device', 'max_payload'];
 
 // --- Pure functions (exported for testing) ---
 
 interface Card {
-    dataset?: { topic?: string };
+    dataset?: { topic?: string; broker?: string };
     cloneNode(deep?: boolean): Node;
 }
 
+/**
+ * SSE endpoint URL for a page opened with `search`, e.g. `/mqtt?topic=frigate/%23`
+ * streams only Frigate topics. Unrelated parameters are dropped.
+ */
+export function streamUrl(search: string): string {
+    const page = new URLSearchParams(search);
+    const params = new URLSearchParams();
+    for (const key of STREAM_PARAMS) {
+        const value = page.get(key);
+        if (value) params.set(key, value);
+    }
+    const query = params.toString();
+    return query ? `/api/mqtt/stream?${query}` : '/api/mqtt/stream';
+}
+
 /** Cards whose data-topic matches filterText (case-insensitive substring). */
 export function applyFilter(cards: Card[], filterText: string): Card[] {
     if (!filterText) return cards;
@@ -36,6 +53,12 @@ export function filterByPrefix(cards: Card[], prefix: string): Card[] {
     });
 }
 
+/** Cards received from the named broker; an empty name keeps every card. */
+export function filterByBroker(cards: Card[], broker: string): Card[] {
+    if (!broker) return cards;
+    return cards.filter(c => c.dataset?.broker === broker);
+}
+
 /** Slice of cards for a given 0-indexed page. */
 export function getPage<T>(cards: T[], page: number, pageSize: number): T[] {
     const start = page * pageSize;
@@ -102,6 +125,7 @@ if (typeof document !== 'undefined') {
     const filterInput = document.getElementById('mqtt-filter') as HTMLInputElement | null;
     const controls    = document.getElementById('mqtt-controls');
     const topicBar    = document.getElementById('mqtt-topics');
+    const brokerBar   = document.getElementById('mqtt-brokers');
 
     // State
     const allCards    = signal<Card[]>([]);
@@ -110,6 +134,7 @@ if (typeof document !== 'undefined') {
     const newCount    = signal(0);
     const filterText  = signal('');
     const activePrefix = signal('');
+    const activeBroker = signal('');
 
     // Derived
     const topTopics = computed(() => {
@@ -123,6 +148,7 @@ if (typeof document !== 'undefined') {
 
     const filtered = computed(() => {
         let cards = allCards.value;
+        if (activeBroker.value) cards = filterByBroker(cards, activeBroker.value);
         if (activePrefix.value) cards = filterByPrefix(cards, activePrefix.value);
         if (filterText.value)   cards = applyFilter(cards, filterText.value);
         return cards;
@@ -185,6 +211,19 @@ if (typeof document !== 'undefined') {
         });
     });
 
+    // Broker chips are rendered by the server; only the active marker moves.
+    brokerBar?.addEventListener('click', (e) => {
+        const btn = (e.target as Element).closest('[data-broker]') as HTMLButtonElement | null;
+        if (!btn) return;
+        brokerBar.querySelectorAll('[data-broker]').forEach(b => b.classList.toggle('leet-btn-active', b === btn));
+        batch(() => {
+            activeBroker.value = btn.dataset.broker ?? '';
+            currentPage.value  = 0;
+            newCount.value     = 0;
+            refreshPage();
+        });
+    });
+
     feed?.addEventListener('click', (e) => {
         const header = (e.target as Element).closest('.mqtt-msg-header');
         if (!header) return;
@@ -212,7 +251,7 @@ if (typeof document !== 'undefined') {
         });
     });
 
-    const es = new EventSource('/api/mqtt/stream');
+    const es = new EventSource(streamUrl(globalThis.location.search));
 
     es.addEventListener('broker', (e) => {
         if (statusBar) statusBar.innerHTML = (e as MessageEvent).data;
@@ -0,0 +1,151 @@
+//! A small subset of JSONPath for picking values out of MQTT payloads.
+//!
+//! Supports the root `$`, `.key`, `['key']` (or `["key"]`) and `[index]`,
+//! which covers what Zigbee and Home Assistant payloads need:
+//! `$.temperature`, `$.update.state`, `$.contacts[0]`,
+//! `$['battery level']`. Wildcards, filters and recursive descent are not
+//! supported.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// A parsed path, e.g. `$.update.state`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct JsonPath {
+    steps: Vec<Step>,
+    source: String,
+}
+
+/// Characters that end a `.key` step, so a path can be followed by an
+/// operator in a condition such as `$.temperature>25`.
+fn ends_key(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '.' | '[' | '=' | '!' | '<' | '>')
+}
+
+impl JsonPath {
+    /// Parse a whole string as a path.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (path, rest) = JsonPath::parse_prefix(s.trim())?;
+        if !rest.is_empty() {
+            return Err(format!("unexpected `{rest}` after JSONPath `{}`", path.source));
+        }
+        Ok(path)
+    }
+
+    /// Parse a path from the start of `s`, returning it and whatever follows.
+    pub fn parse_prefix(s: &str) -> Result<(Self, &str), String> {
+        let mut rest = s
+            .strip_prefix('$')
+            .ok_or_else(|| format!("JSONPath `{s}` must start with `$`"))?;
+        let mut steps = Vec::new();
+        loop {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(ends_key).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(format!("empty key in JSONPath `{s}`"));
+                }
+                steps.push(Step::Key(after[..end].to_owned()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let close = after.find(']').ok_or_else(|| format!("unclosed `[` in JSONPath `{s}`"))?;
+                let inner = &after[..close];
+                let quoted = inner
+                    .strip_prefix('\'')
+                    .and_then(|k| k.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|k| k.strip_suffix('"')));
+                let step = match quoted {
+                    Some(key) => Step::Key(key.to_owned()),
+                    None => Step::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| format!("`[{inner}]` in JSONPath `{s}` is not an index or quoted key"))?,
+                    ),
+                };
+                steps.push(step);
+                rest = &after[close + 1..];
+            } else {
+                break;
+            }
+        }
+        let source = s[..s.len() - rest.len()].to_owned();
+        Ok((JsonPath { steps, source }, rest))
+    }
+
+    /// The value at this path in `value`, if there is one.
+    pub fn get<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.steps.iter().try_fold(value, |v, step| match step {
+            Step::Key(key) => v.get(key.as_str()),
+            Step::Index(i) => v.get(*i),
+        })
+    }
+}
+
+impl std::fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl TryFrom<String> for JsonPath {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        JsonPath::parse(&s)
+    }
+}
+
+impl From<JsonPath> for String {
+    fn from(path: JsonPath) -> Self {
+        path.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn gets_keys_indexes_and_quoted_keys() {
+        let payload = json!({
+            "temperature": 21.5,
+            "update": { "state": "idle" },
+            "contacts": [true, false],
+            "battery level": 80,
+        });
+        let get = |p: &str| JsonPath::parse(p).unwrap().get(&payload).cloned();
+        assert_eq!(get("$"), Some(payload.clone()));
+        assert_eq!(get("$.temperature"), Some(json!(21.5)));
+        assert_eq!(get("$.update.state"), Some(json!("idle")));
+        assert_eq!(get("$.contacts[1]"), Some(json!(false)));
+        assert_eq!(get("$['battery level']"), Some(json!(80)));
+        assert_eq!(get(r#"$["update"].state"#), Some(json!("idle")));
+        assert_eq!(get("$.missing.key"), None);
+        assert_eq!(get("$.contacts[5]"), None);
+    }
+
+    #[test]
+    fn parse_prefix_stops_at_an_operator() {
+        let (path, rest) = JsonPath::parse_prefix("$.temperature>25").unwrap();
+        assert_eq!(path.to_string(), "$.temperature");
+        assert_eq!(rest, ">25");
+        let (path, rest) = JsonPath::parse_prefix("$.a[0] == 1").unwrap();
+        assert_eq!(path.to_string(), "$.a[0]");
+        assert_eq!(rest, " == 1");
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for bad in ["temperature", "$.", "$.a[", "$.a[x]", "$.a b"] {
+            assert!(JsonPath::parse(bad).is_err(), "{bad}");
+        }
+    }
+}
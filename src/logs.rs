@@ -15,9 +15,10 @@ use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader};
 
 use crate::{
-    auth::{AuthUserInfo, GmUser},
+    auth::AuthUserInfo,
     error::Error,
     index::NavLink,
+    permission::{LogsRead, RequirePermission},
     ServerState,
 };
 
@@ -51,27 +52,21 @@ struct LogsErrorsPage {
     nav_links: Arc<[NavLink]>,
 }
 
-/// GET `/logs/app` — renders the app trace log page (GM only).
+/// GET `/logs/app` — renders the app trace log page (requires `logs.read`).
 pub async fn logs_app_route(
-    user: GmUser,
+    user: RequirePermission<LogsRead>,
     State(state): State<ServerState>,
 ) -> Result<Html<String>, Error> {
-    let auth_user = Some(AuthUserInfo {
-        username: user.0.username.clone(),
-        role: user.0.role.clone(),
-    });
+    let auth_user = Some(user.0.info());
     Ok(Html(LogsAppPage { version: crate::VERSION, auth_user, nav_links: state.nav_links.clone() }.render()?))
 }
 
-/// GET `/logs/errors` — renders the error log page (GM only).
+/// GET `/logs/errors` — renders the error log page (requires `logs.read`).
 pub async fn logs_errors_route(
-    user: GmUser,
+    user: RequirePermission<LogsRead>,
     State(state): State<ServerState>,
 ) -> Result<Html<String>, Error> {
-    let auth_user = Some(AuthUserInfo {
-        username: user.0.username.clone(),
-        role: user.0.role.clone(),
-    });
+    let auth_user = Some(user.0.info());
     Ok(Html(LogsErrorsPage { version: crate::VERSION, auth_user, nav_links: state.nav_links.clone() }.render()?))
 }
 
@@ -92,7 +87,7 @@ async fn read_last_lines(path: &PathBuf, n: usize) -> Vec<String> {
 /// then polls the file for new lines, sending each as an SSE `data` event.
 fn tail_log_stream(path: PathBuf) -> impl Stream<Item = Result<Event, Infallible>> {
     let (tx, rx) = tokio::sync::mpsc::channel::<String>(128);
-    let _ = tokio::spawn(async move {
+    drop(tokio::spawn(async move {
         // Send backlog to new client.
         for line in read_last_lines(&path, BACKLOG_LINES).await {
             if tx.send(line).await.is_err() {
@@ -125,15 +120,15 @@ fn tail_log_stream(path: PathBuf) -> impl Stream<Item = Result<Event, Infallible
                 Err(_) => return,
             }
         }
-    });
+    }));
     futures::stream::unfold(rx, |mut rx| async move {
         rx.recv().await.map(|l| (Ok(Event::default().data(l)), rx))
     })
 }
 
-/// GET `/api/logs/app/stream` — SSE stream of app trace log lines (GM only).
+/// GET `/api/logs/app/stream` — SSE stream of app trace log lines (requires `logs.read`).
 pub async fn logs_app_stream_route(
-    _user: GmUser,
+    _user: RequirePermission<LogsRead>,
     State(state): State<ServerState>,
 ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Error> {
     let path = state
@@ -145,9 +140,9 @@ pub async fn logs_app_stream_route(
     Ok(Sse::new(tail_log_stream(path)).keep_alive(KeepAlive::default()))
 }
 
-/// GET `/api/logs/errors/stream` — SSE stream of error log lines (GM only).
+/// GET `/api/logs/errors/stream` — SSE stream of error log lines (requires `logs.read`).
 pub async fn logs_errors_stream_route(
-    _user: GmUser,
+    _user: RequirePermission<LogsRead>,
     State(state): State<ServerState>,
 ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Error> {
     let path = state
@@ -37,21 +37,44 @@ use route::Routes;
 use serde::{Deserialize, Serialize};
 use tower_http::{services::ServeDir, trace::TraceLayer};
 
-use crate::{error::Error, index::{Index, NavLink}};
+use crate::{error::Error, index::{Index, NavLink}, permission::Permission};
 
+mod account;
+mod admin;
+mod api_token;
+mod audit;
 mod auth;
 mod breaker;
 mod breaker_detail;
+mod challenge;
+mod csrf;
+mod device_alias;
+mod device_health;
+mod device_state;
 mod error;
 mod index;
+mod invite;
 mod io;
+mod json_path;
 mod logs;
+mod metrics;
 mod mqtt;
+mod mqtt_history;
+mod mqtt_metrics;
+mod mqtt_rules;
+mod mqtt_tree;
 mod notes;
+mod permission;
 mod qr;
+mod rate_limit;
+mod recovery;
 mod route;
 mod services;
+mod session;
 mod tailscale;
+mod user;
+#[cfg(test)]
+mod test_support;
 
 /// Application version string (semver + git hash).
 pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("GIT_HASH"));
@@ -117,6 +140,31 @@ pub enum Route {
     #[strum(serialize = "/auth/register")]
     AuthRegister,
 
+    /// Self-service session and passkey management page.
+    #[serde(rename = "/auth/account")]
+    #[strum(serialize = "/auth/account")]
+    AuthAccount,
+
+    /// User administration page (GM only).
+    #[serde(rename = "/admin/users")]
+    #[strum(serialize = "/admin/users")]
+    AdminUsers,
+
+    /// Registration invite management page (GM only).
+    #[serde(rename = "/admin/invites")]
+    #[strum(serialize = "/admin/invites")]
+    AdminInvites,
+
+    /// API token management page (GM only).
+    #[serde(rename = "/admin/tokens")]
+    #[strum(serialize = "/admin/tokens")]
+    AdminTokens,
+
+    /// Audit log page (GM only).
+    #[serde(rename = "/admin/audit")]
+    #[strum(serialize = "/admin/audit")]
+    AdminAudit,
+
     /// MQTT live-feed page (GM only).
     #[serde(rename = "/mqtt")]
     #[strum(serialize = "/mqtt")]
@@ -142,7 +190,37 @@ pub enum Route {
     #[strum(serialize = "/mqtt/devices")]
     MqttDevices,
 
-    /// Prometheus metrics scrape endpoint (unauthenticated; internal only).
+    /// Save or remove a device's alias (GM only).
+    #[serde(rename = "/mqtt/devices/alias")]
+    #[strum(serialize = "/mqtt/devices/alias")]
+    MqttDeviceAlias,
+
+    /// Stored MQTT message search page (GM only; needs `[mqtt.history]`).
+    #[serde(rename = "/mqtt/history")]
+    #[strum(serialize = "/mqtt/history")]
+    MqttHistory,
+
+    /// JSON API for the stored MQTT message search (GM only).
+    #[serde(rename = "/api/mqtt/history")]
+    #[strum(serialize = "/api/mqtt/history")]
+    MqttHistoryApi,
+
+    /// Latest message per topic as a collapsible tree.
+    #[serde(rename = "/mqtt/tree")]
+    #[strum(serialize = "/mqtt/tree")]
+    MqttTree,
+
+    /// Clear a retained topic by publishing an empty retained payload.
+    #[serde(rename = "/mqtt/tree/clear")]
+    #[strum(serialize = "/mqtt/tree/clear")]
+    MqttTreeClear,
+
+    /// Each MQTT rule's trigger, actions and last firing (GM only).
+    #[serde(rename = "/mqtt/rules")]
+    #[strum(serialize = "/mqtt/rules")]
+    MqttRules,
+
+    /// Prometheus metrics scrape endpoint (guarded by the optional `[metrics]` block).
     #[serde(rename = "/metrics")]
     #[strum(serialize = "/metrics")]
     Metrics,
@@ -208,6 +286,8 @@ pub struct ServerState {
     pub log_config: Option<logs::LogConfig>,
     /// Systemd units to monitor, or `None` if not configured.
     pub systemd_config: Option<services::SystemdConfig>,
+    /// Access control for `/metrics`, or `None` to leave it open.
+    pub metrics_config: Option<metrics::MetricsConfig>,
     /// Site-wide navigation links, built at startup from enabled features.
     pub nav_links: Arc<[NavLink]>,
 }
@@ -234,7 +314,7 @@ impl ServerState {
 
         let has_notes = notes_store.is_some();
         let has_mqtt = config.mqtt.is_some();
-        let has_mqtt_devices = config.mqtt.as_ref().map_or(false, |m| !m.integrations.is_empty());
+        let has_mqtt_devices = config.mqtt.as_ref().is_some_and(|m| !m.integrations.is_empty());
         let has_logs = config.log_config.is_some();
         let service_urls: std::collections::HashSet<String> = config
             .systemd
@@ -243,12 +323,12 @@ impl ServerState {
             .unwrap_or_default();
         let nav_links: Arc<[NavLink]> = {
             let mut links = vec![
-                NavLink { name: "home".into(), href: "/".into() },
+                NavLink { name: "home".into(), href: "/".into(), permission: None },
             ];
-            if has_mqtt { links.push(NavLink { name: "mqtt".into(), href: "/mqtt".into() }); }
-            if has_notes { links.push(NavLink { name: "notes".into(), href: "/notes".into() }); }
-            links.push(NavLink { name: "breaker".into(), href: "/breaker".into() });
-            links.push(NavLink { name: "tailscale".into(), href: "/tailscale".into() });
+            if has_mqtt { links.push(NavLink { name: "mqtt".into(), href: "/mqtt".into(), permission: Some(Permission::MqttRead) }); }
+            if has_notes { links.push(NavLink { name: "notes".into(), href: "/notes".into(), permission: None }); }
+            links.push(NavLink { name: "breaker".into(), href: "/breaker".into(), permission: Some(Permission::BreakerRead) });
+            links.push(NavLink { name: "tailscale".into(), href: "/tailscale".into(), permission: Some(Permission::TailscaleRead) });
             links.into()
         };
         let index = Index::new(config.routes.clone(), has_notes, has_mqtt, has_mqtt_devices, has_logs, &service_urls, config.logo_url.clone(), nav_links.clone()).await?;
@@ -257,68 +337,159 @@ impl ServerState {
         let breaker_content = Arc::new(breaker::BreakerContent::new(store.as_ref()));
 
         let auth_state = if let Some(ref auth_config) = config.auth {
-            Some(Arc::new(auth::AuthState::new(auth_config.clone()).await?))
+            let auth = Arc::new(auth::AuthState::new(auth_config.clone()).await?);
+            drop(tokio::spawn(auth::run_session_sweep_task(Arc::clone(&auth))));
+            Some(auth)
         } else {
             None
         };
 
         let mqtt_state = if let Some(ref mqtt_config) = config.mqtt {
             let (tx, _) = tokio::sync::broadcast::channel(256);
-            let task_tx = tx.clone();
-            let task_config = mqtt_config.clone();
-            let (status_tx, _) = tokio::sync::watch::channel("connecting".to_string());
-            let status_tx = Arc::new(status_tx);
-            let task_status_tx = Arc::clone(&status_tx);
             let recent_messages = Arc::new(tokio::sync::Mutex::new(
                 std::collections::VecDeque::with_capacity(mqtt_config.scrollback),
             ));
-            let task_recent = Arc::clone(&recent_messages);
-            let (mqtt_client, eventloop) = mqtt::setup_mqtt_client(mqtt_config);
-            let publish_client = mqtt_client.clone();
-            let _ = tokio::spawn(async move {
-                mqtt::run_mqtt_task(task_config, mqtt_client, eventloop, task_tx, task_status_tx, task_recent).await;
-                tracing::error!("mqtt task exited unexpectedly");
-            });
 
-            // Build Prometheus state when integrations are configured.
+            // Subscribe the history writer before any broker can deliver a message.
+            let history = match (&mqtt_config.history, &auth_state) {
+                (Some(history_config), Some(auth)) => {
+                    let store: Arc<dyn mqtt_history::HistoryStore> =
+                        Arc::new(mqtt_history::PgHistoryStore::new(auth.db.clone()));
+                    drop(tokio::spawn(mqtt_history::run_history_task(
+                        Arc::clone(&store),
+                        history_config.clone(),
+                        tx.subscribe(),
+                    )));
+                    Some(store)
+                }
+                (Some(_), None) => {
+                    tracing::warn!("[mqtt.history] needs the [auth] database; message history is disabled");
+                    None
+                }
+                (None, _) => None,
+            };
+            let tree = Arc::new(tokio::sync::Mutex::new(mqtt_tree::TopicTree::default()));
+            drop(tokio::spawn(mqtt_tree::run_topic_tree_task(Arc::clone(&tree), tx.subscribe())));
+            // The rules task needs the finished MqttState to publish, so it
+            // starts below; subscribing now queues messages until then.
+            let rules = Arc::new(mqtt_rules::RuleEngine::new(mqtt_config.rules.clone()));
+            let rules_rx = (!rules.is_empty()).then(|| tx.subscribe());
+            let alert_ntfy = mqtt_config
+                .alert_ntfy_url
+                .as_ref()
+                .map(|url| recovery::NtfyDelivery::new(url.clone()));
+
+            let mut brokers = Vec::with_capacity(mqtt_config.brokers.len());
+            for broker_config in &mqtt_config.brokers {
+                let task_tx = tx.clone();
+                let task_config = broker_config.clone();
+                let scrollback = mqtt_config.scrollback;
+                let (status_tx, _) = tokio::sync::watch::channel("connecting".to_string());
+                let status_tx = Arc::new(status_tx);
+                let task_status_tx = Arc::clone(&status_tx);
+                let task_recent = Arc::clone(&recent_messages);
+                let (mqtt_client, eventloop) = mqtt::setup_mqtt_client(broker_config, &config.ca_path).await?;
+                let publish_client = mqtt_client.clone();
+                let name = broker_config.name.clone();
+                drop(tokio::spawn(async move {
+                    mqtt::run_mqtt_task(task_config, scrollback, mqtt_client, eventloop, task_tx, task_status_tx, task_recent)
+                        .await;
+                    tracing::error!(broker = %name, "mqtt task exited unexpectedly");
+                }));
+                brokers.push(mqtt::Broker { name: broker_config.name.clone(), status_tx, publish_client });
+            }
+
+            // Build Prometheus state when integrations or metric mappings are configured.
             let parsed_integrations = Arc::new(mqtt::parse_integrations(&mqtt_config.integrations));
-            let prometheus = if !parsed_integrations.is_empty() {
+            let prometheus = if !parsed_integrations.is_empty() || !mqtt_config.metrics.is_empty() {
                 let registry = prometheus::Registry::new();
                 let messages_total = prometheus::IntCounterVec::new(
                     prometheus::opts!("mqtt_messages_total", "MQTT messages by integration and device"),
                     &["integration", "device"],
                 )
                 .map_err(|e| Error::AuthSetup(format!("prometheus counter: {e}")))?;
+                let last_seen = prometheus::IntGaugeVec::new(
+                    prometheus::opts!(
+                        "mqtt_device_last_seen_seconds",
+                        "Unix time of the latest MQTT message from each device"
+                    ),
+                    &["integration", "device"],
+                )
+                .map_err(|e| Error::AuthSetup(format!("prometheus gauge: {e}")))?;
                 registry
                     .register(Box::new(messages_total.clone()))
                     .map_err(|e| Error::AuthSetup(format!("prometheus register: {e}")))?;
-                Some(mqtt::PrometheusState { registry, messages_total })
+                registry
+                    .register(Box::new(last_seen.clone()))
+                    .map_err(|e| Error::AuthSetup(format!("prometheus register: {e}")))?;
+                Some(mqtt::PrometheusState { registry, messages_total, last_seen })
             } else {
                 None
             };
 
+            // Aliases live in the auth database; without it devices keep their ids.
+            let alias_store = auth_state.as_ref().map(|auth| -> Arc<dyn device_alias::AliasStore> {
+                Arc::new(device_alias::PgAliasStore::new(auth.db.clone()))
+            });
+            let saved_aliases = match &alias_store {
+                Some(store) => store.list().await.unwrap_or_else(|err| {
+                    tracing::warn!(%err, "failed to load device aliases");
+                    Vec::new()
+                }),
+                None => Vec::new(),
+            };
+            let aliases = Arc::new(device_alias::DeviceAliases::new(
+                Arc::clone(&parsed_integrations),
+                saved_aliases,
+            ));
+
+            if let (false, Some(ps)) = (mqtt_config.metrics.is_empty(), &prometheus) {
+                let exporter = mqtt_metrics::MetricExporter::new(
+                    &mqtt_config.metrics,
+                    &ps.registry,
+                    Arc::clone(&aliases),
+                    mqtt_config.metrics_device_label,
+                )
+                .map_err(|e| Error::AuthSetup(format!("prometheus metric mapping: {e}")))?;
+                drop(tokio::spawn(mqtt_metrics::run_metric_export_task(exporter, tx.subscribe())));
+            }
+
+            let device_states = Arc::new(device_state::DeviceStates::default());
+
             // Spawn device tracker if integrations are configured and auth (DB) is available.
             if let (false, Some(auth)) = (parsed_integrations.is_empty(), &auth_state) {
                 let tracker_rx = tx.subscribe();
                 let tracker_db = auth.db.clone();
-                let tracker_metrics =
-                    prometheus.as_ref().map(|p| p.messages_total.clone());
-                let _ = tokio::spawn(mqtt::run_device_tracker_task(
+                drop(tokio::spawn(mqtt::run_device_tracker_task(
                     Arc::clone(&parsed_integrations),
                     tracker_db,
-                    tracker_metrics,
+                    prometheus.clone(),
+                    Arc::clone(&aliases),
+                    Arc::clone(&device_states),
+                    mqtt_config.metrics_device_label,
+                    alert_ntfy.clone(),
                     tracker_rx,
-                ));
+                )));
             }
 
-            Some(Arc::new(mqtt::MqttState {
+            let mqtt_state = Arc::new(mqtt::MqttState {
                 tx,
-                status_tx,
                 recent_messages,
                 prometheus,
                 integrations: parsed_integrations,
-                publish_client,
-            }))
+                brokers,
+                history,
+                tree,
+                aliases,
+                alias_store,
+                rules,
+                device_states,
+            });
+            if let Some(rx) = rules_rx {
+                let runner = mqtt_rules::RuleRunner::new(Arc::clone(&mqtt_state), alert_ntfy)?;
+                drop(tokio::spawn(mqtt_rules::run_rules_task(runner, rx)));
+            }
+            Some(mqtt_state)
         } else {
             None
         };
@@ -334,12 +505,16 @@ impl ServerState {
             mqtt_state,
             log_config: config.log_config.clone(),
             systemd_config: config.systemd.clone(),
+            metrics_config: config.metrics.clone(),
             nav_links: nav_links.clone(),
         })
     }
 }
 
 fn build_router(state: ServerState) -> axum::Router {
+    // Unauthenticated auth ceremonies are rate-limited per client IP.
+    let ip_limit = axum::middleware::from_fn_with_state(state.clone(), rate_limit::limit_by_ip);
+
     axum::Router::new()
         .route(Route::Home.as_str(), get(index::index))
         .route(Route::Certificates.as_str(), get(ca_route))
@@ -353,17 +528,67 @@ fn build_router(state: ServerState) -> axum::Router {
         .route("/notes/{slug}", get(notes::notes_detail_route))
         .route(Route::AuthLogin.as_str(), get(auth::login_page))
         .route(Route::AuthRegister.as_str(), get(auth::register_page))
-        .route("/auth/register/challenge", axum::routing::post(auth::start_registration))
-        .route("/auth/register/finish", axum::routing::post(auth::finish_registration))
-        .route("/auth/login/challenge/discoverable", axum::routing::post(auth::start_discoverable_auth))
-        .route("/auth/login/finish/discoverable", axum::routing::post(auth::finish_discoverable_auth))
+        .route(
+            "/auth/register/challenge",
+            axum::routing::post(auth::start_registration).layer(ip_limit.clone()),
+        )
+        .route(
+            "/auth/register/finish",
+            axum::routing::post(auth::finish_registration).layer(ip_limit.clone()),
+        )
+        .route("/auth/account/passkeys/challenge", axum::routing::post(auth::start_add_passkey))
+        .route("/auth/account/passkeys/finish", axum::routing::post(auth::finish_add_passkey))
+        .route(
+            "/auth/login/challenge/discoverable",
+            axum::routing::post(auth::start_discoverable_auth).layer(ip_limit.clone()),
+        )
+        .route(
+            "/auth/login/finish/discoverable",
+            axum::routing::post(auth::finish_discoverable_auth).layer(ip_limit.clone()),
+        )
         .route("/auth/logout", axum::routing::post(auth::logout))
-        .route("/auth/recover", get(auth::recover_page).post(auth::start_recovery))
-        .route("/auth/recover/verify", axum::routing::post(auth::verify_recovery))
+        .route(
+            "/auth/recover",
+            get(auth::recover_page).merge(axum::routing::post(auth::start_recovery).layer(ip_limit.clone())),
+        )
+        .route(
+            "/auth/recover/verify",
+            axum::routing::post(auth::verify_recovery).layer(ip_limit),
+        )
+        .route(Route::AuthAccount.as_str(), get(account::account_page))
+        .route("/auth/account/sessions/revoke", axum::routing::post(account::revoke_session))
+        .route("/auth/account/passkeys/rename", axum::routing::post(account::rename_passkey))
+        .route("/auth/account/passkeys/delete", axum::routing::post(account::delete_passkey))
+        .route("/auth/account/email", axum::routing::post(account::set_email))
+        .route("/auth/account/backup-codes", axum::routing::post(account::generate_codes))
+        .route(Route::AdminUsers.as_str(), get(admin::users_page))
+        .route("/admin/users/role", axum::routing::post(admin::set_role))
+        .route("/admin/users/disable", axum::routing::post(admin::set_disabled))
+        .route("/admin/users/delete", axum::routing::post(admin::delete_user))
+        .route(
+            Route::AdminInvites.as_str(),
+            get(admin::invites_page).post(admin::create_invite),
+        )
+        .route("/admin/invites/revoke", axum::routing::post(admin::revoke_invite))
+        .route(
+            Route::AdminTokens.as_str(),
+            get(admin::tokens_page).post(admin::create_token),
+        )
+        .route("/admin/tokens/revoke", axum::routing::post(admin::revoke_token))
+        .route(Route::AdminAudit.as_str(), get(admin::audit_page))
         .route(Route::Mqtt.as_str(), get(mqtt::mqtt_page_route))
         .route(Route::MqttStream.as_str(), get(mqtt::mqtt_stream_route))
         .route(Route::MqttDevices.as_str(), get(mqtt::mqtt_devices_route))
+        .route(Route::MqttDeviceAlias.as_str(), axum::routing::post(device_alias::save_alias_route))
+        .route("/mqtt/devices/{integration}/{device}", get(device_state::device_page))
+        .route("/api/mqtt/devices/{integration}/{device}/state", get(device_state::device_state_api))
+        .route(Route::MqttHistory.as_str(), get(mqtt_history::history_page))
+        .route(Route::MqttHistoryApi.as_str(), get(mqtt_history::history_api))
+        .route(Route::MqttTree.as_str(), get(mqtt_tree::tree_page))
+        .route(Route::MqttTreeClear.as_str(), axum::routing::post(mqtt_tree::clear_retained_route))
+        .route(Route::MqttRules.as_str(), get(mqtt_rules::rules_page))
         .route(Route::MqttDeviceMessages.as_str(), get(mqtt::device_messages_route))
+        .route("/api/mqtt/payload/{id}", get(mqtt::payload_route))
         .route(Route::MqttPublish.as_str(), axum::routing::post(mqtt::publish_route))
         .route(Route::Metrics.as_str(), get(mqtt::metrics_route))
         .route(Route::LogsApp.as_str(), get(logs::logs_app_route))
@@ -373,6 +598,7 @@ fn build_router(state: ServerState) -> axum::Router {
         .route(Route::Services.as_str(), get(services::services_route))
         .route(Route::ServicesApi.as_str(), get(services::services_api_route))
         .nest_service("/assets", ServeDir::new("assets"))
+        .layer(axum::middleware::from_fn(csrf::protect))
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(|request: &axum::http::Request<_>| {
@@ -430,14 +656,19 @@ pub struct Config {
     /// services route returns 404.
     #[serde(default)]
     pub systemd: Option<services::SystemdConfig>,
+    /// Bearer token and/or network allow-list for `/metrics`. If absent, the
+    /// endpoint is unauthenticated.
+    #[serde(default)]
+    pub metrics: Option<metrics::MetricsConfig>,
     /// Optional URL for a logo image shown on the index page.
     #[serde(default)]
     pub logo_url: Option<String>,
 }
 
 impl Config {
-    /// Load configuration from `path`, then override `auth.db_url` with the
-    /// `GREEN_DB_URL` environment variable if set.
+    /// Load configuration from `path`, then override secrets (`auth.db_url`,
+    /// `auth.smtp.password`, broker passwords, `metrics.bearer_token`) from
+    /// `GREEN_*` environment variables if set.
     pub async fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
         let mut config: Self = load_toml_file(&path.as_ref().to_path_buf()).await?;
         // Allow overriding the database URL via environment variable so that
@@ -448,10 +679,26 @@ impl Config {
         {
             auth.db_url = db_url;
         }
-        if let Ok(pw) = std::env::var("GREEN_MQTT_PASSWORD")
-            && let Some(ref mut mqtt) = config.mqtt
+        if let Ok(pw) = std::env::var("GREEN_SMTP_PASSWORD")
+            && let Some(ref mut smtp) = config.auth.as_mut().and_then(|auth| auth.smtp.as_mut())
+        {
+            smtp.password = Some(pw);
+        }
+        if let Some(ref mut mqtt) = config.mqtt {
+            for broker in &mut mqtt.brokers {
+                let mut pw = std::env::var(mqtt::password_env_var(&broker.name));
+                if broker.name == mqtt::DEFAULT_BROKER {
+                    pw = pw.or_else(|_| std::env::var("GREEN_MQTT_PASSWORD"));
+                }
+                if let Ok(pw) = pw {
+                    broker.password = Some(pw);
+                }
+            }
+        }
+        if let Ok(token) = std::env::var("GREEN_METRICS_TOKEN")
+            && let Some(ref mut metrics) = config.metrics
         {
-            mqtt.password = Some(pw);
+            metrics.bearer_token = Some(token);
         }
         Ok(config)
     }
@@ -493,8 +740,8 @@ async fn run(config: Config) -> Result<(), Error> {
         .await
         .expect("Failed to bind to address");
 
-    // Start the server
-    axum::serve(listener, app)
+    // Start the server. Connect info lets `/metrics` check the client address.
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .map_err(|source| Error::ServerStart { source })?;
 
@@ -0,0 +1,116 @@
+//! Access control for the Prometheus `/metrics` endpoint.
+//!
+//! The scrape output names every device and integration seen on the broker,
+//! so the optional `[metrics]` config block can require a static bearer token,
+//! restrict scrapers to a set of networks, or both. Without the block the
+//! endpoint stays open.
+
+use std::net::IpAddr;
+
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::bearer_token, error::Error, session::hash_token};
+
+/// `[metrics]` config block.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    /// If set, scrapers must send `Authorization: Bearer <token>`.
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+    /// If non-empty, only clients in one of these networks may scrape,
+    /// e.g. `["10.0.0.5/32", "fd7a:115c:a1e0::/48"]`.
+    #[serde(default)]
+    pub allowed_cidrs: Vec<IpNet>,
+}
+
+impl std::fmt::Debug for MetricsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsConfig")
+            .field("bearer_token", &self.bearer_token.as_ref().map(|_| "<redacted>"))
+            .field("allowed_cidrs", &self.allowed_cidrs)
+            .finish()
+    }
+}
+
+impl MetricsConfig {
+    /// Check a scrape from `peer`. Clients outside `allowed_cidrs` get
+    /// [`Error::Forbidden`]; a missing or wrong bearer token gets
+    /// [`Error::Unauthorized`].
+    pub fn authorize(&self, headers: &HeaderMap, peer: IpAddr) -> Result<(), Error> {
+        // An IPv4 client on a dual-stack socket shows up as `::ffff:a.b.c.d`.
+        let peer = peer.to_canonical();
+        if !self.allowed_cidrs.is_empty() && !self.allowed_cidrs.iter().any(|net| net.contains(&peer)) {
+            return Err(Error::Forbidden);
+        }
+        if let Some(expected) = &self.bearer_token {
+            let presented = bearer_token(headers);
+            // Compare digests so the comparison time doesn't depend on how
+            // much of the secret matched.
+            if presented.map(hash_token) != Some(hash_token(expected)) {
+                return Err(Error::Unauthorized);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+
+    use super::*;
+
+    fn config(bearer_token: Option<&str>, cidrs: &[&str]) -> MetricsConfig {
+        MetricsConfig {
+            bearer_token: bearer_token.map(str::to_owned),
+            allowed_cidrs: cidrs.iter().map(|c| c.parse().unwrap()).collect(),
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let _ = headers.insert(header::AUTHORIZATION, format!("Bearer {token}").parse().unwrap());
+        headers
+    }
+
+    const LAN: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 1, 20));
+    const PROMETHEUS: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 5));
+
+    #[test]
+    fn default_config_allows_everyone() {
+        assert!(MetricsConfig::default().authorize(&HeaderMap::new(), LAN).is_ok());
+    }
+
+    #[test]
+    fn bearer_token_must_match() {
+        let cfg = config(Some("s3cret"), &[]);
+        assert!(matches!(cfg.authorize(&HeaderMap::new(), LAN), Err(Error::Unauthorized)));
+        assert!(matches!(cfg.authorize(&bearer("wrong"), LAN), Err(Error::Unauthorized)));
+        assert!(cfg.authorize(&bearer("s3cret"), LAN).is_ok());
+    }
+
+    #[test]
+    fn clients_outside_allowed_cidrs_are_forbidden() {
+        let cfg = config(Some("s3cret"), &["10.0.0.0/24"]);
+        assert!(matches!(cfg.authorize(&bearer("s3cret"), LAN), Err(Error::Forbidden)));
+        assert!(cfg.authorize(&bearer("s3cret"), PROMETHEUS).is_ok());
+
+        let mapped: IpAddr = "::ffff:10.0.0.5".parse().unwrap();
+        assert!(config(None, &["10.0.0.0/24"]).authorize(&HeaderMap::new(), mapped).is_ok());
+    }
+
+    #[test]
+    fn parses_from_toml_and_redacts_token() {
+        let cfg: MetricsConfig = toml::from_str(
+            r#"
+            bearer_token = "s3cret"
+            allowed_cidrs = ["10.0.0.0/24", "fd7a:115c:a1e0::/48"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.allowed_cidrs.len(), 2);
+        assert!(!format!("{cfg:?}").contains("s3cret"));
+    }
+}
@@ -4,8 +4,13 @@ use std::{
     collections::{HashMap, VecDeque},
     convert::Infallible,
     future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
     time::{Duration, Instant},
 };
 
@@ -13,22 +18,36 @@ use time::format_description::well_known::Rfc3339;
 
 use askama::Template;
 use axum::{
-    extract::{Query, State},
+    extract::{ConnectInfo, Path as UrlPath, Query, State},
+    http::{HeaderMap, header},
     response::{
-        Html,
+        Html, IntoResponse,
         sse::{Event, KeepAlive, Sse},
     },
     Json,
 };
 use futures::StreamExt as _;
-use rumqttc::{AsyncClient, Event as MqttEvent, EventLoop, MqttOptions, Packet, QoS};
+use rumqttc::{
+    AsyncClient, Event as MqttEvent, EventLoop, MqttOptions, Packet, QoS, TlsConfiguration, Transport,
+};
 use serde::{Deserialize, Serialize};
 use tokio::sync::{broadcast, watch, Mutex as TokioMutex};
 
 use crate::{
-    auth::{AuthUserInfo, GmUser},
+    audit::{AuditAction, AuditOutcome, RequestMeta},
+    auth::AuthUserInfo,
     error::Error,
+    io::read_file,
     index::NavLink,
+    mqtt_history::{HistoryConfig, HistoryQuery, HistoryStore},
+    mqtt_metrics::{MetricMappingConfig, validate_metric_mappings},
+    mqtt_rules::{RuleConfig, RuleEngine, validate_rules},
+    mqtt_tree::TopicTree,
+    device_alias::{AliasStore, DeviceAlias, DeviceAliases, render_alias_form},
+    device_health::{CHECK_INTERVAL, DeviceAlert, DeviceHealth, OfflineWatch},
+    device_state::{DeviceStates, render_state_link},
+    permission::{MqttPublish, MqttRead, Permission, RequirePermission},
+    recovery::NtfyDelivery,
     ServerState,
 };
 
@@ -48,18 +67,131 @@ pub(crate) enum PatternSegment {
 }
 
 /// Per-integration device-tracking configuration.
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct IntegrationConfig {
     /// Topic pattern with `{device}`, `*`/`+` (any segment), and `**` (glob) tokens.
     pub pattern: String,
     /// Optional display name; defaults to the first literal segment of the pattern.
     #[serde(default)]
     pub name: Option<String>,
+    /// How often, in minutes, a device is expected to send a message. Silent
+    /// that long it is stale; after twice that it is offline. Unset = devices
+    /// aren't watched.
+    #[serde(default)]
+    pub expected_interval_mins: Option<u32>,
+    /// Per-device overrides of `expected_interval_mins`, keyed by device id.
+    /// `0` stops watching that device.
+    #[serde(default)]
+    pub device_expected_interval_mins: HashMap<String, u32>,
+}
+
+/// What the `device` label of the `mqtt_messages_total` counter holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceLabel {
+    /// The device id from the topic.
+    #[default]
+    Id,
+    /// The device's alias where it has one, else its id. Devices sharing an
+    /// alias keep their ids so their series stay apart. Renaming a device
+    /// starts a new series.
+    Alias,
 }
 
-/// Configuration for the MQTT broker connection.
+/// `[mqtt]` config block: one or more brokers sharing a feed and device
+/// tracking.
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RawMqttConfig")]
 pub struct MqttConfig {
+    /// Brokers to connect to, in the order their status pills are shown.
+    pub brokers: Vec<BrokerConfig>,
+    /// Number of recent messages (across all brokers) to replay to new SSE
+    /// clients. Defaults to 200.
+    pub scrollback: usize,
+    /// Integration configs for device tracking. Empty = no tracking.
+    pub integrations: Vec<IntegrationConfig>,
+    /// Persistent message history, off unless `[mqtt.history]` is present.
+    pub history: Option<HistoryConfig>,
+    /// Label devices in Prometheus by id (the default) or alias.
+    pub metrics_device_label: DeviceLabel,
+    /// ntfy topic URL for device offline and back-online alerts. No alerts are
+    /// sent without it; `[auth] ntfy_url` is kept for recovery codes.
+    pub alert_ntfy_url: Option<String>,
+    /// Automations run on incoming messages, from `[[mqtt.rules]]`.
+    pub rules: Vec<RuleConfig>,
+    /// Payload fields exported to Prometheus, from `[[mqtt.metrics]]`.
+    pub metrics: Vec<MetricMappingConfig>,
+}
+
+/// `[mqtt]` as written. Brokers are listed as `[[mqtt.brokers]]`; a config
+/// with the connection settings directly in `[mqtt]` (the original
+/// single-broker layout) is read as one broker named `default`.
+#[derive(Deserialize)]
+struct RawMqttConfig {
+    #[serde(default)]
+    brokers: Vec<BrokerConfig>,
+    #[serde(default = "default_scrollback")]
+    scrollback: usize,
+    #[serde(default)]
+    integrations: Vec<IntegrationConfig>,
+    history: Option<HistoryConfig>,
+    #[serde(default)]
+    metrics_device_label: DeviceLabel,
+    #[serde(default)]
+    alert_ntfy_url: Option<String>,
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+    #[serde(default)]
+    metrics: Vec<MetricMappingConfig>,
+    #[serde(flatten)]
+    single: toml::Table,
+}
+
+impl TryFrom<RawMqttConfig> for MqttConfig {
+    type Error = String;
+
+    fn try_from(raw: RawMqttConfig) -> Result<Self, Self::Error> {
+        let mut brokers = raw.brokers;
+        if !raw.single.is_empty() {
+            if !brokers.is_empty() {
+                return Err("set broker options in [[mqtt.brokers]] or directly in [mqtt], not both".into());
+            }
+            let broker = BrokerConfig::deserialize(toml::Value::Table(raw.single)).map_err(|e| e.to_string())?;
+            brokers.push(broker);
+        }
+        if brokers.is_empty() {
+            return Err("at least one broker is required".into());
+        }
+        for (i, broker) in brokers.iter().enumerate() {
+            if brokers[..i].iter().any(|b| b.name == broker.name) {
+                return Err(format!("duplicate broker name `{}`", broker.name));
+            }
+        }
+        if let Some(ref history) = raw.history {
+            history.validate().map_err(|e| e.to_string())?;
+        }
+        validate_rules(&raw.rules, &brokers)?;
+        validate_metric_mappings(&raw.metrics)?;
+        Ok(MqttConfig {
+            brokers,
+            scrollback: raw.scrollback,
+            integrations: raw.integrations,
+            history: raw.history,
+            metrics_device_label: raw.metrics_device_label,
+            alert_ntfy_url: raw.alert_ntfy_url,
+            rules: raw.rules,
+            metrics: raw.metrics,
+        })
+    }
+}
+
+/// Connection settings for one broker.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BrokerConfig {
+    /// Shown on the broker's status pill and used to target publishes.
+    /// Defaults to `default`.
+    #[serde(default = "default_broker_name")]
+    pub name: String,
     /// Broker hostname or IP address.
     #[serde(default = "default_host")]
     pub host: String,
@@ -75,14 +207,40 @@ pub struct MqttConfig {
     /// Topics to subscribe to. Defaults to `["#"]` (all topics).
     #[serde(default = "default_topics")]
     pub topics: Vec<String>,
-    /// Number of recent messages to replay to new SSE clients. Defaults to 200.
-    #[serde(default = "default_scrollback")]
-    pub scrollback: usize,
-    /// Integration configs for device tracking. Empty = no tracking.
-    #[serde(default)]
-    pub integrations: Vec<IntegrationConfig>,
     /// MQTT client ID sent to the broker. Must be unique per connected instance.
     pub client_id: String,
+    /// Connect over TLS (usually port 8883). The broker's certificate is
+    /// verified against `ca_path`.
+    #[serde(default)]
+    pub tls: bool,
+    /// PEM CA bundle for verifying the broker. Defaults to the server's
+    /// top-level `ca_path`.
+    #[serde(default)]
+    pub ca_path: Option<PathBuf>,
+    /// PEM client certificate for mutual TLS. Requires `client_key_path`.
+    #[serde(default)]
+    pub client_cert_path: Option<PathBuf>,
+    /// PEM private key for `client_cert_path`.
+    #[serde(default)]
+    pub client_key_path: Option<PathBuf>,
+}
+
+/// Environment variable holding the password for broker `name`, e.g.
+/// `GREEN_MQTT_PASSWORD_FRIGATE` for `frigate`. `GREEN_MQTT_PASSWORD` is the
+/// fallback for the [`DEFAULT_BROKER`] only.
+pub fn password_env_var(name: &str) -> String {
+    let suffix: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    format!("GREEN_MQTT_PASSWORD_{suffix}")
+}
+
+/// Name of the broker configured with the single-broker `[mqtt]` keys.
+pub const DEFAULT_BROKER: &str = "default";
+
+fn default_broker_name() -> String {
+    DEFAULT_BROKER.to_string()
 }
 
 fn default_host() -> String {
@@ -112,6 +270,10 @@ pub(crate) struct Integration {
     /// Original pattern string — stored in the DB to detect stale entries after config changes.
     pattern: String,
     segments: Vec<PatternSegment>,
+    /// How long a device may stay silent before it is stale, if watched.
+    expected_interval: Option<Duration>,
+    /// Per-device overrides of `expected_interval`; `None` means not watched.
+    device_expected_interval: HashMap<String, Option<Duration>>,
 }
 
 /// Parse [`IntegrationConfig`] slices into [`Integration`] values ready for matching.
@@ -142,11 +304,113 @@ pub(crate) fn parse_integrations(cfgs: &[IntegrationConfig]) -> Vec<Integration>
                     .unwrap_or_else(|| "unknown".to_string())
             });
 
-            Integration { display_name, pattern: cfg.pattern.clone(), segments }
+            let minutes = |m: u32| (m > 0).then(|| Duration::from_secs(u64::from(m) * 60));
+            Integration {
+                display_name,
+                pattern: cfg.pattern.clone(),
+                segments,
+                expected_interval: cfg.expected_interval_mins.and_then(minutes),
+                device_expected_interval: cfg
+                    .device_expected_interval_mins
+                    .iter()
+                    .map(|(device, &m)| (device.clone(), minutes(m)))
+                    .collect(),
+            }
         })
         .collect()
 }
 
+impl Integration {
+    /// Display name, as stored in `mqtt_devices`.
+    pub(crate) fn name(&self) -> &str {
+        &self.display_name
+    }
+
+    /// How long `device` may stay silent before it is stale, or `None` if it
+    /// isn't watched.
+    pub(crate) fn expected_interval(&self, device: &str) -> Option<Duration> {
+        self.device_expected_interval
+            .get(device)
+            .copied()
+            .unwrap_or(self.expected_interval)
+    }
+
+    /// The device id in `topic`, if it matches this integration's pattern.
+    pub(crate) fn device<'t>(&self, topic: &'t str) -> Option<&'t str> {
+        match_topic(&self.segments, topic)
+    }
+
+    /// The levels of `topic` after the device id, e.g. `availability` for
+    /// `zigbee2mqtt/lamp/availability`; empty for the device topic itself.
+    pub(crate) fn subtopic<'t>(&self, topic: &'t str) -> &'t str {
+        let Some(device_level) = self.segments.iter().position(|s| matches!(s, PatternSegment::Capture)) else {
+            return "";
+        };
+        topic
+            .match_indices('/')
+            .nth(device_level)
+            .map_or("", |(i, _)| &topic[i + 1..])
+    }
+
+    /// MQTT topic filter covering the topics of `device` under this
+    /// integration, e.g. `zigbee2mqtt/0xABCD/#` for `zigbee2mqtt/{device}/**`.
+    fn device_filter(&self, device: &str) -> String {
+        self.segments
+            .iter()
+            .map(|seg| match seg {
+                PatternSegment::Literal(l) => l.as_str(),
+                PatternSegment::Capture => device,
+                PatternSegment::Any => "+",
+                PatternSegment::Glob => "#",
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Check that `filter` is a valid MQTT topic filter: `+` and `#` only as a
+/// whole level, and `#` only as the last one.
+pub(crate) fn validate_topic_filter(filter: &str) -> Result<(), String> {
+    if filter.is_empty() {
+        return Err("empty topic filter".into());
+    }
+    let levels: Vec<&str> = filter.split('/').collect();
+    for (i, level) in levels.iter().enumerate() {
+        let wildcard_in_level = *level != "+" && *level != "#" && level.contains(['+', '#']);
+        let misplaced_hash = *level == "#" && i + 1 != levels.len();
+        if wildcard_in_level || misplaced_hash {
+            return Err(format!("`{filter}` is not a valid topic filter"));
+        }
+    }
+    Ok(())
+}
+
+/// Does `topic` match the MQTT topic filter `filter`? `+` matches one level,
+/// a trailing `#` matches the parent level and everything below it. As in
+/// MQTT, wildcards at the start never match `$`-prefixed system topics.
+pub(crate) fn topic_matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+    let mut levels = topic.split('/');
+    for f in filter.split('/') {
+        match f {
+            "#" => return true,
+            "+" => {
+                if levels.next().is_none() {
+                    return false;
+                }
+            }
+            literal => {
+                if levels.next() != Some(literal) {
+                    return false;
+                }
+            }
+        }
+    }
+    levels.next().is_none()
+}
+
 /// Try to match `topic` against `segments`, returning the captured device ID.
 ///
 /// Returns `None` if the pattern does not match or contains no `{device}` capture.
@@ -207,32 +471,150 @@ fn match_integrations<'i, 't>(
     None
 }
 
+/// The integration name and device id `topic` belongs to, if any.
+pub(crate) fn device_for_topic<'i, 't>(integrations: &'i [Integration], topic: &'t str) -> Option<(&'i str, &'t str)> {
+    match_integrations(integrations, topic).map(|(integration, device)| (integration.name(), device))
+}
+
+/// What an MQTT payload's bytes are, detected when the message arrives.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PayloadKind {
+    /// UTF-8 text that isn't a JSON object or array.
+    #[default]
+    Text,
+    /// A JSON object or array.
+    Json,
+    /// A JPEG image, by its magic bytes.
+    Jpeg,
+    /// A PNG image, by its magic bytes.
+    Png,
+    /// Anything else that isn't valid UTF-8 (CBOR, protobuf, raw sensor frames…).
+    Binary,
+}
+
+impl PayloadKind {
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            return PayloadKind::Jpeg;
+        }
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return PayloadKind::Png;
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => match serde_json::from_str::<serde_json::Value>(text) {
+                Ok(serde_json::Value::Object(_) | serde_json::Value::Array(_)) => PayloadKind::Json,
+                _ => PayloadKind::Text,
+            },
+            Err(_) => PayloadKind::Binary,
+        }
+    }
+
+    /// Text and JSON payloads are kept as text; the rest are kept as bytes.
+    pub fn is_text(self) -> bool {
+        matches!(self, PayloadKind::Text | PayloadKind::Json)
+    }
+
+    /// `Content-Type` for the raw payload download.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            PayloadKind::Text => "text/plain; charset=utf-8",
+            PayloadKind::Json => "application/json",
+            PayloadKind::Jpeg => "image/jpeg",
+            PayloadKind::Png => "image/png",
+            PayloadKind::Binary => "application/octet-stream",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            PayloadKind::Text => "txt",
+            PayloadKind::Json => "json",
+            PayloadKind::Jpeg => "jpg",
+            PayloadKind::Png => "png",
+            PayloadKind::Binary => "bin",
+        }
+    }
+}
+
+/// Source of [`MqttMessage::id`].
+static NEXT_MESSAGE_ID: AtomicU64 = AtomicU64::new(1);
+
 /// A single MQTT publish received from the broker.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MqttMessage {
+    /// Unique for the life of the process, increasing in arrival order. Used
+    /// to fetch the raw payload from `/api/mqtt/payload/{id}`. Always 0 for
+    /// messages loaded from the history.
+    #[serde(default)]
+    pub id: u64,
+    /// Name of the broker the message arrived on.
+    pub broker: String,
     /// The topic the message was published to.
     pub topic: String,
-    /// UTF-8 payload (non-UTF-8 bytes are replaced with the replacement character).
+    #[serde(default)]
+    pub kind: PayloadKind,
+    /// Text and JSON payloads as received. Binary payloads are summarised
+    /// here (e.g. `image/jpeg, 48213 bytes`) and kept in `raw`.
     pub payload: String,
+    /// Bytes of a binary payload; empty for text. Never serialized.
+    #[serde(skip)]
+    pub raw: Arc<[u8]>,
+    /// Whether the broker delivered this as a retained message.
+    #[serde(default)]
+    pub retain: bool,
     /// RFC 3339 timestamp of when the message was received by this server.
     pub received_at: String,
 }
 
+impl MqttMessage {
+    /// A message with a fresh id, classifying `bytes` by [`PayloadKind::detect`].
+    pub fn new(broker: &str, topic: String, bytes: &[u8], received_at: String) -> Self {
+        let kind = PayloadKind::detect(bytes);
+        let (payload, raw) = match std::str::from_utf8(bytes) {
+            Ok(text) if kind.is_text() => (text.to_owned(), Arc::default()),
+            _ => (format!("{}, {} bytes", kind.content_type(), bytes.len()), Arc::from(bytes)),
+        };
+        MqttMessage {
+            id: NEXT_MESSAGE_ID.fetch_add(1, Ordering::Relaxed),
+            broker: broker.to_owned(),
+            topic,
+            kind,
+            payload,
+            raw,
+            retain: false,
+            received_at,
+        }
+    }
+
+    /// The payload as received.
+    pub fn bytes(&self) -> &[u8] {
+        if self.kind.is_text() { self.payload.as_bytes() } else { &self.raw }
+    }
+}
+
 /// Fan-out channel payload: either a received MQTT message or a broker status change.
 #[derive(Debug, Clone)]
 pub enum BrokerEvent {
     /// A publish message received from the broker.
     Message(MqttMessage),
-    /// The broker connection status changed (e.g. `"connected"`, `"error"`).
-    Status { status: String },
+    /// A broker's connection status changed (e.g. `"connected"`, `"error: …"`).
+    Status { broker: String, status: String },
 }
 
-/// Prometheus registry and counter for MQTT device messages.
+/// Prometheus registry and per-device metrics for MQTT device messages.
+#[derive(Clone)]
 pub struct PrometheusState {
     /// Prometheus scrape registry (not the global default).
     pub registry: prometheus::Registry,
     /// `mqtt_messages_total{integration, device}` counter.
     pub messages_total: prometheus::IntCounterVec,
+    /// `mqtt_device_last_seen_seconds{integration, device}` gauge: Unix time
+    /// of each device's latest message.
+    pub last_seen: prometheus::IntGaugeVec,
 }
 
 impl std::fmt::Debug for PrometheusState {
@@ -241,22 +623,71 @@ impl std::fmt::Debug for PrometheusState {
     }
 }
 
+/// A configured broker's live connection, stored in [`MqttState`].
+#[derive(Debug)]
+pub struct Broker {
+    /// Name from [`BrokerConfig::name`].
+    pub name: String,
+    /// Last known status (`"connected"`, `"error: …"`, `"connecting"`).
+    /// New SSE clients call `.borrow()` for an immediate snapshot without waiting.
+    pub status_tx: Arc<watch::Sender<String>>,
+    /// Cloned client handle used for publishing outbound messages (e.g. device commands).
+    pub publish_client: AsyncClient,
+}
+
 /// Shared MQTT fan-out state stored in [`ServerState`].
 #[derive(Debug)]
 pub struct MqttState {
-    /// Broadcast sender; SSE handlers subscribe by calling `tx.subscribe()`.
+    /// Broadcast sender shared by every broker; SSE handlers subscribe by
+    /// calling `tx.subscribe()`.
     pub tx: broadcast::Sender<BrokerEvent>,
-    /// Last known broker status (`"connected"`, `"error"`, `"connecting"`).
-    /// New SSE clients call `.borrow()` for an immediate snapshot without waiting.
-    pub status_tx: Arc<watch::Sender<String>>,
-    /// Ring buffer of recent messages replayed to new SSE clients on connect.
+    /// Ring buffer of recent messages from all brokers, replayed to new SSE
+    /// clients on connect.
     pub recent_messages: Arc<TokioMutex<VecDeque<MqttMessage>>>,
-    /// Prometheus metrics, present only when integrations are configured.
+    /// Prometheus metrics, present only when integrations or metric mappings
+    /// are configured.
     pub prometheus: Option<PrometheusState>,
     /// Parsed integrations, shared with the device tracker task and message filter handler.
     pub(crate) integrations: Arc<Vec<Integration>>,
-    /// Cloned client handle used for publishing outbound messages (e.g. device commands).
-    pub publish_client: AsyncClient,
+    /// Connected brokers, in config order.
+    pub brokers: Vec<Broker>,
+    /// Persistent message history, when `[mqtt.history]` is configured.
+    pub history: Option<Arc<dyn HistoryStore>>,
+    /// Latest message per topic, for the `/mqtt/tree` browser.
+    pub tree: Arc<TokioMutex<TopicTree>>,
+    /// Device aliases, for cards and metric labels.
+    pub aliases: Arc<DeviceAliases>,
+    /// Where aliases are saved; `None` without the `[auth]` database.
+    pub alias_store: Option<Arc<dyn AliasStore>>,
+    /// `[[mqtt.rules]]` and what each has done since startup.
+    pub rules: Arc<RuleEngine>,
+    /// Latest merged state of each device, kept by the device tracker.
+    pub device_states: Arc<DeviceStates>,
+}
+
+impl MqttState {
+    /// The broker to publish to: the one called `name`, or the only broker
+    /// when `name` is `None`.
+    pub fn broker(&self, name: Option<&str>) -> Result<&Broker, Error> {
+        match (name, self.brokers.as_slice()) {
+            (Some(name), brokers) => brokers
+                .iter()
+                .find(|b| b.name == name)
+                .ok_or_else(|| Error::InvalidPublishRequest(format!("unknown broker `{name}`"))),
+            (None, [only]) => Ok(only),
+            (None, _) => Err(Error::InvalidPublishRequest(
+                "broker is required when more than one is configured".into(),
+            )),
+        }
+    }
+
+    /// Current `(broker, status)` pairs, in config order.
+    fn statuses(&self) -> Vec<(String, String)> {
+        self.brokers
+            .iter()
+            .map(|b| (b.name.clone(), b.status_tx.borrow().clone()))
+            .collect()
+    }
 }
 
 /// Abstraction over the MQTT client's subscribe call, injected into
@@ -283,15 +714,16 @@ impl MqttSubscriber for AsyncClient {
 /// Extracted for testability — called by [`run_mqtt_task`] on every (re)connect.
 async fn handle_conn_ack(
     client: &impl MqttSubscriber,
+    broker: &str,
     topics: &[String],
     status_tx: &Arc<watch::Sender<String>>,
     tx: &broadcast::Sender<BrokerEvent>,
     host: &str,
     port: u16,
 ) {
-    tracing::info!(host, port, "MQTT connected");
+    tracing::info!(broker, host, port, "MQTT connected");
     let _ = status_tx.send_replace("connected".into());
-    let _ = tx.send(BrokerEvent::Status { status: "connected".into() });
+    let _ = tx.send(BrokerEvent::Status { broker: broker.to_owned(), status: "connected".into() });
     // Re-subscribe after every (re)connect so reconnects pick up the same topics.
     for topic in topics {
         if let Err(err) = client.subscribe(topic, QoS::AtMostOnce).await {
@@ -303,17 +735,15 @@ async fn handle_conn_ack(
 /// Handle a Publish packet: store in the ring buffer and broadcast to SSE clients.
 /// Extracted for testability — called by [`run_mqtt_task`] on every received message.
 async fn handle_publish(
+    broker: &str,
     topic: String,
     payload: &[u8],
+    retain: bool,
     scrollback: usize,
     tx: &broadcast::Sender<BrokerEvent>,
     recent_messages: &Arc<TokioMutex<VecDeque<MqttMessage>>>,
 ) {
-    let msg = MqttMessage {
-        topic,
-        payload: String::from_utf8_lossy(payload).into_owned(),
-        received_at: utc_now(),
-    };
+    let msg = MqttMessage { retain, ..MqttMessage::new(broker, topic, payload, utc_now()) };
     tracing::trace!(topic = %msg.topic, "MQTT message received");
     {
         let mut buf = recent_messages.lock().await;
@@ -328,20 +758,63 @@ async fn handle_publish(
 /// Handle an event loop error: update status and broadcast.
 /// Extracted for testability — the retry sleep stays in [`run_mqtt_task`].
 fn handle_error(
+    broker: &str,
     err: &rumqttc::ConnectionError,
     status_tx: &Arc<watch::Sender<String>>,
     tx: &broadcast::Sender<BrokerEvent>,
 ) {
-    tracing::warn!(%err, "MQTT eventloop error, will retry");
-    let _ = status_tx.send_replace("error".into());
-    let _ = tx.send(BrokerEvent::Status { status: "error".into() });
+    tracing::warn!(broker, %err, "MQTT eventloop error, will retry");
+    // The reason (refused connection, TLS handshake failure, …) is shown in
+    // the status bar after the `error` state.
+    let status = format!("error: {err}");
+    let _ = status_tx.send_replace(status.clone());
+    let _ = tx.send(BrokerEvent::Status { broker: broker.to_owned(), status });
+}
+
+/// Build the TLS transport for `config`, or `None` for a plaintext connection.
+/// Files are read up front so a bad path fails at startup; certificates that
+/// don't parse or verify surface as connection errors instead.
+async fn tls_transport(config: &BrokerConfig, default_ca_path: &Path) -> Result<Option<Transport>, Error> {
+    let client_paths = match (&config.client_cert_path, &config.client_key_path) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        (None, None) => None,
+        _ => {
+            return Err(Error::MqttSetup(
+                "client_cert_path and client_key_path must be set together".into(),
+            ));
+        }
+    };
+    if !config.tls {
+        if config.ca_path.is_some() || client_paths.is_some() {
+            return Err(Error::MqttSetup("certificate paths require tls = true".into()));
+        }
+        return Ok(None);
+    }
+
+    let ca = read_file(config.ca_path.as_deref().unwrap_or(default_ca_path)).await?;
+    let client_auth = match client_paths {
+        Some((cert, key)) => Some((read_file(cert).await?.into_bytes(), read_file(key).await?.into_bytes())),
+        None => None,
+    };
+    Ok(Some(Transport::tls_with_config(TlsConfiguration::Simple {
+        ca: ca.into_bytes(),
+        alpn: None,
+        client_auth,
+    })))
 }
 
 /// Create and configure an MQTT client from config without connecting.
 /// The returned `AsyncClient` can be cloned for publishing; pass the `EventLoop`
-/// to [`run_mqtt_task`] to drive the connection.
-pub fn setup_mqtt_client(config: &MqttConfig) -> (AsyncClient, EventLoop) {
+/// to [`run_mqtt_task`] to drive the connection. With `tls` set and no
+/// `ca_path` of its own, the broker is verified against `default_ca_path`.
+pub async fn setup_mqtt_client(
+    config: &BrokerConfig,
+    default_ca_path: &Path,
+) -> Result<(AsyncClient, EventLoop), Error> {
     let mut opts = MqttOptions::new(&config.client_id, &config.host, config.port);
+    if let Some(transport) = tls_transport(config, default_ca_path).await? {
+        let _ = opts.set_transport(transport);
+    }
     let _ = opts.set_keep_alive(Duration::from_secs(10));
     // Some topics (e.g. Frigate snapshots, zigbee2mqtt device lists) send large
     // payloads. Raise the limit to 1 MiB to avoid repeated reconnect loops.
@@ -349,12 +822,15 @@ pub fn setup_mqtt_client(config: &MqttConfig) -> (AsyncClient, EventLoop) {
     if let (Some(user), Some(pass)) = (config.username.as_deref(), config.password.as_deref()) {
         let _ = opts.set_credentials(user, pass);
     }
-    AsyncClient::new(opts, 64)
+    Ok(AsyncClient::new(opts, 64))
 }
 
-/// Spawn the MQTT subscriber task. Runs forever, reconnecting automatically.
+/// Spawn the subscriber task for one broker. Runs forever, reconnecting
+/// automatically. Messages are tagged with the broker's name and kept in the
+/// shared `recent_messages` buffer, capped at `scrollback`.
 pub async fn run_mqtt_task(
-    config: MqttConfig,
+    config: BrokerConfig,
+    scrollback: usize,
     client: AsyncClient,
     mut eventloop: EventLoop,
     tx: broadcast::Sender<BrokerEvent>,
@@ -364,14 +840,16 @@ pub async fn run_mqtt_task(
     loop {
         match eventloop.poll().await {
             Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
-                handle_publish(publish.topic, &publish.payload, config.scrollback, &tx, &recent_messages).await;
+                handle_publish(&config.name, publish.topic, &publish.payload, publish.retain, scrollback, &tx, &recent_messages)
+                    .await;
             }
             Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
-                handle_conn_ack(&client, &config.topics, &status_tx, &tx, &config.host, config.port).await;
+                handle_conn_ack(&client, &config.name, &config.topics, &status_tx, &tx, &config.host, config.port)
+                    .await;
             }
             Ok(_) => {}
             Err(err) => {
-                handle_error(&err, &status_tx, &tx);
+                handle_error(&config.name, &err, &status_tx, &tx);
                 tokio::time::sleep(Duration::from_secs(5)).await;
             }
         }
@@ -392,10 +870,15 @@ pub struct DeviceRow {
     pub last_seen: String,
     /// Total number of messages seen from this device.
     pub message_count: i64,
+    /// Friendly name and notes, if the device has been given some.
+    pub alias: Option<DeviceAlias>,
+    /// Whether it is reporting on time; `None` if it isn't watched.
+    pub health: Option<DeviceHealth>,
 }
 
 /// Background task: listen for MQTT messages, extract device IDs, persist to DB, and
-/// increment the Prometheus counter.
+/// update the Prometheus metrics, labelled by alias when `label` is
+/// [`DeviceLabel::Alias`].
 ///
 /// On startup, purges any rows whose stored pattern no longer matches the current config
 /// so that pattern changes are automatically reflected without manual DB cleanup.
@@ -404,10 +887,20 @@ pub struct DeviceRow {
 /// (to record `first_seen`), then subsequent writes are batched for up to
 /// [`DB_WRITE_INTERVAL`] so that high-frequency devices don't generate one query
 /// per message.
+///
+/// Devices of integrations with an offline interval are checked every
+/// [`CHECK_INTERVAL`]; going offline and coming back are sent to `alerts`.
+///
+/// Every message is also folded into the device's entry in `states`.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_device_tracker_task(
     integrations: Arc<Vec<Integration>>,
     db: sqlx::PgPool,
-    metrics: Option<prometheus::IntCounterVec>,
+    metrics: Option<PrometheusState>,
+    aliases: Arc<DeviceAliases>,
+    states: Arc<DeviceStates>,
+    label: DeviceLabel,
+    alerts: Option<NtfyDelivery>,
     mut rx: broadcast::Receiver<BrokerEvent>,
 ) {
     // Purge stale rows for each integration before processing live messages.
@@ -415,51 +908,112 @@ pub async fn run_device_tracker_task(
         cleanup_stale_pattern(&db, &integration.display_name, &integration.pattern).await;
     }
 
+    let metric_device = |integration: &str, device_id: &str| match label {
+        DeviceLabel::Id => device_id.to_owned(),
+        DeviceLabel::Alias => aliases.metric_label(integration, device_id).unwrap_or_else(|| device_id.to_owned()),
+    };
+    let mut watch = OfflineWatch::default();
+    for (integration, device_id, last_seen) in load_last_seen(&db).await {
+        if let Some(ref m) = metrics {
+            let device = metric_device(&integration, &device_id);
+            m.last_seen.with_label_values(&[&integration, &device]).set(last_seen.unix_timestamp());
+        }
+        let expected_interval = integrations
+            .iter()
+            .find(|i| i.display_name == integration)
+            .and_then(|i| i.expected_interval(&device_id));
+        if let Some(expected_interval) = expected_interval {
+            watch.seed(&integration, &device_id, last_seen, expected_interval, time::OffsetDateTime::now_utc());
+        }
+    }
+
     // (integration, pattern, device_id) → (pending_count, last_write)
     // `last_write = None` means the device has never been written to the DB.
     let mut pending: HashMap<(String, String, String), (i64, Option<Instant>)> = HashMap::new();
+    let mut check_timer = tokio::time::interval(CHECK_INTERVAL);
 
     loop {
-        match rx.recv().await {
-            Ok(BrokerEvent::Message(ref msg)) => {
-                if let Some((integration, device_id)) =
-                    match_integrations(&integrations, &msg.topic)
-                {
-                    let key = (
-                        integration.display_name.clone(),
-                        integration.pattern.clone(),
-                        device_id.to_string(),
-                    );
-                    let (count, last_write) = pending.entry(key.clone()).or_insert((0, None));
-                    *count += 1;
-
-                    let should_write = match last_write {
-                        None => true,
-                        Some(t) => t.elapsed() >= DB_WRITE_INTERVAL,
-                    };
-                    if should_write {
-                        upsert_device(&db, &key.0, &key.1, &key.2, *count).await;
-                        *count = 0;
-                        *last_write = Some(Instant::now());
-                    }
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(BrokerEvent::Message(ref msg)) => {
+                    if let Some((integration, device_id)) =
+                        match_integrations(&integrations, &msg.topic)
+                    {
+                        let key = (
+                            integration.display_name.clone(),
+                            integration.pattern.clone(),
+                            device_id.to_string(),
+                        );
+                        let (count, last_write) = pending.entry(key.clone()).or_insert((0, None));
+                        *count += 1;
+
+                        let should_write = match last_write {
+                            None => true,
+                            Some(t) => t.elapsed() >= DB_WRITE_INTERVAL,
+                        };
+                        if should_write {
+                            upsert_device(&db, &key.0, &key.1, &key.2, *count).await;
+                            *count = 0;
+                            *last_write = Some(Instant::now());
+                        }
+
+                        let now = time::OffsetDateTime::now_utc();
+                        states.record(&integration.display_name, device_id, integration.subtopic(&msg.topic), msg, now);
+                        if let Some(ref m) = metrics {
+                            let device = metric_device(&integration.display_name, device_id);
+                            let labels = [integration.display_name.as_str(), device.as_str()];
+                            m.messages_total.with_label_values(&labels).inc();
+                            m.last_seen.with_label_values(&labels).set(now.unix_timestamp());
+                        }
 
-                    if let Some(ref counter) = metrics {
-                        counter.with_label_values(&[&integration.display_name, device_id]).inc();
+                        if let Some(expected_interval) = integration.expected_interval(device_id)
+                            && let Some(alert) = watch.seen(&integration.display_name, device_id, expected_interval, now)
+                        {
+                            send_device_alert(alerts.as_ref(), &aliases, alert);
+                        }
                     }
                 }
-            }
-            Ok(BrokerEvent::Status { .. }) => {}
-            Err(broadcast::error::RecvError::Lagged(n)) => {
-                tracing::warn!(n, "device tracker lagged, skipping messages");
-            }
-            Err(broadcast::error::RecvError::Closed) => {
-                tracing::warn!("device tracker broadcast channel closed, task exiting");
-                break;
+                Ok(BrokerEvent::Status { .. }) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!(n, "device tracker lagged, skipping messages");
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    tracing::warn!("device tracker broadcast channel closed, task exiting");
+                    break;
+                }
+            },
+            _ = check_timer.tick() => {
+                for alert in watch.check(time::OffsetDateTime::now_utc()) {
+                    send_device_alert(alerts.as_ref(), &aliases, alert);
+                }
             }
         }
     }
 }
 
+/// Log `alert` and, when alerts are configured, post it to ntfy in the
+/// background so a slow ntfy server doesn't hold up the tracker.
+fn send_device_alert(alerts: Option<&NtfyDelivery>, aliases: &DeviceAliases, alert: DeviceAlert) {
+    let (integration, device_id) = alert.device();
+    let name = aliases.name(integration, device_id);
+    tracing::info!(integration, device_id, "{}", alert.title(name.as_deref()));
+    if let Some(ntfy) = alerts.cloned() {
+        drop(tokio::spawn(async move { alert.send(&ntfy, name).await }));
+    }
+}
+
+/// `(integration, device_id, last_seen)` for every tracked device, so a
+/// restart keeps watching devices that have since gone quiet.
+async fn load_last_seen(db: &sqlx::PgPool) -> Vec<(String, String, time::OffsetDateTime)> {
+    let rows = sqlx::query_as("SELECT integration, device_id, last_seen FROM mqtt_devices")
+        .fetch_all(db)
+        .await;
+    rows.unwrap_or_else(|err| {
+        tracing::warn!(%err, "failed to load mqtt device last_seen times");
+        Vec::new()
+    })
+}
+
 /// How long to accumulate message counts before flushing to the database.
 const DB_WRITE_INTERVAL: Duration = Duration::from_secs(60);
 
@@ -524,7 +1078,7 @@ mod tests {
     // ── parse_integrations / match_topic / match_integrations ─────────────────
 
     fn parsed(pattern: &str) -> Vec<PatternSegment> {
-        parse_integrations(&[IntegrationConfig { pattern: pattern.to_string(), name: None }])
+        parse_integrations(&[IntegrationConfig { pattern: pattern.to_string(), name: None, ..Default::default() }])
             .into_iter()
             .next()
             .unwrap()
@@ -583,10 +1137,11 @@ mod tests {
     #[test]
     fn match_integrations_returns_first_match_with_name() {
         let cfgs = vec![
-            IntegrationConfig { pattern: "other/#".to_string(), name: None },
+            IntegrationConfig { pattern: "other/#".to_string(), name: None, ..Default::default() },
             IntegrationConfig {
                 pattern: "z2m/{device}/**".to_string(),
                 name: Some("Zigbee".to_string()),
+                ..Default::default()
             },
         ];
         let integrations = parse_integrations(&cfgs);
@@ -602,6 +1157,7 @@ mod tests {
         let cfgs = vec![IntegrationConfig {
             pattern: "zigbee2mqtt/{device}/**".to_string(),
             name: None,
+            ..Default::default()
         }];
         let integrations = parse_integrations(&cfgs);
         assert!(match_integrations(&integrations, "homeassistant/sensor/state").is_none());
@@ -612,6 +1168,7 @@ mod tests {
         let cfgs = vec![IntegrationConfig {
             pattern: "zigbee2mqtt/{device}/**".to_string(),
             name: None,
+            ..Default::default()
         }];
         let integrations = parse_integrations(&cfgs);
         assert_eq!(integrations[0].pattern, "zigbee2mqtt/{device}/**");
@@ -622,6 +1179,7 @@ mod tests {
         let cfgs = vec![IntegrationConfig {
             pattern: "z2m/{device}/**".to_string(),
             name: Some("Zigbee".to_string()),
+            ..Default::default()
         }];
         let integrations = parse_integrations(&cfgs);
         let (integration, _device) =
@@ -629,11 +1187,23 @@ mod tests {
         assert_eq!(integration.pattern, "z2m/{device}/**");
     }
 
+    #[test]
+    fn subtopic_is_what_follows_the_device_level() {
+        let integrations = parse_integrations(&[
+            IntegrationConfig { pattern: "zigbee2mqtt/{device}/**".to_string(), ..Default::default() },
+            IntegrationConfig { pattern: "homeassistant/+/{device}/**".to_string(), ..Default::default() },
+        ]);
+        assert_eq!(integrations[0].subtopic("zigbee2mqtt/lamp"), "");
+        assert_eq!(integrations[0].subtopic("zigbee2mqtt/lamp/availability"), "availability");
+        assert_eq!(integrations[1].subtopic("homeassistant/sensor/attic/state/raw"), "state/raw");
+    }
+
     #[test]
     fn integration_display_name_defaults_to_first_literal() {
         let cfgs = vec![IntegrationConfig {
             pattern: "mybridge/{device}/**".to_string(),
             name: None,
+            ..Default::default()
         }];
         let integrations = parse_integrations(&cfgs);
         assert_eq!(integrations[0].display_name, "mybridge");
@@ -644,6 +1214,7 @@ mod tests {
         let cfgs = vec![IntegrationConfig {
             pattern: "{device}/**".to_string(),
             name: None,
+            ..Default::default()
         }];
         let integrations = parse_integrations(&cfgs);
         assert_eq!(integrations[0].display_name, "unknown");
@@ -684,26 +1255,26 @@ mod tests {
     #[test]
     fn mqtt_config_default_host() {
         let cfg: MqttConfig = toml::from_str(r#"client_id = "test""#).unwrap();
-        assert_eq!(cfg.host, "localhost");
+        assert_eq!(cfg.brokers[0].host, "localhost");
     }
 
     #[test]
     fn mqtt_config_default_port() {
         let cfg: MqttConfig = toml::from_str(r#"client_id = "test""#).unwrap();
-        assert_eq!(cfg.port, 1883);
+        assert_eq!(cfg.brokers[0].port, 1883);
     }
 
     #[test]
     fn mqtt_config_default_topics_is_wildcard() {
         let cfg: MqttConfig = toml::from_str(r#"client_id = "test""#).unwrap();
-        assert_eq!(cfg.topics, vec!["#"]);
+        assert_eq!(cfg.brokers[0].topics, vec!["#"]);
     }
 
     #[test]
     fn mqtt_config_default_credentials_are_none() {
         let cfg: MqttConfig = toml::from_str(r#"client_id = "test""#).unwrap();
-        assert!(cfg.username.is_none());
-        assert!(cfg.password.is_none());
+        assert!(cfg.brokers[0].username.is_none());
+        assert!(cfg.brokers[0].password.is_none());
     }
 
     #[test]
@@ -717,11 +1288,131 @@ mod tests {
             topics = ["home/#", "sensors/#"]
         "#)
         .unwrap();
-        assert_eq!(cfg.host, "broker.example.com");
-        assert_eq!(cfg.port, 8883);
-        assert_eq!(cfg.username.as_deref(), Some("user"));
-        assert_eq!(cfg.password.as_deref(), Some("pass"));
-        assert_eq!(cfg.topics, vec!["home/#", "sensors/#"]);
+        assert_eq!(cfg.brokers[0].host, "broker.example.com");
+        assert_eq!(cfg.brokers[0].port, 8883);
+        assert_eq!(cfg.brokers[0].username.as_deref(), Some("user"));
+        assert_eq!(cfg.brokers[0].password.as_deref(), Some("pass"));
+        assert_eq!(cfg.brokers[0].topics, vec!["home/#", "sensors/#"]);
+    }
+
+    // ── Brokers ───────────────────────────────────────────────────────────────
+
+    #[test]
+    fn mqtt_config_single_broker_layout_is_named_default() {
+        let cfg: MqttConfig = toml::from_str(r#"client_id = "test""#).unwrap();
+        assert_eq!(cfg.brokers.len(), 1);
+        assert_eq!(cfg.brokers[0].name, "default");
+        assert_eq!(cfg.scrollback, 200);
+    }
+
+    #[test]
+    fn mqtt_config_parses_named_brokers() {
+        let cfg: MqttConfig = toml::from_str(
+            r#"scrollback = 50
+               [[brokers]]
+               name = "zigbee"
+               client_id = "green-zigbee"
+               [[brokers]]
+               name = "frigate"
+               host = "nvr.lan"
+               client_id = "green-frigate"
+               topics = ["frigate/#"]
+               [[integrations]]
+               pattern = "zigbee2mqtt/{device}/**"
+            "#,
+        )
+        .unwrap();
+        let names: Vec<&str> = cfg.brokers.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["zigbee", "frigate"]);
+        assert_eq!(cfg.brokers[1].host, "nvr.lan");
+        assert_eq!(cfg.brokers[1].topics, vec!["frigate/#"]);
+        assert_eq!(cfg.scrollback, 50);
+        assert_eq!(cfg.integrations.len(), 1);
+    }
+
+    #[test]
+    fn mqtt_config_rejects_mixed_layouts_duplicates_and_no_brokers() {
+        let mixed = r#"client_id = "a"
+            [[brokers]]
+            client_id = "b"
+        "#;
+        let duplicate = r#"[[brokers]]
+            name = "x"
+            client_id = "a"
+            [[brokers]]
+            name = "x"
+            client_id = "b"
+        "#;
+        for toml_str in [mixed, duplicate, "scrollback = 10"] {
+            assert!(toml::from_str::<MqttConfig>(toml_str).is_err(), "{toml_str}");
+        }
+    }
+
+    #[test]
+    fn password_env_var_is_per_broker() {
+        assert_eq!(password_env_var("frigate"), "GREEN_MQTT_PASSWORD_FRIGATE");
+        assert_eq!(password_env_var("home-assistant"), "GREEN_MQTT_PASSWORD_HOME_ASSISTANT");
+    }
+
+    // ── TLS ───────────────────────────────────────────────────────────────────
+
+    fn tls_config(extra: &str) -> BrokerConfig {
+        toml::from_str(&format!("client_id = \"test\"\n{extra}")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn plaintext_config_has_no_tls_transport() {
+        let cfg = tls_config("");
+        assert!(!cfg.tls);
+        assert!(tls_transport(&cfg, Path::new("/nonexistent")).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn tls_reads_ca_from_default_path_and_client_auth() {
+        let dir = std::env::temp_dir().join(format!("green-mqtt-tls-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ca = dir.join("ca.pem");
+        let cert = dir.join("client.pem");
+        let key = dir.join("client.key");
+        std::fs::write(&ca, "ca").unwrap();
+        std::fs::write(&cert, "cert").unwrap();
+        std::fs::write(&key, "key").unwrap();
+
+        let cfg = tls_config(&format!(
+            "tls = true\nclient_cert_path = {cert:?}\nclient_key_path = {key:?}"
+        ));
+        let transport = tls_transport(&cfg, &ca).await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        match transport {
+            Some(Transport::Tls(TlsConfiguration::Simple { ca, client_auth, .. })) => {
+                assert_eq!(ca, b"ca");
+                assert_eq!(client_auth, Some((b"cert".to_vec(), b"key".to_vec())));
+            }
+            _ => panic!("expected a TLS transport"),
+        }
+    }
+
+    #[tokio::test]
+    async fn tls_with_missing_ca_fails_setup() {
+        let cfg = tls_config("tls = true\nca_path = \"/nonexistent/ca.pem\"");
+        assert!(matches!(
+            tls_transport(&cfg, Path::new("/also/nonexistent")).await,
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn client_cert_without_key_or_tls_is_rejected() {
+        let half = tls_config("tls = true\nclient_cert_path = \"client.pem\"");
+        assert!(matches!(
+            tls_transport(&half, Path::new("ca.pem")).await,
+            Err(Error::MqttSetup(_))
+        ));
+        let no_tls = tls_config("ca_path = \"ca.pem\"");
+        assert!(matches!(
+            tls_transport(&no_tls, Path::new("ca.pem")).await,
+            Err(Error::MqttSetup(_))
+        ));
     }
 
     // ── MqttMessage serde ─────────────────────────────────────────────────────
@@ -729,9 +1420,11 @@ mod tests {
     #[test]
     fn mqtt_message_round_trips_json() {
         let msg = MqttMessage {
+            broker: "default".into(),
             topic: "home/temp".into(),
             payload: "21.5".into(),
             received_at: "2026-03-15T12:00:00Z".into(),
+            ..Default::default()
         };
         let json = serde_json::to_string(&msg).unwrap();
         let decoded: MqttMessage = serde_json::from_str(&json).unwrap();
@@ -743,9 +1436,11 @@ mod tests {
     #[test]
     fn mqtt_message_serialized_field_names() {
         let msg = MqttMessage {
+            broker: "default".into(),
             topic: "t".into(),
             payload: "p".into(),
             received_at: "r".into(),
+            ..Default::default()
         };
         let v: serde_json::Value = serde_json::to_value(&msg).unwrap();
         assert!(v.get("topic").is_some());
@@ -753,6 +1448,37 @@ mod tests {
         assert!(v.get("received_at").is_some());
     }
 
+    #[test]
+    fn payload_kind_detects_images_json_text_and_binary() {
+        assert_eq!(PayloadKind::detect(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10]), PayloadKind::Jpeg);
+        assert_eq!(PayloadKind::detect(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), PayloadKind::Png);
+        assert_eq!(PayloadKind::detect(br#"{"state":"ON"}"#), PayloadKind::Json);
+        assert_eq!(PayloadKind::detect(b"[1,2]"), PayloadKind::Json);
+        assert_eq!(PayloadKind::detect(b"21.5"), PayloadKind::Text);
+        assert_eq!(PayloadKind::detect(b""), PayloadKind::Text);
+        // CBOR map {"a": 1}: not UTF-8 past the first byte.
+        assert_eq!(PayloadKind::detect(&[0xA1, 0x61, 0x61, 0x01, 0xFF]), PayloadKind::Binary);
+    }
+
+    #[test]
+    fn new_message_keeps_bytes_only_for_binary() {
+        let text = MqttMessage::new("default", "t".into(), b"on", "r".into());
+        assert_eq!(text.payload, "on");
+        assert!(text.raw.is_empty());
+        assert_eq!(text.bytes(), b"on");
+
+        let frame = [0x00, 0x9F, 0xFF, 0x10];
+        let binary = MqttMessage::new("default", "t".into(), &frame, "r".into());
+        assert_eq!(binary.kind, PayloadKind::Binary);
+        assert_eq!(binary.payload, "application/octet-stream, 4 bytes");
+        assert_eq!(binary.bytes(), frame);
+        assert!(binary.id > text.id, "ids increase in arrival order");
+
+        let json = serde_json::to_value(&binary).unwrap();
+        assert_eq!(json["kind"], "binary");
+        assert!(json.get("raw").is_none(), "bytes are never serialized");
+    }
+
     // ── utc_now ───────────────────────────────────────────────────────────────
 
     #[test]
@@ -798,9 +1524,9 @@ mod tests {
         let (tx, mut rx) = broadcast::channel(16);
         let (status_tx, _) = watch::channel("connecting".to_string());
         let status_tx = Arc::new(status_tx);
-        handle_conn_ack(&MockSubscriber::default(), &[], &status_tx, &tx, "h", 1883).await;
+        handle_conn_ack(&MockSubscriber::default(), "default", &[], &status_tx, &tx, "h", 1883).await;
         assert_eq!(*status_tx.borrow(), "connected");
-        assert!(matches!(rx.try_recv(), Ok(BrokerEvent::Status { status }) if status == "connected"));
+        assert!(matches!(rx.try_recv(), Ok(BrokerEvent::Status { status, .. }) if status == "connected"));
     }
 
     #[tokio::test]
@@ -812,6 +1538,7 @@ mod tests {
         let mock = MockSubscriber { subscribed: Arc::clone(&subscribed) };
         handle_conn_ack(
             &mock,
+            "default",
             &["home/#".to_string(), "sensors/+".to_string()],
             &status_tx,
             &tx,
@@ -826,11 +1553,12 @@ mod tests {
     async fn publish_stored_in_buffer_and_broadcast() {
         let (tx, mut rx) = broadcast::channel(16);
         let recent = Arc::new(TokioMutex::new(VecDeque::new()));
-        handle_publish("home/temp".to_string(), b"21.5", 200, &tx, &recent).await;
+        handle_publish("default", "home/temp".to_string(), b"21.5", true, 200, &tx, &recent).await;
         let buf = recent.lock().await;
         assert_eq!(buf.len(), 1);
         assert_eq!(buf[0].topic, "home/temp");
         assert_eq!(buf[0].payload, "21.5");
+        assert!(buf[0].retain);
         drop(buf);
         assert!(matches!(rx.try_recv(), Ok(BrokerEvent::Message(m)) if m.topic == "home/temp"));
     }
@@ -841,7 +1569,7 @@ mod tests {
         let recent = Arc::new(TokioMutex::new(VecDeque::new()));
         let cap = 3;
         for i in 0..5u8 {
-            handle_publish(format!("t/{i}"), &[i], cap, &tx, &recent).await;
+            handle_publish("default", format!("t/{i}"), &[i], false, cap, &tx, &recent).await;
         }
         let buf = recent.lock().await;
         assert_eq!(buf.len(), cap);
@@ -857,28 +1585,35 @@ mod tests {
         let err = rumqttc::ConnectionError::Io(std::io::Error::from(
             std::io::ErrorKind::ConnectionReset,
         ));
-        handle_error(&err, &status_tx, &tx);
-        assert_eq!(*status_tx.borrow(), "error");
-        assert!(matches!(rx.try_recv(), Ok(BrokerEvent::Status { status }) if status == "error"));
+        handle_error("default", &err, &status_tx, &tx);
+        let expected = format!("error: {err}");
+        assert_eq!(*status_tx.borrow(), expected);
+        assert!(matches!(rx.try_recv(), Ok(BrokerEvent::Status { status, .. }) if status == expected));
     }
 
     // ── build_event_stream ────────────────────────────────────────────────────
 
+    fn statuses(status: &str) -> Vec<(String, String)> {
+        vec![("default".into(), status.into())]
+    }
+
     fn msg(topic: &str) -> MqttMessage {
         MqttMessage {
+            broker: "default".into(),
             topic: topic.into(),
             payload: "p".into(),
             received_at: "2026-01-01T00:00:00Z".into(),
+            ..Default::default()
         }
     }
 
     #[tokio::test]
     async fn event_stream_first_event_is_initial_status() {
         let (tx, rx) = broadcast::channel(16);
-        let stream = build_event_stream("connected".into(), vec![], rx);
+        let stream = build_event_stream(statuses("connected"), vec![], rx, StreamFilter::default());
         let events: Vec<_> = futures::StreamExt::take(stream, 1).collect().await;
         match &events[0] {
-            BrokerEvent::Status { status } => assert_eq!(status, "connected"),
+            BrokerEvent::Status { status, .. } => assert_eq!(status, "connected"),
             _ => panic!("expected Status event first"),
         }
         drop(tx);
@@ -888,7 +1623,7 @@ mod tests {
     async fn event_stream_backlog_follows_status() {
         let (tx, rx) = broadcast::channel(16);
         let backlog = vec![msg("a"), msg("b")];
-        let stream = build_event_stream("connected".into(), backlog, rx);
+        let stream = build_event_stream(statuses("connected"), backlog, rx, StreamFilter::default());
         // Take status + 2 history events
         let events: Vec<_> = futures::StreamExt::take(stream, 3).collect().await;
         assert!(matches!(&events[0], BrokerEvent::Status { .. }));
@@ -901,7 +1636,7 @@ mod tests {
     async fn event_stream_backlog_order_is_oldest_first() {
         let (tx, rx) = broadcast::channel(16);
         let backlog = vec![msg("first"), msg("second"), msg("third")];
-        let stream = build_event_stream("connected".into(), backlog, rx);
+        let stream = build_event_stream(statuses("connected"), backlog, rx, StreamFilter::default());
         let events: Vec<_> = futures::StreamExt::take(stream, 4).collect().await;
         // events[0] = status; events[1..] = history in order
         let topics: Vec<&str> = events[1..]
@@ -921,7 +1656,7 @@ mod tests {
         // Send a live message before draining the stream past history
         let _ = tx.send(BrokerEvent::Message(msg("live")));
         // stream: 1 status (no backlog) + 1 live message
-        let stream = build_event_stream("connected".into(), vec![], rx);
+        let stream = build_event_stream(statuses("connected"), vec![], rx, StreamFilter::default());
         let events: Vec<_> = futures::StreamExt::take(stream, 2).collect().await;
         assert!(matches!(&events[1], BrokerEvent::Message(m) if m.topic == "live"));
         drop(tx);
@@ -930,10 +1665,10 @@ mod tests {
     #[tokio::test]
     async fn event_stream_live_status_forwarded() {
         let (tx, rx) = broadcast::channel(16);
-        let _ = tx.send(BrokerEvent::Status { status: "error".into() });
-        let stream = build_event_stream("connected".into(), vec![], rx);
+        let _ = tx.send(BrokerEvent::Status { broker: "default".into(), status: "error".into() });
+        let stream = build_event_stream(statuses("connected"), vec![], rx, StreamFilter::default());
         let events: Vec<_> = futures::StreamExt::take(stream, 2).collect().await;
-        assert!(matches!(&events[1], BrokerEvent::Status { status } if status == "error"));
+        assert!(matches!(&events[1], BrokerEvent::Status { status, .. } if status == "error"));
         drop(tx);
     }
 
@@ -941,41 +1676,152 @@ mod tests {
     async fn event_stream_ends_when_channel_closed() {
         let (tx, rx) = broadcast::channel(16);
         drop(tx);
-        let stream = build_event_stream("connecting".into(), vec![], rx);
+        let stream = build_event_stream(statuses("connecting"), vec![], rx, StreamFilter::default());
         // Only the initial status; live part immediately returns None
         let events: Vec<_> = stream.collect().await;
         assert_eq!(events.len(), 1);
-        assert!(matches!(&events[0], BrokerEvent::Status { status } if status == "connecting"));
+        assert!(matches!(&events[0], BrokerEvent::Status { status, .. } if status == "connecting"));
     }
 
-    // ── html_escape ───────────────────────────────────────────────────────────
-
-    #[test]
-    fn html_escape_ampersand() {
-        assert_eq!(html_escape("a&b"), "a&amp;b");
+    fn zigbee_integrations() -> Arc<Vec<Integration>> {
+        Arc::new(parse_integrations(&[IntegrationConfig {
+            pattern: "zigbee2mqtt/{device}/**".to_string(),
+            name: None,
+            ..Default::default()
+        }]))
     }
 
-    #[test]
-    fn html_escape_lt_gt() {
-        assert_eq!(html_escape("<tag>"), "&lt;tag&gt;");
+    fn stream_filter(query: StreamQuery) -> StreamFilter {
+        query.filter(&zigbee_integrations()).unwrap()
     }
 
-    #[test]
-    fn html_escape_quote() {
-        assert_eq!(html_escape(r#"say "hi""#), "say &quot;hi&quot;");
+    async fn filtered_topics(filter: StreamFilter, backlog: &[&str], live: &[&str]) -> Vec<String> {
+        let (tx, rx) = broadcast::channel(16);
+        for topic in live {
+            let _ = tx.send(BrokerEvent::Message(msg(topic)));
+        }
+        drop(tx);
+        let backlog = backlog.iter().map(|t| msg(t)).collect();
+        build_event_stream(vec![], backlog, rx, filter)
+            .filter_map(|e| std::future::ready(match e {
+                BrokerEvent::Message(m) => Some(m.topic),
+                BrokerEvent::Status { .. } => None,
+            }))
+            .collect()
+            .await
     }
 
-    #[test]
-    fn html_escape_no_special_chars_unchanged() {
-        assert_eq!(html_escape("hello world"), "hello world");
+    #[tokio::test]
+    async fn event_stream_topic_filter_applies_to_backlog_and_live() {
+        let filter = stream_filter(StreamQuery {
+            topic: "zigbee2mqtt/+/set, frigate/#".into(),
+            ..Default::default()
+        });
+        let topics = filtered_topics(
+            filter,
+            &["zigbee2mqtt/lamp/set", "zigbee2mqtt/lamp", "frigate/events"],
+            &["frigate/cam/snapshot", "other/topic", "zigbee2mqtt/plug/set"],
+        )
+        .await;
+        assert_eq!(
+            topics,
+            ["zigbee2mqtt/lamp/set", "frigate/events", "frigate/cam/snapshot", "zigbee2mqtt/plug/set"]
+        );
     }
 
-    #[test]
-    fn html_escape_all_specials() {
-        assert_eq!(html_escape(r#"<a href="x&y">z</a>"#), "&lt;a href=&quot;x&amp;y&quot;&gt;z&lt;/a&gt;");
+    #[tokio::test]
+    async fn event_stream_device_filter_uses_integrations() {
+        let filter = stream_filter(StreamQuery {
+            integration: "zigbee2mqtt".into(),
+            device: "lamp".into(),
+            ..Default::default()
+        });
+        let topics = filtered_topics(
+            filter,
+            &["zigbee2mqtt/lamp", "zigbee2mqtt/plug"],
+            &["zigbee2mqtt/lamp/availability", "frigate/lamp"],
+        )
+        .await;
+        assert_eq!(topics, ["zigbee2mqtt/lamp", "zigbee2mqtt/lamp/availability"]);
     }
 
-    // ── truncate_at_char ──────────────────────────────────────────────────────
+    #[tokio::test]
+    async fn event_stream_caps_payload_size() {
+        let filter = stream_filter(StreamQuery { max_payload: Some(4), ..Default::default() });
+        let (tx, rx) = broadcast::channel(16);
+        let big = MqttMessage { payload: "x".repeat(5000), ..msg("frigate/cam/snapshot") };
+        let _ = tx.send(BrokerEvent::Message(big));
+        drop(tx);
+        let events: Vec<_> = build_event_stream(vec![], vec![msg("small")], rx, filter).collect().await;
+        assert!(matches!(&events[0], BrokerEvent::Message(m) if m.payload == "p"));
+        assert!(matches!(
+            &events[1],
+            BrokerEvent::Message(m) if m.payload == "[5000 bytes, over the 4-byte limit]"
+        ));
+    }
+
+    #[test]
+    fn stream_query_rejects_bad_filters() {
+        let integrations = zigbee_integrations();
+        let bad_topic = StreamQuery { topic: "a/#/b".into(), ..Default::default() };
+        let unknown = StreamQuery { integration: "zwave".into(), ..Default::default() };
+        assert!(matches!(bad_topic.filter(&integrations), Err(Error::InvalidStreamFilter(_))));
+        assert!(matches!(unknown.filter(&integrations), Err(Error::InvalidStreamFilter(_))));
+    }
+
+    // ── topic filters ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn topic_matches_follows_mqtt_wildcards() {
+        assert!(topic_matches("a/b", "a/b"));
+        assert!(!topic_matches("a/b", "a/b/c"));
+        assert!(topic_matches("a/+/c", "a/b/c"));
+        assert!(!topic_matches("a/+", "a/b/c"));
+        assert!(topic_matches("a/#", "a"));
+        assert!(topic_matches("a/#", "a/b/c"));
+        assert!(topic_matches("#", "anything/at/all"));
+        assert!(!topic_matches("#", "$SYS/broker/uptime"));
+        assert!(!topic_matches("+/broker/uptime", "$SYS/broker/uptime"));
+    }
+
+    #[test]
+    fn validate_topic_filter_rejects_partial_and_misplaced_wildcards() {
+        for ok in ["a/b", "a/+/c", "a/#", "#", "+"] {
+            assert!(validate_topic_filter(ok).is_ok(), "{ok}");
+        }
+        for bad in ["", "a/b#", "a/#/c", "a+/b"] {
+            assert!(validate_topic_filter(bad).is_err(), "{bad}");
+        }
+    }
+
+    // ── html_escape ───────────────────────────────────────────────────────────
+
+    #[test]
+    fn html_escape_ampersand() {
+        assert_eq!(html_escape("a&b"), "a&amp;b");
+    }
+
+    #[test]
+    fn html_escape_lt_gt() {
+        assert_eq!(html_escape("<tag>"), "&lt;tag&gt;");
+    }
+
+    #[test]
+    fn html_escape_quote() {
+        assert_eq!(html_escape(r#"say "hi""#), "say &quot;hi&quot;");
+    }
+
+    #[test]
+    fn html_escape_no_special_chars_unchanged() {
+        assert_eq!(html_escape("hello world"), "hello world");
+    }
+
+    #[test]
+    fn html_escape_all_specials() {
+        assert_eq!(html_escape(r#"<a href="x&y">z</a>"#), "&lt;a href=&quot;x&amp;y&quot;&gt;z&lt;/a&gt;");
+    }
+
+    // ── truncate_at_char ──────────────────────────────────────────────────────
 
     #[test]
     fn truncate_at_char_ascii_within_limit() {
@@ -1090,28 +1936,116 @@ mod tests {
 
     #[test]
     fn render_message_card_contains_topic() {
-        let msg = MqttMessage { topic: "home/temp".to_owned(), payload: "21.5".to_owned(), received_at: "2026-01-01T12:00:00Z".to_owned() };
-        let html = render_message_card(&msg);
+        let msg = MqttMessage { broker: "default".to_owned(), topic: "home/temp".to_owned(), payload: "21.5".to_owned(), received_at: "2026-01-01T12:00:00Z".to_owned(), ..Default::default() };
+        let html = render_message_card(&msg, None);
         assert!(html.contains("home/temp"));
         assert!(html.contains("mqtt-msg"));
     }
 
     #[test]
     fn render_message_card_topic_is_escaped() {
-        let msg = MqttMessage { topic: "home/<test>".to_owned(), payload: "".to_owned(), received_at: "2026-01-01T00:00:00Z".to_owned() };
-        let html = render_message_card(&msg);
+        let msg = MqttMessage { broker: "default".to_owned(), topic: "home/<test>".to_owned(), payload: "".to_owned(), received_at: "2026-01-01T00:00:00Z".to_owned(), ..Default::default() };
+        let html = render_message_card(&msg, None);
         assert!(html.contains("&lt;test&gt;"), "topic is HTML-escaped");
     }
 
     #[test]
     fn render_message_card_includes_time() {
-        let msg = MqttMessage { topic: "t".to_owned(), payload: "p".to_owned(), received_at: "2026-03-17T23:15:24Z".to_owned() };
-        let html = render_message_card(&msg);
+        let msg = MqttMessage { broker: "default".to_owned(), topic: "t".to_owned(), payload: "p".to_owned(), received_at: "2026-03-17T23:15:24Z".to_owned(), ..Default::default() };
+        let html = render_message_card(&msg, None);
         assert!(html.contains("23:15:24"), "formatted time present");
     }
 
+    #[test]
+    fn render_message_card_shows_the_alias() {
+        let msg = MqttMessage { topic: "zigbee2mqtt/0xABCD".to_owned(), payload: "{}".to_owned(), ..Default::default() };
+        let html = render_message_card(&msg, Some("garage <door>"));
+        assert!(html.contains(r#"<span class="mqtt-msg-alias">garage &lt;door&gt;</span>"#));
+        assert!(!render_message_card(&msg, None).contains("mqtt-msg-alias"));
+    }
+
+    #[test]
+    fn render_message_card_is_tagged_with_broker() {
+        let msg = MqttMessage { broker: "frigate".to_owned(), topic: "t".to_owned(), payload: "p".to_owned(), received_at: "2026-03-17T23:15:24Z".to_owned(), ..Default::default() };
+        let html = render_message_card(&msg, None);
+        assert!(html.contains(r#"data-broker="frigate""#));
+        assert!(html.contains(r#"<span class="mqtt-msg-broker">frigate</span>"#));
+    }
+
+    #[test]
+    fn render_message_card_shows_images_as_thumbnails() {
+        let msg = MqttMessage::new("default", "frigate/cam/snapshot".into(), &[0xFF, 0xD8, 0xFF, 0xDB], "r".into());
+        let html = render_message_card(&msg, None);
+        let href = format!("/api/mqtt/payload/{}", msg.id);
+        assert!(html.contains(&format!(r#"<img class="mqtt-msg-image" src="{href}""#)));
+        assert!(html.contains(r#"class="mqtt-msg-download""#));
+        assert!(!html.contains("mqtt-msg-hex"));
+    }
+
+    #[test]
+    fn render_message_card_hex_dumps_binary() {
+        let mut frame = vec![0x00, 0x41, 0x42, 0xFF];
+        frame.resize(HEX_DUMP_LIMIT + 10, 0);
+        let msg = MqttMessage::new("default", "sensor/raw".into(), &frame, "r".into());
+        let html = render_message_card(&msg, None);
+        assert!(html.contains("mqtt-msg-hex"));
+        assert!(html.contains("00000000  00 41 42 ff 00"), "offset and hex bytes: {html}");
+        assert!(html.contains(".AB."), "printable bytes in the ascii column");
+        assert!(html.contains("… 10 more bytes"));
+    }
+
+    #[test]
+    fn render_message_card_binary_from_history_shows_summary() {
+        let msg = MqttMessage {
+            topic: "sensor/raw".into(),
+            kind: PayloadKind::Binary,
+            payload: "application/octet-stream, 4 bytes".into(),
+            ..Default::default()
+        };
+        let html = render_message_card(&msg, None);
+        assert!(html.contains("application/octet-stream, 4 bytes"));
+        assert!(!html.contains("/api/mqtt/payload/"), "nothing to download");
+    }
+
     // ── render_status_html ────────────────────────────────────────────────────
 
+    #[test]
+    fn render_status_bar_single_broker_has_no_pill() {
+        assert_eq!(render_status_bar(&statuses("connected")), render_status_html("connected"));
+    }
+
+    #[test]
+    fn render_status_bar_renders_a_pill_per_broker() {
+        let html = render_status_bar(&[
+            ("zigbee".into(), "connected".into()),
+            ("frigate".into(), "error: connection refused".into()),
+        ]);
+        assert_eq!(html.matches("mqtt-broker-status").count(), 2);
+        assert!(html.contains(r#"data-broker="zigbee""#));
+        assert!(html.contains("mqtt-dot-connected"));
+        assert!(html.contains("mqtt-dot-error"));
+    }
+
+    #[tokio::test]
+    async fn sse_status_update_keeps_other_brokers() {
+        let (tx, rx) = broadcast::channel(16);
+        let initial = vec![
+            ("zigbee".to_string(), "connected".to_string()),
+            ("frigate".to_string(), "connecting".to_string()),
+        ];
+        let _ = tx.send(BrokerEvent::Status { broker: "frigate".into(), status: "connected".into() });
+        drop(tx);
+        let events: Vec<_> = build_event_stream(initial.clone(), vec![], rx, StreamFilter::default()).collect().await;
+        assert_eq!(events.len(), 3, "one status per broker, then the live update");
+
+        // The SSE stream re-renders the whole bar with both brokers.
+        let (tx, rx) = broadcast::channel(16);
+        let _ = tx.send(BrokerEvent::Status { broker: "frigate".into(), status: "connected".into() });
+        drop(tx);
+        let sse: Vec<_> = build_sse_stream(initial, vec![], rx, StreamFilter::default(), Arc::default()).collect().await;
+        assert_eq!(sse.len(), 3);
+    }
+
     #[test]
     fn render_status_html_connected() {
         let html = render_status_html("connected");
@@ -1119,6 +2053,13 @@ mod tests {
         assert!(html.contains("connected"));
     }
 
+    #[test]
+    fn render_status_html_error_keeps_dot_class_and_shows_reason() {
+        let html = render_status_html("error: TLS: No valid CA certificate provided");
+        assert!(html.contains(r#"class="mqtt-dot mqtt-dot-error""#));
+        assert!(html.contains("No valid CA certificate provided"));
+    }
+
     #[test]
     fn render_status_html_escapes_special_chars() {
         let html = render_status_html("err<or>");
@@ -1128,91 +2069,91 @@ mod tests {
     // ── publish_route / device_messages_route handler tests ───────────────────
 
     use crate::{
-        auth::{AuthConfig, AuthState, Role, SessionData},
+        auth::{AuthConfig, AuthUser, Role},
         breaker::BreakerContent,
         breaker_detail::{BreakerData, BreakerStore},
         index::Index,
         route::Routes,
     };
+    use strum::IntoEnumIterator as _;
     use axum::{
         body::Body,
         http::{Request, StatusCode},
         routing::{get, post},
         Router,
     };
-    use std::{collections::HashMap, path::Path, time::Instant};
+    use std::{collections::HashMap, path::Path};
     use tower::ServiceExt;
     use uuid::Uuid;
 
-    async fn state_with_mqtt() -> ServerState {
-        let auth_state = AuthState::new_for_testing(AuthConfig {
-            rp_id: "localhost".to_string(),
-            rp_origin: "http://localhost".to_string(),
-            db_url: "postgres://localhost/nonexistent".to_string(),
-            gm_users: vec!["gm".to_string()],
-            ntfy_url: None,
-        })
-        .unwrap();
-
-        let (tx, _) = broadcast::channel(16);
+    /// A broker whose client never connects. The EventLoop is held alive in a
+    /// spawned task so the internal channel stays open, allowing publish() to
+    /// enqueue without error.
+    fn test_broker(name: &str) -> Broker {
         let (publish_client, eventloop) =
-            AsyncClient::new(MqttOptions::new("green-test", "localhost", 1883), 64);
-        // Hold the EventLoop alive in a spawned task so the internal channel
-        // stays open, allowing publish() to enqueue without error.
-        let _ = tokio::spawn(async move {
+            AsyncClient::new(MqttOptions::new(format!("green-test-{name}"), "localhost", 1883), 64);
+        drop(tokio::spawn(async move {
             let _hold = eventloop;
             std::future::pending::<()>().await
-        });
-        let integrations = parse_integrations(&[IntegrationConfig {
+        }));
+        Broker {
+            name: name.to_owned(),
+            status_tx: Arc::new(watch::channel("connecting".to_string()).0),
+            publish_client,
+        }
+    }
+
+    async fn state_with_mqtt() -> ServerState {
+        state_with_brokers(&["default"]).await
+    }
+
+    async fn state_with_brokers(names: &[&str]) -> ServerState {
+        state_with(names, None).await
+    }
+
+    async fn state_with(names: &[&str], history: Option<Arc<dyn HistoryStore>>) -> ServerState {
+        state_with_rules(names, history, RuleEngine::default()).await
+    }
+
+    async fn state_with_rules(
+        names: &[&str],
+        history: Option<Arc<dyn HistoryStore>>,
+        rules: RuleEngine,
+    ) -> ServerState {
+        let (tx, _) = broadcast::channel(16);
+        let integrations = Arc::new(parse_integrations(&[IntegrationConfig {
             pattern: "zigbee2mqtt/{device}/**".to_string(),
             name: None,
-        }]);
+            ..Default::default()
+        }]));
         let mqtt_state = Arc::new(MqttState {
             tx,
-            status_tx: Arc::new(watch::channel("connecting".to_string()).0),
             recent_messages: Arc::new(TokioMutex::new(VecDeque::new())),
             prometheus: None,
-            integrations: Arc::new(integrations),
-            publish_client,
+            integrations: Arc::clone(&integrations),
+            brokers: names.iter().map(|name| test_broker(name)).collect(),
+            history,
+            tree: Arc::default(),
+            aliases: Arc::new(DeviceAliases::new(integrations, vec![])),
+            alias_store: Some(Arc::new(crate::device_alias::MemoryAliasStore::default())),
+            rules: Arc::new(rules),
+            device_states: Arc::default(),
         });
 
-        let store = Arc::new(
-            BreakerStore::from_data(BreakerData {
-                todos: vec![],
-                slots: HashMap::new(),
-                couples: vec![],
-            })
-            .unwrap(),
-        );
-        let breaker_content = Arc::new(BreakerContent::new(store.as_ref()));
-        ServerState {
-            certificate: Arc::from(""),
-            breaker_content,
-            breaker_detail_store: store,
-            index: Index::new(Routes::default(), false, false, false, false, &Default::default(), None, Arc::new([])).await.unwrap(),
-            tailscale_socket: Arc::from(Path::new("/tmp/fake.sock")),
-            notes_store: None,
-            auth_state: Some(Arc::new(auth_state)),
-            mqtt_state: Some(mqtt_state),
-            log_config: None,
-            systemd_config: None,
-            nav_links: Arc::new([]),
-        }
+        let mut state = crate::test_support::server_state(AuthConfig {
+            gm_users: vec!["gm".to_string()],
+            ..crate::test_support::auth_config()
+        })
+        .await;
+        state.mqtt_state = Some(mqtt_state);
+        state
     }
 
     async fn insert_gm_session(state: &ServerState) -> String {
         let auth = state.auth_state.as_ref().unwrap();
-        let token = Uuid::new_v4().to_string();
-        let _ = auth.session_store.write().await.insert(
-            token.clone(),
-            SessionData {
-                user_id: Uuid::new_v4(),
-                username: "gm".to_string(),
-                role: Role::Gm,
-                created_at: Instant::now(),
-            },
-        );
-        token
+        auth.create_session(Uuid::new_v4(), "gm", Role::Gm, None, false)
+            .await
+            .unwrap()
     }
 
     #[test]
@@ -1227,6 +2168,7 @@ mod tests {
     async fn publish_route_returns_204_for_gm() {
         let state = state_with_mqtt().await;
         let token = insert_gm_session(&state).await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
         let app = Router::new()
             .route("/api/mqtt/publish", post(publish_route))
             .with_state(state);
@@ -1241,6 +2183,15 @@ mod tests {
             .unwrap();
         let resp = app.oneshot(req).await.unwrap();
         assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let events = auth
+            .audit_log
+            .list(&crate::audit::AuditFilter { limit: 10, ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, AuditAction::MqttPublish);
+        assert_eq!(events[0].target.as_deref(), Some("test/bulb/set"));
     }
 
     #[tokio::test]
@@ -1249,22 +2200,612 @@ mod tests {
         let token = insert_gm_session(&state).await;
         let app = Router::new()
             .route("/api/mqtt/device-messages", get(device_messages_route))
-            .with_state(state);
+            .with_state(state.clone());
         let req = Request::builder()
             .method("GET")
             .uri("/api/mqtt/device-messages?integration=zigbee2mqtt&device=0xABCD")
             .header("cookie", format!("green_session={token}"))
             .body(Body::empty())
             .unwrap();
-        let resp = app.oneshot(req).await.unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
         assert_eq!(resp.status(), StatusCode::OK);
         let bytes = axum::body::to_bytes(resp.into_body(), 1 << 20).await.unwrap();
         let html = std::str::from_utf8(&bytes).unwrap();
         assert!(html.contains("device-cmd-form"), "panel should include the command form");
+        assert!(html.contains(r#"href="/mqtt/devices/zigbee2mqtt/0xABCD""#), "panel links to the state page");
         assert!(
             html.contains("no recent messages") || html.contains("leet-muted"),
             "empty buffer should show muted message"
         );
+
+        let read_only = api_token(&state, &[Permission::MqttRead]).await;
+        let req = Request::builder()
+            .uri("/api/mqtt/device-messages?integration=zigbee2mqtt&device=0xABCD")
+            .header("authorization", format!("Bearer {read_only}"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(resp.into_body(), 1 << 20).await.unwrap();
+        let html = std::str::from_utf8(&bytes).unwrap();
+        assert!(!html.contains("device-cmd-form"), "no command form without mqtt.publish");
+    }
+
+    #[tokio::test]
+    async fn device_state_page_and_api_show_merged_state() {
+        let state = state_with_mqtt().await;
+        let gm = insert_gm_session(&state).await;
+        let player = state
+            .auth_state
+            .as_ref()
+            .unwrap()
+            .create_session(Uuid::new_v4(), "player", Role::Player, None, false)
+            .await
+            .unwrap();
+        let mqtt = state.mqtt_state.as_ref().unwrap();
+        for payload in [r#"{"temperature":21.5,"battery":90}"#, r#"{"temperature":22}"#] {
+            let msg = MqttMessage::new("default", "zigbee2mqtt/attic".into(), payload.as_bytes(), utc_now());
+            mqtt.device_states.record("zigbee2mqtt", "attic", "", &msg, time::OffsetDateTime::now_utc());
+        }
+
+        let app = Router::new()
+            .route("/mqtt/devices/{integration}/{device}", get(crate::device_state::device_page))
+            .route("/api/mqtt/devices/{integration}/{device}/state", get(crate::device_state::device_state_api))
+            .with_state(state);
+        let (status, html) = get_body(app.clone(), "/mqtt/devices/zigbee2mqtt/attic", &gm).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains("device-state-spark"), "temperature has two points: {html}");
+        assert!(html.contains("21.5 – 22"));
+        assert!(html.contains(r#"href="/api/mqtt/devices/zigbee2mqtt/attic/state""#));
+
+        let (status, body) = get_body(app.clone(), "/api/mqtt/devices/zigbee2mqtt/attic/state", &gm).await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["state"], serde_json::json!({"temperature": 22, "battery": 90}));
+        assert_eq!(json["name"], serde_json::Value::Null);
+
+        let (status, html) = get_body(app.clone(), "/mqtt/devices/zigbee2mqtt/quiet", &gm).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains("no state received"));
+        let (status, _) = get_body(app.clone(), "/api/mqtt/devices/zigbee2mqtt/quiet/state", &gm).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = get_body(app.clone(), "/mqtt/devices/nope/attic", &gm).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = get_body(app, "/mqtt/devices/zigbee2mqtt/attic", &player).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    fn publish_request(token: &str, body: &'static str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/api/mqtt/publish")
+            .header("content-type", "application/json")
+            .header("cookie", format!("green_session={token}"))
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn publish_route_targets_named_broker() {
+        let state = state_with_brokers(&["zigbee", "frigate"]).await;
+        let token = insert_gm_session(&state).await;
+        let app = Router::new()
+            .route("/api/mqtt/publish", post(publish_route))
+            .with_state(state);
+
+        let named = r#"{"broker":"frigate","topic":"frigate/cam/set","payload":"ON"}"#;
+        let resp = app.clone().oneshot(publish_request(&token, named)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let unnamed = r#"{"topic":"frigate/cam/set","payload":"ON"}"#;
+        let resp = app.clone().oneshot(publish_request(&token, unnamed)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let unknown = r#"{"broker":"nope","topic":"t","payload":"p"}"#;
+        let resp = app.oneshot(publish_request(&token, unknown)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn device_panel_preselects_the_devices_broker() {
+        let state = state_with_brokers(&["zigbee", "frigate"]).await;
+        let token = insert_gm_session(&state).await;
+        {
+            let mqtt = state.mqtt_state.as_ref().unwrap();
+            let mut recent = mqtt.recent_messages.lock().await;
+            recent.push_back(MqttMessage {
+                broker: "frigate".into(),
+                topic: "zigbee2mqtt/0xABCD/state".into(),
+                payload: "{}".into(),
+                received_at: "2026-01-01T00:00:00Z".into(),
+                ..Default::default()
+            });
+        }
+        let app = Router::new()
+            .route("/api/mqtt/device-messages", get(device_messages_route))
+            .with_state(state);
+        let req = Request::builder()
+            .uri("/api/mqtt/device-messages?integration=zigbee2mqtt&device=0xABCD")
+            .header("cookie", format!("green_session={token}"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        let bytes = axum::body::to_bytes(resp.into_body(), 1 << 20).await.unwrap();
+        let html = std::str::from_utf8(&bytes).unwrap();
+        assert!(html.contains(r#"<option value="zigbee">zigbee</option>"#));
+        assert!(html.contains(r#"<option value="frigate" selected>frigate</option>"#));
+    }
+
+    #[tokio::test]
+    async fn payload_route_serves_raw_bytes_with_content_type() {
+        let state = state_with_mqtt().await;
+        let token = insert_gm_session(&state).await;
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        let msg = MqttMessage::new("default", "cam/snapshot".into(), &png, utc_now());
+        let id = msg.id;
+        state.mqtt_state.as_ref().unwrap().recent_messages.lock().await.push_back(msg);
+        let app = Router::new()
+            .route("/api/mqtt/payload/{id}", get(payload_route))
+            .with_state(state);
+
+        let get_payload = |id: u64| {
+            Request::builder()
+                .uri(format!("/api/mqtt/payload/{id}"))
+                .header("cookie", format!("green_session={token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let resp = app.clone().oneshot(get_payload(id)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "image/png");
+        assert_eq!(resp.headers()["x-content-type-options"], "nosniff");
+        assert_eq!(
+            resp.headers()["content-disposition"],
+            format!(r#"inline; filename="{id}.png""#).as_str()
+        );
+        let bytes = axum::body::to_bytes(resp.into_body(), 1 << 20).await.unwrap();
+        assert_eq!(bytes.as_ref(), png.as_slice());
+
+        let resp = app.oneshot(get_payload(id + 1000)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn history_with(messages: &[(&str, &str)]) -> Arc<dyn HistoryStore> {
+        let config: HistoryConfig = toml::from_str("").unwrap();
+        let store = Arc::new(crate::mqtt_history::MemoryHistoryStore::default());
+        let (tx, rx) = broadcast::channel(16);
+        let task = tokio::spawn(crate::mqtt_history::run_history_task(store.clone(), config, rx));
+        for (topic, payload) in messages {
+            let msg = MqttMessage {
+                broker: "default".into(),
+                topic: (*topic).into(),
+                payload: (*payload).into(),
+                received_at: utc_now(),
+                ..Default::default()
+            };
+            let _ = tx.send(BrokerEvent::Message(msg));
+        }
+        drop(tx);
+        task.await.unwrap();
+        store
+    }
+
+    /// An API token owned by a GM, limited to `scopes`.
+    async fn api_token(state: &ServerState, scopes: &[Permission]) -> String {
+        let owner = AuthUser {
+            user_id: Uuid::new_v4(),
+            username: "gm".into(),
+            role: Role::Gm,
+            permissions: Permission::iter().collect(),
+            api_token: None,
+            csrf_token: String::new(),
+        };
+        state
+            .auth_state
+            .as_ref()
+            .unwrap()
+            .create_api_token(&owner, "script", scopes.iter().copied().collect())
+            .await
+            .unwrap()
+    }
+
+    async fn get_body(app: Router, uri: &str, token: &str) -> (StatusCode, String) {
+        let req = Request::builder()
+            .uri(uri)
+            .header("cookie", format!("green_session={token}"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), 1 << 20).await.unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn device_panel_falls_back_to_history_for_quiet_devices() {
+        let history = history_with(&[
+            ("zigbee2mqtt/0xABCD", r#"{"temperature":21.5}"#),
+            ("zigbee2mqtt/0xEEEE", r#"{"temperature":3}"#),
+        ])
+        .await;
+        let state = state_with(&["default"], Some(history)).await;
+        let token = insert_gm_session(&state).await;
+        let app = Router::new()
+            .route("/api/mqtt/device-messages", get(device_messages_route))
+            .with_state(state);
+        let (status, html) =
+            get_body(app, "/api/mqtt/device-messages?integration=zigbee2mqtt&device=0xABCD", &token).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains("21.5"));
+        assert!(!html.contains("0xEEEE"));
+        assert!(!html.contains("no recent messages"));
+    }
+
+    #[test]
+    fn device_filter_covers_the_integration_pattern() {
+        let integrations = parse_integrations(&[IntegrationConfig {
+            pattern: "homeassistant/*/{device}/**".to_string(),
+            name: None,
+            ..Default::default()
+        }]);
+        assert_eq!(integrations[0].device_filter("lamp"), "homeassistant/+/lamp/#");
+    }
+
+    #[tokio::test]
+    async fn history_api_requires_mqtt_read() {
+        let history = history_with(&[
+            ("zigbee2mqtt/lamp", r#"{"state":"ON"}"#),
+            ("zigbee2mqtt/lamp", r#"{"state":"OFF"}"#),
+            ("frigate/events", "person"),
+        ])
+        .await;
+        let state = state_with(&["default"], Some(history)).await;
+        let gm = insert_gm_session(&state).await;
+        let player = state
+            .auth_state
+            .as_ref()
+            .unwrap()
+            .create_session(Uuid::new_v4(), "player", Role::Player, None, false)
+            .await
+            .unwrap();
+        let app = Router::new()
+            .route("/api/mqtt/history", get(crate::mqtt_history::history_api))
+            .route("/mqtt/history", get(crate::mqtt_history::history_page))
+            .with_state(state.clone());
+
+        let (status, body) = get_body(app.clone(), "/api/mqtt/history?topic=zigbee2mqtt/%2B&payload=OFF", &gm).await;
+        assert_eq!(status, StatusCode::OK);
+        let found: Vec<MqttMessage> = serde_json::from_str(&body).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].payload, r#"{"state":"OFF"}"#);
+
+        let (status, _) = get_body(app.clone(), "/api/mqtt/history?topic=a/%23/b", &gm).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, html) = get_body(app.clone(), "/mqtt/history?topic=frigate/%23", &gm).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains("person"));
+        assert!(!html.contains("zigbee2mqtt/lamp"));
+
+        let (status, _) = get_body(app.clone(), "/mqtt/history", &player).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let bearer = |token: &str| {
+            Request::builder()
+                .uri("/api/mqtt/history?topic=frigate/%23")
+                .header("authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let mqtt_read = api_token(&state, &[Permission::MqttRead]).await;
+        let resp = app.clone().oneshot(bearer(&mqtt_read)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let breaker_read = api_token(&state, &[Permission::BreakerRead]).await;
+        let resp = app.oneshot(bearer(&breaker_read)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn history_routes_404_without_history() {
+        let state = state_with_mqtt().await;
+        let gm = insert_gm_session(&state).await;
+        let app = Router::new()
+            .route("/api/mqtt/history", get(crate::mqtt_history::history_api))
+            .with_state(state);
+        let (status, _) = get_body(app, "/api/mqtt/history", &gm).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn rules_page_shows_dry_runs_and_errors_to_gms() {
+        let rules: MqttConfig = toml::from_str(
+            r#"client_id = "test"
+               [[rules]]
+               name = "door left open"
+               trigger = { topic = "zigbee2mqtt/+/contact", condition = "$.contact == false" }
+               dry_run = true
+               actions = [{ type = "publish", topic = "zigbee2mqtt/hall_light/set", payload = '{"state":"ON"}' }]
+               [[rules]]
+               name = "tell someone"
+               trigger = { topic = "zigbee2mqtt/+/contact" }
+               actions = [{ type = "ntfy", message = "{{topic}} changed" }]"#,
+        )
+        .unwrap();
+        let state = state_with_rules(&["default"], None, RuleEngine::new(rules.rules)).await;
+        let gm = insert_gm_session(&state).await;
+        let player = state
+            .auth_state
+            .as_ref()
+            .unwrap()
+            .create_session(Uuid::new_v4(), "player", Role::Player, None, false)
+            .await
+            .unwrap();
+
+        let mqtt = Arc::clone(state.mqtt_state.as_ref().unwrap());
+        let runner = crate::mqtt_rules::RuleRunner::new(Arc::clone(&mqtt), None).unwrap();
+        let door = MqttMessage::new("default", "zigbee2mqtt/door/contact".into(), br#"{"contact":false}"#, utc_now());
+        for triggered in mqtt.rules.evaluate(&door, Instant::now()) {
+            runner.fire(triggered, door.topic.clone()).await;
+        }
+
+        let app = Router::new()
+            .route("/mqtt/rules", get(crate::mqtt_rules::rules_page))
+            .with_state(state.clone());
+        let (status, html) = get_body(app.clone(), "/mqtt/rules", &gm).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains("dry run"));
+        assert!(html.contains("would publish {&#34;state&#34;:&#34;ON&#34;} to zigbee2mqtt/hall_light/set"), "{html}");
+        assert!(html.contains("no ntfy url"), "ntfy without a url is reported");
+        assert!(html.contains("1 time, last at"));
+
+        let (status, _) = get_body(app.clone(), "/mqtt/rules", &player).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let admin = api_token(&state, &[Permission::MqttAdmin]).await;
+        let req = Request::builder()
+            .uri("/mqtt/rules")
+            .header("authorization", format!("Bearer {admin}"))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app.oneshot(req).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn topic_tree_page_and_clearing_a_retained_topic() {
+        let state = state_with_mqtt().await;
+        let gm = insert_gm_session(&state).await;
+        let auth = Arc::clone(state.auth_state.as_ref().unwrap());
+        let player = auth
+            .create_session(Uuid::new_v4(), "player", Role::Player, None, false)
+            .await
+            .unwrap();
+        let tree = Arc::clone(&state.mqtt_state.as_ref().unwrap().tree);
+        {
+            let mut tree = tree.lock().await;
+            let retained = MqttMessage::new("default", "zigbee2mqtt/bridge/devices".into(), b"[]", utc_now());
+            tree.record(&MqttMessage { retain: true, ..retained });
+            tree.record(&MqttMessage::new("default", "zigbee2mqtt/lamp".into(), b"{}", utc_now()));
+        }
+        let app = Router::new()
+            .route("/mqtt/tree", get(crate::mqtt_tree::tree_page))
+            .route("/mqtt/tree/clear", post(crate::mqtt_tree::clear_retained_route))
+            .with_state(state);
+
+        let (status, html) = get_body(app.clone(), "/mqtt/tree", &gm).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains("bridge") && html.contains("lamp"));
+        assert!(html.contains("clear retained"));
+
+        let (status, _) = get_body(app.clone(), "/mqtt/tree", &player).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let clear = |token: &str, topic: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/mqtt/tree/clear")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .header("cookie", format!("green_session={token}"))
+                .body(Body::from(format!("broker=default&topic={topic}")))
+                .unwrap()
+        };
+        let resp = app.clone().oneshot(clear(&player, "zigbee2mqtt/bridge/devices")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = app.clone().oneshot(clear(&gm, "zigbee2mqtt/%23")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = app.oneshot(clear(&gm, "zigbee2mqtt/bridge/devices")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers()["location"], "/mqtt/tree");
+        assert_eq!(tree.lock().await.topic_count(), 1, "cleared topic is gone");
+
+        let events = auth
+            .audit_log
+            .list(&crate::audit::AuditFilter { limit: 10, ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].target.as_deref(), Some("zigbee2mqtt/bridge/devices"));
+    }
+
+    #[tokio::test]
+    async fn saving_an_alias_requires_mqtt_admin() {
+        let state = state_with_mqtt().await;
+        let mqtt = Arc::clone(state.mqtt_state.as_ref().unwrap());
+        let app = Router::new()
+            .route("/mqtt/devices/alias", post(crate::device_alias::save_alias_route))
+            .with_state(state.clone());
+        let save = |token: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/mqtt/devices/alias")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .header("authorization", format!("Bearer {token}"))
+                .body(Body::from("integration=zigbee2mqtt&device_id=0xABCD&name=garage+door"))
+                .unwrap()
+        };
+
+        let reader = api_token(&state, &[Permission::MqttRead, Permission::MqttPublish]).await;
+        let resp = app.clone().oneshot(save(&reader)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert_eq!(mqtt.aliases.name_for_topic("zigbee2mqtt/0xABCD"), None);
+
+        let admin = api_token(&state, &[Permission::MqttAdmin]).await;
+        let resp = app.oneshot(save(&admin)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(mqtt.aliases.name_for_topic("zigbee2mqtt/0xABCD").as_deref(), Some("garage door"));
+    }
+
+    #[tokio::test]
+    async fn saving_an_alias_names_the_device_in_its_panel() {
+        let state = state_with_mqtt().await;
+        let gm = insert_gm_session(&state).await;
+        let player = state
+            .auth_state
+            .as_ref()
+            .unwrap()
+            .create_session(Uuid::new_v4(), "player", Role::Player, None, false)
+            .await
+            .unwrap();
+        let mqtt = Arc::clone(state.mqtt_state.as_ref().unwrap());
+        mqtt.recent_messages.lock().await.push_back(MqttMessage::new(
+            "default",
+            "zigbee2mqtt/0xABCD".into(),
+            br#"{"contact":false}"#,
+            utc_now(),
+        ));
+        let app = Router::new()
+            .route("/mqtt/devices/alias", post(crate::device_alias::save_alias_route))
+            .route("/api/mqtt/device-messages", get(device_messages_route))
+            .with_state(state);
+        let save = |token: &str, body: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/mqtt/devices/alias")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .header("cookie", format!("green_session={token}"))
+                .body(Body::from(body.to_owned()))
+                .unwrap()
+        };
+        let body = "integration=zigbee2mqtt&device_id=0xABCD&name=garage+door&room=garage&tags=door";
+
+        let resp = app.clone().oneshot(save(&player, body)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let unknown = "integration=frigate&device_id=cam&name=porch";
+        let resp = app.clone().oneshot(save(&gm, unknown)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = app.clone().oneshot(save(&gm, body)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers()["location"], "/mqtt/devices");
+        assert_eq!(mqtt.aliases.name_for_topic("zigbee2mqtt/0xABCD").as_deref(), Some("garage door"));
+        let stored = mqtt.alias_store.as_ref().unwrap().list().await.unwrap();
+        assert_eq!(stored[0].room, "garage");
+
+        let uri = "/api/mqtt/device-messages?integration=zigbee2mqtt&device=0xABCD";
+        let (_, html) = get_body(app.clone(), uri, &gm).await;
+        assert!(html.contains(r#"<span class="mqtt-msg-alias">garage door</span>"#));
+        assert!(html.contains(r#"class="device-alias-form""#));
+        assert!(html.contains(r#"name="tags" type="text" placeholder="tags, comma separated" value="door""#));
+
+        // A blank name removes the alias.
+        let resp = app.oneshot(save(&gm, "integration=zigbee2mqtt&device_id=0xABCD&name=")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(mqtt.aliases.get("zigbee2mqtt", "0xABCD"), None);
+        assert!(mqtt.alias_store.as_ref().unwrap().list().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn mqtt_config_reads_metrics_device_label() {
+        let cfg: MqttConfig = toml::from_str(r#"client_id = "test""#).unwrap();
+        assert_eq!(cfg.metrics_device_label, DeviceLabel::Id);
+        let cfg: MqttConfig =
+            toml::from_str("client_id = \"test\"\nmetrics_device_label = \"alias\"").unwrap();
+        assert_eq!(cfg.metrics_device_label, DeviceLabel::Alias);
+        assert!(toml::from_str::<MqttConfig>("client_id = \"test\"\nmetrics_device_label = \"name\"").is_err());
+    }
+
+    #[test]
+    fn integration_expected_interval_uses_device_overrides() {
+        let cfg: MqttConfig = toml::from_str(
+            r#"client_id = "test"
+               [[integrations]]
+               pattern = "zigbee2mqtt/{device}/**"
+               expected_interval_mins = 60
+               device_expected_interval_mins = { doorbell = 5, spare = 0 }
+               [[integrations]]
+               pattern = "frigate/{device}/**""#,
+        )
+        .unwrap();
+        let integrations = parse_integrations(&cfg.integrations);
+        assert_eq!(integrations[0].expected_interval("0xABCD"), Some(Duration::from_secs(60 * 60)));
+        assert_eq!(integrations[0].expected_interval("doorbell"), Some(Duration::from_secs(5 * 60)));
+        assert_eq!(integrations[0].expected_interval("spare"), None);
+        assert_eq!(integrations[1].expected_interval("front"), None);
+    }
+
+    #[test]
+    fn mqtt_config_rejects_invalid_rules() {
+        let err = toml::from_str::<MqttConfig>(
+            r#"client_id = "test"
+               [[rules]]
+               name = "loop"
+               trigger = { topic = "a/#" }
+               actions = [{ type = "publish", topic = "a/b", payload = "x" }]"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("would fire the rule again"), "{err}");
+        assert!(toml::from_str::<MqttConfig>(
+            "client_id = \"test\"\n[[rules]]\nname = \"x\"\ntrigger = { topic = \"a\", condition = \"temperature\" }\nactions = []"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn mqtt_config_reads_and_validates_metric_mappings() {
+        let cfg: MqttConfig = toml::from_str(
+            r#"client_id = "test"
+               [[metrics]]
+               pattern = "zigbee2mqtt/{device}"
+               field = "$.temperature"
+               name = "zigbee_temperature_celsius"
+               labels = { unit = "celsius" }"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.metrics.len(), 1);
+        assert_eq!(cfg.metrics[0].field.to_string(), "$.temperature");
+        assert_eq!(cfg.metrics[0].stale_after_mins, 60);
+        let err = toml::from_str::<MqttConfig>(
+            "client_id = \"test\"\n[[metrics]]\npattern = \"z/{device}\"\nfield = \"$.t\"\nname = \"mqtt_messages_total\"",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("already exported"), "{err}");
+        assert!(toml::from_str::<MqttConfig>(
+            "client_id = \"test\"\n[[metrics]]\npattern = \"z/{device}\"\nfield = \"temperature\"\nname = \"t\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn mqtt_config_reads_history_block() {
+        let cfg: MqttConfig = toml::from_str(
+            r#"client_id = "test"
+               [history]
+               retention_days = 7
+               [[history.retention]]
+               pattern = "frigate/#"
+               days = 1
+            "#,
+        )
+        .unwrap();
+        let history = cfg.history.unwrap();
+        assert_eq!(history.retention_days, 7);
+        assert_eq!(history.retention_days_for("frigate/events"), 1);
+        assert_eq!(cfg.brokers.len(), 1);
+
+        let bad = "client_id = \"test\"\n[[history.retention]]\npattern = \"a/#/b\"\ndays = 1";
+        assert!(toml::from_str::<MqttConfig>(bad).is_err());
     }
 
     #[tokio::test]
@@ -1305,30 +2846,57 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn metrics_route_returns_text_plain_with_prometheus() {
-        // Build a state with Prometheus configured.
+    async fn mqtt_stream_route_validates_filters() {
+        let state = state_with_mqtt().await;
+        let token = insert_gm_session(&state).await;
+        let app = Router::new()
+            .route("/api/mqtt/stream", get(mqtt_stream_route))
+            .with_state(state);
+        for (uri, expected) in [
+            ("/api/mqtt/stream?topic=zigbee2mqtt/%23&integration=zigbee2mqtt&max_payload=1024", StatusCode::OK),
+            ("/api/mqtt/stream?integration=zwave", StatusCode::BAD_REQUEST),
+            ("/api/mqtt/stream?topic=a/%23/b", StatusCode::BAD_REQUEST),
+        ] {
+            let req = Request::builder()
+                .uri(uri)
+                .header("cookie", format!("green_session={token}"))
+                .body(Body::empty())
+                .unwrap();
+            assert_eq!(app.clone().oneshot(req).await.unwrap().status(), expected, "{uri}");
+        }
+    }
+
+    /// A router serving `/metrics` with Prometheus configured, as if requests
+    /// came from `peer`.
+    async fn metrics_app(metrics_config: Option<crate::metrics::MetricsConfig>, peer: &str) -> Router {
         let (tx, _) = broadcast::channel(16);
-        let (publish_client, eventloop) =
-            AsyncClient::new(MqttOptions::new("green-test-metrics", "localhost", 1883), 64);
-        let _ = tokio::spawn(async move {
-            let _hold = eventloop;
-            std::future::pending::<()>().await
-        });
         let registry = prometheus::Registry::new();
         let messages_total = prometheus::IntCounterVec::new(
             prometheus::opts!("mqtt_messages_total", "test"),
             &["integration", "device"],
         )
         .unwrap();
+        let last_seen = prometheus::IntGaugeVec::new(
+            prometheus::opts!("mqtt_device_last_seen_seconds", "test"),
+            &["integration", "device"],
+        )
+        .unwrap();
         let _ = registry.register(Box::new(messages_total.clone()));
+        let _ = registry.register(Box::new(last_seen.clone()));
         messages_total.with_label_values(&["zigbee2mqtt", "0xABCD"]).inc();
+        last_seen.with_label_values(&["zigbee2mqtt", "0xABCD"]).set(1_773_576_000);
         let mqtt_state = Arc::new(MqttState {
             tx,
-            status_tx: Arc::new(watch::channel("connecting".to_string()).0),
             recent_messages: Arc::new(TokioMutex::new(VecDeque::new())),
-            prometheus: Some(PrometheusState { registry, messages_total }),
+            prometheus: Some(PrometheusState { registry, messages_total, last_seen }),
             integrations: Arc::new(vec![]),
-            publish_client,
+            brokers: vec![test_broker("metrics")],
+            history: None,
+            tree: Arc::default(),
+            aliases: Arc::default(),
+            alias_store: None,
+            rules: Arc::default(),
+            device_states: Arc::default(),
         });
         let store = Arc::new(
             BreakerStore::from_data(BreakerData { todos: vec![], slots: HashMap::new(), couples: vec![] })
@@ -1345,11 +2913,19 @@ mod tests {
             mqtt_state: Some(mqtt_state),
             log_config: None,
             systemd_config: None,
+            metrics_config,
             nav_links: Arc::new([]),
         };
-        let app = Router::new()
+        let peer: SocketAddr = peer.parse().unwrap();
+        Router::new()
             .route("/metrics", get(metrics_route))
-            .with_state(state);
+            .with_state(state)
+            .layer(axum::extract::connect_info::MockConnectInfo(peer))
+    }
+
+    #[tokio::test]
+    async fn metrics_route_returns_text_plain_with_prometheus() {
+        let app = metrics_app(None, "192.168.1.20:40000").await;
         let req = Request::builder()
             .uri("/metrics")
             .body(Body::empty())
@@ -1361,6 +2937,35 @@ mod tests {
         let bytes = axum::body::to_bytes(resp.into_body(), 1 << 20).await.unwrap();
         let body = std::str::from_utf8(&bytes).unwrap();
         assert!(body.contains("mqtt_messages_total"), "prometheus metric present");
+        assert!(body.contains(
+            r#"mqtt_device_last_seen_seconds{device="0xABCD",integration="zigbee2mqtt"} 1773576000"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn metrics_route_checks_allow_list_then_token() {
+        let config = crate::metrics::MetricsConfig {
+            bearer_token: Some("s3cret".into()),
+            allowed_cidrs: vec!["10.0.0.0/24".parse().unwrap()],
+        };
+        let scrape = |token: Option<&str>| {
+            let mut req = Request::builder().uri("/metrics");
+            if let Some(token) = token {
+                req = req.header("authorization", format!("Bearer {token}"));
+            }
+            req.body(Body::empty()).unwrap()
+        };
+
+        let lan = metrics_app(Some(config.clone()), "192.168.1.20:40000").await;
+        let resp = lan.oneshot(scrape(Some("s3cret"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let prometheus = metrics_app(Some(config), "10.0.0.5:40000").await;
+        let resp = prometheus.clone().oneshot(scrape(None)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers()["www-authenticate"], "Bearer");
+        let resp = prometheus.oneshot(scrape(Some("s3cret"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
     }
 }
 
@@ -1370,41 +2975,141 @@ struct MqttPage {
     version: &'static str,
     auth_user: Option<AuthUserInfo>,
     nav_links: Arc<[NavLink]>,
+    /// Broker names for the filter chips; the chips are hidden for a single broker.
+    brokers: Vec<String>,
+    /// Whether `[mqtt.history]` is on, to link GMs to the search page.
+    history: bool,
+    /// Whether any `[[mqtt.rules]]` are configured, to link GMs to them.
+    rules: bool,
 }
 
-/// GET `/mqtt` — renders the MQTT live-feed page (GM only).
+/// GET `/mqtt` — renders the MQTT live-feed page (requires `mqtt.read`).
 pub async fn mqtt_page_route(
-    user: GmUser,
+    user: RequirePermission<MqttRead>,
     State(state): State<ServerState>,
 ) -> Result<Html<String>, Error> {
-    let auth_user = Some(AuthUserInfo {
-        username: user.0.username.clone(),
-        role: user.0.role.clone(),
-    });
+    let auth_user = Some(user.0.info());
+    let brokers = state
+        .mqtt_state
+        .as_ref()
+        .map(|mqtt| mqtt.brokers.iter().map(|b| b.name.clone()).collect())
+        .unwrap_or_default();
+    let history = state.mqtt_state.as_ref().is_some_and(|mqtt| mqtt.history.is_some());
+    let rules = state.mqtt_state.as_ref().is_some_and(|mqtt| !mqtt.rules.is_empty());
     let page = MqttPage {
         version: crate::VERSION,
         auth_user,
         nav_links: state.nav_links.clone(),
+        brokers,
+        history,
+        rules,
     };
     Ok(Html(page.render()?))
 }
 
+/// Query parameters for `/api/mqtt/stream`. Empty fields match everything;
+/// the rest must all match.
+#[derive(Debug, Deserialize, Default)]
+pub struct StreamQuery {
+    /// Comma-separated MQTT topic filters, e.g. `zigbee2mqtt/#,frigate/+/events`.
+    /// A message must match at least one.
+    #[serde(default)]
+    pub topic: String,
+    /// Only messages from this integration (its display name).
+    #[serde(default)]
+    pub integration: String,
+    /// Only messages from this device ID, under `integration` if set.
+    #[serde(default)]
+    pub device: String,
+    /// Payloads longer than this many bytes are replaced with a size note.
+    pub max_payload: Option<usize>,
+}
+
+impl StreamQuery {
+    fn filter(&self, integrations: &Arc<Vec<Integration>>) -> Result<StreamFilter, Error> {
+        let topics: Vec<String> = self
+            .topic
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_owned)
+            .collect();
+        for topic in &topics {
+            validate_topic_filter(topic).map_err(Error::InvalidStreamFilter)?;
+        }
+        let integration = Some(self.integration.trim()).filter(|i| !i.is_empty());
+        if let Some(name) = integration
+            && !integrations.iter().any(|i| i.display_name == name)
+        {
+            return Err(Error::InvalidStreamFilter(format!("unknown integration `{name}`")));
+        }
+        Ok(StreamFilter {
+            topics,
+            integration: integration.map(str::to_owned),
+            device: Some(self.device.trim()).filter(|d| !d.is_empty()).map(str::to_owned),
+            integrations: Arc::clone(integrations),
+            max_payload: self.max_payload,
+        })
+    }
+}
+
+/// Which messages an SSE client receives, and how large, from [`StreamQuery`].
+/// The default passes everything through unchanged.
+#[derive(Debug, Default)]
+struct StreamFilter {
+    topics: Vec<String>,
+    integration: Option<String>,
+    device: Option<String>,
+    integrations: Arc<Vec<Integration>>,
+    max_payload: Option<usize>,
+}
+
+impl StreamFilter {
+    /// `msg` as this client should see it, or `None` if it's filtered out.
+    fn apply(&self, mut msg: MqttMessage) -> Option<MqttMessage> {
+        if !self.topics.is_empty() && !self.topics.iter().any(|f| topic_matches(f, &msg.topic)) {
+            return None;
+        }
+        if self.integration.is_some() || self.device.is_some() {
+            let (integration, device) = match_integrations(&self.integrations, &msg.topic)?;
+            if self.integration.as_deref().is_some_and(|i| i != integration.display_name)
+                || self.device.as_deref().is_some_and(|d| d != device)
+            {
+                return None;
+            }
+        }
+        // Binary cards never carry their bytes, so only text is capped.
+        if let Some(max) = self.max_payload
+            && msg.kind.is_text()
+            && msg.payload.len() > max
+        {
+            msg.payload = format!("[{} bytes, over the {max}-byte limit]", msg.payload.len());
+        }
+        Some(msg)
+    }
+}
+
 /// Build the logical event stream for a new SSE client.
 ///
 /// Emits:
-/// 1. A [`BrokerEvent::Status`] with `current_status` (sent immediately so the
-///    client doesn't have to wait for the next real event).
+/// 1. A [`BrokerEvent::Status`] per `(broker, status)` in `current_statuses`
+///    (sent immediately so the client doesn't have to wait for the next real event).
 /// 2. One [`BrokerEvent::Message`] per `backlog` entry (oldest first).
 /// 3. Live [`BrokerEvent`]s from `rx` as they arrive.
 ///
-/// The stream ends when `rx`'s broadcast channel is closed.
+/// Backlog and live messages both go through `filter`; status events always
+/// pass. The stream ends when `rx`'s broadcast channel is closed.
 fn build_event_stream(
-    current_status: String,
+    current_statuses: Vec<(String, String)>,
     backlog: Vec<MqttMessage>,
     rx: broadcast::Receiver<BrokerEvent>,
+    filter: StreamFilter,
 ) -> impl futures::Stream<Item = BrokerEvent> {
-    let status_stream =
-        futures::stream::once(std::future::ready(BrokerEvent::Status { status: current_status }));
+    let status_stream = futures::stream::iter(
+        current_statuses
+            .into_iter()
+            .map(|(broker, status)| BrokerEvent::Status { broker, status }),
+    );
     let history_stream = futures::stream::iter(backlog.into_iter().map(BrokerEvent::Message));
     let live_stream = futures::stream::unfold(rx, |mut rx| async move {
         loop {
@@ -1417,7 +3122,16 @@ fn build_event_stream(
             }
         }
     });
-    status_stream.chain(history_stream).chain(live_stream)
+    status_stream
+        .chain(history_stream)
+        .chain(live_stream)
+        .filter_map(move |event| {
+            let event = match event {
+                BrokerEvent::Message(msg) => filter.apply(msg).map(BrokerEvent::Message),
+                status => Some(status),
+            };
+            std::future::ready(event)
+        })
 }
 
 // ─── Server-side card rendering ──────────────────────────────────────────────
@@ -1426,8 +3140,10 @@ fn build_event_stream(
 const TRUNCATE_LIMIT: usize = 280;
 /// Maximum characters shown per key-value entry in object payloads.
 const VALUE_TRUNCATE: usize = 120;
+/// Bytes shown in the hex dump of a binary payload.
+const HEX_DUMP_LIMIT: usize = 256;
 
-fn html_escape(s: &str) -> String {
+pub(crate) fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -1436,7 +3152,7 @@ fn html_escape(s: &str) -> String {
 
 /// Return a `&str` sub-slice of at most `max_chars` Unicode scalar values.
 /// Always splits at a valid char boundary.
-fn truncate_at_char(s: &str, max_chars: usize) -> &str {
+pub(crate) fn truncate_at_char(s: &str, max_chars: usize) -> &str {
     match s.char_indices().nth(max_chars) {
         Some((byte_idx, _)) => &s[..byte_idx],
         None => s,
@@ -1444,7 +3160,7 @@ fn truncate_at_char(s: &str, max_chars: usize) -> &str {
 }
 
 /// Extract HH:MM:SS from an ISO 8601 timestamp (e.g. `"2026-03-17T23:15:24Z"` → `"23:15:24"`).
-fn format_time(iso: &str) -> String {
+pub(crate) fn format_time(iso: &str) -> String {
     iso.find('T')
         .filter(|&t| iso.len() >= t + 9)
         .map(|t| iso[t + 1..t + 9].to_owned())
@@ -1499,55 +3215,147 @@ fn render_payload_body(raw: &str) -> String {
     }
 }
 
-/// Render an MQTT message as an HTML card fragment for SSE delivery.
-fn render_message_card(msg: &MqttMessage) -> String {
+/// Classic `offset  hex bytes  ascii` dump, 16 bytes per line.
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<47}  {ascii}\n", line * 16, hex.join(" "))
+        })
+        .collect()
+}
+
+/// Render the body of a binary card: an inline thumbnail for images, a hex
+/// dump of the first [`HEX_DUMP_LIMIT`] bytes otherwise. Both link to the raw
+/// payload while it is still in the ring buffer.
+fn render_binary_body(msg: &MqttMessage) -> String {
+    let summary = html_escape(&msg.payload);
+    // Messages loaded from the history have no bytes to show.
+    if msg.raw.is_empty() {
+        return format!(r#"<pre class="mqtt-msg-body">{summary}</pre>"#);
+    }
+    let href = format!("/api/mqtt/payload/{}", msg.id);
+    let download = format!(r#"<a class="mqtt-msg-download" href="{href}" download>{summary}</a>"#);
+    match msg.kind {
+        PayloadKind::Jpeg | PayloadKind::Png => format!(
+            r#"<a href="{href}" target="_blank" rel="noopener"><img class="mqtt-msg-image" src="{href}" loading="lazy" alt="{summary}"></a>{download}"#
+        ),
+        _ => {
+            let shown = &msg.raw[..msg.raw.len().min(HEX_DUMP_LIMIT)];
+            let more = match msg.raw.len() - shown.len() {
+                0 => String::new(),
+                n => format!("… {n} more bytes\n"),
+            };
+            format!(
+                r#"<pre class="mqtt-msg-body mqtt-msg-hex">{}{more}</pre>{download}"#,
+                html_escape(&hex_dump(shown)),
+            )
+        }
+    }
+}
+
+/// Render an MQTT message as an HTML card fragment for SSE delivery, headed
+/// by the device's alias when it has one.
+fn render_message_card(msg: &MqttMessage, alias: Option<&str>) -> String {
+    let broker_esc = html_escape(&msg.broker);
     let topic_esc = html_escape(&msg.topic);
-    let body = render_payload_body(&msg.payload);
+    let alias_html = alias
+        .map(|a| format!(r#"<span class="mqtt-msg-alias">{}</span>"#, html_escape(a)))
+        .unwrap_or_default();
+    let body = if msg.kind.is_text() {
+        render_payload_body(&msg.payload)
+    } else {
+        render_binary_body(msg)
+    };
     let time = format_time(&msg.received_at);
     format!(
-        r#"<div class="mqtt-msg mqtt-msg-new" data-topic="{topic_esc}" data-received-at="{received_at}"><div class="mqtt-msg-header"><span class="mqtt-msg-topic" title="{topic_esc}">{topic_esc}</span><span class="mqtt-msg-time">{time}</span></div>{body}</div>"#,
+        r#"<div class="mqtt-msg mqtt-msg-new" data-broker="{broker_esc}" data-topic="{topic_esc}" data-received-at="{received_at}"><div class="mqtt-msg-header">{alias_html}<span class="mqtt-msg-topic" title="{topic_esc}">{topic_esc}</span><span class="mqtt-msg-broker">{broker_esc}</span><span class="mqtt-msg-time">{time}</span></div>{body}</div>"#,
         received_at = msg.received_at,
     )
 }
 
-/// Render the status bar inner HTML for SSE delivery.
+/// Render a status dot and its text.
 fn render_status_html(status: &str) -> String {
+    // `error: <reason>` keeps the `error` dot and shows the reason as text.
+    let state = status.split(':').next().unwrap_or(status);
+    let state_esc = html_escape(state);
     let status_esc = html_escape(status);
     format!(
-        r#"<span class="mqtt-dot mqtt-dot-{status_esc}"></span><span class="mqtt-status-text">{status_esc}</span>"#
+        r#"<span class="mqtt-dot mqtt-dot-{state_esc}"></span><span class="mqtt-status-text">{status_esc}</span>"#
     )
 }
 
+/// Render the status bar inner HTML for SSE delivery: a single status, or one
+/// labelled pill per broker when there are several.
+fn render_status_bar(statuses: &[(String, String)]) -> String {
+    match statuses {
+        [(_, status)] => render_status_html(status),
+        _ => statuses
+            .iter()
+            .map(|(broker, status)| {
+                format!(
+                    r#"<span class="mqtt-broker-status" data-broker="{}"><span class="mqtt-broker-name">{}</span>{}</span>"#,
+                    html_escape(broker),
+                    html_escape(broker),
+                    render_status_html(status),
+                )
+            })
+            .collect(),
+    }
+}
+
 /// Map a [`BrokerEvent`] stream to SSE wire events.
 /// Messages are sent as pre-rendered HTML card fragments (event name `message`).
-/// Status changes are sent as pre-rendered HTML status bar fragments (event name `broker`).
+/// Status changes are sent as the whole pre-rendered HTML status bar, covering
+/// every broker (event name `broker`).
 fn build_sse_stream(
-    current_status: String,
+    current_statuses: Vec<(String, String)>,
     backlog: Vec<MqttMessage>,
     rx: broadcast::Receiver<BrokerEvent>,
+    filter: StreamFilter,
+    aliases: Arc<DeviceAliases>,
 ) -> impl futures::Stream<Item = Result<Event, Infallible>> {
-    build_event_stream(current_status, backlog, rx).map(|ev| {
-        Ok(match ev {
-            BrokerEvent::Message(msg) => Event::default().data(render_message_card(&msg)),
-            BrokerEvent::Status { status } => {
-                Event::default().event("broker").data(render_status_html(&status))
+    let bar = current_statuses.clone();
+    build_event_stream(current_statuses, backlog, rx, filter).scan(bar, move |bar, ev| {
+        let event = match ev {
+            BrokerEvent::Message(msg) => {
+                let alias = aliases.name_for_topic(&msg.topic);
+                Event::default().data(render_message_card(&msg, alias.as_deref()))
             }
-        })
+            BrokerEvent::Status { broker, status } => {
+                match bar.iter_mut().find(|(name, _)| *name == broker) {
+                    Some(entry) => entry.1 = status,
+                    None => bar.push((broker, status)),
+                }
+                Event::default().event("broker").data(render_status_bar(bar))
+            }
+        };
+        std::future::ready(Some(Ok(event)))
     })
 }
 
-/// GET `/api/mqtt/stream` — SSE stream of live MQTT messages (GM only).
+/// GET `/api/mqtt/stream` — SSE stream of live MQTT messages (requires `mqtt.read`),
+/// narrowed by the [`StreamQuery`] parameters.
 pub async fn mqtt_stream_route(
-    _user: GmUser,
+    _user: RequirePermission<MqttRead>,
     State(state): State<ServerState>,
+    Query(query): Query<StreamQuery>,
 ) -> Result<Sse<impl futures::Stream<Item = Result<Event, Infallible>>>, Error> {
     let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    let filter = query.filter(&mqtt.integrations)?;
     let rx = mqtt.tx.subscribe();
 
-    let current_status = mqtt.status_tx.borrow().clone();
+    let current_statuses = mqtt.statuses();
     let backlog: Vec<MqttMessage> = mqtt.recent_messages.lock().await.iter().cloned().collect();
 
-    Ok(Sse::new(build_sse_stream(current_status, backlog, rx)).keep_alive(KeepAlive::default()))
+    let aliases = Arc::clone(&mqtt.aliases);
+    Ok(Sse::new(build_sse_stream(current_statuses, backlog, rx, filter, aliases)).keep_alive(KeepAlive::default()))
 }
 
 // ─── Publish endpoint ────────────────────────────────────────────────────────
@@ -1555,24 +3363,40 @@ pub async fn mqtt_stream_route(
 /// Request body for `POST /api/mqtt/publish`.
 #[derive(Debug, Deserialize)]
 pub struct MqttPublishRequest {
+    /// Name of the broker to publish to. May be omitted when only one is
+    /// configured.
+    #[serde(default)]
+    pub broker: Option<String>,
     /// Full MQTT topic to publish to.
     pub topic: String,
     /// Payload string (typically JSON for smart-home integrations).
     pub payload: String,
 }
 
-/// POST `/api/mqtt/publish` — publish a message to the broker (GM only).
+/// POST `/api/mqtt/publish` — publish a message to the broker (requires `mqtt.publish`).
 pub async fn publish_route(
-    _user: GmUser,
+    user: RequirePermission<MqttPublish>,
     State(state): State<ServerState>,
+    meta: RequestMeta,
     Json(req): Json<MqttPublishRequest>,
 ) -> Result<axum::http::StatusCode, Error> {
     let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
-    mqtt.publish_client
+    let broker = mqtt.broker(req.broker.as_deref())?;
+    let published = broker
+        .publish_client
         .publish(&req.topic, QoS::AtLeastOnce, false, req.payload.as_bytes().to_vec())
         .await
-        .map_err(|e| Error::Database(format!("mqtt publish: {e}")))?;
-    tracing::info!(topic = %req.topic, "published mqtt message");
+        .map_err(|e| Error::Database(format!("mqtt publish: {e}")));
+    if let Some(auth) = state.auth_state.as_ref() {
+        auth.audit(
+            meta.event(AuditAction::MqttPublish, AuditOutcome::from(&published))
+                .actor(&user.0.username)
+                .target(&req.topic),
+        )
+        .await;
+    }
+    published?;
+    tracing::info!(broker = %broker.name, topic = %req.topic, "published mqtt message");
     Ok(axum::http::StatusCode::NO_CONTENT)
 }
 
@@ -1587,10 +3411,14 @@ pub struct DeviceMessagesQuery {
     pub device: String,
 }
 
+/// Most stored messages shown in a device panel when the ring buffer has none.
+const DEVICE_HISTORY_LIMIT: i64 = 20;
+
 /// GET `/api/mqtt/device-messages` — returns recent ring-buffer messages for one device
-/// as pre-rendered HTML card fragments (GM only; no DB required).
+/// as pre-rendered HTML card fragments (requires `mqtt.read`). A quiet device with
+/// nothing in the buffer falls back to the persistent history, when configured.
 pub async fn device_messages_route(
-    _user: GmUser,
+    user: RequirePermission<MqttRead>,
     State(state): State<ServerState>,
     Query(params): Query<DeviceMessagesQuery>,
 ) -> Result<Html<String>, Error> {
@@ -1602,66 +3430,159 @@ pub async fn device_messages_route(
         .find(|i| i.display_name == params.integration)
         .ok_or(Error::NotFound)?;
 
-    let messages: Vec<MqttMessage> = mqtt
+    let mut messages: Vec<MqttMessage> = mqtt
         .recent_messages
         .lock()
         .await
         .iter()
         .filter(|msg| {
-            match_topic(&integration.segments, &msg.topic).as_deref()
+            match_topic(&integration.segments, &msg.topic)
                 == Some(params.device.as_str())
         })
         .cloned()
         .collect();
 
+    if let (true, Some(history)) = (messages.is_empty(), &mqtt.history) {
+        let query = HistoryQuery {
+            topic: Some(integration.device_filter(&params.device)),
+            limit: DEVICE_HISTORY_LIMIT,
+            ..Default::default()
+        };
+        match history.search(&query).await {
+            // Stored newest first; the panel lists oldest first like the buffer.
+            Ok(stored) => messages = stored.into_iter().rev().collect(),
+            Err(err) => tracing::warn!(%err, device = %params.device, "failed to load device history"),
+        }
+    }
+
+    let alias = mqtt.aliases.get(&integration.display_name, &params.device);
+    let alias_name = alias.as_ref().map(|a| a.name.as_str());
     let messages_html: String = if messages.is_empty() {
         r#"<p class="leet-muted">no recent messages in buffer</p>"#.to_owned()
     } else {
-        messages.iter().map(render_message_card).collect()
+        messages.iter().map(|msg| render_message_card(msg, alias_name)).collect()
+    };
+
+    let form_html = if user.0.permissions.contains(&Permission::MqttPublish) {
+        let form = render_cmd_form(mqtt, messages.last().map(|m| m.broker.as_str()));
+        format!(r#"<hr class="device-cmd-sep">{form}"#)
+    } else {
+        String::new()
+    };
+
+    let alias_html = if user.0.permissions.contains(&Permission::MqttAdmin) {
+        let form = render_alias_form(&integration.display_name, &params.device, alias.as_ref());
+        format!(r#"<hr class="device-cmd-sep">{form}"#)
+    } else {
+        String::new()
+    };
+
+    let state_link = render_state_link(&integration.display_name, &params.device);
+    Ok(Html(format!(r#"{state_link}{messages_html}{form_html}{alias_html}"#)))
+}
+
+/// The publish form in a device panel. With several brokers, it defaults to
+/// `last_seen_on`, the one the device was last heard on.
+fn render_cmd_form(mqtt: &MqttState, last_seen_on: Option<&str>) -> String {
+    let broker_html = if mqtt.brokers.len() > 1 {
+        let options: String = mqtt
+            .brokers
+            .iter()
+            .map(|b| {
+                let selected = if Some(b.name.as_str()) == last_seen_on { " selected" } else { "" };
+                let name = html_escape(&b.name);
+                format!(r#"<option value="{name}"{selected}>{name}</option>"#)
+            })
+            .collect();
+        format!(r#"<select class="device-cmd-broker" name="broker">{options}</select>"#)
+    } else {
+        String::new()
     };
 
-    let form_html = r#"<form class="device-cmd-form">
+    format!(r#"<form class="device-cmd-form">
 <div class="device-cmd-fields">
-<input class="device-cmd-topic" name="topic" type="text" placeholder="topic  e.g. zigbee2mqtt/device/set" autocomplete="off" spellcheck="false">
-<textarea class="device-cmd-payload" name="payload" rows="2" placeholder='payload  e.g. {"state":"ON","brightness":200}'></textarea>
+{broker_html}<input class="device-cmd-topic" name="topic" type="text" placeholder="topic  e.g. zigbee2mqtt/device/set" autocomplete="off" spellcheck="false">
+<textarea class="device-cmd-payload" name="payload" rows="2" placeholder='payload  e.g. {{"state":"ON","brightness":200}}'></textarea>
 </div>
 <div class="device-cmd-actions">
 <button class="leet-btn" type="submit">send</button>
 <span class="device-cmd-status"></span>
 </div>
-</form>"#;
+</form>"#)
+}
 
-    Ok(Html(format!(
-        r#"{messages_html}<hr class="device-cmd-sep">{form_html}"#
-    )))
+/// GET `/api/mqtt/payload/{id}` — the raw bytes of a message still in the ring
+/// buffer, with a `Content-Type` for its [`PayloadKind`] (requires `mqtt.read`).
+pub async fn payload_route(
+    _user: RequirePermission<MqttRead>,
+    UrlPath(id): UrlPath<u64>,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, Error> {
+    let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    let msg = mqtt
+        .recent_messages
+        .lock()
+        .await
+        .iter()
+        .find(|m| m.id == id)
+        .cloned()
+        .ok_or(Error::NotFound)?;
+    let headers = [
+        (header::CONTENT_TYPE, msg.kind.content_type().to_owned()),
+        // Never let the browser guess: a payload is whatever a device sent.
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!(r#"inline; filename="{id}.{}""#, msg.kind.extension()),
+        ),
+    ];
+    Ok((headers, msg.bytes().to_vec()))
 }
 
 // ─── Devices page ─────────────────────────────────────────────────────────────
 
+/// A link on the devices page that shows only devices in one state.
+#[derive(Debug)]
+struct HealthFilter {
+    health: DeviceHealth,
+    count: usize,
+    active: bool,
+}
+
 #[derive(Template)]
 #[template(path = "mqtt_devices.html")]
 struct MqttDevicesPage {
     devices: Vec<DeviceRow>,
+    /// Empty when no device is watched for going offline.
+    health_filters: Vec<HealthFilter>,
+    filtered: bool,
     auth_user: Option<AuthUserInfo>,
     version: &'static str,
     nav_links: Arc<[NavLink]>,
 }
 
-/// GET `/mqtt/devices` — MQTT device inventory table (GM only).
+/// Query string for `/mqtt/devices`.
+#[derive(Debug, Deserialize)]
+pub struct DevicesParams {
+    /// Only list devices in this state, e.g. `offline`.
+    #[serde(default)]
+    pub state: Option<DeviceHealth>,
+}
+
+/// GET `/mqtt/devices` — MQTT device inventory table (requires `mqtt.read`).
 pub async fn mqtt_devices_route(
-    user: GmUser,
+    user: RequirePermission<MqttRead>,
+    Query(params): Query<DevicesParams>,
     State(state): State<ServerState>,
 ) -> Result<Html<String>, Error> {
     let auth = state.auth_state.as_ref().ok_or(Error::MqttNotConfigured)?;
-    let auth_user = Some(AuthUserInfo {
-        username: user.0.username.clone(),
-        role: user.0.role.clone(),
-    });
+    let auth_user = Some(user.0.info());
 
     let rows = sqlx::query(
         "SELECT integration, device_id,
                 to_char(first_seen AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') AS first_seen,
                 to_char(last_seen  AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') AS last_seen,
+                last_seen AS last_seen_at,
                 message_count
          FROM mqtt_devices
          ORDER BY integration, device_id",
@@ -1670,33 +3591,75 @@ pub async fn mqtt_devices_route(
     .await
     .map_err(|e| Error::Database(e.to_string()))?;
 
+    let now = time::OffsetDateTime::now_utc();
     let devices: Vec<DeviceRow> = rows
         .into_iter()
         .map(|row| {
             use sqlx::Row as _;
+            let integration: String = row.get("integration");
+            let device_id: String = row.get("device_id");
+            let alias = state.mqtt_state.as_ref().and_then(|mqtt| mqtt.aliases.get(&integration, &device_id));
+            let health = state
+                .mqtt_state
+                .as_ref()
+                .and_then(|mqtt| mqtt.integrations.iter().find(|i| i.display_name == integration))
+                .and_then(|i| i.expected_interval(&device_id))
+                .map(|after| DeviceHealth::at(row.get("last_seen_at"), now, after));
             DeviceRow {
-                integration: row.get("integration"),
-                device_id: row.get("device_id"),
+                integration,
+                device_id,
                 first_seen: row.get("first_seen"),
                 last_seen: row.get("last_seen"),
                 message_count: row.get("message_count"),
+                alias,
+                health,
             }
         })
         .collect();
 
-    let page = MqttDevicesPage { devices, auth_user, version: crate::VERSION, nav_links: state.nav_links.clone() };
+    let health_filters = if devices.iter().any(|d| d.health.is_some()) {
+        [DeviceHealth::Online, DeviceHealth::Stale, DeviceHealth::Offline]
+            .into_iter()
+            .map(|health| HealthFilter {
+                health,
+                count: devices.iter().filter(|d| d.health == Some(health)).count(),
+                active: params.state == Some(health),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let devices = match params.state {
+        Some(wanted) => devices.into_iter().filter(|d| d.health == Some(wanted)).collect(),
+        None => devices,
+    };
+
+    let page = MqttDevicesPage {
+        devices,
+        health_filters,
+        filtered: params.state.is_some(),
+        auth_user,
+        version: crate::VERSION,
+        nav_links: state.nav_links.clone(),
+    };
     Ok(Html(page.render()?))
 }
 
 // ─── Prometheus metrics endpoint ─────────────────────────────────────────────
 
-/// GET `/metrics` — Prometheus text exposition format (no auth; Prometheus scrapers
-/// can't do cookie auth). Expose only when integrations are configured.
+/// GET `/metrics` — Prometheus text exposition format. Prometheus scrapers can't
+/// do cookie auth, so access is checked against the optional `[metrics]` block
+/// instead. Expose only when integrations or metric mappings are configured.
 pub async fn metrics_route(
     State(state): State<ServerState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> Result<([(axum::http::HeaderName, &'static str); 1], String), Error> {
     let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
     let ps = mqtt.prometheus.as_ref().ok_or(Error::MqttNotConfigured)?;
+    if let Some(metrics) = &state.metrics_config {
+        metrics.authorize(&headers, peer.ip())?;
+    }
 
     let encoder = prometheus::TextEncoder::new();
     let metric_families = ps.registry.gather();
@@ -1704,5 +3667,5 @@ pub async fn metrics_route(
         .encode_to_string(&metric_families)
         .map_err(|e| Error::PrometheusEncode(e.to_string()))?;
 
-    Ok(([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], body))
+    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body))
 }
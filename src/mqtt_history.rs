@@ -0,0 +1,645 @@
+//! Persistent MQTT message history.
+//!
+//! With an `[mqtt.history]` block, every message on [`MqttState::tx`] is also
+//! written to the `mqtt_messages` table in batches, so it survives restarts
+//! and outlives the in-memory scrollback. How long a message is kept depends
+//! on the first `[[mqtt.history.retention]]` rule whose topic filter matches
+//! it; expired rows are purged hourly. Users with `mqtt.read` can search the
+//! history at `/mqtt/history` (or `/api/mqtt/history` for JSON) by topic
+//! filter, broker, time range and payload substring.
+//!
+//! Binary payloads are stored as their summary text (kind and size), not
+//! their bytes. Recording is best-effort: a failed write is logged and the
+//! batch dropped.
+//!
+//! [`MqttState::tx`]: crate::mqtt::MqttState::tx
+
+use std::{sync::Arc, time::Duration};
+
+use askama::Template;
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::Html,
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row, postgres::PgRow};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tokio::sync::broadcast;
+
+use crate::{
+    ServerState,
+    account::format_timestamp,
+    auth::AuthUserInfo,
+    error::Error,
+    index::NavLink,
+    mqtt::{BrokerEvent, MqttMessage, topic_matches, validate_topic_filter},
+    permission::{MqttRead, RequirePermission},
+};
+
+/// How long received messages are buffered before being written, at most.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often expired messages are deleted.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Results returned when a search doesn't ask for a limit.
+const DEFAULT_SEARCH_LIMIT: i64 = 100;
+
+/// Most results a single search may return.
+const MAX_SEARCH_LIMIT: i64 = 1000;
+
+// ─── Config ───────────────────────────────────────────────────────────────────
+
+/// `[mqtt.history]` config block.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistoryConfig {
+    /// Days to keep messages that no retention rule matches.
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+    /// Per-topic retention, checked in order; the first match wins.
+    #[serde(default)]
+    pub retention: Vec<RetentionRule>,
+    /// Messages written per insert. A partial batch is flushed every few
+    /// seconds regardless.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+}
+
+/// Keep messages whose topic matches `pattern` for `days` days. `days = 0`
+/// means they aren't stored at all.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RetentionRule {
+    /// MQTT topic filter, e.g. `zigbee2mqtt/+/availability` or `frigate/#`.
+    pub pattern: String,
+    pub days: u32,
+}
+
+fn default_retention_days() -> u32 {
+    30
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+impl HistoryConfig {
+    /// Reject retention rules whose pattern isn't a valid topic filter.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.retention
+            .iter()
+            .try_for_each(|rule| validate_filter(&rule.pattern))
+    }
+
+    /// Days to keep a message on `topic`.
+    pub fn retention_days_for(&self, topic: &str) -> u32 {
+        self.retention
+            .iter()
+            .find(|rule| topic_matches(&rule.pattern, topic))
+            .map_or(self.retention_days, |rule| rule.days)
+    }
+
+    /// The row to store for `msg`, or `None` if its topic isn't retained.
+    fn record(&self, msg: MqttMessage) -> Option<HistoryRecord> {
+        let days = self.retention_days_for(&msg.topic);
+        if days == 0 {
+            return None;
+        }
+        let received_at = OffsetDateTime::parse(&msg.received_at, &Rfc3339)
+            .unwrap_or_else(|_| OffsetDateTime::now_utc());
+        Some(HistoryRecord {
+            expires_at: received_at + time::Duration::days(i64::from(days)),
+            received_at,
+            message: msg,
+        })
+    }
+}
+
+// ─── Topic filters ────────────────────────────────────────────────────────────
+
+fn validate_filter(filter: &str) -> Result<(), Error> {
+    validate_topic_filter(filter).map_err(Error::InvalidHistoryQuery)
+}
+
+/// A Postgres regular expression matching the same topics as `filter`.
+fn topic_regex(filter: &str) -> String {
+    let mut re = String::from("^");
+    for (i, level) in filter.split('/').enumerate() {
+        match (i, level) {
+            (0, "#") => re.push_str("[^$].*"),
+            (_, "#") => re.push_str("(/.*)?"),
+            (i, level) => {
+                if i > 0 {
+                    re.push('/');
+                }
+                match level {
+                    "+" if i == 0 => re.push_str("([^$/][^/]*)?"),
+                    "+" => re.push_str("[^/]*"),
+                    literal => {
+                        for c in literal.chars() {
+                            if r"\.^$|?*+()[]{}".contains(c) {
+                                re.push('\\');
+                            }
+                            re.push(c);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    re.push('$');
+    re
+}
+
+// ─── Store ────────────────────────────────────────────────────────────────────
+
+/// One stored message.
+#[derive(Debug, Clone)]
+pub struct HistoryRecord {
+    pub message: MqttMessage,
+    pub received_at: OffsetDateTime,
+    /// When the retention rule lets it be purged.
+    pub expires_at: OffsetDateTime,
+}
+
+/// Which messages to return. Unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    /// MQTT topic filter; see [`topic_matches`](crate::mqtt::topic_matches).
+    pub topic: Option<String>,
+    pub broker: Option<String>,
+    /// Case-sensitive substring of the payload.
+    pub payload: Option<String>,
+    /// Inclusive lower bound on `received_at`.
+    pub since: Option<OffsetDateTime>,
+    /// Exclusive upper bound on `received_at`.
+    pub until: Option<OffsetDateTime>,
+    pub limit: i64,
+}
+
+impl HistoryQuery {
+    pub fn matches(&self, record: &HistoryRecord) -> bool {
+        let msg = &record.message;
+        self.topic.as_deref().is_none_or(|f| topic_matches(f, &msg.topic))
+            && self.broker.as_deref().is_none_or(|b| msg.broker == b)
+            && self.payload.as_deref().is_none_or(|p| msg.payload.contains(p))
+            && self.since.is_none_or(|t| record.received_at >= t)
+            && self.until.is_none_or(|t| record.received_at < t)
+    }
+}
+
+/// Storage backend for message history.
+pub trait HistoryStore: Send + Sync + std::fmt::Debug {
+    fn insert<'a>(&'a self, records: &'a [HistoryRecord]) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Messages matching `query`, newest first, at most `query.limit`.
+    fn search<'a>(&'a self, query: &'a HistoryQuery) -> BoxFuture<'a, Result<Vec<MqttMessage>, Error>>;
+
+    /// Delete every expired message, returning how many were removed.
+    fn purge_expired(&self, now: OffsetDateTime) -> BoxFuture<'_, Result<u64, Error>>;
+}
+
+// ─── Postgres ─────────────────────────────────────────────────────────────────
+
+/// [`HistoryStore`] backed by the `mqtt_messages` table.
+#[derive(Debug, Clone)]
+pub struct PgHistoryStore {
+    db: PgPool,
+}
+
+impl PgHistoryStore {
+    pub fn new(db: PgPool) -> Self {
+        PgHistoryStore { db }
+    }
+}
+
+fn message_from_row(row: &PgRow) -> Result<MqttMessage, Error> {
+    let received_at: OffsetDateTime = row.get("received_at");
+    let kind: String = row.get("kind");
+    Ok(MqttMessage {
+        broker: row.get("broker"),
+        topic: row.get("topic"),
+        kind: kind
+            .parse()
+            .map_err(|_| Error::Database(format!("unknown payload kind `{kind}`")))?,
+        payload: row.get("payload"),
+        received_at: received_at.format(&Rfc3339).unwrap_or_default(),
+        ..Default::default()
+    })
+}
+
+impl HistoryStore for PgHistoryStore {
+    fn insert<'a>(&'a self, records: &'a [HistoryRecord]) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let brokers: Vec<&str> = records.iter().map(|r| r.message.broker.as_str()).collect();
+            let topics: Vec<&str> = records.iter().map(|r| r.message.topic.as_str()).collect();
+            let kinds: Vec<String> = records.iter().map(|r| r.message.kind.to_string()).collect();
+            let payloads: Vec<&str> = records.iter().map(|r| r.message.payload.as_str()).collect();
+            let received: Vec<OffsetDateTime> = records.iter().map(|r| r.received_at).collect();
+            let expires: Vec<OffsetDateTime> = records.iter().map(|r| r.expires_at).collect();
+            let _ = sqlx::query(
+                "INSERT INTO mqtt_messages (broker, topic, kind, payload, received_at, expires_at) \
+                 SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::timestamptz[], $6::timestamptz[])",
+            )
+            .bind(brokers)
+            .bind(topics)
+            .bind(kinds)
+            .bind(payloads)
+            .bind(received)
+            .bind(expires)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn search<'a>(&'a self, query: &'a HistoryQuery) -> BoxFuture<'a, Result<Vec<MqttMessage>, Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT broker, topic, kind, payload, received_at \
+                 FROM mqtt_messages \
+                 WHERE ($1::text IS NULL OR topic ~ $1) \
+                   AND ($2::text IS NULL OR broker = $2) \
+                   AND ($3::text IS NULL OR strpos(payload, $3) > 0) \
+                   AND ($4::timestamptz IS NULL OR received_at >= $4) \
+                   AND ($5::timestamptz IS NULL OR received_at < $5) \
+                 ORDER BY received_at DESC, id DESC \
+                 LIMIT $6",
+            )
+            .bind(query.topic.as_deref().map(topic_regex))
+            .bind(query.broker.as_deref())
+            .bind(query.payload.as_deref())
+            .bind(query.since)
+            .bind(query.until)
+            .bind(query.limit)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            rows.iter().map(message_from_row).collect()
+        })
+    }
+
+    fn purge_expired(&self, now: OffsetDateTime) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            let result = sqlx::query("DELETE FROM mqtt_messages WHERE expires_at <= $1")
+                .bind(now)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(result.rows_affected())
+        })
+    }
+}
+
+// ─── In-memory (tests) ────────────────────────────────────────────────────────
+
+/// [`HistoryStore`] kept in process memory, so handler tests don't need Postgres.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryHistoryStore {
+    records: tokio::sync::RwLock<Vec<HistoryRecord>>,
+}
+
+#[cfg(test)]
+impl HistoryStore for MemoryHistoryStore {
+    fn insert<'a>(&'a self, records: &'a [HistoryRecord]) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.records.write().await.extend_from_slice(records);
+            Ok(())
+        })
+    }
+
+    fn search<'a>(&'a self, query: &'a HistoryQuery) -> BoxFuture<'a, Result<Vec<MqttMessage>, Error>> {
+        Box::pin(async move {
+            Ok(self
+                .records
+                .read()
+                .await
+                .iter()
+                .rev()
+                .filter(|r| query.matches(r))
+                .take(usize::try_from(query.limit).unwrap_or(0))
+                .map(|r| r.message.clone())
+                .collect())
+        })
+    }
+
+    fn purge_expired(&self, now: OffsetDateTime) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            let mut records = self.records.write().await;
+            let before = records.len();
+            records.retain(|r| r.expires_at > now);
+            Ok((before - records.len()) as u64)
+        })
+    }
+}
+
+// ─── Writer task ──────────────────────────────────────────────────────────────
+
+/// Write `batch` to `store` and clear it. Failures are logged, not retried.
+async fn flush(store: &dyn HistoryStore, batch: &mut Vec<HistoryRecord>) {
+    if batch.is_empty() {
+        return;
+    }
+    if let Err(err) = store.insert(batch).await {
+        tracing::warn!(%err, dropped = batch.len(), "failed to write mqtt history");
+    }
+    batch.clear();
+}
+
+/// Background task: record every message from `rx` in `store`, in batches of
+/// `config.batch_size` or every [`FLUSH_INTERVAL`], whichever comes first,
+/// and purge expired messages every [`PURGE_INTERVAL`].
+pub async fn run_history_task(
+    store: Arc<dyn HistoryStore>,
+    config: HistoryConfig,
+    mut rx: broadcast::Receiver<BrokerEvent>,
+) {
+    let mut batch = Vec::with_capacity(config.batch_size);
+    let mut flush_timer = tokio::time::interval(FLUSH_INTERVAL);
+    let mut purge_timer = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(BrokerEvent::Message(msg)) => {
+                    batch.extend(config.record(msg));
+                    if batch.len() >= config.batch_size {
+                        flush(store.as_ref(), &mut batch).await;
+                    }
+                }
+                Ok(BrokerEvent::Status { .. }) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!(n, "mqtt history writer lagged, skipping messages");
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    flush(store.as_ref(), &mut batch).await;
+                    tracing::warn!("mqtt history broadcast channel closed, task exiting");
+                    break;
+                }
+            },
+            _ = flush_timer.tick() => flush(store.as_ref(), &mut batch).await,
+            _ = purge_timer.tick() => match store.purge_expired(OffsetDateTime::now_utc()).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!(n, "purged expired mqtt history"),
+                Err(err) => tracing::warn!(%err, "failed to purge mqtt history"),
+            },
+        }
+    }
+}
+
+// ─── Search page and API ──────────────────────────────────────────────────────
+
+/// Search parameters from the history page's form or the API's query string.
+/// Empty fields match everything.
+#[derive(Debug, Deserialize, Default)]
+pub struct HistoryParams {
+    /// MQTT topic filter, e.g. `zigbee2mqtt/+/state`.
+    #[serde(default)]
+    pub topic: String,
+    #[serde(default)]
+    pub broker: String,
+    /// Substring the payload must contain.
+    #[serde(default)]
+    pub payload: String,
+    /// Start of the range: a `YYYY-MM-DD` day (UTC) or an RFC 3339 time.
+    #[serde(default)]
+    pub from: String,
+    /// End of the range: a `YYYY-MM-DD` day (UTC, inclusive) or an RFC 3339
+    /// time (exclusive).
+    #[serde(default)]
+    pub to: String,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+impl HistoryParams {
+    fn query(&self) -> Result<HistoryQuery, Error> {
+        fn non_empty(s: &str) -> Option<&str> {
+            Some(s.trim()).filter(|s| !s.is_empty())
+        }
+        // The instant `s` names, and whether it was a whole day.
+        fn instant(s: &str) -> Result<(OffsetDateTime, bool), Error> {
+            if let Ok(t) = OffsetDateTime::parse(s, &Rfc3339) {
+                return Ok((t, false));
+            }
+            let date = time::Date::parse(s, time::macros::format_description!("[year]-[month]-[day]"))
+                .map_err(|_| {
+                    Error::InvalidHistoryQuery(format!("`{s}` is not a YYYY-MM-DD date or RFC 3339 time"))
+                })?;
+            Ok((date.midnight().assume_utc(), true))
+        }
+
+        let topic = non_empty(&self.topic).map(str::to_owned);
+        if let Some(ref filter) = topic {
+            validate_filter(filter)?;
+        }
+        Ok(HistoryQuery {
+            topic,
+            broker: non_empty(&self.broker).map(str::to_owned),
+            payload: non_empty(&self.payload).map(str::to_owned),
+            since: non_empty(&self.from).map(|s| instant(s).map(|(t, _)| t)).transpose()?,
+            until: non_empty(&self.to)
+                .map(|s| {
+                    instant(s).map(|(t, day)| if day { t + time::Duration::days(1) } else { t })
+                })
+                .transpose()?,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_SEARCH_LIMIT)
+                .clamp(1, MAX_SEARCH_LIMIT),
+        })
+    }
+}
+
+/// The configured history store, or 404 when `[mqtt.history]` is off.
+fn history_store(state: &ServerState) -> Result<&Arc<dyn HistoryStore>, Error> {
+    let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    mqtt.history.as_ref().ok_or(Error::NotFound)
+}
+
+/// One message, as shown on the history page.
+#[derive(Debug)]
+pub struct HistoryRow {
+    pub received_at: String,
+    pub broker: String,
+    pub topic: String,
+    pub payload: String,
+}
+
+#[derive(Template)]
+#[template(path = "mqtt_history.html")]
+struct MqttHistoryPage {
+    version: &'static str,
+    auth_user: Option<AuthUserInfo>,
+    nav_links: Arc<[NavLink]>,
+    messages: Vec<HistoryRow>,
+    /// The search as submitted, to refill the form.
+    params: HistoryParams,
+    /// Broker names for the broker filter; hidden for a single broker.
+    brokers: Vec<String>,
+    error: Option<String>,
+}
+
+/// GET `/mqtt/history` — search stored messages, newest first.
+pub async fn history_page(
+    user: RequirePermission<MqttRead>,
+    State(state): State<ServerState>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Html<String>, Error> {
+    let store = history_store(&state)?;
+    let (messages, error) = match params.query() {
+        Ok(query) => (store.search(&query).await?, None),
+        Err(e) => (Vec::new(), Some(e.to_string())),
+    };
+    let messages = messages
+        .into_iter()
+        .map(|m| HistoryRow {
+            received_at: OffsetDateTime::parse(&m.received_at, &Rfc3339)
+                .map(format_timestamp)
+                .unwrap_or(m.received_at),
+            broker: m.broker,
+            topic: m.topic,
+            payload: m.payload,
+        })
+        .collect();
+    let brokers = state
+        .mqtt_state
+        .as_ref()
+        .map(|mqtt| mqtt.brokers.iter().map(|b| b.name.clone()).collect())
+        .unwrap_or_default();
+
+    let page = MqttHistoryPage {
+        version: crate::VERSION,
+        auth_user: Some(user.0.info()),
+        nav_links: state.nav_links.clone(),
+        messages,
+        params,
+        brokers,
+        error,
+    };
+    Ok(Html(page.render()?))
+}
+
+/// GET `/api/mqtt/history` — the same search as JSON.
+pub async fn history_api(
+    _user: RequirePermission<MqttRead>,
+    State(state): State<ServerState>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Json<Vec<MqttMessage>>, Error> {
+    let store = history_store(&state)?;
+    Ok(Json(store.search(&params.query()?).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(broker: &str, topic: &str, payload: &str, received_at: &str) -> MqttMessage {
+        MqttMessage::new(broker, topic.to_owned(), payload.as_bytes(), received_at.to_owned())
+    }
+
+    fn config(toml_str: &str) -> HistoryConfig {
+        toml::from_str(toml_str).unwrap()
+    }
+
+    #[test]
+    fn topic_regex_translates_wildcards_and_escapes_literals() {
+        assert_eq!(topic_regex("a.b/+/c"), r"^a\.b/[^/]*/c$");
+        assert_eq!(topic_regex("a/#"), "^a(/.*)?$");
+        assert_eq!(topic_regex("#"), "^[^$].*$");
+    }
+
+    #[test]
+    fn first_matching_rule_sets_retention() {
+        let cfg = config(
+            r#"retention_days = 7
+               [[retention]]
+               pattern = "zigbee2mqtt/+/availability"
+               days = 0
+               [[retention]]
+               pattern = "zigbee2mqtt/#"
+               days = 90
+            "#,
+        );
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.retention_days_for("zigbee2mqtt/lamp/availability"), 0);
+        assert_eq!(cfg.retention_days_for("zigbee2mqtt/lamp"), 90);
+        assert_eq!(cfg.retention_days_for("frigate/events"), 7);
+
+        let received = "2026-03-01T00:00:00Z";
+        assert!(cfg.record(message("default", "zigbee2mqtt/lamp/availability", "online", received)).is_none());
+        let record = cfg.record(message("default", "frigate/events", "{}", received)).unwrap();
+        assert_eq!(record.expires_at - record.received_at, time::Duration::days(7));
+    }
+
+    #[test]
+    fn history_config_defaults() {
+        let cfg = config("");
+        assert_eq!(cfg.retention_days, 30);
+        assert_eq!(cfg.batch_size, 100);
+        assert!(config("[[retention]]\npattern = \"a/#/b\"\ndays = 1").validate().is_err());
+    }
+
+    #[test]
+    fn params_parse_days_and_times() {
+        let query = HistoryParams {
+            topic: "zigbee2mqtt/+/state".into(),
+            from: "2026-03-01".into(),
+            to: "2026-03-01".into(),
+            limit: Some(1_000_000),
+            ..Default::default()
+        }
+        .query()
+        .unwrap();
+        assert_eq!(query.until.unwrap() - query.since.unwrap(), time::Duration::days(1));
+        assert_eq!(query.limit, MAX_SEARCH_LIMIT);
+
+        let query = HistoryParams { to: "2026-03-01T12:00:00Z".into(), ..Default::default() }
+            .query()
+            .unwrap();
+        assert_eq!(query.until.unwrap().hour(), 12);
+        assert_eq!(query.limit, DEFAULT_SEARCH_LIMIT);
+
+        assert!(HistoryParams { from: "yesterday".into(), ..Default::default() }.query().is_err());
+        assert!(HistoryParams { topic: "a/#/b".into(), ..Default::default() }.query().is_err());
+    }
+
+    #[tokio::test]
+    async fn writer_task_flushes_on_close_and_search_filters() {
+        let store: Arc<dyn HistoryStore> = Arc::new(MemoryHistoryStore::default());
+        let (tx, rx) = broadcast::channel(16);
+        let task = tokio::spawn(run_history_task(Arc::clone(&store), config(""), rx));
+
+        for (broker, topic, payload, at) in [
+            ("zigbee", "zigbee2mqtt/lamp", r#"{"state":"ON"}"#, "2026-03-01T10:00:00Z"),
+            ("zigbee", "zigbee2mqtt/lamp", r#"{"state":"OFF"}"#, "2026-03-01T11:00:00Z"),
+            ("frigate", "frigate/events", "person", "2026-03-02T10:00:00Z"),
+        ] {
+            let _ = tx.send(BrokerEvent::Message(message(broker, topic, payload, at)));
+        }
+        drop(tx);
+        task.await.unwrap();
+
+        let all = store.search(&HistoryQuery { limit: 10, ..Default::default() }).await.unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].topic, "frigate/events", "newest first");
+
+        let query = HistoryParams {
+            topic: "zigbee2mqtt/#".into(),
+            payload: "OFF".into(),
+            to: "2026-03-01".into(),
+            ..Default::default()
+        }
+        .query()
+        .unwrap();
+        let found = store.search(&query).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].payload, r#"{"state":"OFF"}"#);
+
+        let far_future = OffsetDateTime::now_utc() + time::Duration::days(365);
+        assert_eq!(store.purge_expired(far_future).await.unwrap(), 3);
+    }
+}
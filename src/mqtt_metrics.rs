@@ -0,0 +1,420 @@
+//! Metric mappings: numeric payload fields exported as Prometheus series.
+//!
+//! Each `[[mqtt.metrics]]` entry names a topic pattern with a `{device}`
+//! capture (the same syntax as integrations), a [`JsonPath`] into the payload
+//! and a metric name. Every matching message with a number at that path —
+//! or a boolean, as 1/0, or a numeric string — updates the series for its
+//! device, labelled `device` plus any constant `labels` of the mapping.
+//!
+//! A `gauge` is set to the value. A `counter` mirrors a cumulative value the
+//! device reports, such as total energy: it grows by the difference from the
+//! last value, and by the whole value when the device's count resets.
+//!
+//! [`run_metric_export_task`] drops series that haven't been updated for
+//! `stale_after_mins`, so a device that disappears stops being exported
+//! instead of reporting its last value forever.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use prometheus::{CounterVec, GaugeVec, Opts, Registry};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::{
+    device_alias::DeviceAliases,
+    json_path::JsonPath,
+    mqtt::{BrokerEvent, DeviceLabel, Integration, IntegrationConfig, MqttMessage, parse_integrations},
+};
+
+/// How often series are checked for staleness.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Metrics the device tracker already exports.
+const RESERVED_NAMES: [&str; 2] = ["mqtt_messages_total", "mqtt_device_last_seen_seconds"];
+
+/// Prometheus metric types a mapping can export.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    /// Exported as-is: the latest value wins.
+    #[default]
+    Gauge,
+    /// A running total from the device. Grows by each increase; a drop is
+    /// taken as a device reset and the new value is added whole.
+    Counter,
+}
+
+/// One `[[mqtt.metrics]]` entry.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MetricMappingConfig {
+    /// Topic pattern with a `{device}` capture, e.g. `zigbee2mqtt/{device}`.
+    pub pattern: String,
+    /// Where the value is in the JSON payload, e.g. `$.temperature`.
+    pub field: JsonPath,
+    /// Metric name, e.g. `zigbee_temperature_celsius`. Mappings may share a
+    /// name if they have the same type and label names.
+    pub name: String,
+    /// `# HELP` text; defaults to the field and pattern.
+    #[serde(default)]
+    pub help: Option<String>,
+    /// `type = "gauge"` (the default) or `"counter"`.
+    #[serde(rename = "type", default)]
+    pub kind: MetricKind,
+    /// Constant labels added to every series, e.g. `{ unit = "celsius" }`.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Minutes without an update before a device's series is dropped; `0`
+    /// keeps it forever.
+    #[serde(default = "default_stale_after_mins")]
+    pub stale_after_mins: u32,
+}
+
+fn default_stale_after_mins() -> u32 {
+    60
+}
+
+/// Is `name` a valid Prometheus metric name, or label name when `label`?
+fn valid_name(name: &str, label: bool) -> bool {
+    let mut chars = name.chars();
+    let extra = |c: char| c == '_' || (!label && c == ':');
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || extra(c))
+        && chars.all(|c| c.is_ascii_alphanumeric() || extra(c))
+}
+
+/// Check `[[mqtt.metrics]]` at startup: valid names, a `{device}` capture,
+/// and consistent types and labels between mappings sharing a name.
+pub fn validate_metric_mappings(mappings: &[MetricMappingConfig]) -> Result<(), String> {
+    for (i, mapping) in mappings.iter().enumerate() {
+        let name = &mapping.name;
+        if !valid_name(name, false) {
+            return Err(format!("`{name}` is not a valid metric name"));
+        }
+        if RESERVED_NAMES.contains(&name.as_str()) {
+            return Err(format!("metric `{name}` is already exported by the device tracker"));
+        }
+        if mapping.pattern.split('/').filter(|seg| *seg == "{device}").count() != 1 {
+            return Err(format!("metric `{name}`: pattern `{}` needs one `{{device}}`", mapping.pattern));
+        }
+        for label in mapping.labels.keys() {
+            if !valid_name(label, true) || label.starts_with("__") || label == "device" {
+                return Err(format!("metric `{name}`: `{label}` can't be used as a label name"));
+            }
+        }
+        if let Some(other) = mappings[..i].iter().find(|m| m.name == *name)
+            && (other.kind != mapping.kind || !other.labels.keys().eq(mapping.labels.keys()))
+        {
+            return Err(format!("metric `{name}` is mapped more than once with different types or labels"));
+        }
+    }
+    Ok(())
+}
+
+// ─── Export ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug)]
+enum Family {
+    Gauge(GaugeVec),
+    Counter(CounterVec),
+}
+
+impl Family {
+    fn remove(&self, labels: &[&str]) {
+        let _ = match self {
+            Family::Gauge(vec) => vec.remove_label_values(labels),
+            Family::Counter(vec) => vec.remove_label_values(labels),
+        };
+    }
+}
+
+#[derive(Debug)]
+struct Mapping {
+    name: String,
+    topic: Integration,
+    field: JsonPath,
+    /// Values of the constant labels, in label-name order.
+    labels: Vec<String>,
+    stale_after: Option<Duration>,
+}
+
+/// The last value of one series and when it arrived.
+#[derive(Debug)]
+struct Series {
+    value: f64,
+    updated: Instant,
+    stale_after: Option<Duration>,
+}
+
+/// Turns messages into updates of the mapped metrics.
+#[derive(Debug)]
+pub struct MetricExporter {
+    mappings: Vec<Mapping>,
+    families: HashMap<String, Family>,
+    /// `(metric, label values)` → last update.
+    series: HashMap<(String, Vec<String>), Series>,
+    aliases: Arc<DeviceAliases>,
+    label: DeviceLabel,
+}
+
+impl MetricExporter {
+    /// Register a metric for each distinct mapping name in `registry`. The
+    /// mappings must have passed [`validate_metric_mappings`].
+    pub fn new(
+        configs: &[MetricMappingConfig],
+        registry: &Registry,
+        aliases: Arc<DeviceAliases>,
+        label: DeviceLabel,
+    ) -> Result<Self, prometheus::Error> {
+        let mut families = HashMap::new();
+        let mut mappings = Vec::new();
+        for config in configs {
+            if !families.contains_key(&config.name) {
+                let help = config
+                    .help
+                    .clone()
+                    .unwrap_or_else(|| format!("{} of MQTT messages on {}", config.field, config.pattern));
+                let opts = Opts::new(config.name.clone(), help);
+                let label_names: Vec<&str> =
+                    std::iter::once("device").chain(config.labels.keys().map(String::as_str)).collect();
+                let family = match config.kind {
+                    MetricKind::Gauge => {
+                        let vec = GaugeVec::new(opts, &label_names)?;
+                        registry.register(Box::new(vec.clone()))?;
+                        Family::Gauge(vec)
+                    }
+                    MetricKind::Counter => {
+                        let vec = CounterVec::new(opts, &label_names)?;
+                        registry.register(Box::new(vec.clone()))?;
+                        Family::Counter(vec)
+                    }
+                };
+                let _ = families.insert(config.name.clone(), family);
+            }
+            let topic = IntegrationConfig {
+                pattern: config.pattern.clone(),
+                name: Some(config.name.clone()),
+                ..Default::default()
+            };
+            mappings.extend(parse_integrations(&[topic]).into_iter().map(|topic| Mapping {
+                name: config.name.clone(),
+                topic,
+                field: config.field.clone(),
+                labels: config.labels.values().cloned().collect(),
+                stale_after: (config.stale_after_mins > 0)
+                    .then(|| Duration::from_secs(u64::from(config.stale_after_mins) * 60)),
+            }));
+        }
+        Ok(MetricExporter { mappings, families, series: HashMap::new(), aliases, label })
+    }
+
+    /// Update every series `msg` has a value for.
+    pub fn observe(&mut self, msg: &MqttMessage, now: Instant) {
+        if !msg.kind.is_text() {
+            return;
+        }
+        let mut payload: Option<Option<Value>> = None;
+        for mapping in &self.mappings {
+            let Some(device_id) = mapping.topic.device(&msg.topic) else {
+                continue;
+            };
+            let payload = payload.get_or_insert_with(|| serde_json::from_str(&msg.payload).ok());
+            let Some(value) = payload.as_ref().and_then(|p| mapping.field.get(p)).and_then(number) else {
+                continue;
+            };
+            let device = match self.label {
+                DeviceLabel::Id => None,
+                DeviceLabel::Alias => self.aliases.metric_label_for_topic(&msg.topic),
+            }
+            .unwrap_or_else(|| device_id.to_owned());
+            let family = &self.families[&mapping.name];
+            // Negative totals aren't counts; skip them rather than panic.
+            if matches!(family, Family::Counter(_)) && value < 0.0 {
+                continue;
+            }
+            let labels: Vec<String> = std::iter::once(device).chain(mapping.labels.iter().cloned()).collect();
+            let label_refs: Vec<&str> = labels.iter().map(String::as_str).collect();
+
+            let series = self
+                .series
+                .entry((mapping.name.clone(), labels.clone()))
+                .or_insert(Series { value: 0.0, updated: now, stale_after: mapping.stale_after });
+            match family {
+                Family::Gauge(vec) => vec.with_label_values(&label_refs).set(value),
+                Family::Counter(vec) => {
+                    let grown = if value >= series.value { value - series.value } else { value };
+                    vec.with_label_values(&label_refs).inc_by(grown);
+                }
+            }
+            series.value = value;
+            series.updated = now;
+            series.stale_after = mapping.stale_after;
+        }
+    }
+
+    /// Drop series not updated within their mapping's `stale_after_mins`,
+    /// returning how many were dropped.
+    pub fn sweep(&mut self, now: Instant) -> usize {
+        let before = self.series.len();
+        let families = &self.families;
+        self.series.retain(|(name, labels), series| {
+            let stale = series.stale_after.is_some_and(|after| now.duration_since(series.updated) >= after);
+            if stale {
+                let label_refs: Vec<&str> = labels.iter().map(String::as_str).collect();
+                families[name].remove(&label_refs);
+            }
+            !stale
+        });
+        before - self.series.len()
+    }
+}
+
+/// A payload value as a sample: numbers as they are, booleans as 1/0 and
+/// numeric strings parsed. Anything else, or a non-finite number, is skipped.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(f64::from(u8::from(*b))),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|n: &f64| n.is_finite())
+}
+
+/// Background task: feed every message from `rx` to `exporter`, and drop
+/// stale series every [`SWEEP_INTERVAL`].
+pub async fn run_metric_export_task(mut exporter: MetricExporter, mut rx: broadcast::Receiver<BrokerEvent>) {
+    let mut sweep_timer = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(BrokerEvent::Message(msg)) => exporter.observe(&msg, Instant::now()),
+                Ok(BrokerEvent::Status { .. }) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!(n, "mqtt metric export lagged, skipping messages");
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    tracing::warn!("mqtt metric export broadcast channel closed, task exiting");
+                    break;
+                }
+            },
+            _ = sweep_timer.tick() => {
+                let dropped = exporter.sweep(Instant::now());
+                if dropped > 0 {
+                    tracing::info!(dropped, "dropped stale mqtt metric series");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::message;
+
+    fn mappings(toml_str: &str) -> Vec<MetricMappingConfig> {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            metrics: Vec<MetricMappingConfig>,
+        }
+        toml::from_str::<Wrapper>(toml_str).unwrap().metrics
+    }
+
+    fn exporter(configs: &[MetricMappingConfig], registry: &Registry) -> MetricExporter {
+        validate_metric_mappings(configs).unwrap();
+        MetricExporter::new(configs, registry, Arc::default(), DeviceLabel::Id).unwrap()
+    }
+
+    fn scrape(registry: &Registry) -> String {
+        prometheus::TextEncoder::new().encode_to_string(&registry.gather()).unwrap()
+    }
+
+    const ZIGBEE: &str = r#"
+        [[metrics]]
+        pattern = "zigbee2mqtt/{device}"
+        field = "$.temperature"
+        name = "zigbee_temperature_celsius"
+        labels = { source = "zigbee" }
+        [[metrics]]
+        pattern = "zigbee2mqtt/{device}"
+        field = "$.occupancy"
+        name = "zigbee_occupancy"
+        stale_after_mins = 0
+        [[metrics]]
+        pattern = "shellies/{device}/energy"
+        field = "$"
+        name = "shelly_energy_watt_minutes_total"
+        type = "counter"
+    "#;
+
+    #[test]
+    fn gauges_follow_the_latest_value() {
+        let registry = Registry::new();
+        let mut exporter = exporter(&mappings(ZIGBEE), &registry);
+        let now = Instant::now();
+        exporter.observe(&message("zigbee2mqtt/attic", r#"{"temperature":21.5,"occupancy":true}"#), now);
+        exporter.observe(&message("zigbee2mqtt/attic", r#"{"temperature":"22.25"}"#), now);
+        exporter.observe(&message("zigbee2mqtt/attic/set", r#"{"temperature":99}"#), now);
+        exporter.observe(&message("zigbee2mqtt/hall", r#"{"temperature":"warm"}"#), now);
+
+        let text = scrape(&registry);
+        assert!(text.contains(r#"zigbee_temperature_celsius{device="attic",source="zigbee"} 22.25"#), "{text}");
+        assert!(text.contains(r#"zigbee_occupancy{device="attic"} 1"#), "{text}");
+        assert!(!text.contains("hall"), "non-numeric values are skipped: {text}");
+        assert!(text.contains("# TYPE zigbee_temperature_celsius gauge"));
+    }
+
+    #[test]
+    fn counters_grow_by_the_difference_and_survive_resets() {
+        let registry = Registry::new();
+        let mut exporter = exporter(&mappings(ZIGBEE), &registry);
+        let now = Instant::now();
+        for total in ["100", "150", "20", "-5"] {
+            exporter.observe(&message("shellies/plug/energy", total), now);
+        }
+        let text = scrape(&registry);
+        assert!(text.contains("# TYPE shelly_energy_watt_minutes_total counter"), "{text}");
+        assert!(text.contains(r#"shelly_energy_watt_minutes_total{device="plug"} 170"#), "{text}");
+    }
+
+    #[test]
+    fn sweep_drops_series_of_devices_that_went_quiet() {
+        let registry = Registry::new();
+        let mut exporter = exporter(&mappings(ZIGBEE), &registry);
+        let t0 = Instant::now();
+        exporter.observe(&message("zigbee2mqtt/attic", r#"{"temperature":21,"occupancy":false}"#), t0);
+        exporter.observe(&message("zigbee2mqtt/hall", r#"{"temperature":19}"#), t0 + Duration::from_secs(50 * 60));
+
+        assert_eq!(exporter.sweep(t0 + Duration::from_secs(61 * 60)), 1);
+        let text = scrape(&registry);
+        assert!(!text.contains(r#"zigbee_temperature_celsius{device="attic""#), "{text}");
+        assert!(text.contains(r#"zigbee_temperature_celsius{device="hall""#), "{text}");
+        assert!(text.contains(r#"zigbee_occupancy{device="attic"} 0"#), "stale_after_mins = 0 keeps it");
+    }
+
+    #[test]
+    fn rejects_invalid_mappings() {
+        let base = r#"pattern = "z/{device}"
+            field = "$.t"
+        "#;
+        for bad in [
+            r#"name = "9lives""#,
+            r#"name = "mqtt_messages_total""#,
+            r#"name = "t"
+               labels = { device = "x" }"#,
+            r#"name = "t"
+               labels = { "bad-label" = "x" }"#,
+        ] {
+            let configs = mappings(&format!("[[metrics]]\n{base}{bad}"));
+            assert!(validate_metric_mappings(&configs).is_err(), "{bad}");
+        }
+        let no_capture = mappings("[[metrics]]\npattern = \"z/+\"\nfield = \"$\"\nname = \"t\"");
+        assert!(validate_metric_mappings(&no_capture).unwrap_err().contains("{device}"));
+        let mixed = mappings(&format!("[[metrics]]\n{base}name = \"t\"\n[[metrics]]\n{base}name = \"t\"\ntype = \"counter\""));
+        assert!(validate_metric_mappings(&mixed).is_err());
+    }
+}
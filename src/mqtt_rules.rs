@@ -0,0 +1,1112 @@
+//! Rules: small automations that react to MQTT messages.
+//!
+//! Each `[[mqtt.rules]]` entry has a trigger — an MQTT topic filter, an
+//! optional [`Condition`] on the JSON payload and a debounce — and a list of
+//! actions: publish a payload, post an ntfy notification or POST a webhook.
+//! [`run_rules_task`] checks every message on the broadcast channel against
+//! every rule and runs the actions of those that fire, in order; a failed
+//! action doesn't stop the ones after it.
+//!
+//! Action text is a [`TextTemplate`] and may use `{{topic}}`, `{{payload}}`,
+//! `{{broker}}`, `{{rule}}` and JSONPath placeholders like `{{$.temperature}}`.
+//!
+//! A rule with `dry_run = true` is evaluated as usual, but `/mqtt/rules` shows
+//! the actions it would have taken instead of taking them. Retained messages
+//! never fire rules, so a restart doesn't replay automations for old state.
+//! A rule whose previous firing is still running is skipped, so a slow
+//! webhook can't pile up tasks.
+
+use std::{
+    cell::LazyCell,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use askama::Template;
+use axum::{extract::State, http::header, response::Html};
+use rumqttc::QoS;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+
+use crate::{
+    ServerState,
+    account::format_timestamp,
+    auth::AuthUserInfo,
+    error::Error,
+    index::NavLink,
+    json_path::JsonPath,
+    mqtt::{BrokerConfig, BrokerEvent, MqttMessage, MqttState, topic_matches, truncate_at_char, validate_topic_filter},
+    permission::{MqttAdmin, RequirePermission},
+    recovery::NtfyDelivery,
+};
+
+/// Characters of a payload shown when describing a publish.
+const PREVIEW_LIMIT: usize = 80;
+
+/// Longest an action may take before it's reported as failed.
+const ACTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Priorities ntfy accepts by name.
+const NTFY_PRIORITIES: [&str; 5] = ["min", "low", "default", "high", "urgent"];
+
+// ─── Conditions ───────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Eq,
+    Ne,
+    Ge,
+    Le,
+    Gt,
+    Lt,
+}
+
+impl Compare {
+    /// Two-character operators first, so `>=` isn't read as `>`.
+    const ALL: [(&'static str, Compare); 6] = [
+        ("==", Compare::Eq),
+        ("!=", Compare::Ne),
+        (">=", Compare::Ge),
+        ("<=", Compare::Le),
+        (">", Compare::Gt),
+        ("<", Compare::Lt),
+    ];
+
+    /// Compare numbers as numbers and strings as strings; `==`/`!=` also
+    /// work on any other JSON, the ordering operators don't.
+    fn test(self, left: &Value, right: &Value) -> bool {
+        use std::cmp::Ordering;
+
+        let ordering = match (left, right) {
+            (Value::Number(a), Value::Number(b)) => a.as_f64().zip(b.as_f64()).and_then(|(a, b)| a.partial_cmp(&b)),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        };
+        let equal = ordering.map_or(left == right, Ordering::is_eq);
+        match self {
+            Compare::Eq => equal,
+            Compare::Ne => !equal,
+            Compare::Ge => ordering.is_some_and(Ordering::is_ge),
+            Compare::Le => ordering.is_some_and(Ordering::is_le),
+            Compare::Gt => ordering == Some(Ordering::Greater),
+            Compare::Lt => ordering == Some(Ordering::Less),
+        }
+    }
+}
+
+/// A test on a JSON payload. A bare [`JsonPath`] passes when the value exists
+/// and isn't `null` or `false`; otherwise the value is compared, as in
+/// `$.temperature > 25` or `$.action == "single"`. An unquoted word on the
+/// right is read as a string.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Condition {
+    path: JsonPath,
+    test: Option<(Compare, Value)>,
+    source: String,
+}
+
+impl Condition {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let source = s.trim();
+        let (path, rest) = JsonPath::parse_prefix(source)?;
+        let rest = rest.trim();
+        let test = if rest.is_empty() {
+            None
+        } else {
+            let (op, value) = Compare::ALL
+                .iter()
+                .find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|v| (*op, v.trim())))
+                .ok_or_else(|| format!("expected ==, !=, <, <=, > or >= after `{path}` in `{source}`"))?;
+            if value.is_empty() {
+                return Err(format!("missing value to compare with in `{source}`"));
+            }
+            let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+            Some((op, value))
+        };
+        Ok(Condition { path, test, source: source.to_owned() })
+    }
+
+    pub fn matches(&self, payload: &Value) -> bool {
+        match (self.path.get(payload), &self.test) {
+            (None, _) => false,
+            (Some(found), None) => !matches!(found, Value::Null | Value::Bool(false)),
+            (Some(found), Some((op, expected))) => op.test(found, expected),
+        }
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Condition::parse(&s)
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> Self {
+        condition.source
+    }
+}
+
+// ─── Templates ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Topic,
+    Payload,
+    Broker,
+    Rule,
+    Path(JsonPath),
+}
+
+/// Action text with `{{…}}` placeholders filled in from the message that
+/// fired the rule. A JSONPath placeholder that finds nothing renders empty;
+/// one that finds a string renders it without quotes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TextTemplate {
+    parts: Vec<Part>,
+    source: String,
+}
+
+impl TextTemplate {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or_else(|| format!("unclosed `{{{{` in `{s}`"))?;
+            parts.push(match after[..end].trim() {
+                "topic" => Part::Topic,
+                "payload" => Part::Payload,
+                "broker" => Part::Broker,
+                "rule" => Part::Rule,
+                path if path.starts_with('$') => Part::Path(JsonPath::parse(path)?),
+                other => return Err(format!("unknown placeholder `{other}` in `{s}`")),
+            });
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        Ok(TextTemplate { parts, source: s.to_owned() })
+    }
+
+    /// The template's text, if it has no placeholders.
+    fn as_static(&self) -> Option<&str> {
+        self.parts.iter().all(|p| matches!(p, Part::Text(_))).then_some(self.source.as_str())
+    }
+
+    fn render(&self, rule: &str, msg: &MqttMessage, json: Option<&Value>) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Topic => msg.topic.clone(),
+                Part::Payload => msg.payload.clone(),
+                Part::Broker => msg.broker.clone(),
+                Part::Rule => rule.to_owned(),
+                Part::Path(path) => match json.and_then(|j| path.get(j)) {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(value) => value.to_string(),
+                    None => String::new(),
+                },
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<String> for TextTemplate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        TextTemplate::parse(&s)
+    }
+}
+
+impl From<TextTemplate> for String {
+    fn from(template: TextTemplate) -> Self {
+        template.source
+    }
+}
+
+// ─── Config ───────────────────────────────────────────────────────────────────
+
+/// One `[[mqtt.rules]]` entry.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RuleConfig {
+    /// Shown on `/mqtt/rules` and in logs. Must be unique.
+    pub name: String,
+    pub trigger: TriggerConfig,
+    pub actions: Vec<ActionConfig>,
+    /// Record what the rule would do without doing it.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// `[mqtt.rules.trigger]`: which messages fire the rule.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TriggerConfig {
+    /// MQTT topic filter, e.g. `zigbee2mqtt/+/action`.
+    pub topic: String,
+    /// Only fire when the JSON payload passes this test.
+    #[serde(default)]
+    pub condition: Option<Condition>,
+    /// Ignore further matches for this many seconds after firing.
+    #[serde(default)]
+    pub debounce_secs: u64,
+}
+
+/// `[[mqtt.rules.actions]]`: one thing a rule does when it fires.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ActionConfig {
+    /// Publish `payload` to `topic`. `broker` may be omitted when only one is
+    /// configured.
+    Publish {
+        topic: TextTemplate,
+        payload: TextTemplate,
+        #[serde(default)]
+        broker: Option<String>,
+        #[serde(default)]
+        retain: bool,
+    },
+    /// Post `message` to ntfy: `url`, else `[mqtt] alert_ntfy_url`. The title
+    /// defaults to the rule name.
+    Ntfy {
+        #[serde(default)]
+        url: Option<String>,
+        #[serde(default)]
+        title: Option<TextTemplate>,
+        message: TextTemplate,
+        #[serde(default)]
+        priority: Option<String>,
+    },
+    /// POST `body` to `url`, or without a body a JSON object describing the
+    /// message.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        body: Option<TextTemplate>,
+    },
+}
+
+impl ActionConfig {
+    /// One-line summary for the rules page.
+    fn summary(&self) -> String {
+        match self {
+            ActionConfig::Publish { topic, broker, retain, .. } => format!(
+                "publish to {}{}{}",
+                topic.source,
+                broker.as_ref().map(|b| format!(" on {b}")).unwrap_or_default(),
+                if *retain { " (retained)" } else { "" },
+            ),
+            ActionConfig::Ntfy { title, .. } => match title {
+                Some(title) => format!("ntfy \"{}\"", title.source),
+                None => "ntfy".to_owned(),
+            },
+            ActionConfig::Webhook { url, .. } => format!("POST {}", redact_url(url)),
+        }
+    }
+}
+
+/// `url` without its path and query, which often carry a secret.
+fn redact_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(u) if u.path() == "/" && u.query().is_none() => u.origin().ascii_serialization(),
+        Ok(u) => format!("{}/…", u.origin().ascii_serialization()),
+        Err(_) => "(invalid url)".to_owned(),
+    }
+}
+
+/// A topic a message can be published to: no wildcards, not empty.
+fn validate_publish_topic(topic: &str) -> Result<(), String> {
+    if topic.is_empty() || topic.contains(['+', '#']) {
+        return Err(format!("`{topic}` is not a topic that can be published to"));
+    }
+    Ok(())
+}
+
+/// Could a topic rendered from `template` match `filter`? Literal levels
+/// before and after the placeholders must fit the filter; the placeholders
+/// may expand to any number of levels, so anything in between matches.
+fn template_may_match(filter: &str, template: &TextTemplate) -> bool {
+    if let Some(topic) = template.as_static() {
+        return topic_matches(filter, topic);
+    }
+    let rendered: String = template
+        .parts
+        .iter()
+        .map(|part| match part {
+            Part::Text(text) => text.as_str(),
+            _ => "\0",
+        })
+        .collect();
+    let levels: Vec<&str> = rendered.split('/').collect();
+    let is_placeholder = |level: &&str| level.contains('\0');
+    let (Some(first), Some(last)) = (levels.iter().position(is_placeholder), levels.iter().rposition(is_placeholder))
+    else {
+        return topic_matches(filter, &rendered);
+    };
+    let (prefix, suffix) = (&levels[..first], &levels[last + 1..]);
+    let filter: Vec<&str> = filter.split('/').collect();
+    for (i, literal) in prefix.iter().enumerate() {
+        match filter.get(i) {
+            None => return false,
+            Some(&"#") => return true,
+            Some(&"+") => {}
+            Some(f) if f == literal => {}
+            Some(_) => return false,
+        }
+    }
+    let rest = &filter[prefix.len()..];
+    if rest.contains(&"#") {
+        return true;
+    }
+    // The placeholders fill at least one level.
+    rest.len() > suffix.len()
+        && rest[rest.len() - suffix.len()..].iter().zip(suffix).all(|(f, literal)| *f == "+" || f == literal)
+}
+
+/// A chain of rules, by index, where each publishes to the next one's trigger
+/// and the last leads back to the first.
+fn find_loop(edges: &[Vec<usize>]) -> Option<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Mark {
+        Unvisited,
+        OnPath,
+        Done,
+    }
+
+    fn visit(i: usize, edges: &[Vec<usize>], marks: &mut [Mark], path: &mut Vec<usize>) -> Option<Vec<usize>> {
+        marks[i] = Mark::OnPath;
+        path.push(i);
+        for &next in &edges[i] {
+            match marks[next] {
+                Mark::OnPath => {
+                    let start = path.iter().position(|&p| p == next).unwrap_or_default();
+                    let mut chain = path[start..].to_vec();
+                    chain.push(next);
+                    return Some(chain);
+                }
+                Mark::Unvisited => {
+                    if let Some(chain) = visit(next, edges, marks, path) {
+                        return Some(chain);
+                    }
+                }
+                Mark::Done => {}
+            }
+        }
+        let _ = path.pop();
+        marks[i] = Mark::Done;
+        None
+    }
+
+    let mut marks = vec![Mark::Unvisited; edges.len()];
+    (0..edges.len()).find_map(|i| {
+        if marks[i] == Mark::Unvisited {
+            visit(i, edges, &mut marks, &mut Vec::new())
+        } else {
+            None
+        }
+    })
+}
+
+/// Check `rules` against the configured `brokers`: unique names, valid
+/// topics, known brokers and ntfy priorities, a debounce on rules that publish
+/// to a templated topic, and no publish that could fire its own rule or start
+/// a loop through other rules.
+pub fn validate_rules(rules: &[RuleConfig], brokers: &[BrokerConfig]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        let name = &rule.name;
+        if name.trim().is_empty() {
+            return Err("every rule needs a name".into());
+        }
+        if rules[..i].iter().any(|r| r.name == *name) {
+            return Err(format!("duplicate rule name `{name}`"));
+        }
+        validate_topic_filter(&rule.trigger.topic).map_err(|e| format!("rule `{name}`: {e}"))?;
+        if rule.actions.is_empty() {
+            return Err(format!("rule `{name}` has no actions"));
+        }
+        for action in &rule.actions {
+            match action {
+                ActionConfig::Publish { topic, broker, .. } => {
+                    match broker {
+                        Some(b) if !brokers.iter().any(|c| c.name == *b) => {
+                            return Err(format!("rule `{name}`: unknown broker `{b}`"));
+                        }
+                        None if brokers.len() > 1 => {
+                            return Err(format!(
+                                "rule `{name}`: publish needs a broker when more than one is configured"
+                            ));
+                        }
+                        _ => {}
+                    }
+                    match topic.as_static() {
+                        Some(topic) => {
+                            validate_publish_topic(topic).map_err(|e| format!("rule `{name}`: {e}"))?;
+                        }
+                        None if rule.trigger.debounce_secs == 0 => {
+                            return Err(format!(
+                                "rule `{name}`: publishing to a templated topic needs a trigger debounce_secs"
+                            ));
+                        }
+                        None => {}
+                    }
+                    if template_may_match(&rule.trigger.topic, topic) {
+                        return Err(format!(
+                            "rule `{name}`: publishing to `{}` would fire the rule again",
+                            topic.source
+                        ));
+                    }
+                }
+                ActionConfig::Ntfy { priority: Some(p), .. } if !NTFY_PRIORITIES.contains(&p.as_str()) => {
+                    return Err(format!("rule `{name}`: ntfy priority must be one of {}", NTFY_PRIORITIES.join(", ")));
+                }
+                ActionConfig::Ntfy { .. } => {}
+                ActionConfig::Webhook { url, .. } => {
+                    let parsed = url::Url::parse(url).map_err(|e| format!("rule `{name}`: webhook url: {e}"))?;
+                    if !matches!(parsed.scheme(), "http" | "https") {
+                        return Err(format!("rule `{name}`: webhook url must be http or https"));
+                    }
+                }
+            }
+        }
+    }
+
+    let edges: Vec<Vec<usize>> = rules
+        .iter()
+        .map(|rule| {
+            let topics: Vec<&TextTemplate> = rule
+                .actions
+                .iter()
+                .filter_map(|action| match action {
+                    ActionConfig::Publish { topic, .. } => Some(topic),
+                    _ => None,
+                })
+                .collect();
+            (0..rules.len())
+                .filter(|&j| topics.iter().any(|t| template_may_match(&rules[j].trigger.topic, t)))
+                .collect()
+        })
+        .collect();
+    if let Some(chain) = find_loop(&edges) {
+        let names: Vec<String> = chain.iter().map(|&i| format!("`{}`", rules[i].name)).collect();
+        return Err(format!("rules {} publish to each other in a loop", names.join(" → ")));
+    }
+    Ok(())
+}
+
+// ─── Evaluation ───────────────────────────────────────────────────────────────
+
+/// An action rendered for one message, ready to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedAction {
+    Publish {
+        broker: Option<String>,
+        topic: String,
+        payload: String,
+        retain: bool,
+    },
+    Ntfy {
+        url: Option<String>,
+        title: String,
+        message: String,
+        priority: String,
+    },
+    Webhook {
+        url: String,
+        body: String,
+    },
+}
+
+impl PlannedAction {
+    /// What the action does, for the rules page and logs.
+    fn describe(&self) -> String {
+        let preview = |s: &str| {
+            if s.chars().count() > PREVIEW_LIMIT {
+                format!("{}…", truncate_at_char(s, PREVIEW_LIMIT))
+            } else {
+                s.to_owned()
+            }
+        };
+        match self {
+            PlannedAction::Publish { topic, payload, .. } => format!("publish {} to {topic}", preview(payload)),
+            PlannedAction::Ntfy { title, message, .. } => format!("ntfy \"{title}\": {}", preview(message)),
+            PlannedAction::Webhook { url, body } => format!("POST {} {}", redact_url(url), preview(body)),
+        }
+    }
+}
+
+/// A rule that fired for a message, with its actions rendered. An action that
+/// can't run for this message (say, its rendered topic would fire the rule
+/// again) is an `Err`.
+#[derive(Debug, Clone)]
+pub struct Triggered {
+    rule: usize,
+    pub name: String,
+    pub dry_run: bool,
+    pub actions: Vec<Result<PlannedAction, String>>,
+}
+
+/// How one action went, as shown on the rules page.
+#[derive(Debug, Clone)]
+pub struct ActionOutcome {
+    pub text: String,
+    pub ok: bool,
+}
+
+/// The latest time a rule fired.
+#[derive(Debug, Clone)]
+pub struct Firing {
+    pub at: OffsetDateTime,
+    pub topic: String,
+    pub outcomes: Vec<ActionOutcome>,
+}
+
+#[derive(Debug, Default)]
+struct RuleState {
+    fired: u64,
+    quiet_until: Option<Instant>,
+    /// Set from when the rule fires until its outcome is recorded.
+    running: bool,
+    last: Option<Firing>,
+    last_error: Option<(OffsetDateTime, String)>,
+}
+
+/// The configured rules and what each has done since startup.
+#[derive(Debug, Default)]
+pub struct RuleEngine {
+    rules: Vec<RuleConfig>,
+    state: Mutex<Vec<RuleState>>,
+}
+
+impl RuleEngine {
+    pub fn new(rules: Vec<RuleConfig>) -> Self {
+        let state = rules.iter().map(|_| RuleState::default()).collect();
+        RuleEngine { rules, state: Mutex::new(state) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Rules that fire for `msg` at `now`, with their actions rendered. Each
+    /// one's debounce window starts here, and it won't fire again until its
+    /// outcome is [recorded](Self::record).
+    pub fn evaluate(&self, msg: &MqttMessage, now: Instant) -> Vec<Triggered> {
+        if msg.retain {
+            return Vec::new();
+        }
+        let json = LazyCell::new(|| {
+            msg.kind.is_text().then(|| serde_json::from_str::<Value>(&msg.payload).ok()).flatten()
+        });
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut triggered = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            let trigger = &rule.trigger;
+            if !topic_matches(&trigger.topic, &msg.topic)
+                || state[i].quiet_until.is_some_and(|until| now < until)
+            {
+                continue;
+            }
+            if let Some(condition) = &trigger.condition
+                && !json.as_ref().is_some_and(|j| condition.matches(j))
+            {
+                continue;
+            }
+            if state[i].running {
+                tracing::debug!(rule = %rule.name, topic = %msg.topic, "previous firing still running, skipped");
+                continue;
+            }
+            state[i].fired += 1;
+            state[i].running = true;
+            state[i].quiet_until =
+                (trigger.debounce_secs > 0).then(|| now + Duration::from_secs(trigger.debounce_secs));
+            triggered.push(Triggered {
+                rule: i,
+                name: rule.name.clone(),
+                dry_run: rule.dry_run,
+                actions: rule.actions.iter().map(|a| plan(rule, a, msg, json.as_ref())).collect(),
+            });
+        }
+        triggered
+    }
+
+    /// Record how a triggered rule's actions went.
+    pub fn record(&self, triggered: &Triggered, topic: &str, at: OffsetDateTime, outcomes: Vec<ActionOutcome>) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(rule) = state.get_mut(triggered.rule) else {
+            return;
+        };
+        rule.running = false;
+        if let Some(failed) = outcomes.iter().find(|o| !o.ok) {
+            rule.last_error = Some((at, failed.text.clone()));
+        }
+        rule.last = Some(Firing { at, topic: topic.to_owned(), outcomes });
+    }
+}
+
+/// Render `action` of `rule` for `msg`.
+fn plan(rule: &RuleConfig, action: &ActionConfig, msg: &MqttMessage, json: Option<&Value>) -> Result<PlannedAction, String> {
+    let render = |t: &TextTemplate| t.render(&rule.name, msg, json);
+    Ok(match action {
+        ActionConfig::Publish { topic, payload, broker, retain } => {
+            let topic = render(topic);
+            validate_publish_topic(&topic)?;
+            if topic_matches(&rule.trigger.topic, &topic) {
+                return Err(format!("publishing to `{topic}` would fire the rule again"));
+            }
+            PlannedAction::Publish { broker: broker.clone(), topic, payload: render(payload), retain: *retain }
+        }
+        ActionConfig::Ntfy { url, title, message, priority } => PlannedAction::Ntfy {
+            url: url.clone(),
+            title: title.as_ref().map_or_else(|| rule.name.clone(), render),
+            message: render(message),
+            priority: priority.clone().unwrap_or_else(|| "default".to_owned()),
+        },
+        ActionConfig::Webhook { url, body } => PlannedAction::Webhook {
+            url: url.clone(),
+            body: body.as_ref().map_or_else(
+                || {
+                    serde_json::json!({
+                        "rule": rule.name,
+                        "broker": msg.broker,
+                        "topic": msg.topic,
+                        "payload": msg.payload,
+                        "received_at": msg.received_at,
+                    })
+                    .to_string()
+                },
+                render,
+            ),
+        },
+    })
+}
+
+// ─── Running actions ──────────────────────────────────────────────────────────
+
+/// Runs planned actions: publishes through the configured brokers and posts
+/// to ntfy and webhooks.
+#[derive(Debug, Clone)]
+pub struct RuleRunner {
+    mqtt: Arc<MqttState>,
+    /// ntfy topic for actions that don't name their own.
+    ntfy: Option<NtfyDelivery>,
+    http: reqwest::Client,
+}
+
+impl RuleRunner {
+    pub fn new(mqtt: Arc<MqttState>, ntfy: Option<NtfyDelivery>) -> Result<Self, Error> {
+        let http = reqwest::Client::builder()
+            .timeout(ACTION_TIMEOUT)
+            .build()
+            .map_err(|e| Error::MqttSetup(format!("webhook client: {e}")))?;
+        Ok(RuleRunner { mqtt, ntfy, http })
+    }
+
+    async fn run(&self, action: &PlannedAction) -> Result<(), String> {
+        match action {
+            PlannedAction::Publish { broker, topic, payload, retain } => {
+                let broker = self.mqtt.broker(broker.as_deref()).map_err(|e| e.to_string())?;
+                broker
+                    .publish_client
+                    .publish(topic, QoS::AtLeastOnce, *retain, payload.as_bytes().to_vec())
+                    .await
+                    .map_err(|e| format!("mqtt publish: {e}"))
+            }
+            PlannedAction::Ntfy { url, title, message, priority } => {
+                let own;
+                let ntfy = match url {
+                    Some(url) => {
+                        own = NtfyDelivery::new(url.clone());
+                        &own
+                    }
+                    None => self
+                        .ntfy
+                        .as_ref()
+                        .ok_or("no ntfy url: set one on the action or in [mqtt] alert_ntfy_url")?,
+                };
+                ntfy.notify(title, priority, message.clone()).await.map_err(|e| format!("ntfy: {e}"))
+            }
+            PlannedAction::Webhook { url, body } => {
+                let content_type = if serde_json::from_str::<Value>(body).is_ok() {
+                    "application/json"
+                } else {
+                    "text/plain; charset=utf-8"
+                };
+                let _ = self
+                    .http
+                    .post(url)
+                    .header(header::CONTENT_TYPE, content_type)
+                    .body(body.clone())
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(|e| format!("webhook: {e}"))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Run a triggered rule's actions in order (or just describe them for a
+    /// dry run) and record the outcome.
+    pub(crate) async fn fire(&self, triggered: Triggered, topic: String) {
+        let mut outcomes = Vec::with_capacity(triggered.actions.len());
+        for action in &triggered.actions {
+            let result = match action {
+                Err(err) => Err(err.clone()),
+                Ok(action) if triggered.dry_run => Ok(format!("would {}", action.describe())),
+                Ok(action) => tokio::time::timeout(ACTION_TIMEOUT, self.run(action))
+                    .await
+                    .unwrap_or_else(|_| Err(format!("timed out after {}s", ACTION_TIMEOUT.as_secs())))
+                    .map(|()| action.describe()),
+            };
+            let outcome = match result {
+                Ok(text) => ActionOutcome { text, ok: true },
+                Err(err) => {
+                    tracing::warn!(rule = %triggered.name, %topic, %err, "rule action failed");
+                    ActionOutcome { text: err, ok: false }
+                }
+            };
+            outcomes.push(outcome);
+        }
+        tracing::info!(rule = %triggered.name, %topic, dry_run = triggered.dry_run, "rule fired");
+        self.mqtt.rules.record(&triggered, &topic, OffsetDateTime::now_utc(), outcomes);
+    }
+}
+
+/// Background task: check every message from `rx` against the rules and fire
+/// the ones that match. Actions run in their own task so a slow webhook
+/// doesn't hold up the next message.
+pub async fn run_rules_task(runner: RuleRunner, mut rx: broadcast::Receiver<BrokerEvent>) {
+    loop {
+        match rx.recv().await {
+            Ok(BrokerEvent::Message(msg)) => {
+                for triggered in runner.mqtt.rules.evaluate(&msg, Instant::now()) {
+                    let runner = runner.clone();
+                    let topic = msg.topic.clone();
+                    drop(tokio::spawn(async move { runner.fire(triggered, topic).await }));
+                }
+            }
+            Ok(BrokerEvent::Status { .. }) => {}
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                tracing::warn!(n, "mqtt rules lagged, skipping messages");
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                tracing::warn!("mqtt rules broadcast channel closed, task exiting");
+                break;
+            }
+        }
+    }
+}
+
+// ─── Rules page ───────────────────────────────────────────────────────────────
+
+/// One rule as shown on `/mqtt/rules`.
+#[derive(Debug)]
+struct RuleRow {
+    name: String,
+    topic: String,
+    condition: Option<String>,
+    debounce_secs: u64,
+    dry_run: bool,
+    actions: Vec<String>,
+    fired: u64,
+    last_at: Option<String>,
+    last_topic: String,
+    outcomes: Vec<ActionOutcome>,
+    last_error: Option<(String, String)>,
+}
+
+#[derive(Template)]
+#[template(path = "mqtt_rules.html")]
+struct MqttRulesPage {
+    version: &'static str,
+    auth_user: Option<AuthUserInfo>,
+    nav_links: Arc<[NavLink]>,
+    rules: Vec<RuleRow>,
+}
+
+/// GET `/mqtt/rules` — each rule's trigger, actions, last firing and last
+/// error.
+pub async fn rules_page(user: RequirePermission<MqttAdmin>, State(state): State<ServerState>) -> Result<Html<String>, Error> {
+    let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    let engine = &mqtt.rules;
+    let rules = {
+        let rule_state = engine.state.lock().unwrap_or_else(PoisonError::into_inner);
+        engine
+            .rules
+            .iter()
+            .zip(rule_state.iter())
+            .map(|(rule, st)| RuleRow {
+                name: rule.name.clone(),
+                topic: rule.trigger.topic.clone(),
+                condition: rule.trigger.condition.as_ref().map(|c| c.source.clone()),
+                debounce_secs: rule.trigger.debounce_secs,
+                dry_run: rule.dry_run,
+                actions: rule.actions.iter().map(ActionConfig::summary).collect(),
+                fired: st.fired,
+                last_at: st.last.as_ref().map(|f| format_timestamp(f.at)),
+                last_topic: st.last.as_ref().map(|f| f.topic.clone()).unwrap_or_default(),
+                outcomes: st.last.as_ref().map(|f| f.outcomes.clone()).unwrap_or_default(),
+                last_error: st.last_error.as_ref().map(|(at, err)| (format_timestamp(*at), err.clone())),
+            })
+            .collect()
+    };
+    let page = MqttRulesPage {
+        version: crate::VERSION,
+        auth_user: Some(user.0.info()),
+        nav_links: state.nav_links.clone(),
+        rules,
+    };
+    Ok(Html(page.render()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support::message;
+
+    fn rules(toml_src: &str) -> Vec<RuleConfig> {
+        #[derive(Deserialize)]
+        struct Rules {
+            rules: Vec<RuleConfig>,
+        }
+        toml::from_str::<Rules>(toml_src).unwrap().rules
+    }
+
+    const GARAGE: &str = r#"
+        [[rules]]
+        name = "garage open"
+        trigger = { topic = "zigbee2mqtt/garage_door", condition = "$.contact == false", debounce_secs = 60 }
+        [[rules.actions]]
+        type = "publish"
+        topic = "zigbee2mqtt/garage_light/set"
+        payload = '{"state": "ON"}'
+        [[rules.actions]]
+        type = "ntfy"
+        message = "{{rule}}: battery {{$.battery}}%"
+        priority = "high"
+    "#;
+
+    #[test]
+    fn conditions_compare_numbers_strings_and_presence() {
+        let payload = json!({"temperature": 26.5, "action": "single", "occupancy": false, "count": 3});
+        let check = |c: &str| Condition::parse(c).unwrap().matches(&payload);
+        assert!(check("$.temperature > 25"));
+        assert!(check("$.temperature>=26.5"));
+        assert!(!check("$.temperature < 25"));
+        assert!(check(r#"$.action == "single""#));
+        assert!(check("$.action == single"));
+        assert!(check("$.action != double"));
+        assert!(check("$.count == 3.0"));
+        assert!(check("$.occupancy == false"));
+        assert!(!check("$.occupancy"));
+        assert!(check("$.action"));
+        assert!(!check("$.missing"));
+        assert!(!check("$.action > 3"), "ordering needs like types");
+    }
+
+    #[test]
+    fn malformed_conditions_and_templates_are_rejected() {
+        assert!(Condition::parse("temperature > 25").is_err());
+        assert!(Condition::parse("$.temperature ~ 25").is_err());
+        assert!(Condition::parse("$.temperature >").is_err());
+        assert!(TextTemplate::parse("{{ nope }}").is_err());
+        assert!(TextTemplate::parse("{{topic").is_err());
+        assert!(TextTemplate::parse("{{ $.a[x] }}").is_err());
+    }
+
+    #[test]
+    fn templates_fill_in_message_fields_and_json_paths() {
+        let t = TextTemplate::parse(r#"{{rule}} on {{ topic }}: {{$.temperature}}°, {{$.name}}, {{$.missing}}!"#).unwrap();
+        let m = message("home/temp", r#"{"temperature": 21.5, "name": "kitchen"}"#);
+        let json: Value = serde_json::from_str(&m.payload).unwrap();
+        assert_eq!(t.render("hot", &m, Some(&json)), "hot on home/temp: 21.5°, kitchen, !");
+        assert_eq!(TextTemplate::parse("plain").unwrap().as_static(), Some("plain"));
+        assert_eq!(t.as_static(), None);
+    }
+
+    #[test]
+    fn config_validation_catches_mistakes() {
+        let brokers: Vec<BrokerConfig> = vec![toml::from_str(r#"client_id = "t""#).unwrap()];
+        assert!(validate_rules(&rules(GARAGE), &brokers).is_ok());
+
+        let loops = r#"
+            [[rules]]
+            name = "echo"
+            trigger = { topic = "a/#" }
+            actions = [{ type = "publish", topic = "a/b", payload = "x" }]
+        "#;
+        assert!(validate_rules(&rules(loops), &brokers).unwrap_err().contains("fire the rule again"));
+
+        let unknown_broker = r#"
+            [[rules]]
+            name = "x"
+            trigger = { topic = "a" }
+            actions = [{ type = "publish", topic = "b", payload = "x", broker = "nope" }]
+        "#;
+        assert!(validate_rules(&rules(unknown_broker), &brokers).unwrap_err().contains("unknown broker"));
+
+        let bad_priority = r#"
+            [[rules]]
+            name = "x"
+            trigger = { topic = "a" }
+            actions = [{ type = "ntfy", message = "m", priority = "loud" }]
+        "#;
+        assert!(validate_rules(&rules(bad_priority), &brokers).is_err());
+
+        let no_actions = "[[rules]]\nname = \"x\"\ntrigger = { topic = \"a\" }\nactions = []";
+        assert!(validate_rules(&rules(no_actions), &brokers).unwrap_err().contains("no actions"));
+
+        let twice = format!("{GARAGE}\n{GARAGE}");
+        assert!(validate_rules(&rules(&twice), &brokers).unwrap_err().contains("duplicate"));
+    }
+
+    #[test]
+    fn config_validation_catches_loops_between_rules() {
+        let brokers: Vec<BrokerConfig> = vec![toml::from_str(r#"client_id = "t""#).unwrap()];
+
+        let ping_pong = r#"
+            [[rules]]
+            name = "ping"
+            trigger = { topic = "a/ping" }
+            actions = [{ type = "publish", topic = "b/pong", payload = "x" }]
+            [[rules]]
+            name = "pong"
+            trigger = { topic = "b/+" }
+            actions = [{ type = "publish", topic = "a/ping", payload = "x" }]
+        "#;
+        let err = validate_rules(&rules(ping_pong), &brokers).unwrap_err();
+        assert!(err.contains("`ping` → `pong` → `ping`"), "{err}");
+
+        let templated_loop = r#"
+            [[rules]]
+            name = "forward"
+            trigger = { topic = "in/+", debounce_secs = 5 }
+            actions = [{ type = "publish", topic = "out/{{$.room}}/set", payload = "x" }]
+            [[rules]]
+            name = "back"
+            trigger = { topic = "out/#" }
+            actions = [{ type = "publish", topic = "in/x", payload = "x" }]
+        "#;
+        assert!(validate_rules(&rules(templated_loop), &brokers).unwrap_err().contains("loop"));
+
+        let chain = r#"
+            [[rules]]
+            name = "forward"
+            trigger = { topic = "in/+", debounce_secs = 5 }
+            actions = [{ type = "publish", topic = "out/{{$.room}}/set", payload = "x" }]
+            [[rules]]
+            name = "state"
+            trigger = { topic = "out/+/state" }
+            actions = [{ type = "publish", topic = "log/state", payload = "x" }]
+        "#;
+        assert!(validate_rules(&rules(chain), &brokers).is_ok());
+
+        let templated_self = r#"
+            [[rules]]
+            name = "mirror"
+            trigger = { topic = "home/+", debounce_secs = 5 }
+            actions = [{ type = "publish", topic = "home/{{$.target}}", payload = "x" }]
+        "#;
+        assert!(validate_rules(&rules(templated_self), &brokers).unwrap_err().contains("fire the rule again"));
+
+        let undebounced = r#"
+            [[rules]]
+            name = "forward"
+            trigger = { topic = "in/+" }
+            actions = [{ type = "publish", topic = "out/{{$.room}}", payload = "x" }]
+        "#;
+        assert!(validate_rules(&rules(undebounced), &brokers).unwrap_err().contains("debounce_secs"));
+    }
+
+    #[test]
+    fn evaluate_checks_topic_condition_and_debounce() {
+        let engine = RuleEngine::new(rules(GARAGE));
+        let now = Instant::now();
+
+        assert!(engine.evaluate(&message("zigbee2mqtt/garage_door", r#"{"contact": true}"#), now).is_empty());
+        assert!(engine.evaluate(&message("zigbee2mqtt/other", r#"{"contact": false}"#), now).is_empty());
+        let retained = MqttMessage { retain: true, ..message("zigbee2mqtt/garage_door", r#"{"contact": false}"#) };
+        assert!(engine.evaluate(&retained, now).is_empty(), "retained messages don't fire rules");
+
+        let open = message("zigbee2mqtt/garage_door", r#"{"contact": false, "battery": 87}"#);
+        let fired = engine.evaluate(&open, now);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(
+            fired[0].actions,
+            vec![
+                Ok(PlannedAction::Publish {
+                    broker: None,
+                    topic: "zigbee2mqtt/garage_light/set".into(),
+                    payload: r#"{"state": "ON"}"#.into(),
+                    retain: false,
+                }),
+                Ok(PlannedAction::Ntfy {
+                    url: None,
+                    title: "garage open".into(),
+                    message: "garage open: battery 87%".into(),
+                    priority: "high".into(),
+                }),
+            ]
+        );
+
+        let later = now + Duration::from_secs(60);
+        assert!(engine.evaluate(&open, later).is_empty(), "previous firing still running");
+        engine.record(&fired[0], &open.topic, OffsetDateTime::now_utc(), Vec::new());
+
+        assert!(engine.evaluate(&open, now + Duration::from_secs(30)).is_empty(), "debounced");
+        assert_eq!(engine.evaluate(&open, now + Duration::from_secs(60)).len(), 1);
+    }
+
+    #[test]
+    fn templated_publish_that_would_refire_the_rule_is_an_error() {
+        let engine = RuleEngine::new(rules(
+            r#"
+            [[rules]]
+            name = "mirror"
+            trigger = { topic = "home/+" }
+            actions = [{ type = "publish", topic = "home/{{$.target}}", payload = "x" }]
+            "#,
+        ));
+        let fired = engine.evaluate(&message("home/a", r#"{"target": "b"}"#), Instant::now());
+        assert!(fired[0].actions[0].as_ref().unwrap_err().contains("fire the rule again"));
+    }
+
+    #[test]
+    fn default_webhook_body_describes_the_message() {
+        let engine = RuleEngine::new(rules(
+            r#"
+            [[rules]]
+            name = "hook"
+            trigger = { topic = "home/#" }
+            actions = [{ type = "webhook", url = "https://hooks.example/abc?token=s3cret" }]
+            "#,
+        ));
+        let fired = engine.evaluate(&message("home/door", "open"), Instant::now());
+        let Ok(PlannedAction::Webhook { body, .. }) = &fired[0].actions[0] else {
+            panic!("expected a webhook");
+        };
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["rule"], "hook");
+        assert_eq!(body["topic"], "home/door");
+        assert_eq!(body["payload"], "open");
+
+        let described = fired[0].actions[0].as_ref().unwrap().describe();
+        assert!(!described.contains("s3cret"), "{described}");
+        assert_eq!(engine.rules[0].actions[0].summary(), "POST https://hooks.example/…");
+    }
+}
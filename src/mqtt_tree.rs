@@ -0,0 +1,428 @@
+//! Topic tree: the latest message on every topic, for browsing retained state
+//! and the topic hierarchy at `/mqtt/tree`.
+//!
+//! [`run_topic_tree_task`] keeps a [`TopicTree`] up to date from the
+//! [`BrokerEvent`] stream. It holds one message per `(broker, topic)`, so it
+//! grows with the number of topics rather than messages; past [`MAX_TOPICS`]
+//! new topics are counted but not kept.
+//!
+//! A retained topic is cleared from the page by publishing an empty retained
+//! payload, which is how MQTT deletes a retained message.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use askama::Template;
+use axum::{
+    Form,
+    extract::State,
+    response::{Html, Redirect},
+};
+use rumqttc::QoS;
+use serde::Deserialize;
+use tokio::sync::{Mutex as TokioMutex, broadcast};
+
+use crate::{
+    ServerState,
+    audit::{AuditAction, AuditOutcome, RequestMeta},
+    auth::AuthUserInfo,
+    error::Error,
+    index::NavLink,
+    mqtt::{BrokerEvent, MqttMessage, format_time, html_escape, truncate_at_char, validate_topic_filter},
+    permission::{MqttPublish, MqttRead, Permission, RequirePermission},
+};
+
+/// Most topics kept per process, across all brokers.
+pub const MAX_TOPICS: usize = 10_000;
+
+/// Characters of a payload shown in the tree.
+const PREVIEW_LIMIT: usize = 120;
+
+/// Where the clear form redirects back to.
+const TREE_PATH: &str = "/mqtt/tree";
+
+/// The latest message on one topic.
+#[derive(Debug, Clone)]
+pub struct TopicEntry {
+    pub last: MqttMessage,
+    /// Messages seen on this topic since startup.
+    pub count: u64,
+    /// A message on this topic has arrived with the retain flag, so the broker
+    /// is holding one for new subscribers.
+    pub retained: bool,
+}
+
+/// Latest message per `(broker, topic)`.
+#[derive(Debug, Default)]
+pub struct TopicTree {
+    entries: BTreeMap<(String, String), TopicEntry>,
+    /// New topics not kept because the tree was full.
+    dropped: u64,
+}
+
+impl TopicTree {
+    pub fn record(&mut self, msg: &MqttMessage) {
+        let key = (msg.broker.clone(), msg.topic.clone());
+        let full = self.entries.len() >= MAX_TOPICS;
+        match self.entries.get_mut(&key) {
+            // An empty payload deletes a retained message. Brokers forward the
+            // delete to existing subscribers without the retain flag, so an
+            // empty payload on a topic already known to be retained counts too.
+            Some(entry) if msg.bytes().is_empty() && (msg.retain || entry.retained) => {
+                let _ = self.entries.remove(&key);
+            }
+            Some(entry) => {
+                entry.count += 1;
+                entry.retained |= msg.retain;
+                entry.last = msg.clone();
+            }
+            None if msg.retain && msg.bytes().is_empty() => {}
+            None if full => self.dropped += 1,
+            None => {
+                let entry = TopicEntry {
+                    last: msg.clone(),
+                    count: 1,
+                    retained: msg.retain,
+                };
+                let _ = self.entries.insert(key, entry);
+            }
+        }
+    }
+
+    /// Forget a topic after its retained message was cleared.
+    pub fn clear(&mut self, broker: &str, topic: &str) -> bool {
+        self.entries.remove(&(broker.to_owned(), topic.to_owned())).is_some()
+    }
+
+    pub fn topic_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The topics seen on `broker`, split into levels on `/`.
+    pub fn nodes(&self, broker: &str) -> Vec<TreeNode> {
+        let mut roots = Vec::new();
+        for ((_, topic), entry) in self.entries.iter().filter(|((b, _), _)| b == broker) {
+            let segments: Vec<&str> = topic.split('/').collect();
+            insert(&mut roots, None, &segments, entry);
+        }
+        roots
+    }
+}
+
+/// One level of the topic hierarchy.
+#[derive(Debug, Default)]
+pub struct TreeNode {
+    /// This level's segment of the topic.
+    pub name: String,
+    /// The topic up to and including this level.
+    pub topic: String,
+    /// Messages on this topic and every topic below it.
+    pub count: u64,
+    /// Newest `received_at` on this topic or below.
+    pub last_updated: String,
+    /// Set when messages are published to exactly this topic.
+    pub entry: Option<TopicEntry>,
+    pub children: Vec<TreeNode>,
+}
+
+fn insert(nodes: &mut Vec<TreeNode>, parent: Option<&str>, segments: &[&str], entry: &TopicEntry) {
+    let Some((name, rest)) = segments.split_first() else {
+        return;
+    };
+    let index = match nodes.iter().position(|n| n.name == *name) {
+        Some(index) => index,
+        None => {
+            nodes.push(TreeNode {
+                name: (*name).to_owned(),
+                topic: parent.map_or_else(|| (*name).to_owned(), |p| format!("{p}/{name}")),
+                ..Default::default()
+            });
+            nodes.len() - 1
+        }
+    };
+    let node = &mut nodes[index];
+    node.count += entry.count;
+    // RFC 3339 UTC timestamps sort lexically.
+    if entry.last.received_at > node.last_updated {
+        node.last_updated.clone_from(&entry.last.received_at);
+    }
+    if rest.is_empty() {
+        node.entry = Some(entry.clone());
+    } else {
+        let topic = node.topic.clone();
+        insert(&mut node.children, Some(&topic), rest, entry);
+    }
+}
+
+/// Keep `tree` up to date with every message on the broadcast channel.
+pub async fn run_topic_tree_task(tree: Arc<TokioMutex<TopicTree>>, mut rx: broadcast::Receiver<BrokerEvent>) {
+    loop {
+        match rx.recv().await {
+            Ok(BrokerEvent::Message(msg)) => tree.lock().await.record(&msg),
+            Ok(BrokerEvent::Status { .. }) => {}
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                tracing::warn!(n, "mqtt topic tree lagged, skipping messages");
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                tracing::warn!("mqtt topic tree broadcast channel closed, task exiting");
+                break;
+            }
+        }
+    }
+}
+
+// ─── Page ─────────────────────────────────────────────────────────────────────
+
+/// Render `nodes` as nested `<details>`; the first level starts open. Clear
+/// buttons are only shown when `can_clear`.
+fn render_nodes(nodes: &[TreeNode], broker: &str, depth: usize, can_clear: bool) -> String {
+    let items: String = nodes
+        .iter()
+        .map(|node| {
+            let open = if depth == 0 { " open" } else { "" };
+            let entry = node
+                .entry
+                .as_ref()
+                .map(|entry| render_entry(entry, broker, &node.topic, can_clear))
+                .unwrap_or_default();
+            let children = if node.children.is_empty() {
+                String::new()
+            } else {
+                render_nodes(&node.children, broker, depth + 1, can_clear)
+            };
+            format!(
+                r#"<li class="mqtt-tree-node"><details{open}><summary><span class="mqtt-tree-name">{name}</span><span class="mqtt-tree-count">{count}</span><span class="mqtt-tree-time" title="{updated}">{time}</span></summary>{entry}{children}</details></li>"#,
+                name = html_escape(&node.name),
+                count = node.count,
+                updated = html_escape(&node.last_updated),
+                time = format_time(&node.last_updated),
+            )
+        })
+        .collect();
+    format!(r#"<ul class="mqtt-tree">{items}</ul>"#)
+}
+
+fn render_entry(entry: &TopicEntry, broker: &str, topic: &str, can_clear: bool) -> String {
+    let payload = &entry.last.payload;
+    let preview = if payload.chars().count() > PREVIEW_LIMIT {
+        format!("{}…", truncate_at_char(payload, PREVIEW_LIMIT))
+    } else {
+        payload.clone()
+    };
+    let (badge, clear) = if entry.retained {
+        let clear = if can_clear {
+            format!(
+                r#"<form method="POST" action="/mqtt/tree/clear" class="mqtt-tree-clear" onsubmit="return confirm('clear this retained message?');"><input type="hidden" name="broker" value="{broker}"><input type="hidden" name="topic" value="{topic_esc}"><button type="submit" class="leet-btn">clear retained</button></form>"#,
+                broker = html_escape(broker),
+                topic_esc = html_escape(topic),
+            )
+        } else {
+            String::new()
+        };
+        (r#"<span class="mqtt-tree-retained">retained</span>"#, clear)
+    } else {
+        ("", String::new())
+    };
+    format!(
+        r#"<div class="mqtt-tree-entry">{badge}<code class="mqtt-tree-payload">{}</code>{clear}</div>"#,
+        html_escape(&preview),
+    )
+}
+
+/// One broker's topics, pre-rendered.
+#[derive(Debug)]
+pub struct BrokerTree {
+    pub name: String,
+    pub html: String,
+    pub empty: bool,
+}
+
+#[derive(Template)]
+#[template(path = "mqtt_tree.html")]
+struct MqttTreePage {
+    version: &'static str,
+    auth_user: Option<AuthUserInfo>,
+    nav_links: Arc<[NavLink]>,
+    trees: Vec<BrokerTree>,
+    topic_count: usize,
+    dropped: u64,
+}
+
+/// GET `/mqtt/tree` — the latest message on every topic, as a collapsible
+/// hierarchy (requires `mqtt.read`).
+pub async fn tree_page(
+    user: RequirePermission<MqttRead>,
+    State(state): State<ServerState>,
+) -> Result<Html<String>, Error> {
+    let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    let auth_user = user.0.info();
+    let can_clear = auth_user.can(&Permission::MqttPublish);
+
+    let tree = mqtt.tree.lock().await;
+    let trees = mqtt
+        .brokers
+        .iter()
+        .map(|broker| {
+            let nodes = tree.nodes(&broker.name);
+            BrokerTree {
+                name: broker.name.clone(),
+                html: render_nodes(&nodes, &broker.name, 0, can_clear),
+                empty: nodes.is_empty(),
+            }
+        })
+        .collect();
+    let page = MqttTreePage {
+        version: crate::VERSION,
+        auth_user: Some(auth_user),
+        nav_links: state.nav_links.clone(),
+        trees,
+        topic_count: tree.topic_count(),
+        dropped: tree.dropped(),
+    };
+    drop(tree);
+    Ok(Html(page.render()?))
+}
+
+/// Form body for `POST /mqtt/tree/clear`.
+#[derive(Debug, Deserialize)]
+pub struct ClearRetainedForm {
+    pub broker: String,
+    pub topic: String,
+}
+
+/// POST `/mqtt/tree/clear` — delete a topic's retained message by publishing
+/// an empty retained payload (requires `mqtt.publish`).
+pub async fn clear_retained_route(
+    user: RequirePermission<MqttPublish>,
+    State(state): State<ServerState>,
+    meta: RequestMeta,
+    Form(form): Form<ClearRetainedForm>,
+) -> Result<Redirect, Error> {
+    let mqtt = state.mqtt_state.as_ref().ok_or(Error::MqttNotConfigured)?;
+    let broker = mqtt.broker(Some(&form.broker))?;
+    validate_topic_filter(&form.topic).map_err(Error::InvalidPublishRequest)?;
+    if form.topic.contains(['+', '#']) {
+        return Err(Error::InvalidPublishRequest("cannot clear a wildcard topic".into()));
+    }
+
+    let published = broker
+        .publish_client
+        .publish(&form.topic, QoS::AtLeastOnce, true, Vec::new())
+        .await
+        .map_err(|e| Error::Database(format!("mqtt publish: {e}")));
+    if let Some(auth) = state.auth_state.as_ref() {
+        auth.audit(
+            meta.event(AuditAction::MqttPublish, AuditOutcome::from(&published))
+                .actor(&user.0.username)
+                .target(&form.topic),
+        )
+        .await;
+    }
+    published?;
+    let _ = mqtt.tree.lock().await.clear(&broker.name, &form.topic);
+    tracing::info!(broker = %broker.name, topic = %form.topic, "cleared retained mqtt message");
+    Ok(Redirect::to(TREE_PATH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(topic: &str, payload: &[u8], retain: bool, received_at: &str) -> MqttMessage {
+        MqttMessage {
+            retain,
+            ..MqttMessage::new("default", topic.to_owned(), payload, received_at.to_owned())
+        }
+    }
+
+    #[test]
+    fn record_keeps_latest_message_and_count_per_topic() {
+        let mut tree = TopicTree::default();
+        tree.record(&msg("home/temp", b"21", true, "2026-01-01T00:00:00Z"));
+        tree.record(&msg("home/temp", b"22", false, "2026-01-01T00:01:00Z"));
+        tree.record(&MqttMessage { broker: "other".into(), ..msg("home/temp", b"9", false, "r") });
+
+        let nodes = tree.nodes("default");
+        let entry = nodes[0].children[0].entry.as_ref().unwrap();
+        assert_eq!(entry.last.payload, "22");
+        assert_eq!(entry.count, 2);
+        assert!(entry.retained, "stays retained after a live update");
+        assert_eq!(tree.topic_count(), 2, "topics are per broker");
+    }
+
+    #[test]
+    fn empty_payload_deletes_a_retained_topic() {
+        let mut tree = TopicTree::default();
+        tree.record(&msg("z2m/bridge/devices", b"[]", true, "r"));
+        tree.record(&msg("z2m/bridge/devices", b"", false, "r"));
+        assert_eq!(tree.topic_count(), 0);
+
+        // Empty payloads on topics that were never retained are just messages.
+        tree.record(&msg("doorbell/ring", b"", false, "r"));
+        tree.record(&msg("doorbell/ring", b"", false, "r"));
+        assert_eq!(tree.topic_count(), 1);
+    }
+
+    #[test]
+    fn clear_forgets_the_topic() {
+        let mut tree = TopicTree::default();
+        tree.record(&msg("a/b", b"1", true, "r"));
+        assert!(tree.clear("default", "a/b"));
+        assert!(!tree.clear("default", "a/b"));
+        assert!(tree.nodes("default").is_empty());
+    }
+
+    #[test]
+    fn record_drops_new_topics_past_the_cap() {
+        let mut tree = TopicTree::default();
+        for i in 0..MAX_TOPICS + 3 {
+            tree.record(&msg(&format!("t/{i}"), b"1", false, "r"));
+        }
+        tree.record(&msg("t/0", b"2", false, "r"));
+        assert_eq!(tree.topic_count(), MAX_TOPICS);
+        assert_eq!(tree.dropped(), 3);
+    }
+
+    #[test]
+    fn nodes_aggregate_counts_and_last_updated() {
+        let mut tree = TopicTree::default();
+        tree.record(&msg("z2m/bridge/state", b"online", true, "2026-01-01T00:00:00Z"));
+        tree.record(&msg("z2m/lamp", b"{}", false, "2026-01-01T00:05:00Z"));
+        tree.record(&msg("z2m/lamp", b"{}", false, "2026-01-01T00:06:00Z"));
+        tree.record(&msg("z2m/lamp/availability", b"online", true, "2026-01-01T00:02:00Z"));
+
+        let roots = tree.nodes("default");
+        assert_eq!(roots.len(), 1);
+        let z2m = &roots[0];
+        assert_eq!((z2m.name.as_str(), z2m.count), ("z2m", 4));
+        assert_eq!(z2m.last_updated, "2026-01-01T00:06:00Z");
+        assert!(z2m.entry.is_none());
+
+        let lamp = z2m.children.iter().find(|n| n.name == "lamp").unwrap();
+        assert_eq!(lamp.topic, "z2m/lamp");
+        assert_eq!(lamp.count, 3, "its own messages plus availability");
+        assert_eq!(lamp.entry.as_ref().unwrap().count, 2);
+        assert_eq!(lamp.children[0].topic, "z2m/lamp/availability");
+    }
+
+    #[test]
+    fn render_escapes_and_offers_clear_only_for_retained_topics() {
+        let mut tree = TopicTree::default();
+        tree.record(&msg("home/<door>", b"open", true, "2026-01-01T10:00:00Z"));
+        tree.record(&msg("home/live", b"x", false, "2026-01-01T10:00:00Z"));
+        let nodes = tree.nodes("default");
+
+        let html = render_nodes(&nodes, "default", 0, true);
+        assert!(html.contains("&lt;door&gt;"));
+        assert!(html.contains(r#"<details open>"#), "first level starts open");
+        assert_eq!(html.matches("mqtt-tree-retained").count(), 1);
+        assert_eq!(html.matches(r#"action="/mqtt/tree/clear""#).count(), 1);
+        assert!(html.contains(r#"name="topic" value="home/&lt;door&gt;""#));
+
+        let read_only = render_nodes(&nodes, "default", 0, false);
+        assert!(!read_only.contains("/mqtt/tree/clear"));
+    }
+}
@@ -13,7 +13,7 @@ use axum::{
 };
 use serde::Deserialize;
 
-use crate::{ServerState, VERSION, auth::{AuthUserInfo, MaybeAuthUser, Role}, error::Error, index::NavLink};
+use crate::{ServerState, VERSION, auth::{AuthUserInfo, MaybeAuthUser}, error::Error, index::NavLink, permission::Permission};
 
 // ─── Slug ─────────────────────────────────────────────────────────────────────
 
@@ -428,7 +428,7 @@ impl NotesStore {
             let slug = Slug::from_stem(stem);
             let title = fm
                 .title
-                .unwrap_or_else(|| stem.replace('-', " ").replace('_', " "));
+                .unwrap_or_else(|| stem.replace(['-', '_'], " "));
 
             let resolved = resolve_wiki_links(body, &slug_set);
 
@@ -546,8 +546,10 @@ pub async fn notes_detail_route(
 ) -> Result<Html<String>, Error> {
     let store: &Arc<NotesStore> = state.notes_store.as_ref().ok_or(Error::NotFound)?;
     let note = store.get(&slug).ok_or(Error::NotFound)?;
-    let is_gm = auth_user.as_ref().map(|u| u.role == Role::Gm).unwrap_or(false);
-    let content = if is_gm {
+    let show_secrets = auth_user
+        .as_ref()
+        .is_some_and(|u| u.can(&Permission::NotesSecrets));
+    let content = if show_secrets {
         note.html_gm.as_str().to_owned()
     } else {
         note.html.as_str().to_owned()
@@ -1094,6 +1096,7 @@ mod tests {
             mqtt_state: None,
             log_config: None,
             systemd_config: None,
+            metrics_config: None,
             nav_links: Arc::new([]),
         }
     }
@@ -0,0 +1,147 @@
+//! Named permissions and the extractor that enforces them.
+//!
+//! Roles are sets of permissions configured under `[auth.roles]`; GMs always
+//! hold every permission. Handlers ask for the one permission they need with
+//! [`RequirePermission`], e.g. `RequirePermission<MqttPublish>`.
+
+use std::{collections::BTreeSet, marker::PhantomData};
+
+use axum::{
+    extract::FromRequestParts,
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{ServerState, auth::AuthUser, error::Error};
+
+/// Something a role can be allowed to do.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
+)]
+pub enum Permission {
+    /// View the breaker panel.
+    #[serde(rename = "breaker.read")]
+    #[strum(serialize = "breaker.read")]
+    BreakerRead,
+    /// View the Tailscale peer list.
+    #[serde(rename = "tailscale.read")]
+    #[strum(serialize = "tailscale.read")]
+    TailscaleRead,
+    /// View the MQTT feed and device inventory.
+    #[serde(rename = "mqtt.read")]
+    #[strum(serialize = "mqtt.read")]
+    MqttRead,
+    /// Publish MQTT messages.
+    #[serde(rename = "mqtt.publish")]
+    #[strum(serialize = "mqtt.publish")]
+    MqttPublish,
+    /// Name devices and view the MQTT rules and their outcomes.
+    #[serde(rename = "mqtt.admin")]
+    #[strum(serialize = "mqtt.admin")]
+    MqttAdmin,
+    /// View the app and error logs.
+    #[serde(rename = "logs.read")]
+    #[strum(serialize = "logs.read")]
+    LogsRead,
+    /// View the systemd services dashboard.
+    #[serde(rename = "services.read")]
+    #[strum(serialize = "services.read")]
+    ServicesRead,
+    /// See the GM-only sections of notes.
+    #[serde(rename = "notes.secrets")]
+    #[strum(serialize = "notes.secrets")]
+    NotesSecrets,
+}
+
+/// The permissions held by one role.
+pub type Permissions = BTreeSet<Permission>;
+
+/// A type-level [`Permission`], so it can be named in [`RequirePermission`].
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+macro_rules! permission_markers {
+    ($($marker:ident),* $(,)?) => {$(
+        #[doc = concat!("Marker for [`Permission::", stringify!($marker), "`].")]
+        #[derive(Debug)]
+        pub struct $marker;
+
+        impl RequiredPermission for $marker {
+            const PERMISSION: Permission = Permission::$marker;
+        }
+    )*};
+}
+
+permission_markers!(
+    BreakerRead,
+    TailscaleRead,
+    MqttRead,
+    MqttPublish,
+    MqttAdmin,
+    LogsRead,
+    ServicesRead,
+);
+
+/// Resolves only if the authenticated user's role grants `P`.
+/// Unauthenticated requests are redirected to `/auth/login` (same as `AuthUser`).
+/// Authenticated requests without the permission get a 403.
+pub struct RequirePermission<P>(pub AuthUser, PhantomData<P>);
+
+impl<P: RequiredPermission + Send> FromRequestParts<ServerState> for RequirePermission<P> {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        if !user.permissions.contains(&P::PERMISSION) {
+            return Err(Error::Forbidden.into_response());
+        }
+        Ok(RequirePermission(user, PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator as _;
+
+    #[test]
+    fn permission_names_round_trip() {
+        for permission in Permission::iter() {
+            let name = permission.to_string();
+            assert_eq!(name.parse::<Permission>().unwrap(), permission);
+            let json = serde_json::to_string(&permission).unwrap();
+            assert_eq!(json, format!("\"{name}\""));
+        }
+    }
+
+    #[test]
+    fn permissions_parse_from_toml_list() {
+        #[derive(Deserialize)]
+        struct Cfg {
+            housemate: Permissions,
+        }
+        let cfg: Cfg = toml::from_str(r#"housemate = ["breaker.read", "mqtt.read"]"#).unwrap();
+        assert_eq!(
+            cfg.housemate,
+            Permissions::from([Permission::BreakerRead, Permission::MqttRead])
+        );
+        assert!(toml::from_str::<Cfg>(r#"housemate = ["mqtt.write"]"#).is_err());
+    }
+}
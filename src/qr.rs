@@ -9,24 +9,27 @@ use axum::{
 use qrcode::{QrCode, render::svg};
 use serde::Deserialize;
 
-use crate::{ServerState, auth::{AuthUserInfo, MaybeAuthUser}, error::Error, index::NavLink};
+use crate::{ServerState, auth::{AuthUserInfo, MaybeAuthUser}, csrf::CsrfToken, error::Error, index::NavLink};
 
 #[derive(Debug, Clone, Template)]
 #[template(path = "qr.html")]
 pub struct QrPage {
     pub version: &'static str,
     pub auth_user: Option<AuthUserInfo>,
+    pub csrf_token: String,
     pub nav_links: Arc<[NavLink]>,
 }
 
 pub async fn qr_page_route(
     MaybeAuthUser(auth_user): MaybeAuthUser,
+    CsrfToken(csrf_token): CsrfToken,
     State(state): State<ServerState>,
 ) -> Result<Html<String>, Error> {
     Ok(Html(
         QrPage {
             version: crate::VERSION,
             auth_user,
+            csrf_token,
             nav_links: state.nav_links.clone(),
         }
         .render()?,
@@ -38,15 +41,19 @@ pub struct QrParams {
     pub data: String,
 }
 
-pub async fn qr_route(Json(params): Json<QrParams>) -> Result<impl IntoResponse, Error> {
-    let code = QrCode::new(params.data.as_bytes()).map_err(|source| Error::QrEncode { source })?;
+/// Render `data` as a black-on-white QR code SVG document.
+pub fn qr_svg(data: &str) -> Result<String, Error> {
+    let code = QrCode::new(data.as_bytes()).map_err(|source| Error::QrEncode { source })?;
 
-    let svg = code
+    Ok(code
         .render::<svg::Color>()
         .dark_color(svg::Color("#000000"))
         .light_color(svg::Color("#ffffff"))
-        .build();
+        .build())
+}
 
+pub async fn qr_route(Json(params): Json<QrParams>) -> Result<impl IntoResponse, Error> {
+    let svg = qr_svg(&params.data)?;
     Ok(([(header::CONTENT_TYPE, "image/svg+xml")], svg))
 }
 
@@ -94,6 +101,7 @@ mod tests {
             mqtt_state: None,
             log_config: None,
             systemd_config: None,
+            metrics_config: None,
             nav_links: Arc::new([]),
         }
     }
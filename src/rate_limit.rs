@@ -0,0 +1,268 @@
+//! Rate limiting for the unauthenticated `/auth/*` endpoints.
+//!
+//! Each client IP and each username gets a token bucket: a burst of requests
+//! is allowed, after which requests are refilled at a steady rate. Requests
+//! over the limit get a 429 with `Retry-After`. Pending challenges and
+//! recovery codes are also capped, so a flood of ceremonies can't grow the
+//! challenge store without bound.
+//!
+//! Behind a reverse proxy such as `tailscale serve` every request arrives from
+//! the proxy's address. Listing the proxy under `trusted_proxies` keys its
+//! requests on the client named in `client_header` instead.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+
+use crate::{ServerState, error::Error};
+
+/// Most buckets a limiter keeps before it forgets clients that are back at a
+/// full bucket.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// `[auth.rate_limit]` config block. Every field has a default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Requests one client IP may make to `/auth/*` in a burst.
+    pub ip_burst: u32,
+    /// Sustained `/auth/*` requests per minute for one client IP.
+    pub ip_per_minute: u32,
+    /// Registration and recovery attempts for one username in a burst.
+    pub username_burst: u32,
+    /// Sustained registration and recovery attempts per minute for one username.
+    pub username_per_minute: u32,
+    /// Most pending registration challenges, login challenges and recovery
+    /// codes held at once (each).
+    pub max_pending: usize,
+    /// Reverse proxies whose `client_header` is trusted. Requests from these
+    /// addresses are limited per forwarded client rather than per proxy.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Header a trusted proxy names the original client in. With a list such
+    /// as `X-Forwarded-For`, the last entry (the one the proxy added) is used.
+    pub client_header: String,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            ip_burst: 20,
+            ip_per_minute: 10,
+            username_burst: 3,
+            username_per_minute: 1,
+            max_pending: 1000,
+            trusted_proxies: Vec::new(),
+            client_header: "X-Forwarded-For".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// A set of token buckets, one per key.
+#[derive(Debug)]
+pub struct RateLimiter {
+    burst: f64,
+    per_second: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, per_minute: u32) -> Self {
+        RateLimiter {
+            burst: f64::from(burst.max(1)),
+            per_second: f64::from(per_minute.max(1)) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refill(&self, bucket: Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+
+    /// Take one token from `key`'s bucket at `now`. Returns how long until a
+    /// token is available if the bucket is empty.
+    pub fn check_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_KEYS && !buckets.contains_key(key) {
+            buckets.retain(|_, b| self.refill(*b, now) < self.burst);
+        }
+        let tokens = buckets
+            .get(key)
+            .map_or(self.burst, |b| self.refill(*b, now));
+        if tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - tokens) / self.per_second));
+        }
+        let _ = buckets.insert(
+            key.to_owned(),
+            Bucket {
+                tokens: tokens - 1.0,
+                updated: now,
+            },
+        );
+        Ok(())
+    }
+
+    /// Take one token from `key`'s bucket, or fail with [`Error::RateLimited`].
+    pub fn check(&self, key: &str) -> Result<(), Error> {
+        self.check_at(key, Instant::now())
+            .map_err(|retry_after| Error::RateLimited { retry_after })
+    }
+}
+
+/// The per-IP and per-username limiters, built from [`RateLimitConfig`].
+#[derive(Debug)]
+pub struct RateLimits {
+    pub ip: RateLimiter,
+    pub username: RateLimiter,
+    pub max_pending: usize,
+    trusted_proxies: Vec<IpNet>,
+    client_header: String,
+}
+
+impl RateLimits {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        RateLimits {
+            ip: RateLimiter::new(config.ip_burst, config.ip_per_minute),
+            username: RateLimiter::new(config.username_burst, config.username_per_minute),
+            max_pending: config.max_pending,
+            trusted_proxies: config.trusted_proxies.clone(),
+            client_header: config.client_header.clone(),
+        }
+    }
+
+    /// The key a request from `peer` is limited under: the forwarded client
+    /// if `peer` is a trusted proxy that named one, otherwise `peer` itself.
+    pub fn client_key(&self, peer: IpAddr, headers: &HeaderMap) -> String {
+        let peer = peer.to_canonical();
+        if self.trusted_proxies.iter().any(|net| net.contains(&peer))
+            && let Some(client) = headers
+                .get(self.client_header.as_str())
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit(',').next())
+                .map(str::trim)
+                .filter(|client| !client.is_empty())
+        {
+            return client.to_owned();
+        }
+        peer.to_string()
+    }
+
+    /// Fail with [`Error::RateLimited`] if a store already holds `len` pending
+    /// entries. `oldest_age` is how long ago its oldest entry was created;
+    /// entries expire after `ttl`, which is when the caller can retry.
+    pub fn ensure_capacity(
+        &self,
+        len: usize,
+        oldest_age: Option<Duration>,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        if len < self.max_pending {
+            return Ok(());
+        }
+        let retry_after = oldest_age.map_or(ttl, |age| ttl.saturating_sub(age));
+        Err(Error::RateLimited { retry_after })
+    }
+}
+
+/// Middleware: rate-limit a route by client IP, or by forwarded client for
+/// requests from a trusted proxy. Does nothing when auth is not configured.
+pub async fn limit_by_ip(
+    State(s): State<ServerState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let Some(auth) = s.auth_state.as_ref() {
+        let client = auth.rate_limits.client_key(peer.ip(), req.headers());
+        if let Err(e) = auth.rate_limits.ip.check(&client) {
+            tracing::warn!(client, uri = %req.uri(), "rate limited");
+            return e.into_response();
+        }
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_burst_then_refills() {
+        let limiter = RateLimiter::new(2, 60);
+        let now = Instant::now();
+        assert!(limiter.check_at("ip", now).is_ok());
+        assert!(limiter.check_at("ip", now).is_ok());
+        let retry_after = limiter.check_at("ip", now).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(1), "got {retry_after:?}");
+
+        assert!(limiter.check_at("other", now).is_ok(), "keys are independent");
+        assert!(limiter.check_at("ip", now + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn capacity_check_reports_when_oldest_entry_expires() {
+        let limits = RateLimits::new(&RateLimitConfig {
+            max_pending: 2,
+            ..RateLimitConfig::default()
+        });
+        let ttl = Duration::from_secs(300);
+        assert!(limits.ensure_capacity(1, None, ttl).is_ok());
+        let Err(Error::RateLimited { retry_after }) =
+            limits.ensure_capacity(2, Some(Duration::from_secs(1)), ttl)
+        else {
+            panic!("expected RateLimited");
+        };
+        assert!(retry_after <= ttl && retry_after > Duration::from_secs(290));
+    }
+
+    #[test]
+    fn forwarded_client_is_only_trusted_from_configured_proxies() {
+        let limits = RateLimits::new(&RateLimitConfig {
+            trusted_proxies: vec!["127.0.0.1/32".parse().unwrap()],
+            ..RateLimitConfig::default()
+        });
+        let mut headers = HeaderMap::new();
+        let _ = headers.insert("x-forwarded-for", "203.0.113.9, 100.64.0.5".parse().unwrap());
+
+        let proxy: IpAddr = "127.0.0.1".parse().unwrap();
+        assert_eq!(limits.client_key(proxy, &headers), "100.64.0.5");
+        let mapped: IpAddr = "::ffff:127.0.0.1".parse().unwrap();
+        assert_eq!(limits.client_key(mapped, &headers), "100.64.0.5");
+        assert_eq!(limits.client_key(proxy, &HeaderMap::new()), "127.0.0.1");
+
+        let direct: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(limits.client_key(direct, &headers), "10.0.0.1");
+    }
+
+    #[test]
+    fn client_header_is_configurable() {
+        let limits = RateLimits::new(&RateLimitConfig {
+            trusted_proxies: vec!["127.0.0.1/32".parse().unwrap()],
+            client_header: "Tailscale-User-Login".to_owned(),
+            ..RateLimitConfig::default()
+        });
+        let mut headers = HeaderMap::new();
+        let _ = headers.insert("x-forwarded-for", "100.64.0.5".parse().unwrap());
+        let _ = headers.insert("tailscale-user-login", "alice@example.com".parse().unwrap());
+        let proxy: IpAddr = "127.0.0.1".parse().unwrap();
+        assert_eq!(limits.client_key(proxy, &headers), "alice@example.com");
+    }
+}
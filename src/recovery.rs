@@ -0,0 +1,509 @@
+//! Ways to get back into an account without a passkey.
+//!
+//! `/auth/recover` sends a short-lived one-time code through every configured
+//! [`RecoveryDelivery`] channel: an [ntfy](https://ntfy.sh) topic, email over
+//! SMTP, or both. Independently of those, a user can generate ten single-use
+//! backup codes from their account page and print them; any unused one is
+//! accepted in place of a one-time code. Only hashes of backup codes are
+//! stored.
+
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::{
+    auth::{AuthConfig, UserRecord},
+    error::Error,
+    session::hash_token,
+};
+
+/// Number of backup codes issued at a time.
+pub const BACKUP_CODE_COUNT: usize = 10;
+
+const CODE_CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// `len` random characters from [`CODE_CHARSET`].
+fn random_code(len: usize) -> String {
+    // 252 = 7 × 36 — reject bytes ≥ 252 to avoid modulo bias
+    let mut code = String::with_capacity(len);
+    while code.len() < len {
+        for &b in Uuid::new_v4().into_bytes().iter() {
+            if code.len() == len {
+                break;
+            }
+            if b < 252 {
+                code.push(CODE_CHARSET[(b as usize) % 36] as char);
+            }
+        }
+    }
+    code
+}
+
+/// A 6-character one-time recovery code.
+pub fn generate_otc() -> String {
+    random_code(6)
+}
+
+/// A fresh set of [`BACKUP_CODE_COUNT`] backup codes, formatted `XXXXX-XXXXX`.
+pub fn generate_backup_codes() -> Vec<String> {
+    (0..BACKUP_CODE_COUNT)
+        .map(|_| {
+            let code = random_code(10);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Hash of a backup code as stored for `user_id`. Dashes, spaces and case
+/// are ignored, so `abcde fghij` matches `ABCDE-FGHIJ`.
+pub fn hash_backup_code(user_id: Uuid, code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    hash_token(&format!("{user_id}:{normalized}"))
+}
+
+// ─── Delivery channels ────────────────────────────────────────────────────────
+
+/// A way of sending a one-time recovery code to a user.
+pub trait RecoveryDelivery: Send + Sync + std::fmt::Debug {
+    /// Where the user should look for the code, e.g. `your email`. Shown on
+    /// the recovery page.
+    fn name(&self) -> &'static str;
+
+    /// Send `code` to `user`. Channels that can't reach this user (e.g. no
+    /// email address on file) do nothing.
+    fn deliver<'a>(&'a self, user: &'a UserRecord, code: &'a str)
+    -> BoxFuture<'a, Result<(), Error>>;
+}
+
+/// The channels configured in `[auth]`, in the order they are tried.
+pub fn channels(config: &AuthConfig) -> Result<Vec<Arc<dyn RecoveryDelivery>>, Error> {
+    let mut channels: Vec<Arc<dyn RecoveryDelivery>> = Vec::new();
+    if let Some(url) = &config.ntfy_url {
+        channels.push(Arc::new(NtfyDelivery::new(url.clone())));
+    }
+    if let Some(smtp) = &config.smtp {
+        channels.push(Arc::new(SmtpDelivery::new(smtp)?));
+    }
+    Ok(channels)
+}
+
+/// Posts codes to a single ntfy topic shared by the household. Device alerts
+/// and rules post to their own topic through [`NtfyDelivery::notify`].
+#[derive(Debug, Clone)]
+pub struct NtfyDelivery {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl NtfyDelivery {
+    pub fn new(url: String) -> Self {
+        NtfyDelivery {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    /// Post `body` to the topic with an ntfy `Title` and `Priority`
+    /// (`min`, `low`, `default`, `high` or `urgent`).
+    pub async fn notify(&self, title: &str, priority: &str, body: String) -> Result<(), reqwest::Error> {
+        let _ = self
+            .client
+            .post(&self.url)
+            .header("Title", title)
+            .header("Priority", priority)
+            .body(body)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)?;
+        Ok(())
+    }
+}
+
+impl RecoveryDelivery for NtfyDelivery {
+    fn name(&self) -> &'static str {
+        "your ntfy app"
+    }
+
+    fn deliver<'a>(
+        &'a self,
+        _user: &'a UserRecord,
+        code: &'a str,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.notify("green recovery", "high", code.to_owned())
+                .await
+                .map_err(|e| Error::RecoveryDelivery(e.to_string()))
+        })
+    }
+}
+
+/// How the SMTP connection is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text. Only for a relay on localhost or a test sink.
+    None,
+    /// Upgrade with STARTTLS (port 587 by default).
+    #[default]
+    Starttls,
+    /// TLS from the first byte (port 465 by default).
+    Tls,
+}
+
+/// `[auth.smtp]` config block.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Defaults to the standard port for `tls`.
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Sender, e.g. `green <green@example.com>`.
+    pub from: String,
+}
+
+impl std::fmt::Debug for SmtpConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmtpConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("tls", &self.tls)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("from", &self.from)
+            .finish()
+    }
+}
+
+/// Emails codes to the address on the user's account.
+#[derive(Debug, Clone)]
+pub struct SmtpDelivery {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpDelivery {
+    pub fn new(config: &SmtpConfig) -> Result<Self, Error> {
+        let setup = |e: lettre::transport::smtp::Error| Error::AuthSetup(format!("smtp: {e}"));
+        let mut builder = match config.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host).map_err(setup)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host).map_err(setup)?,
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        let from = config
+            .from
+            .parse()
+            .map_err(|e| Error::AuthSetup(format!("smtp: invalid from address: {e}")))?;
+        Ok(SmtpDelivery {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+impl RecoveryDelivery for SmtpDelivery {
+    fn name(&self) -> &'static str {
+        "your email"
+    }
+
+    fn deliver<'a>(
+        &'a self,
+        user: &'a UserRecord,
+        code: &'a str,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let Some(email) = &user.email else {
+                tracing::debug!(username = %user.username, "no email address; skipping");
+                return Ok(());
+            };
+            let to = email
+                .parse()
+                .map_err(|e| Error::RecoveryDelivery(format!("invalid address `{email}`: {e}")))?;
+            let message = Message::builder()
+                .from(self.from.clone())
+                .to(Mailbox::new(Some(user.username.clone()), to))
+                .subject("green recovery code")
+                .header(ContentType::TEXT_PLAIN)
+                .body(format!(
+                    "Your green recovery code is {code}\n\n\
+                     It expires in 10 minutes. If you didn't ask for it, ignore this email.\n"
+                ))
+                .map_err(|e| Error::RecoveryDelivery(e.to_string()))?;
+            let _ = self
+                .transport
+                .send(message)
+                .await
+                .map_err(|e| Error::RecoveryDelivery(e.to_string()))?;
+            Ok(())
+        })
+    }
+}
+
+// ─── Backup codes ─────────────────────────────────────────────────────────────
+
+/// Storage backend for hashed backup codes, see [`hash_backup_code`].
+pub trait BackupCodeStore: Send + Sync + std::fmt::Debug {
+    /// Replace all of a user's backup codes.
+    fn replace<'a>(&'a self, user_id: Uuid, code_hashes: &'a [String])
+    -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Delete one code. Returns `false` if the user has no such code.
+    fn consume<'a>(&'a self, user_id: Uuid, code_hash: &'a str) -> BoxFuture<'a, Result<bool, Error>>;
+
+    /// How many unused codes the user has left.
+    fn remaining(&self, user_id: Uuid) -> BoxFuture<'_, Result<i64, Error>>;
+}
+
+/// [`BackupCodeStore`] backed by the `backup_codes` table.
+#[derive(Debug, Clone)]
+pub struct PgBackupCodeStore {
+    db: PgPool,
+}
+
+impl PgBackupCodeStore {
+    pub fn new(db: PgPool) -> Self {
+        PgBackupCodeStore { db }
+    }
+}
+
+impl BackupCodeStore for PgBackupCodeStore {
+    fn replace<'a>(
+        &'a self,
+        user_id: Uuid,
+        code_hashes: &'a [String],
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut tx = self
+                .db
+                .begin()
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            let _ = sqlx::query("DELETE FROM backup_codes WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            let _ = sqlx::query(
+                "INSERT INTO backup_codes (user_id, code_hash) SELECT $1, UNNEST($2::text[])",
+            )
+            .bind(user_id)
+            .bind(code_hashes)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            tx.commit()
+                .await
+                .map_err(|e| Error::Database(e.to_string()))
+        })
+    }
+
+    fn consume<'a>(&'a self, user_id: Uuid, code_hash: &'a str) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let result = sqlx::query("DELETE FROM backup_codes WHERE user_id = $1 AND code_hash = $2")
+                .bind(user_id)
+                .bind(code_hash)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(result.rows_affected() > 0)
+        })
+    }
+
+    fn remaining(&self, user_id: Uuid) -> BoxFuture<'_, Result<i64, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query("SELECT COUNT(*) AS remaining FROM backup_codes WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(row.get("remaining"))
+        })
+    }
+}
+
+/// [`BackupCodeStore`] kept in process memory, so handler tests don't need Postgres.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryBackupCodeStore {
+    codes: tokio::sync::RwLock<std::collections::HashMap<Uuid, Vec<String>>>,
+}
+
+#[cfg(test)]
+impl BackupCodeStore for MemoryBackupCodeStore {
+    fn replace<'a>(
+        &'a self,
+        user_id: Uuid,
+        code_hashes: &'a [String],
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = self.codes.write().await.insert(user_id, code_hashes.to_vec());
+            Ok(())
+        })
+    }
+
+    fn consume<'a>(&'a self, user_id: Uuid, code_hash: &'a str) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            let mut codes = self.codes.write().await;
+            let Some(hashes) = codes.get_mut(&user_id) else {
+                return Ok(false);
+            };
+            let before = hashes.len();
+            hashes.retain(|h| h != code_hash);
+            Ok(hashes.len() < before)
+        })
+    }
+
+    fn remaining(&self, user_id: Uuid) -> BoxFuture<'_, Result<i64, Error>> {
+        Box::pin(async move {
+            let codes = self.codes.read().await;
+            Ok(codes.get(&user_id).map_or(0, |h| h.len() as i64))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Role;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    fn user(email: Option<&str>) -> UserRecord {
+        UserRecord {
+            id: Uuid::new_v4(),
+            username: "alice".into(),
+            role: Role::Player,
+            disabled: false,
+            email: email.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn backup_codes_are_unique_and_normalized_before_hashing() {
+        let codes = generate_backup_codes();
+        assert_eq!(codes.len(), BACKUP_CODE_COUNT);
+        assert!(codes.iter().all(|c| c.len() == 11 && c.as_bytes()[5] == b'-'));
+        let unique: std::collections::HashSet<_> = codes.iter().collect();
+        assert_eq!(unique.len(), BACKUP_CODE_COUNT);
+
+        let id = Uuid::new_v4();
+        assert_eq!(hash_backup_code(id, "ABCDE-FGHIJ"), hash_backup_code(id, " abcde fghij"));
+        assert_ne!(
+            hash_backup_code(id, "ABCDE-FGHIJ"),
+            hash_backup_code(Uuid::new_v4(), "ABCDE-FGHIJ"),
+            "hashes are per user"
+        );
+    }
+
+    #[tokio::test]
+    async fn backup_codes_are_single_use() {
+        let store = MemoryBackupCodeStore::default();
+        let id = Uuid::new_v4();
+        let hashes: Vec<String> = ["A", "B"].iter().map(|c| hash_backup_code(id, c)).collect();
+        store.replace(id, &hashes).await.unwrap();
+        assert!(store.consume(id, &hashes[0]).await.unwrap());
+        assert!(!store.consume(id, &hashes[0]).await.unwrap());
+        assert_eq!(store.remaining(id).await.unwrap(), 1);
+    }
+
+    /// Accept one SMTP session and return the message data it carried.
+    async fn smtp_sink(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+        let mut data = String::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let reply: &[u8] = match line.split(' ').next().unwrap_or("") {
+                "EHLO" | "HELO" => b"250 sink\r\n",
+                "DATA" => {
+                    write.write_all(b"354 go ahead\r\n").await.unwrap();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        if line == "." {
+                            break;
+                        }
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    b"250 queued\r\n"
+                }
+                "QUIT" => {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => b"250 OK\r\n",
+            };
+            write.write_all(reply).await.unwrap();
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn smtp_delivery_emails_the_code() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = tokio::spawn(smtp_sink(listener));
+
+        let delivery = SmtpDelivery::new(&SmtpConfig {
+            host: "127.0.0.1".into(),
+            port: Some(port),
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            from: "green <green@example.com>".into(),
+        })
+        .unwrap();
+        // Without an address on file there is nothing to send.
+        delivery.deliver(&user(None), "ABC123").await.unwrap();
+        delivery
+            .deliver(&user(Some("alice@example.com")), "ABC123")
+            .await
+            .unwrap();
+
+        let data = sink.await.unwrap();
+        assert!(data.contains("To: alice <alice@example.com>"), "{data}");
+        assert!(data.contains("ABC123"), "{data}");
+    }
+
+    #[test]
+    fn smtp_config_parses_and_redacts_password() {
+        let cfg: SmtpConfig = toml::from_str(
+            r#"
+            host = "smtp.example.com"
+            username = "green"
+            password = "hunter2"
+            from = "green <green@example.com>"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.tls, SmtpTls::Starttls);
+        assert!(!format!("{cfg:?}").contains("hunter2"));
+    }
+}
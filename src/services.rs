@@ -9,9 +9,10 @@ use std::sync::Arc;
 
 use crate::{
     ServerState, VERSION,
-    auth::{AuthUser, AuthUserInfo, GmUser},
+    auth::AuthUserInfo,
     error::Error,
     index::NavLink,
+    permission::{RequirePermission, ServicesRead},
 };
 
 // ─── Config ───────────────────────────────────────────────────────────────────
@@ -131,10 +132,10 @@ pub fn parse_systemctl_output(name: &str, output: &str, icon_url: Option<String>
                 "ActiveState" => active_state = value.to_owned(),
                 "SubState" => sub_state = value.to_owned(),
                 "MainPID" => {
-                    if let Ok(n) = value.parse::<u32>() {
-                        if n > 0 {
-                            pid = Some(n);
-                        }
+                    if let Ok(n) = value.parse::<u32>()
+                        && n > 0
+                    {
+                        pid = Some(n);
                     }
                 }
                 "ExecMainStartTimestamp" if !value.is_empty() => {
@@ -191,7 +192,7 @@ async fn query_unit(unit: &UnitConfig) -> ServiceStatus {
 
 /// Query all configured units concurrently.
 pub async fn query_all(config: &SystemdConfig) -> Vec<ServiceStatus> {
-    futures::future::join_all(config.units.iter().map(|u| query_unit(u))).await
+    futures::future::join_all(config.units.iter().map(query_unit)).await
 }
 
 // ─── Templates ───────────────────────────────────────────────────────────────
@@ -207,29 +208,25 @@ struct ServicesPage {
 
 // ─── Handlers ────────────────────────────────────────────────────────────────
 
-fn auth_user_info(user: &AuthUser) -> AuthUserInfo {
-    AuthUserInfo { username: user.username.clone(), role: user.role.clone() }
-}
-
-/// `GET /services` — service status dashboard (GM only).
+/// `GET /services` — service status dashboard (requires `services.read`).
 pub async fn services_route(
-    GmUser(user): GmUser,
+    user: RequirePermission<ServicesRead>,
     State(state): State<ServerState>,
 ) -> Result<Html<String>, Error> {
     let config = state.systemd_config.as_ref().ok_or(Error::NotFound)?;
     let services = query_all(config).await;
     let page = ServicesPage {
         version: VERSION,
-        auth_user: Some(auth_user_info(&user)),
+        auth_user: Some(user.0.info()),
         services,
         nav_links: state.nav_links.clone(),
     };
     Ok(Html(page.render()?))
 }
 
-/// `GET /api/services` — JSON list of current service statuses (GM only).
+/// `GET /api/services` — JSON list of current service statuses (requires `services.read`).
 pub async fn services_api_route(
-    _user: GmUser,
+    _user: RequirePermission<ServicesRead>,
     State(state): State<ServerState>,
 ) -> Result<Json<Vec<ServiceStatus>>, Error> {
     let config = state.systemd_config.as_ref().ok_or(Error::NotFound)?;
@@ -0,0 +1,470 @@
+//! Login session persistence.
+//!
+//! Sessions live in the `sessions` table so they survive restarts and can be
+//! shared between instances. Only the SHA-256 hash of the cookie token is
+//! stored; the raw token exists solely in the user's cookie.
+
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Row, postgres::PgRow};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{auth::Role, error::Error};
+
+/// A single logged-in device.
+#[derive(Debug, Clone)]
+pub struct SessionData {
+    /// The `users.id` of the account the session belongs to.
+    pub user_id: Uuid,
+    pub username: String,
+    /// Role at lookup time; the Postgres store reads it from `users`, so a
+    /// role change applies to existing sessions.
+    pub role: Role,
+    /// When the user logged in.
+    pub created_at: OffsetDateTime,
+    /// Time of the latest request on this session, for the idle timeout.
+    pub last_seen: OffsetDateTime,
+    /// `User-Agent` of the login request, shown on the account page.
+    pub user_agent: Option<String>,
+    /// Long-lived "remember this device" session with no idle timeout.
+    pub kiosk: bool,
+}
+
+/// Session lifetime policy, built from the `[auth]` config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionTtl {
+    /// Maximum gap between requests before a regular session expires.
+    pub idle: Duration,
+    /// Maximum lifetime of a regular session, however active it is.
+    pub absolute: Duration,
+    /// Maximum lifetime of a kiosk session. Kiosk sessions never idle out.
+    pub kiosk: Duration,
+}
+
+impl SessionTtl {
+    /// Whether `session` is still usable at `now`.
+    pub fn is_valid(&self, session: &SessionData, now: OffsetDateTime) -> bool {
+        let age = now - session.created_at;
+        if session.kiosk {
+            age <= self.kiosk
+        } else {
+            age <= self.absolute && now - session.last_seen <= self.idle
+        }
+    }
+}
+
+/// Hex-encoded SHA-256 of a session token, used as the storage key.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Storage backend for login sessions, keyed by [`hash_token`].
+///
+/// Boxed futures keep the trait object-safe so [`crate::auth::AuthState`] can
+/// hold an `Arc<dyn SessionStore>` and tests can swap in an in-memory store.
+pub trait SessionStore: Send + Sync + std::fmt::Debug {
+    /// Store a new session under `token_hash`.
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        session: &'a SessionData,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// The session stored under `token_hash`, if any. Expiry is checked by
+    /// the caller with [`SessionTtl::is_valid`].
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, Error>>;
+
+    /// Delete the session stored under `token_hash`, e.g. on logout. Removing
+    /// a missing session is not an error.
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Every session belonging to `user_id` with its token hash, most recently
+    /// active first.
+    fn list_for_user(&self, user_id: Uuid) -> BoxFuture<'_, Result<Vec<(String, SessionData)>, Error>>;
+
+    /// Remove every session belonging to `user_id`, returning how many were removed.
+    fn remove_for_user(&self, user_id: Uuid) -> BoxFuture<'_, Result<u64, Error>>;
+
+    /// Record activity on a session by moving its `last_seen` to `now`.
+    fn touch<'a>(&'a self, token_hash: &'a str, now: OffsetDateTime)
+    -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Remove every session that `ttl` no longer considers valid at `now`,
+    /// returning how many were removed.
+    fn purge_expired(&self, ttl: SessionTtl, now: OffsetDateTime)
+    -> BoxFuture<'_, Result<u64, Error>>;
+}
+
+// ─── Postgres ─────────────────────────────────────────────────────────────────
+
+/// [`SessionStore`] backed by the `sessions` table.
+///
+/// Lookups read the role from the `users` row and skip disabled accounts, so
+/// admin changes apply to live sessions on their next request.
+#[derive(Debug, Clone)]
+pub struct PgSessionStore {
+    db: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(db: PgPool) -> Self {
+        PgSessionStore { db }
+    }
+}
+
+impl SessionStore for PgSessionStore {
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        session: &'a SessionData,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let role = session.role.as_str();
+            let _ = sqlx::query(
+                "INSERT INTO sessions (token_hash, user_id, role, created_at, last_seen, user_agent, kiosk) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(token_hash)
+            .bind(session.user_id)
+            .bind(role)
+            .bind(session.created_at)
+            .bind(session.last_seen)
+            .bind(session.user_agent.as_deref())
+            .bind(session.kiosk)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "SELECT s.user_id, u.username, u.role, s.created_at, s.last_seen, s.user_agent, s.kiosk \
+                 FROM sessions s \
+                 JOIN users u ON u.id = s.user_id \
+                 WHERE s.token_hash = $1 AND NOT u.disabled",
+            )
+            .bind(token_hash)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+            row.as_ref().map(session_from_row).transpose()
+        })
+    }
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query("DELETE FROM sessions WHERE token_hash = $1")
+                .bind(token_hash)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn list_for_user(&self, user_id: Uuid) -> BoxFuture<'_, Result<Vec<(String, SessionData)>, Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT s.token_hash, s.user_id, u.username, u.role, s.created_at, s.last_seen, \
+                        s.user_agent, s.kiosk \
+                 FROM sessions s \
+                 JOIN users u ON u.id = s.user_id \
+                 WHERE s.user_id = $1 \
+                 ORDER BY s.last_seen DESC",
+            )
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+            rows.iter()
+                .map(|row| Ok((row.get("token_hash"), session_from_row(row)?)))
+                .collect()
+        })
+    }
+
+    fn remove_for_user(&self, user_id: Uuid) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            let result = sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(result.rows_affected())
+        })
+    }
+
+    fn touch<'a>(
+        &'a self,
+        token_hash: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query("UPDATE sessions SET last_seen = $2 WHERE token_hash = $1")
+                .bind(token_hash)
+                .bind(now)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn purge_expired(
+        &self,
+        ttl: SessionTtl,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            let result = sqlx::query(
+                "DELETE FROM sessions \
+                 WHERE (kiosk AND created_at < $3) \
+                    OR (NOT kiosk AND (created_at < $1 OR last_seen < $2))",
+            )
+            .bind(now - ttl.absolute)
+            .bind(now - ttl.idle)
+            .bind(now - ttl.kiosk)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(result.rows_affected())
+        })
+    }
+}
+
+fn session_from_row(row: &PgRow) -> Result<SessionData, Error> {
+    Ok(SessionData {
+        user_id: row.get("user_id"),
+        username: row.get("username"),
+        role: Role::from(row.get::<String, _>("role")),
+        created_at: row.get("created_at"),
+        last_seen: row.get("last_seen"),
+        user_agent: row.get("user_agent"),
+        kiosk: row.get("kiosk"),
+    })
+}
+
+// ─── In-memory (tests) ────────────────────────────────────────────────────────
+
+/// [`SessionStore`] kept in process memory, so handler tests don't need Postgres.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: tokio::sync::RwLock<std::collections::HashMap<String, SessionData>>,
+}
+
+#[cfg(test)]
+impl SessionStore for MemorySessionStore {
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        session: &'a SessionData,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = self
+                .sessions
+                .write()
+                .await
+                .insert(token_hash.to_owned(), session.clone());
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, Error>> {
+        Box::pin(async move { Ok(self.sessions.read().await.get(token_hash).cloned()) })
+    }
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = self.sessions.write().await.remove(token_hash);
+            Ok(())
+        })
+    }
+
+    fn list_for_user(&self, user_id: Uuid) -> BoxFuture<'_, Result<Vec<(String, SessionData)>, Error>> {
+        Box::pin(async move {
+            let mut sessions: Vec<_> = self
+                .sessions
+                .read()
+                .await
+                .iter()
+                .filter(|(_, s)| s.user_id == user_id)
+                .map(|(hash, s)| (hash.clone(), s.clone()))
+                .collect();
+            sessions.sort_by_key(|(_, s)| std::cmp::Reverse(s.last_seen));
+            Ok(sessions)
+        })
+    }
+
+    fn remove_for_user(&self, user_id: Uuid) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            let mut map = self.sessions.write().await;
+            let before = map.len();
+            map.retain(|_, s| s.user_id != user_id);
+            Ok((before - map.len()) as u64)
+        })
+    }
+
+    fn touch<'a>(
+        &'a self,
+        token_hash: &'a str,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            if let Some(session) = self.sessions.write().await.get_mut(token_hash) {
+                session.last_seen = now;
+            }
+            Ok(())
+        })
+    }
+
+    fn purge_expired(
+        &self,
+        ttl: SessionTtl,
+        now: OffsetDateTime,
+    ) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            let mut map = self.sessions.write().await;
+            let before = map.len();
+            map.retain(|_, s| ttl.is_valid(s, now));
+            Ok((before - map.len()) as u64)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(user_id: Uuid, created_at: OffsetDateTime) -> SessionData {
+        SessionData {
+            user_id,
+            username: "alice".into(),
+            role: Role::Player,
+            created_at,
+            last_seen: created_at,
+            user_agent: None,
+            kiosk: false,
+        }
+    }
+
+    const TTL: SessionTtl = SessionTtl {
+        idle: Duration::from_secs(60 * 60),
+        absolute: Duration::from_secs(24 * 60 * 60),
+        kiosk: Duration::from_secs(30 * 24 * 60 * 60),
+    };
+
+    #[test]
+    fn hash_token_is_hex_sha256() {
+        let hash = hash_token("token");
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn hash_token_is_deterministic_and_distinct() {
+        assert_eq!(hash_token("a"), hash_token("a"));
+        assert_ne!(hash_token("a"), hash_token("b"));
+        assert_ne!(hash_token("a"), "a");
+    }
+
+    #[tokio::test]
+    async fn memory_store_remove_for_user_only_removes_that_user() {
+        let store = MemorySessionStore::default();
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let now = OffsetDateTime::now_utc();
+        store.insert("a1", &session(alice, now)).await.unwrap();
+        store.insert("a2", &session(alice, now)).await.unwrap();
+        store.insert("b1", &session(bob, now)).await.unwrap();
+
+        assert_eq!(store.remove_for_user(alice).await.unwrap(), 2);
+        assert!(store.get("a1").await.unwrap().is_none());
+        assert!(store.get("b1").await.unwrap().is_some());
+    }
+
+    #[test]
+    fn idle_session_expires_before_absolute_limit() {
+        let now = OffsetDateTime::now_utc();
+        let mut s = session(Uuid::new_v4(), now - time::Duration::hours(2));
+        assert!(!TTL.is_valid(&s, now));
+        s.last_seen = now - time::Duration::minutes(5);
+        assert!(TTL.is_valid(&s, now));
+    }
+
+    #[test]
+    fn active_session_expires_at_absolute_limit() {
+        let now = OffsetDateTime::now_utc();
+        let mut s = session(Uuid::new_v4(), now - time::Duration::hours(25));
+        s.last_seen = now;
+        assert!(!TTL.is_valid(&s, now));
+    }
+
+    #[test]
+    fn kiosk_session_ignores_idle_limit() {
+        let now = OffsetDateTime::now_utc();
+        let mut s = session(Uuid::new_v4(), now - time::Duration::days(10));
+        s.kiosk = true;
+        assert!(TTL.is_valid(&s, now));
+        s.created_at = now - time::Duration::days(31);
+        assert!(!TTL.is_valid(&s, now));
+    }
+
+    #[tokio::test]
+    async fn memory_store_list_for_user_is_most_recent_first() {
+        let store = MemorySessionStore::default();
+        let alice = Uuid::new_v4();
+        let now = OffsetDateTime::now_utc();
+        store.insert("old", &session(alice, now - time::Duration::hours(1))).await.unwrap();
+        store.insert("new", &session(alice, now)).await.unwrap();
+        store.insert("bob", &session(Uuid::new_v4(), now)).await.unwrap();
+
+        let hashes: Vec<_> = store
+            .list_for_user(alice)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(hash, _)| hash)
+            .collect();
+        assert_eq!(hashes, ["new", "old"]);
+    }
+
+    #[tokio::test]
+    async fn memory_store_touch_updates_last_seen() {
+        let store = MemorySessionStore::default();
+        let now = OffsetDateTime::now_utc();
+        let then = now - time::Duration::minutes(30);
+        store.insert("a", &session(Uuid::new_v4(), then)).await.unwrap();
+
+        store.touch("a", now).await.unwrap();
+        assert_eq!(store.get("a").await.unwrap().unwrap().last_seen, now);
+    }
+
+    #[tokio::test]
+    async fn memory_store_purge_expired_keeps_valid_sessions() {
+        let store = MemorySessionStore::default();
+        let now = OffsetDateTime::now_utc();
+        let old = now - time::Duration::hours(2);
+        store.insert("idle", &session(Uuid::new_v4(), old)).await.unwrap();
+        store.insert("new", &session(Uuid::new_v4(), now)).await.unwrap();
+        let mut kiosk = session(Uuid::new_v4(), old);
+        kiosk.kiosk = true;
+        store.insert("kiosk", &kiosk).await.unwrap();
+
+        let purged = store.purge_expired(TTL, now).await.unwrap();
+        assert_eq!(purged, 1);
+        assert!(store.get("idle").await.unwrap().is_none());
+        assert!(store.get("new").await.unwrap().is_some());
+        assert!(store.get("kiosk").await.unwrap().is_some());
+    }
+}
@@ -5,7 +5,7 @@ use axum::{extract::State, response::Html};
 use serde::Deserialize;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use crate::{auth::{AuthUserInfo, GmUser}, error::Error, index::NavLink, ServerState};
+use crate::{auth::AuthUserInfo, error::Error, index::NavLink, permission::{RequirePermission, TailscaleRead}, ServerState};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -150,6 +150,30 @@ async fn fetch_status(socket_path: &Path) -> Result<TailscaleStatus, Error> {
         .map_err(|source| Error::TailscaleDeserialize { source })
 }
 
+pub async fn tailscale_route(
+    user: RequirePermission<TailscaleRead>,
+    State(state): State<ServerState>,
+) -> Result<Html<String>, Error> {
+    let mut status = fetch_status(&state.tailscale_socket).await?;
+
+    let mut peers: Vec<TailscalePeer> = status.peer.drain().map(|(_, v)| v).collect();
+    peers.sort_by(|a, b| a.host_name.cmp(&b.host_name));
+
+    let auth_user = Some(user.0.info());
+
+    let page = TailscalePage {
+        version: crate::VERSION,
+        ts_version: status.version,
+        backend_state: status.backend_state,
+        self_peer: status.self_peer,
+        peers,
+        auth_user,
+        nav_links: state.nav_links.clone(),
+    };
+
+    Ok(Html(page.render()?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -407,30 +431,3 @@ mod tests {
         assert!(peer.flags().contains(&"active"));
     }
 }
-
-pub async fn tailscale_route(
-    user: GmUser,
-    State(state): State<ServerState>,
-) -> Result<Html<String>, Error> {
-    let mut status = fetch_status(&state.tailscale_socket).await?;
-
-    let mut peers: Vec<TailscalePeer> = status.peer.drain().map(|(_, v)| v).collect();
-    peers.sort_by(|a, b| a.host_name.cmp(&b.host_name));
-
-    let auth_user = Some(AuthUserInfo {
-        username: user.0.username.clone(),
-        role: user.0.role.clone(),
-    });
-
-    let page = TailscalePage {
-        version: crate::VERSION,
-        ts_version: status.version,
-        backend_state: status.backend_state,
-        self_peer: status.self_peer,
-        peers,
-        auth_user,
-        nav_links: state.nav_links.clone(),
-    };
-
-    Ok(Html(page.render()?))
-}
@@ -0,0 +1,72 @@
+//! Fixtures shared by the handler tests.
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use crate::{
+    ServerState,
+    auth::{AuthConfig, AuthState},
+    breaker::BreakerContent,
+    breaker_detail::{BreakerData, BreakerDetailStore, BreakerStore},
+    index::Index,
+    invite::RegistrationMode,
+    mqtt::{DEFAULT_BROKER, MqttMessage},
+    route::Routes,
+};
+
+/// A message on `topic` from the default broker, received at a fixed time.
+pub fn message(topic: &str, payload: &str) -> MqttMessage {
+    MqttMessage::new(DEFAULT_BROKER, topic.to_owned(), payload.as_bytes(), "2026-03-15T12:00:00Z".to_owned())
+}
+
+/// `[auth]` with open registration, no GMs, in-memory stores and the default
+/// session lifetimes. Override fields with struct update syntax.
+pub fn auth_config() -> AuthConfig {
+    AuthConfig {
+        rp_id: "localhost".to_string(),
+        rp_origin: "http://localhost".to_string(),
+        db_url: "postgres://localhost/nonexistent".to_string(),
+        gm_users: vec![],
+        ntfy_url: None,
+        smtp: None,
+        idle_ttl: 60 * 60,
+        absolute_ttl: 24 * 60 * 60,
+        kiosk_ttl: 30 * 24 * 60 * 60,
+        registration: RegistrationMode::Open,
+        roles: Default::default(),
+        rate_limit: Default::default(),
+        challenge_store: Default::default(),
+    }
+}
+
+/// A [`ServerState`] with auth from `auth_config` on in-memory stores, an
+/// empty breaker panel and nothing else configured.
+pub async fn server_state(auth_config: AuthConfig) -> ServerState {
+    let auth_state = AuthState::new_for_testing(auth_config).unwrap();
+
+    let data = BreakerData {
+        todos: vec![],
+        slots: HashMap::new(),
+        couples: vec![],
+    };
+    let store = Arc::new(BreakerStore::from_data(data).unwrap());
+    let breaker_content = Arc::new(BreakerContent::new(store.as_ref()));
+    let breaker_detail_store: Arc<dyn BreakerDetailStore> = store;
+    let index = Index::new(Routes::default(), false, false, false, false, &Default::default(), None, Arc::new([]))
+        .await
+        .unwrap();
+
+    ServerState {
+        certificate: Arc::from("fake-cert"),
+        breaker_content,
+        breaker_detail_store,
+        index,
+        tailscale_socket: Arc::from(Path::new("/tmp/fake.sock")),
+        notes_store: None,
+        auth_state: Some(Arc::new(auth_state)),
+        mqtt_state: None,
+        log_config: None,
+        systemd_config: None,
+        metrics_config: None,
+        nav_links: Arc::new([]),
+    }
+}
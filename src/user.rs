@@ -0,0 +1,644 @@
+//! Account and passkey storage.
+//!
+//! Accounts live in the `users` table, their passkeys as one credential list
+//! per user in `passkeys`, and nicknames and timestamps for each passkey in
+//! `passkey_meta`, keyed by [`crate::auth::credential_key`]. Registration,
+//! sign-in and the admin and account pages all go through [`UserStore`], so
+//! they can run against an in-memory backend in tests.
+
+use std::collections::HashMap;
+
+use futures::future::BoxFuture;
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use webauthn_rs::prelude::Passkey;
+
+use crate::{
+    auth::{PasskeyMeta, Role, UserRecord},
+    error::Error,
+};
+
+/// An account's id and its registered passkeys.
+pub type UserPasskeys = (Uuid, Vec<Passkey>);
+
+/// An account's username and its registered passkeys.
+pub type NamedPasskeys = (String, Vec<Passkey>);
+
+/// Storage backend for accounts and their passkeys.
+pub trait UserStore: Send + Sync + std::fmt::Debug {
+    /// The account's id and passkeys. Returns `None` if no user with this
+    /// username exists.
+    fn load_passkeys<'a>(
+        &'a self,
+        username: &'a str,
+    ) -> BoxFuture<'a, Result<Option<UserPasskeys>, Error>>;
+
+    /// The account's username and passkeys. Returns `None` if no user with
+    /// this UUID exists.
+    fn load_passkeys_by_id(&self, user_id: Uuid) -> BoxFuture<'_, Result<Option<NamedPasskeys>, Error>>;
+
+    /// Create a new account with `role` and its first passkey. Fails with
+    /// [`Error::UsernameTaken`] if the username already exists — never touches
+    /// an existing account.
+    fn create<'a>(
+        &'a self,
+        user_id: Uuid,
+        username: &'a str,
+        role: Role,
+        passkey: &'a Passkey,
+    ) -> BoxFuture<'a, Result<UserRecord, Error>>;
+
+    /// Write `passkeys` as an existing user's full credential list and return
+    /// the account. Fails with [`Error::NotFound`] if there is no such user.
+    fn save_passkeys<'a>(
+        &'a self,
+        user_id: Uuid,
+        passkeys: &'a [Passkey],
+    ) -> BoxFuture<'a, Result<UserRecord, Error>>;
+
+    /// Returns `None` if no user with this UUID exists.
+    fn load(&self, user_id: Uuid) -> BoxFuture<'_, Result<Option<UserRecord>, Error>>;
+
+    /// Every account with its passkey count, ordered by username.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(UserRecord, i64)>, Error>>;
+
+    /// Change an account's role. Fails with [`Error::NotFound`] if there is
+    /// no such user; so do [`UserStore::set_disabled`] and
+    /// [`UserStore::set_email`].
+    fn set_role(&self, user_id: Uuid, role: Role) -> BoxFuture<'_, Result<(), Error>>;
+
+    fn set_disabled(&self, user_id: Uuid, disabled: bool) -> BoxFuture<'_, Result<(), Error>>;
+
+    fn set_email(&self, user_id: Uuid, email: Option<String>) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Delete an account with its passkeys and their metadata. Fails with
+    /// [`Error::NotFound`] if there is no such user.
+    fn delete(&self, user_id: Uuid) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Whether any account currently holds the GM role.
+    fn gm_exists(&self) -> BoxFuture<'_, Result<bool, Error>>;
+
+    /// Nickname and timestamps for each of `user_id`'s passkeys, keyed by
+    /// credential key.
+    fn passkey_meta(&self, user_id: Uuid) -> BoxFuture<'_, Result<HashMap<String, PasskeyMeta>, Error>>;
+
+    /// Stamp `created_at` on a freshly registered passkey.
+    fn record_passkey_created<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Stamp `last_used_at` on the passkey that just signed in.
+    fn record_passkey_used<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Set or clear the nickname of one passkey.
+    fn rename_passkey<'a>(
+        &'a self,
+        user_id: Uuid,
+        key: &'a str,
+        nickname: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Forget the metadata of a removed passkey.
+    fn delete_passkey_meta<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+// ─── Postgres ─────────────────────────────────────────────────────────────────
+
+/// [`UserStore`] backed by the `users`, `passkeys` and `passkey_meta` tables.
+#[derive(Debug, Clone)]
+pub struct PgUserStore {
+    db: PgPool,
+}
+
+impl PgUserStore {
+    pub fn new(db: PgPool) -> Self {
+        PgUserStore { db }
+    }
+
+    async fn update<T>(&self, sql: &str, user_id: Uuid, value: T) -> Result<(), Error>
+    where
+        T: for<'q> sqlx::Encode<'q, sqlx::Postgres> + sqlx::Type<sqlx::Postgres> + Send,
+    {
+        let result = sqlx::query(sql)
+            .bind(user_id)
+            .bind(value)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+}
+
+fn passkeys_from_json(credentials: Value) -> Result<Vec<Passkey>, Error> {
+    serde_json::from_value(credentials)
+        .map_err(|e| Error::Database(format!("failed to deserialize passkeys: {e}")))
+}
+
+impl UserStore for PgUserStore {
+    fn load_passkeys<'a>(
+        &'a self,
+        username: &'a str,
+    ) -> BoxFuture<'a, Result<Option<UserPasskeys>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "SELECT u.id, COALESCE(p.credentials, '[]'::jsonb) AS credentials \
+                 FROM users u \
+                 LEFT JOIN passkeys p ON p.user_id = u.id \
+                 WHERE u.username = $1",
+            )
+            .bind(username)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+            row.map(|row| Ok((row.get("id"), passkeys_from_json(row.get("credentials"))?)))
+                .transpose()
+        })
+    }
+
+    fn load_passkeys_by_id(&self, user_id: Uuid) -> BoxFuture<'_, Result<Option<NamedPasskeys>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "SELECT u.username, COALESCE(p.credentials, '[]'::jsonb) AS credentials \
+                 FROM users u \
+                 LEFT JOIN passkeys p ON p.user_id = u.id \
+                 WHERE u.id = $1",
+            )
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+            row.map(|row| Ok((row.get("username"), passkeys_from_json(row.get("credentials"))?)))
+                .transpose()
+        })
+    }
+
+    fn create<'a>(
+        &'a self,
+        user_id: Uuid,
+        username: &'a str,
+        role: Role,
+        passkey: &'a Passkey,
+    ) -> BoxFuture<'a, Result<UserRecord, Error>> {
+        Box::pin(async move {
+            let credentials = serde_json::to_value(std::slice::from_ref(passkey))
+                .map_err(|e| Error::Database(format!("failed to serialize passkeys: {e}")))?;
+
+            let mut tx = self
+                .db
+                .begin()
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+
+            let row = sqlx::query(
+                "INSERT INTO users (id, username, display_name, role) \
+                 VALUES ($1, $2, $2, $3) \
+                 ON CONFLICT DO NOTHING \
+                 RETURNING id, username, role, disabled, email",
+            )
+            .bind(user_id)
+            .bind(username)
+            .bind(role.as_str())
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?
+            .ok_or(Error::UsernameTaken)?;
+            let user = UserRecord::from_row(&row)?;
+
+            let _ = sqlx::query(
+                "INSERT INTO passkeys (user_id, credentials, updated_at) VALUES ($1, $2, NOW())",
+            )
+            .bind(user.id)
+            .bind(credentials)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+            tx.commit()
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+
+            Ok(user)
+        })
+    }
+
+    fn save_passkeys<'a>(
+        &'a self,
+        user_id: Uuid,
+        passkeys: &'a [Passkey],
+    ) -> BoxFuture<'a, Result<UserRecord, Error>> {
+        Box::pin(async move {
+            let credentials = serde_json::to_value(passkeys)
+                .map_err(|e| Error::Database(format!("failed to serialize passkeys: {e}")))?;
+
+            let _ = sqlx::query(
+                "INSERT INTO passkeys (user_id, credentials, updated_at) \
+                 VALUES ($1, $2, NOW()) \
+                 ON CONFLICT (user_id) DO UPDATE SET credentials = EXCLUDED.credentials, updated_at = NOW()",
+            )
+            .bind(user_id)
+            .bind(credentials)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+            self.load(user_id).await?.ok_or(Error::NotFound)
+        })
+    }
+
+    fn load(&self, user_id: Uuid) -> BoxFuture<'_, Result<Option<UserRecord>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query("SELECT id, username, role, disabled, email FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            row.as_ref().map(UserRecord::from_row).transpose()
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(UserRecord, i64)>, Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT u.id, u.username, u.role, u.disabled, u.email, \
+                        COALESCE(jsonb_array_length(p.credentials), 0)::BIGINT AS passkey_count \
+                 FROM users u \
+                 LEFT JOIN passkeys p ON p.user_id = u.id \
+                 ORDER BY u.username",
+            )
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+            rows.iter()
+                .map(|row| Ok((UserRecord::from_row(row)?, row.get("passkey_count"))))
+                .collect()
+        })
+    }
+
+    fn set_role(&self, user_id: Uuid, role: Role) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            self.update("UPDATE users SET role = $2 WHERE id = $1", user_id, role.as_str())
+                .await
+        })
+    }
+
+    fn set_disabled(&self, user_id: Uuid, disabled: bool) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            self.update("UPDATE users SET disabled = $2 WHERE id = $1", user_id, disabled)
+                .await
+        })
+    }
+
+    fn set_email(&self, user_id: Uuid, email: Option<String>) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            self.update("UPDATE users SET email = $2 WHERE id = $1", user_id, email)
+                .await
+        })
+    }
+
+    fn delete(&self, user_id: Uuid) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            // Passkeys, passkey metadata and sessions cascade.
+            let result = sqlx::query("DELETE FROM users WHERE id = $1")
+                .bind(user_id)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound);
+            }
+            Ok(())
+        })
+    }
+
+    fn gm_exists(&self) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE role = $1)")
+                .bind(Role::Gm.as_str())
+                .fetch_one(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))
+        })
+    }
+
+    fn passkey_meta(&self, user_id: Uuid) -> BoxFuture<'_, Result<HashMap<String, PasskeyMeta>, Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                "SELECT credential_id, nickname, created_at, last_used_at \
+                 FROM passkey_meta WHERE user_id = $1",
+            )
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+            Ok(rows
+                .into_iter()
+                .map(|row| {
+                    let meta = PasskeyMeta {
+                        nickname: row.get("nickname"),
+                        created_at: row.get("created_at"),
+                        last_used_at: row.get("last_used_at"),
+                    };
+                    (row.get("credential_id"), meta)
+                })
+                .collect())
+        })
+    }
+
+    fn record_passkey_created<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query(
+                "INSERT INTO passkey_meta (user_id, credential_id, created_at) \
+                 VALUES ($1, $2, NOW()) \
+                 ON CONFLICT (user_id, credential_id) DO NOTHING",
+            )
+            .bind(user_id)
+            .bind(key)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn record_passkey_used<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query(
+                "INSERT INTO passkey_meta (user_id, credential_id, last_used_at) \
+                 VALUES ($1, $2, NOW()) \
+                 ON CONFLICT (user_id, credential_id) DO UPDATE SET last_used_at = NOW()",
+            )
+            .bind(user_id)
+            .bind(key)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn rename_passkey<'a>(
+        &'a self,
+        user_id: Uuid,
+        key: &'a str,
+        nickname: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query(
+                "INSERT INTO passkey_meta (user_id, credential_id, nickname) \
+                 VALUES ($1, $2, $3) \
+                 ON CONFLICT (user_id, credential_id) DO UPDATE SET nickname = EXCLUDED.nickname",
+            )
+            .bind(user_id)
+            .bind(key)
+            .bind(nickname)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn delete_passkey_meta<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = sqlx::query("DELETE FROM passkey_meta WHERE user_id = $1 AND credential_id = $2")
+                .bind(user_id)
+                .bind(key)
+                .execute(&self.db)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            Ok(())
+        })
+    }
+}
+
+// ─── In-memory (tests) ────────────────────────────────────────────────────────
+
+/// [`UserStore`] kept in process memory, so handler tests don't need Postgres.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryUserStore {
+    users: tokio::sync::RwLock<Vec<(UserRecord, Vec<Passkey>)>>,
+    meta: tokio::sync::RwLock<HashMap<(Uuid, String), PasskeyMeta>>,
+}
+
+#[cfg(test)]
+impl MemoryUserStore {
+    /// Add an account without passkeys, as if it had been created earlier.
+    pub async fn insert(&self, username: &str, role: Role) -> Uuid {
+        let user = UserRecord {
+            id: Uuid::new_v4(),
+            username: username.to_owned(),
+            role,
+            disabled: false,
+            email: None,
+        };
+        let id = user.id;
+        self.users.write().await.push((user, Vec::new()));
+        id
+    }
+
+    /// Apply `change` to the account `user_id`, or fail with [`Error::NotFound`].
+    async fn update(&self, user_id: Uuid, change: impl FnOnce(&mut UserRecord)) -> Result<(), Error> {
+        let mut users = self.users.write().await;
+        let (user, _) = users.iter_mut().find(|(user, _)| user.id == user_id).ok_or(Error::NotFound)?;
+        change(user);
+        Ok(())
+    }
+
+    /// Apply `change` to the metadata of one passkey, creating it if needed.
+    async fn update_meta(&self, user_id: Uuid, key: &str, change: impl FnOnce(&mut PasskeyMeta)) {
+        change(self.meta.write().await.entry((user_id, key.to_owned())).or_default());
+    }
+}
+
+#[cfg(test)]
+impl UserStore for MemoryUserStore {
+    fn load_passkeys<'a>(
+        &'a self,
+        username: &'a str,
+    ) -> BoxFuture<'a, Result<Option<UserPasskeys>, Error>> {
+        Box::pin(async move {
+            Ok(self
+                .users
+                .read()
+                .await
+                .iter()
+                .find(|(user, _)| user.username == username)
+                .map(|(user, passkeys)| (user.id, passkeys.clone())))
+        })
+    }
+
+    fn load_passkeys_by_id(&self, user_id: Uuid) -> BoxFuture<'_, Result<Option<NamedPasskeys>, Error>> {
+        Box::pin(async move {
+            Ok(self
+                .users
+                .read()
+                .await
+                .iter()
+                .find(|(user, _)| user.id == user_id)
+                .map(|(user, passkeys)| (user.username.clone(), passkeys.clone())))
+        })
+    }
+
+    fn create<'a>(
+        &'a self,
+        user_id: Uuid,
+        username: &'a str,
+        role: Role,
+        passkey: &'a Passkey,
+    ) -> BoxFuture<'a, Result<UserRecord, Error>> {
+        Box::pin(async move {
+            let mut users = self.users.write().await;
+            if users
+                .iter()
+                .any(|(user, _)| user.id == user_id || user.username == username)
+            {
+                return Err(Error::UsernameTaken);
+            }
+            let user = UserRecord {
+                id: user_id,
+                username: username.to_owned(),
+                role,
+                disabled: false,
+                email: None,
+            };
+            users.push((user.clone(), vec![passkey.clone()]));
+            Ok(user)
+        })
+    }
+
+    fn save_passkeys<'a>(
+        &'a self,
+        user_id: Uuid,
+        passkeys: &'a [Passkey],
+    ) -> BoxFuture<'a, Result<UserRecord, Error>> {
+        Box::pin(async move {
+            let mut users = self.users.write().await;
+            let (user, stored) = users
+                .iter_mut()
+                .find(|(user, _)| user.id == user_id)
+                .ok_or(Error::NotFound)?;
+            *stored = passkeys.to_vec();
+            Ok(user.clone())
+        })
+    }
+
+    fn load(&self, user_id: Uuid) -> BoxFuture<'_, Result<Option<UserRecord>, Error>> {
+        Box::pin(async move {
+            Ok(self
+                .users
+                .read()
+                .await
+                .iter()
+                .find(|(user, _)| user.id == user_id)
+                .map(|(user, _)| user.clone()))
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<(UserRecord, i64)>, Error>> {
+        Box::pin(async move {
+            let mut users: Vec<(UserRecord, i64)> = self
+                .users
+                .read()
+                .await
+                .iter()
+                .map(|(user, passkeys)| (user.clone(), passkeys.len() as i64))
+                .collect();
+            users.sort_by(|(a, _), (b, _)| a.username.cmp(&b.username));
+            Ok(users)
+        })
+    }
+
+    fn set_role(&self, user_id: Uuid, role: Role) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(self.update(user_id, |user| user.role = role))
+    }
+
+    fn set_disabled(&self, user_id: Uuid, disabled: bool) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(self.update(user_id, move |user| user.disabled = disabled))
+    }
+
+    fn set_email(&self, user_id: Uuid, email: Option<String>) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(self.update(user_id, |user| user.email = email))
+    }
+
+    fn delete(&self, user_id: Uuid) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut users = self.users.write().await;
+            let before = users.len();
+            users.retain(|(user, _)| user.id != user_id);
+            if users.len() == before {
+                return Err(Error::NotFound);
+            }
+            self.meta.write().await.retain(|(id, _), _| *id != user_id);
+            Ok(())
+        })
+    }
+
+    fn gm_exists(&self) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            Ok(self
+                .users
+                .read()
+                .await
+                .iter()
+                .any(|(user, _)| user.role == Role::Gm))
+        })
+    }
+
+    fn passkey_meta(&self, user_id: Uuid) -> BoxFuture<'_, Result<HashMap<String, PasskeyMeta>, Error>> {
+        Box::pin(async move {
+            Ok(self
+                .meta
+                .read()
+                .await
+                .iter()
+                .filter(|((id, _), _)| *id == user_id)
+                .map(|((_, key), meta)| (key.clone(), meta.clone()))
+                .collect())
+        })
+    }
+
+    fn record_passkey_created<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let now = time::OffsetDateTime::now_utc();
+            self.update_meta(user_id, key, |meta| {
+                let _ = meta.created_at.get_or_insert(now);
+            })
+            .await;
+            Ok(())
+        })
+    }
+
+    fn record_passkey_used<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let now = time::OffsetDateTime::now_utc();
+            self.update_meta(user_id, key, |meta| meta.last_used_at = Some(now)).await;
+            Ok(())
+        })
+    }
+
+    fn rename_passkey<'a>(
+        &'a self,
+        user_id: Uuid,
+        key: &'a str,
+        nickname: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.update_meta(user_id, key, |meta| meta.nickname = nickname.map(str::to_owned)).await;
+            Ok(())
+        })
+    }
+
+    fn delete_passkey_meta<'a>(&'a self, user_id: Uuid, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let _ = self.meta.write().await.remove(&(user_id, key.to_owned()));
+            Ok(())
+        })
+    }
+}
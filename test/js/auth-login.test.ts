@@ -82,6 +82,19 @@ test('authenticateDiscoverable passes challengeId to finish endpoint', async ()
     assert.equal(bodies[0].challenge_id, 'uuid-123');
 });
 
+test('authenticateDiscoverable sends kiosk flag to finish endpoint', async () => {
+    const bodies = [];
+    const fetch = async (url, opts) => {
+        if (opts?.body) bodies.push(JSON.parse(opts.body));
+        if (!url.includes('finish')) return okJson({ publicKey: {}, challenge_id: 'x' });
+        return okEmpty();
+    };
+    await authenticateDiscoverable({ startAuthentication: async () => ({}), fetch });
+    await authenticateDiscoverable({ startAuthentication: async () => ({}), fetch, kiosk: true });
+    assert.equal(bodies[0].kiosk, false);
+    assert.equal(bodies[1].kiosk, true);
+});
+
 test('authenticateDiscoverable throws on challenge failure', async () => {
     await assert.rejects(
         () => authenticateDiscoverable({
@@ -1,6 +1,6 @@
 import { test } from 'node:test';
 import assert from 'node:assert/strict';
-import { register } from '../../src/js/auth-register.ts';
+import { addPasskey, register } from '../../src/js/auth-register.ts';
 
 // ── helpers ──────────────────────────────────────────────────────────────────
 
@@ -121,3 +121,51 @@ test('posts to the correct endpoints', async () => {
     assert.equal(urls[0], '/auth/register/challenge');
     assert.equal(urls[1], '/auth/register/finish');
 });
+
+test('sends invite token with the challenge request', async () => {
+    const bodies = [];
+    const mockFetch = async (url, opts) => {
+        bodies.push(JSON.parse(opts.body));
+        if (url.includes('challenge')) return okJson({ publicKey: { challenge: 'abc' } });
+        return okEmpty();
+    };
+
+    await register('newbie', {
+        startRegistration: async () => ({}),
+        fetch: mockFetch,
+        invite: 'tok-123',
+    });
+
+    assert.equal(bodies[0].invite, 'tok-123');
+    assert.equal(bodies[1].invite, undefined);
+});
+
+test('addPasskey posts the credential to the account endpoints', async () => {
+    const calls = [];
+    const mockFetch = async (url, opts) => {
+        calls.push({ url, body: opts.body ? JSON.parse(opts.body) : undefined });
+        if (url.includes('challenge')) return okJson({ publicKey: { challenge: 'def' } });
+        return okEmpty();
+    };
+
+    const redirect = await addPasskey({
+        startRegistration: async () => ({ id: 'second-device' }),
+        fetch: mockFetch,
+    });
+
+    assert.equal(redirect, '/auth/account');
+    assert.equal(calls[0].url, '/auth/account/passkeys/challenge');
+    assert.equal(calls[1].url, '/auth/account/passkeys/finish');
+    assert.deepEqual(calls[1].body, { credential: { id: 'second-device' } });
+});
+
+test('addPasskey throws with fallback message when finish fails', async () => {
+    const mockFetch = async (url) => {
+        if (url.includes('challenge')) return okJson({ publicKey: { challenge: 'def' } });
+        return err('');
+    };
+    await assert.rejects(
+        () => addPasskey({ startRegistration: async () => ({}), fetch: mockFetch }),
+        /adding passkey failed/,
+    );
+});
@@ -0,0 +1,95 @@
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { addFormToken, isSameOrigin, needsToken, readToken, TokenForm, withCsrf } from '../../src/js/csrf.ts';
+
+const ORIGIN = 'https://green.example';
+
+function recordingFetch() {
+    const calls: { input: unknown; init?: RequestInit }[] = [];
+    const fetchFn = ((input: unknown, init?: RequestInit) => {
+        calls.push({ input, init });
+        return Promise.resolve(new Response('ok'));
+    }) as typeof globalThis.fetch;
+    return { calls, fetchFn };
+}
+
+function makeForm(method: string, action: string, hasField = false): TokenForm & { added: [string, string][] } {
+    return {
+        method,
+        action,
+        added: [],
+        querySelector: () => (hasField ? {} : null),
+        appendHidden(name, value) { this.added.push([name, value]); },
+    };
+}
+
+// ── readToken ─────────────────────────────────────────────────────────────────
+
+test('readToken returns the meta content', () => {
+    const doc = { querySelector: () => ({ getAttribute: () => 'abc' }) } as unknown as Document;
+    assert.equal(readToken(doc), 'abc');
+});
+
+test('readToken returns null without a meta tag', () => {
+    const doc = { querySelector: () => null } as unknown as Document;
+    assert.equal(readToken(doc), null);
+});
+
+// ── needsToken / isSameOrigin ─────────────────────────────────────────────────
+
+test('needsToken only for state-changing methods', () => {
+    assert.equal(needsToken(undefined), false);
+    assert.equal(needsToken('get'), false);
+    assert.equal(needsToken('HEAD'), false);
+    assert.equal(needsToken('post'), true);
+    assert.equal(needsToken('DELETE'), true);
+});
+
+test('isSameOrigin accepts relative paths and rejects other hosts', () => {
+    assert.equal(isSameOrigin('/api/mqtt/publish', ORIGIN), true);
+    assert.equal(isSameOrigin(`${ORIGIN}/auth/logout`, ORIGIN), true);
+    assert.equal(isSameOrigin('https://evil.example/steal', ORIGIN), false);
+    assert.equal(isSameOrigin('//evil.example/steal', ORIGIN), false);
+});
+
+// ── withCsrf ──────────────────────────────────────────────────────────────────
+
+test('withCsrf adds the header to same-origin POSTs', async () => {
+    const { calls, fetchFn } = recordingFetch();
+    await withCsrf(fetchFn, 'tok', ORIGIN)('/auth/register/challenge', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+    });
+    const headers = calls[0].init?.headers as Headers;
+    assert.equal(headers.get('X-CSRF-Token'), 'tok');
+    assert.equal(headers.get('Content-Type'), 'application/json');
+});
+
+test('withCsrf leaves GETs untouched', async () => {
+    const { calls, fetchFn } = recordingFetch();
+    const init = { method: 'GET' };
+    await withCsrf(fetchFn, 'tok', ORIGIN)('/api/services', init);
+    assert.equal(calls[0].init, init);
+});
+
+test('withCsrf never sends the token cross-origin', async () => {
+    const { calls, fetchFn } = recordingFetch();
+    await withCsrf(fetchFn, 'tok', ORIGIN)('https://ntfy.example/topic', { method: 'POST' });
+    assert.equal(calls[0].init?.headers, undefined);
+});
+
+// ── addFormToken ──────────────────────────────────────────────────────────────
+
+test('addFormToken appends a hidden field to POST forms', () => {
+    const form = makeForm('post', `${ORIGIN}/auth/logout`);
+    assert.equal(addFormToken(form, 'tok', ORIGIN), true);
+    assert.deepEqual(form.added, [['csrf_token', 'tok']]);
+});
+
+test('addFormToken skips GET forms and forms that already have the field', () => {
+    const get = makeForm('get', `${ORIGIN}/admin/audit`);
+    const done = makeForm('post', `${ORIGIN}/auth/logout`, true);
+    assert.equal(addFormToken(get, 'tok', ORIGIN), false);
+    assert.equal(addFormToken(done, 'tok', ORIGIN), false);
+    assert.deepEqual([...get.added, ...done.added], []);
+});
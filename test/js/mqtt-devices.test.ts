@@ -91,6 +91,18 @@ test('sendCommand POSTs JSON to /api/mqtt/publish', async () => {
     assert.equal(body.payload, '{"state":"ON"}');
 });
 
+test('sendCommand includes the broker only when given', async () => {
+    const bodies: string[] = [];
+    const fetch = async (_: unknown, opts?: RequestInit) => {
+        bodies.push(opts?.body as string);
+        return { ok: true, text: async () => '', status: 204 };
+    };
+    await sendCommand('t', 'p', { fetch, broker: 'frigate' });
+    await sendCommand('t', 'p', { fetch });
+    assert.equal(JSON.parse(bodies[0]).broker, 'frigate');
+    assert.equal('broker' in JSON.parse(bodies[1]), false);
+});
+
 test('sendCommand sets Content-Type header', async () => {
     let capturedHeaders: HeadersInit | undefined;
     await sendCommand('t', 'p', {
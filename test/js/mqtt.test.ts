@@ -1,6 +1,6 @@
 import { test } from 'node:test';
 import assert from 'node:assert/strict';
-import { applyFilter, filterByPrefix, getPage, totalPages, renderControls } from '../../src/js/mqtt.ts';
+import { applyFilter, filterByBroker, filterByPrefix, getPage, totalPages, renderControls, streamUrl } from '../../src/js/mqtt.ts';
 
 // Helpers
 function cards(...topics) {
@@ -35,6 +35,34 @@ test('filterByPrefix: no match returns empty', () => {
     assert.equal(filterByPrefix(cs, 'other').length, 0);
 });
 
+// ── streamUrl ────────────────────────────────────────────────────────────────
+
+test('streamUrl: no filters streams everything', () => {
+    assert.equal(streamUrl(''), '/api/mqtt/stream');
+    assert.equal(streamUrl('?utm=x'), '/api/mqtt/stream');
+});
+
+test('streamUrl: forwards stream filters only', () => {
+    const url = streamUrl('?topic=frigate%2F%23&max_payload=2048&page=3');
+    assert.equal(url, '/api/mqtt/stream?topic=frigate%2F%23&max_payload=2048');
+});
+
+// ── filterByBroker ───────────────────────────────────────────────────────────
+
+test('filterByBroker: empty broker returns all cards', () => {
+    const cs = [{ dataset: { topic: 'a', broker: 'zigbee' } }];
+    assert.deepEqual(filterByBroker(cs, ''), cs);
+});
+
+test('filterByBroker: keeps only cards from the named broker', () => {
+    const cs = [
+        { dataset: { topic: 'a', broker: 'zigbee' } },
+        { dataset: { topic: 'b', broker: 'frigate' } },
+        { dataset: { topic: 'c' } },
+    ];
+    assert.deepEqual(filterByBroker(cs, 'frigate'), [cs[1]]);
+});
+
 // ── applyFilter ──────────────────────────────────────────────────────────────
 
 test('applyFilter: empty filterText returns all cards', () => {